@@ -0,0 +1,40 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use mouse_lang::interpreter::Interpreter;
+use mouse_lang::lexer::tokenize;
+use mouse_lang::parser::{parse, Program};
+
+/// A million iterations that read two variables and reassign two others,
+/// so the time is dominated by identifier lookup and assignment.
+const VARIABLE_LOOP: &str = "
+let i = 0;
+let a = 1;
+let b = 2;
+let sum = 0;
+while i < 1000000 {
+    sum = sum + a + b;
+    i = i + 1;
+}
+";
+
+fn compile(source: &str) -> Program {
+    let tokens = tokenize(source.to_string()).expect("benchmark source should tokenize");
+    parse(&tokens).expect("benchmark source should parse")
+}
+
+fn variable_loop_benchmark(c: &mut Criterion) {
+    let program = compile(VARIABLE_LOOP);
+
+    let mut group = c.benchmark_group("interpreter");
+    group.sample_size(10);
+    group.bench_function("variable_loop_1m", |b| {
+        b.iter(|| {
+            let mut interpreter = Interpreter::new();
+            interpreter.interpret(&program).unwrap();
+            interpreter
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, variable_loop_benchmark);
+criterion_main!(benches);
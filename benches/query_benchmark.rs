@@ -1,109 +1,82 @@
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
 use mouse_lang::db::row_schemaless::{Settings, TableRowSchemaless};
 use mouse_lang::db::{DBValue, FilterEntity};
 use std::collections::HashMap;
 use std::hint::black_box;
+use tempfile::TempDir;
 
-async fn setup_test_table() -> TableRowSchemaless {
-    let mut table = TableRowSchemaless::new(
+/// Each setup seeds a fresh table in its own temporary directory, removed
+/// when the returned `TempDir` is dropped.
+async fn temp_table() -> (TableRowSchemaless, TempDir) {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let table = TableRowSchemaless::new(
         "id".to_string(),
         Settings {
-            base_path: "test_db/benchmark".to_string(),
+            base_path: dir.path().to_string_lossy().into_owned(),
+            ..Settings::default()
         },
     )
     .await;
+    (table, dir)
+}
 
-    // Insert test data if the table is empty
-    if table.is_empty().await {
-        println!("Inserting 100,000 rows for benchmark...");
-        for i in 0..100000 {
-            table
-                .insert(HashMap::from([
-                    ("id".to_string(), DBValue::Number(i as f64)),
-                    (
-                        "column1".to_string(),
-                        DBValue::String(format!("value{}", i)),
-                    ),
-                    (
-                        "column2".to_string(),
-                        DBValue::String(format!("value{}- {}", i, i)),
-                    ),
-                    (
-                        "date".to_string(),
-                        DBValue::Timestamp(1672531200 + i * 86400),
-                    ),
-                    ("amount".to_string(), DBValue::Number((i * 2) as f64)),
-                ]))
-                .await;
-        }
-        println!("Test data inserted successfully!");
+async fn insert_rows(table: &mut TableRowSchemaless) {
+    for i in 0..100000 {
+        table
+            .insert(HashMap::from([
+                ("id".to_string(), DBValue::Number(i as f64)),
+                (
+                    "column1".to_string(),
+                    DBValue::String(format!("value{}", i)),
+                ),
+                (
+                    "column2".to_string(),
+                    DBValue::String(format!("value{}- {}", i, i)),
+                ),
+                (
+                    "date".to_string(),
+                    DBValue::Timestamp(1672531200 + i * 86400),
+                ),
+                ("amount".to_string(), DBValue::Number((i * 2) as f64)),
+            ]))
+            .await
+            .unwrap();
     }
+}
+
+async fn setup_test_table() -> (TableRowSchemaless, TempDir) {
+    let (mut table, dir) = temp_table().await;
+
+    println!("Inserting 100,000 rows for benchmark...");
+    insert_rows(&mut table).await;
+    println!("Test data inserted successfully!");
 
-    table
+    (table, dir)
 }
 
-async fn setup_test_table_with_indexes() -> TableRowSchemaless {
-    let mut table = TableRowSchemaless::new(
-        "id".to_string(),
-        Settings {
-            base_path: "test_db/benchmark_indexed".to_string(),
-        },
-    )
-    .await;
+async fn setup_test_table_with_indexes() -> (TableRowSchemaless, TempDir) {
+    let (mut table, dir) = temp_table().await;
 
-    // Insert test data if the table is empty
-    if table.is_empty().await {
-        println!("Inserting 100,000 rows for indexed benchmark...");
-        for i in 0..100000 {
-            table
-                .insert(HashMap::from([
-                    ("id".to_string(), DBValue::Number(i as f64)),
-                    (
-                        "column1".to_string(),
-                        DBValue::String(format!("value{}", i)),
-                    ),
-                    (
-                        "column2".to_string(),
-                        DBValue::String(format!("value{}- {}", i, i)),
-                    ),
-                    (
-                        "date".to_string(),
-                        DBValue::Timestamp(1672531200 + i * 86400),
-                    ),
-                    ("amount".to_string(), DBValue::Number((i * 2) as f64)),
-                ]))
-                .await;
-        }
-        println!("Test data inserted successfully!");
-
-        // Create indexes on commonly queried columns
-        println!("Creating indexes...");
-        table.create_index("column1").await;
-        table.create_index("column2").await;
-        table.create_index("amount").await;
-        table.create_index("date").await;
-        println!("Indexes created: {:?}", table.list_indexes());
-    } else {
-        // Ensure indexes exist even if data was already present
-        let indexes = table.list_indexes();
-        if !indexes.contains(&"column1".to_string()) {
-            println!("Creating indexes on existing data...");
-            table.create_index("column1").await;
-            table.create_index("column2").await;
-            table.create_index("amount").await;
-            table.create_index("date").await;
-            println!("Indexes created: {:?}", table.list_indexes());
-        }
-    }
+    println!("Inserting 100,000 rows for indexed benchmark...");
+    insert_rows(&mut table).await;
+    println!("Test data inserted successfully!");
 
-    table
+    // Create indexes on commonly queried columns
+    println!("Creating indexes...");
+    table.create_index("column1").await.unwrap();
+    table.create_index("column2").await.unwrap();
+    table.create_index("amount").await.unwrap();
+    table.create_index("date").await.unwrap();
+    println!("Indexes created: {:?}", table.list_indexes());
+
+    (table, dir)
 }
 
 fn query_simple_equals(c: &mut Criterion) {
     let runtime = tokio::runtime::Runtime::new().unwrap();
 
     // Setup once
-    let table = runtime.block_on(setup_test_table());
+    let (table, _dir) = runtime.block_on(setup_test_table());
 
     c.bench_function("query_simple_equals_no_index", |b| {
         b.to_async(&runtime).iter(|| async {
@@ -124,7 +97,7 @@ fn query_simple_equals_indexed(c: &mut Criterion) {
     let runtime = tokio::runtime::Runtime::new().unwrap();
 
     // Setup once with indexes
-    let table = runtime.block_on(setup_test_table_with_indexes());
+    let (table, _dir) = runtime.block_on(setup_test_table_with_indexes());
 
     c.bench_function("query_simple_equals_with_index", |b| {
         b.to_async(&runtime).iter(|| async {
@@ -145,7 +118,7 @@ fn query_or_multiple_conditions(c: &mut Criterion) {
     let runtime = tokio::runtime::Runtime::new().unwrap();
 
     // Setup once
-    let table = runtime.block_on(setup_test_table());
+    let (table, _dir) = runtime.block_on(setup_test_table());
 
     c.bench_function("query_or_multiple_conditions_no_index", |b| {
         b.to_async(&runtime).iter(|| async {
@@ -180,7 +153,7 @@ fn query_or_multiple_conditions_indexed(c: &mut Criterion) {
     let runtime = tokio::runtime::Runtime::new().unwrap();
 
     // Setup once with indexes
-    let table = runtime.block_on(setup_test_table_with_indexes());
+    let (table, _dir) = runtime.block_on(setup_test_table_with_indexes());
 
     c.bench_function("query_or_multiple_conditions_with_index", |b| {
         b.to_async(&runtime).iter(|| async {
@@ -215,7 +188,7 @@ fn query_and_conditions(c: &mut Criterion) {
     let runtime = tokio::runtime::Runtime::new().unwrap();
 
     // Setup once
-    let table = runtime.block_on(setup_test_table());
+    let (table, _dir) = runtime.block_on(setup_test_table());
 
     c.bench_function("query_and_conditions_no_index", |b| {
         b.to_async(&runtime).iter(|| async {
@@ -240,7 +213,7 @@ fn query_and_conditions_indexed(c: &mut Criterion) {
     let runtime = tokio::runtime::Runtime::new().unwrap();
 
     // Setup once with indexes
-    let table = runtime.block_on(setup_test_table_with_indexes());
+    let (table, _dir) = runtime.block_on(setup_test_table_with_indexes());
 
     c.bench_function("query_and_conditions_with_index", |b| {
         b.to_async(&runtime).iter(|| async {
@@ -265,7 +238,7 @@ fn query_timestamp_range(c: &mut Criterion) {
     let runtime = tokio::runtime::Runtime::new().unwrap();
 
     // Setup once
-    let table = runtime.block_on(setup_test_table());
+    let (table, _dir) = runtime.block_on(setup_test_table());
 
     c.bench_function("query_timestamp_range_no_index", |b| {
         b.to_async(&runtime).iter(|| async {
@@ -294,7 +267,7 @@ fn query_timestamp_range_indexed(c: &mut Criterion) {
     let runtime = tokio::runtime::Runtime::new().unwrap();
 
     // Setup once with indexes
-    let table = runtime.block_on(setup_test_table_with_indexes());
+    let (table, _dir) = runtime.block_on(setup_test_table_with_indexes());
 
     c.bench_function("query_timestamp_range_with_index", |b| {
         b.to_async(&runtime).iter(|| async {
@@ -323,7 +296,7 @@ fn query_complex_nested(c: &mut Criterion) {
     let runtime = tokio::runtime::Runtime::new().unwrap();
 
     // Setup once
-    let table = runtime.block_on(setup_test_table());
+    let (table, _dir) = runtime.block_on(setup_test_table());
 
     c.bench_function("query_complex_nested_no_index", |b| {
         b.to_async(&runtime).iter(|| async {
@@ -358,7 +331,7 @@ fn query_complex_nested_indexed(c: &mut Criterion) {
     let runtime = tokio::runtime::Runtime::new().unwrap();
 
     // Setup once with indexes
-    let table = runtime.block_on(setup_test_table_with_indexes());
+    let (table, _dir) = runtime.block_on(setup_test_table_with_indexes());
 
     c.bench_function("query_complex_nested_with_index", |b| {
         b.to_async(&runtime).iter(|| async {
@@ -389,8 +362,53 @@ fn query_complex_nested_indexed(c: &mut Criterion) {
     });
 }
 
+fn insert_with_indexes(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("insert");
+    group.sample_size(10);
+    group.bench_function("insert_10k_single_rows_with_3_indexes", |b| {
+        b.iter_batched(
+            || {
+                runtime.block_on(async {
+                    let (mut table, dir) = temp_table().await;
+                    table.create_index("column1").await.unwrap();
+                    table.create_index("amount").await.unwrap();
+                    table.create_index("date").await.unwrap();
+                    (table, dir)
+                })
+            },
+            |(mut table, _dir)| {
+                runtime.block_on(async {
+                    for i in 0..10000 {
+                        table
+                            .insert(HashMap::from([
+                                ("id".to_string(), DBValue::Number(i as f64)),
+                                (
+                                    "column1".to_string(),
+                                    DBValue::String(format!("value{}", i)),
+                                ),
+                                (
+                                    "date".to_string(),
+                                    DBValue::Timestamp(1672531200 + i * 86400),
+                                ),
+                                ("amount".to_string(), DBValue::Number((i * 2) as f64)),
+                            ]))
+                            .await
+                            .unwrap();
+                    }
+                    table.close().await;
+                })
+            },
+            BatchSize::PerIteration,
+        );
+    });
+    group.finish();
+}
+
 criterion_group!(
     benches,
+    insert_with_indexes,
     query_simple_equals,
     query_simple_equals_indexed,
     query_or_multiple_conditions,
@@ -0,0 +1,164 @@
+//! Lock file that keeps two read-write handles, in this process or another,
+//! from writing the same table at once.
+//!
+//! The lock itself is an exclusive OS file lock on the lock file, which the
+//! OS releases when its holder exits, so a lock left behind by a crashed
+//! process is simply taken. The file holds the holder's process id for
+//! error messages only.
+
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How often `acquire` tries to read the id of a holder that has locked the
+/// file but not written its id yet, and how long it waits between tries.
+const HOLDER_ID_ATTEMPTS: u32 = 20;
+const HOLDER_ID_WAIT: Duration = Duration::from_millis(5);
+
+/// Why a lock could not be taken.
+#[derive(Debug)]
+pub(crate) enum LockError {
+    /// A live process holds it.
+    Held {
+        pid: u32,
+    },
+    Io(io::Error),
+}
+
+/// A held lock on a file holding our process id; the file is removed again
+/// on drop.
+#[derive(Debug)]
+pub(crate) struct TableLock {
+    path: PathBuf,
+    file: File,
+}
+
+impl TableLock {
+    /// Locks the file at `path`, creating it if needed.
+    pub(crate) fn acquire(path: &Path) -> Result<TableLock, LockError> {
+        loop {
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)
+                .map_err(LockError::Io)?;
+            match file.try_lock() {
+                Ok(()) => {}
+                Err(TryLockError::WouldBlock) => match holder_of(path) {
+                    // Released and removed while we looked
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                    Err(e) => return Err(LockError::Io(e)),
+                    Ok(pid) => return Err(LockError::Held { pid }),
+                },
+                Err(TryLockError::Error(e)) => return Err(LockError::Io(e)),
+            }
+            // The holder before us may have removed the file between our
+            // open and lock; then this lock guards nothing and we start over
+            if !is_same_file(&file, path) {
+                continue;
+            }
+
+            file.set_len(0).map_err(LockError::Io)?;
+            write!(file, "{}", std::process::id()).map_err(LockError::Io)?;
+            return Ok(TableLock {
+                path: path.to_path_buf(),
+                file,
+            });
+        }
+    }
+}
+
+impl Drop for TableLock {
+    fn drop(&mut self) {
+        // Removed while still locked, so whoever opened it meanwhile sees
+        // that it is gone once `file` closes and the lock is released
+        let _ = fs::remove_file(&self.path);
+        let _ = self.file.unlock();
+    }
+}
+
+/// The process id of whoever holds the lock at `path`, once they have
+/// written it.
+fn holder_of(path: &Path) -> io::Result<u32> {
+    for _ in 0..HOLDER_ID_ATTEMPTS {
+        if let Ok(pid) = fs::read_to_string(path)?.trim().parse() {
+            return Ok(pid);
+        }
+        std::thread::sleep(HOLDER_ID_WAIT);
+    }
+    Err(io::Error::new(
+        io::ErrorKind::WouldBlock,
+        "the table is locked by a process that did not write its id",
+    ))
+}
+
+/// Whether `path` still names the file `file` has open.
+#[cfg(unix)]
+fn is_same_file(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(open), Ok(named)) => open.dev() == named.dev() && open.ino() == named.ino(),
+        _ => false,
+    }
+}
+
+/// Elsewhere a locked file cannot be removed, so it is always the same.
+#[cfg(not(unix))]
+fn is_same_file(_file: &File, _path: &Path) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_racing_writers_never_hold_the_lock_together() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("table.lock");
+        // Left behind by a writer that crashed
+        fs::write(&path, "4000000000").unwrap();
+
+        let holders = Arc::new(AtomicUsize::new(0));
+        let acquired = Arc::new(AtomicUsize::new(0));
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let (path, holders, acquired) = (path.clone(), holders.clone(), acquired.clone());
+                std::thread::spawn(move || {
+                    for _ in 0..50 {
+                        let Ok(lock) = TableLock::acquire(&path) else {
+                            continue;
+                        };
+                        assert_eq!(holders.fetch_add(1, Ordering::SeqCst), 0);
+                        acquired.fetch_add(1, Ordering::SeqCst);
+                        std::thread::yield_now();
+                        holders.fetch_sub(1, Ordering::SeqCst);
+                        drop(lock);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert!(acquired.load(Ordering::SeqCst) > 0);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_held_lock_names_its_holder() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("table.lock");
+        let _lock = TableLock::acquire(&path).unwrap();
+        assert!(matches!(
+            TableLock::acquire(&path),
+            Err(LockError::Held { pid }) if pid == std::process::id()
+        ));
+    }
+}
@@ -1,8 +1,13 @@
+use crate::iso8601;
 use bincode::{Decode, Encode};
+use chrono::{DateTime, SecondsFormat};
 use serde::{Deserialize, Serialize};
 
+pub(crate) mod lock;
 pub mod query_engine;
 pub mod row_schemaless;
+pub mod scanner;
+mod typed;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Encode, Decode)]
 pub enum DBValue {
@@ -12,7 +17,75 @@ pub enum DBValue {
     Null,
 }
 
+/// Largest integer magnitude an f64 holds exactly (2^53).
+pub const MAX_SAFE_INTEGER: i64 = 1 << 53;
+
 impl DBValue {
+    /// Stores a script integer as a `Number`. Integers beyond ±2^53 would be
+    /// rounded by f64, so they are rejected instead (store them as a
+    /// `Timestamp` or `String` if they need to survive exactly).
+    pub fn from_integer(n: i64) -> Option<DBValue> {
+        if (-MAX_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(&n) {
+            Some(DBValue::Number(n as f64))
+        } else {
+            None
+        }
+    }
+
+    /// Reads the value back as an integer: timestamps always convert, numbers
+    /// only if they are whole and within ±2^53.
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            DBValue::Timestamp(ts) => Some(*ts),
+            DBValue::Number(n) if n.fract() == 0.0 && n.abs() <= MAX_SAFE_INTEGER as f64 => {
+                Some(*n as i64)
+            }
+            _ => None,
+        }
+    }
+
+    /// Parses text (a CSV cell, a CLI filter argument) as a value of type
+    /// `type_hint`. Timestamps accept Unix seconds or an ISO 8601 date or
+    /// date-time; null accepts an empty string or `null`.
+    pub fn parse(type_hint: &DBValueType, text: &str) -> Result<DBValue, String> {
+        match type_hint {
+            DBValueType::String => Ok(DBValue::String(text.to_string())),
+            DBValueType::Number => text
+                .trim()
+                .parse()
+                .map(DBValue::Number)
+                .map_err(|_| format!("invalid number: {}", text)),
+            DBValueType::Timestamp => {
+                let text = text.trim();
+                if let Ok(seconds) = text.parse() {
+                    return Ok(DBValue::Timestamp(seconds));
+                }
+                iso8601::parse(text)
+                    .map(|date| DBValue::Timestamp(date.timestamp()))
+                    .map_err(|e| format!("invalid timestamp, {}", e))
+            }
+            DBValueType::Null => match text.trim() {
+                "" => Ok(DBValue::Null),
+                other if other.eq_ignore_ascii_case("null") => Ok(DBValue::Null),
+                other => Err(format!("expected null, got {}", other)),
+            },
+        }
+    }
+
+    /// JSON form used for export: timestamps become RFC 3339 strings and
+    /// whole numbers are written without a fractional part.
+    pub fn to_json_value(&self) -> serde_json::Value {
+        match self {
+            DBValue::String(s) => serde_json::Value::String(s.clone()),
+            DBValue::Number(_) if self.as_integer().is_some() => self.as_integer().unwrap().into(),
+            DBValue::Number(n) => serde_json::Number::from_f64(*n)
+                .map(serde_json::Value::Number)
+                .unwrap_or(serde_json::Value::Null),
+            DBValue::Timestamp(_) => serde_json::Value::String(self.to_string()),
+            DBValue::Null => serde_json::Value::Null,
+        }
+    }
+
     pub fn vtype(&self) -> DBValueType {
         match self {
             DBValue::String(_) => DBValueType::String,
@@ -23,6 +96,27 @@ impl DBValue {
     }
 }
 
+/// Formats Unix seconds as RFC 3339 in UTC, or as the plain number when it is
+/// outside the range chrono can represent.
+fn format_timestamp(seconds: i64) -> String {
+    match DateTime::from_timestamp(seconds, 0) {
+        Some(date) => date.to_rfc3339_opts(SecondsFormat::Secs, true),
+        None => seconds.to_string(),
+    }
+}
+
+impl std::fmt::Display for DBValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DBValue::String(s) => write!(f, "{}", s),
+            // f64's Display already drops a trailing `.0` and never uses exponents
+            DBValue::Number(n) => write!(f, "{}", n),
+            DBValue::Timestamp(ts) => write!(f, "{}", format_timestamp(*ts)),
+            DBValue::Null => write!(f, "null"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Eq, PartialEq, Hash, Deserialize, Encode, Decode)]
 pub enum DBValueType {
     String,
@@ -47,3 +141,234 @@ pub enum FilterEntity {
     Column(String),
     Null,
 }
+
+/// Options for `TableRowSchemaless::query_with`.
+#[derive(Debug, Clone, Default)]
+pub struct QueryOptions {
+    /// Adds each row's version as a `__version` column, to pass back as
+    /// the expected version of a later `update`.
+    pub include_versions: bool,
+}
+
+/// A failed table operation as reported to scripts, naming the operation
+/// and, where it is known, the table.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DbError {
+    /// The handle was closed or never opened.
+    Closed {
+        operation: &'static str,
+        handle: u64,
+    },
+    /// A script value that cannot be stored in a column.
+    UnsupportedValue {
+        operation: &'static str,
+        table: String,
+        value: String,
+    },
+    Io {
+        operation: &'static str,
+        table: String,
+        message: String,
+    },
+    /// No row with this id, or it could not be read.
+    NoSuchRow {
+        operation: &'static str,
+        table: String,
+        row_id: u64,
+    },
+    /// A write through a handle opened with `OpenMode::ReadOnly`.
+    ReadOnly {
+        operation: &'static str,
+        table: String,
+    },
+    /// A row that does not fit the Rust type it was read into, or a value
+    /// that has no column form; `message` names the column.
+    Conversion {
+        operation: &'static str,
+        table: String,
+        message: String,
+    },
+    /// Another handle, possibly in another process, has the table open for
+    /// writing.
+    Locked { holder_pid: u32 },
+    /// A conditional update of a row that changed since it was read.
+    VersionConflict {
+        row_id: u64,
+        expected: u64,
+        actual: u64,
+    },
+}
+
+impl std::fmt::Display for DbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DbError::Closed { operation, handle } => {
+                write!(f, "db.{}: table handle {} is closed", operation, handle)
+            }
+            DbError::UnsupportedValue {
+                operation,
+                table,
+                value,
+            } => write!(
+                f,
+                "db.{} on table '{}': cannot store {}",
+                operation, table, value
+            ),
+            DbError::Io {
+                operation,
+                table,
+                message,
+            } => write!(f, "db.{} on table '{}': {}", operation, table, message),
+            DbError::NoSuchRow {
+                operation,
+                table,
+                row_id,
+            } => write!(
+                f,
+                "db.{} on table '{}': no row {}",
+                operation, table, row_id
+            ),
+            DbError::ReadOnly { operation, table } => write!(
+                f,
+                "db.{} on table '{}': the table is open read-only",
+                operation, table
+            ),
+            DbError::Conversion {
+                operation,
+                table,
+                message,
+            } => write!(f, "db.{} on table '{}': {}", operation, table, message),
+            DbError::Locked { holder_pid } => {
+                write!(f, "the table is open for writing in process {}", holder_pid)
+            }
+            DbError::VersionConflict {
+                row_id,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "row {} was changed concurrently: expected version {}, found {}",
+                row_id, expected, actual
+            ),
+        }
+    }
+}
+
+impl From<DbError> for String {
+    fn from(error: DbError) -> Self {
+        error.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_integer_roundtrip() {
+        let ts = 1_700_000_000_000;
+        let value = DBValue::from_integer(ts).unwrap();
+        assert_eq!(value.as_integer(), Some(ts));
+
+        assert_eq!(
+            DBValue::from_integer(-MAX_SAFE_INTEGER)
+                .unwrap()
+                .as_integer(),
+            Some(-MAX_SAFE_INTEGER)
+        );
+    }
+
+    #[test]
+    fn test_integer_beyond_f64_precision_is_rejected() {
+        assert_eq!(DBValue::from_integer(MAX_SAFE_INTEGER + 1), None);
+        assert_eq!(DBValue::from_integer(i64::MAX), None);
+        assert_eq!(DBValue::Number(1.5).as_integer(), None);
+        assert_eq!(DBValue::Number(1e300).as_integer(), None);
+        assert_eq!(DBValue::Timestamp(i64::MAX).as_integer(), Some(i64::MAX));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(DBValue::String("a b".to_string()).to_string(), "a b");
+        assert_eq!(DBValue::Number(2.0).to_string(), "2");
+        assert_eq!(DBValue::Number(-0.25).to_string(), "-0.25");
+        assert_eq!(
+            DBValue::Number(-1e21).to_string(),
+            "-1000000000000000000000"
+        );
+        assert_eq!(
+            DBValue::Number(9007199254740993.0).to_string(),
+            "9007199254740992"
+        );
+        assert_eq!(DBValue::Null.to_string(), "null");
+        assert_eq!(
+            DBValue::Timestamp(1672531200).to_string(),
+            "2023-01-01T00:00:00Z"
+        );
+        assert_eq!(
+            DBValue::Timestamp(-86400).to_string(),
+            "1969-12-31T00:00:00Z"
+        );
+        assert_eq!(
+            DBValue::Timestamp(i64::MAX).to_string(),
+            i64::MAX.to_string()
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            DBValue::parse(&DBValueType::Number, " -12.5 "),
+            Ok(DBValue::Number(-12.5))
+        );
+        assert!(DBValue::parse(&DBValueType::Number, "twelve").is_err());
+        assert_eq!(
+            DBValue::parse(&DBValueType::String, " x "),
+            Ok(DBValue::String(" x ".to_string()))
+        );
+        assert_eq!(
+            DBValue::parse(&DBValueType::Null, "NULL"),
+            Ok(DBValue::Null)
+        );
+        assert_eq!(
+            DBValue::parse(&DBValueType::Timestamp, "1672531200"),
+            Ok(DBValue::Timestamp(1672531200))
+        );
+        // Offsets are converted to UTC
+        assert_eq!(
+            DBValue::parse(&DBValueType::Timestamp, "2023-01-01T02:00:00+02:00"),
+            Ok(DBValue::Timestamp(1672531200))
+        );
+        assert_eq!(
+            DBValue::parse(&DBValueType::Timestamp, "2023-01-01"),
+            Ok(DBValue::Timestamp(1672531200))
+        );
+        assert!(DBValue::parse(&DBValueType::Timestamp, "yesterday").is_err());
+    }
+
+    #[test]
+    fn test_timestamp_display_parse_roundtrip() {
+        for ts in [0, 1672531200, -1_000_000_000, 4_102_444_800] {
+            let text = DBValue::Timestamp(ts).to_string();
+            assert_eq!(
+                DBValue::parse(&DBValueType::Timestamp, &text),
+                Ok(DBValue::Timestamp(ts))
+            );
+        }
+    }
+
+    #[test]
+    fn test_to_json_value() {
+        use serde_json::json;
+
+        assert_eq!(DBValue::Number(3.0).to_json_value(), json!(3));
+        assert_eq!(DBValue::Number(0.5).to_json_value(), json!(0.5));
+        assert_eq!(DBValue::Number(f64::NAN).to_json_value(), json!(null));
+        assert_eq!(
+            DBValue::Timestamp(1672531200).to_json_value(),
+            json!("2023-01-01T00:00:00Z")
+        );
+        assert_eq!(DBValue::String("s".to_string()).to_json_value(), json!("s"));
+        assert_eq!(DBValue::Null.to_json_value(), json!(null));
+    }
+}
@@ -152,27 +152,18 @@ fn evaluate_filter(filter: &FilterEntity, fields: &HashMap<String, DBValue>) ->
                 evaluate_to_value(left, fields),
                 evaluate_to_value(right, fields),
             ) {
-                (Some(l), Some(r)) => values_equal(&l, &r),
+                (Some(l), Some(r)) => match coerce_dates(l, r) {
+                    Some((l, r)) => values_equal(&l, &r),
+                    None => false,
+                },
                 _ => false,
             }
         }
         FilterEntity::GreaterThan(left, right) => {
-            match (
-                evaluate_to_value(left, fields),
-                evaluate_to_value(right, fields),
-            ) {
-                (Some(DBValue::Number(l)), Some(DBValue::Number(r))) => l > r,
-                _ => false,
-            }
+            compare(left, right, fields) == Some(std::cmp::Ordering::Greater)
         }
         FilterEntity::LessThan(left, right) => {
-            match (
-                evaluate_to_value(left, fields),
-                evaluate_to_value(right, fields),
-            ) {
-                (Some(DBValue::Number(l)), Some(DBValue::Number(r))) => l < r,
-                _ => false,
-            }
+            compare(left, right, fields) == Some(std::cmp::Ordering::Less)
         }
         FilterEntity::FuzzyMatch(left, right, threshold) => {
             match (
@@ -202,6 +193,32 @@ fn evaluate_filter(filter: &FilterEntity, fields: &HashMap<String, DBValue>) ->
     }
 }
 
+/// Orders two numbers or two timestamps; anything else is incomparable.
+fn compare(
+    left: &FilterEntity,
+    right: &FilterEntity,
+    fields: &HashMap<String, DBValue>,
+) -> Option<std::cmp::Ordering> {
+    let left = evaluate_to_value(left, fields)?;
+    let right = evaluate_to_value(right, fields)?;
+    match coerce_dates(left, right)? {
+        (DBValue::Number(l), DBValue::Number(r)) => l.partial_cmp(&r),
+        (DBValue::Timestamp(l), DBValue::Timestamp(r)) => Some(l.cmp(&r)),
+        _ => None,
+    }
+}
+
+/// A string compared with a timestamp is read as an ISO 8601 date, so
+/// filters can use literals like `"2023-06-01"`. `None` if it is not one.
+fn coerce_dates(left: DBValue, right: DBValue) -> Option<(DBValue, DBValue)> {
+    let as_timestamp = |text: &str| DBValue::parse(&DBValueType::Timestamp, text).ok();
+    match (left, right) {
+        (DBValue::String(l), r @ DBValue::Timestamp(_)) => Some((as_timestamp(&l)?, r)),
+        (l @ DBValue::Timestamp(_), DBValue::String(r)) => Some((l, as_timestamp(&r)?)),
+        other => Some(other),
+    }
+}
+
 /// Evaluate a filter entity to a concrete value
 fn evaluate_to_value(filter: &FilterEntity, fields: &HashMap<String, DBValue>) -> Option<DBValue> {
     match filter {
@@ -1,12 +1,27 @@
-use crate::db::{query_engine, DBValue, DBValueType, FilterEntity};
+use crate::db::lock::{LockError, TableLock};
+use crate::db::scanner::{RowScanner, ScanPosition};
+use crate::db::{query_engine, DBValue, DBValueType, DbError, FilterEntity, QueryOptions};
 use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
 use tokio::fs::OpenOptions;
 use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
 
-#[derive(Clone)]
+/// Whether a handle may change the table.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OpenMode {
+    /// Only one read-write handle per table at a time, across processes.
+    #[default]
+    ReadWrite,
+    /// Never writes, creates or locks anything, so any number can be open
+    /// next to a writer.
+    ReadOnly,
+}
+
+#[derive(Clone, Default)]
 pub struct Settings {
     pub base_path: String,
+    pub mode: OpenMode,
 }
 
 pub struct TableRowSchemaless {
@@ -16,16 +31,78 @@ pub struct TableRowSchemaless {
     // Indexes: column_name -> (indexed_value -> Vec<row_id>)
     indexes: Arc<RwLock<HashMap<String, BTreeMap<String, Vec<u64>>>>>,
     next_row_id: Arc<RwLock<u64>>,
+    // Inserts only update indexes in memory; `flush_indexes` writes them out
+    indexes_dirty: bool,
+    // Held while open read-write, released on drop
+    _lock: Option<TableLock>,
+}
+
+type Indexes = HashMap<String, BTreeMap<String, Vec<u64>>>;
+
+/// Column holding a row's version: 1 on insert, bumped by every `update`.
+/// Rows written before versioning have none and count as version 0.
+/// `scan` returns it, queries only when asked to.
+pub const VERSION_COLUMN: &str = "__version";
+
+fn version_of(row: &HashMap<String, DBValue>) -> u64 {
+    match row.get(VERSION_COLUMN) {
+        Some(DBValue::Number(n)) if *n >= 0.0 => *n as u64,
+        _ => 0,
+    }
+}
+
+/// Byte range of record `row_id` in a table file, after its length prefix.
+fn record_range(file: &[u8], row_id: u64) -> Option<std::ops::Range<usize>> {
+    let mut start = 0;
+    for id in 0.. {
+        let prefix = file.get(start..start + 4)?;
+        let len = u32::from_le_bytes(prefix.try_into().ok()?) as usize;
+        let record = start + 4..start + 4 + len;
+        if record.end > file.len() {
+            return None;
+        }
+        if id == row_id {
+            return Some(record);
+        }
+        start = record.end;
+    }
+    None
 }
 
 impl TableRowSchemaless {
+    /// Like `open`, but panics if the table cannot be opened.
     pub async fn new(pk: String, settings: Settings) -> Self {
-        // create file
-        if !std::path::Path::new(&settings.base_path).exists() {
-            tokio::fs::create_dir_all(&settings.base_path)
-                .await
-                .expect("Failed to create directory");
-        }
+        Self::open(pk, settings)
+            .await
+            .unwrap_or_else(|e| panic!("Failed to open table: {}", e))
+    }
+
+    /// Opens table `pk` in `settings.base_path`. Read-write handles create
+    /// the directory if needed and lock the table until they are dropped;
+    /// while another live handle holds the lock this fails with
+    /// `DbError::Locked`.
+    pub async fn open(pk: String, settings: Settings) -> Result<Self, DbError> {
+        let io_error = |e: std::io::Error| DbError::Io {
+            operation: "open",
+            table: pk.clone(),
+            message: e.to_string(),
+        };
+        let lock = match settings.mode {
+            OpenMode::ReadWrite => {
+                tokio::fs::create_dir_all(&settings.base_path)
+                    .await
+                    .map_err(io_error)?;
+                let lock_path = Path::new(&settings.base_path).join(format!("{}.lock", pk));
+                match TableLock::acquire(&lock_path) {
+                    Ok(lock) => Some(lock),
+                    Err(LockError::Held { pid }) => {
+                        return Err(DbError::Locked { holder_pid: pid })
+                    }
+                    Err(LockError::Io(e)) => return Err(io_error(e)),
+                }
+            }
+            OpenMode::ReadOnly => None,
+        };
 
         let mut table = Self {
             settings,
@@ -33,16 +110,41 @@ impl TableRowSchemaless {
             known_columns: HashSet::new(),
             indexes: Arc::new(RwLock::new(HashMap::new())),
             next_row_id: Arc::new(RwLock::new(0)),
+            indexes_dirty: false,
+            _lock: lock,
         };
 
         // Load indexes from disk if they exist
-        table.load_indexes().await;
+        let mut indexed_rows = table.load_indexes().await;
 
         // Initialize next_row_id by counting existing rows
-        let row_count = table.size().await;
-        *table.next_row_id.write().unwrap() = row_count as u64;
+        let row_count = table.size().await as u64;
+        *table.next_row_id.write().unwrap() = row_count;
 
-        table
+        // Rows inserted after the last flush are missing from the saved
+        // indexes, e.g. after a crash; index them now.
+        if indexed_rows > row_count {
+            for index in table.indexes.write().unwrap().values_mut() {
+                index.clear();
+            }
+            indexed_rows = 0;
+        }
+        if indexed_rows < row_count {
+            table.index_rows_from(indexed_rows).await;
+        }
+
+        Ok(table)
+    }
+
+    /// Fails with `DbError::ReadOnly` unless the handle may write.
+    fn check_writable(&self, operation: &'static str) -> Result<(), DbError> {
+        match self.settings.mode {
+            OpenMode::ReadWrite => Ok(()),
+            OpenMode::ReadOnly => Err(DbError::ReadOnly {
+                operation,
+                table: self.primary_key.clone(),
+            }),
+        }
     }
 
     fn value_to_index_key(value: &DBValue) -> String {
@@ -55,69 +157,43 @@ impl TableRowSchemaless {
     }
 
     /// Create an index on a specified column
-    pub async fn create_index(&mut self, column: &str) {
-        let mut indexes = self.indexes.write().unwrap();
-
+    pub async fn create_index(&mut self, column: &str) -> Result<(), DbError> {
+        self.check_writable("create_index")?;
         // Check if index already exists
-        if indexes.contains_key(column) {
-            return;
+        if self.indexes.read().unwrap().contains_key(column) {
+            return Ok(());
         }
 
-        // Create new index
-        let mut index: BTreeMap<String, Vec<u64>> = BTreeMap::new();
-
         // Read all rows and build index
-        let file_result = OpenOptions::new()
-            .read(true)
-            .open(format!("{}/{}", self.settings.base_path, self.primary_key))
-            .await;
-
-        if let Ok(file) = file_result {
-            let mut reader = BufReader::new(file);
-            let mut row_id = 0u64;
-
-            loop {
-                let mut len_bytes = [0u8; 4];
-                match reader.read_exact(&mut len_bytes).await {
-                    Ok(_) => {}
-                    Err(_) => break,
-                }
-                let len = u32::from_le_bytes(len_bytes) as usize;
-
-                let mut buffer = vec![0u8; len];
-                if reader.read_exact(&mut buffer).await.is_err() {
-                    break;
-                }
-
-                let config = bincode::config::standard();
-                if let Ok((row, _)) =
-                    bincode::decode_from_slice::<HashMap<String, DBValue>, _>(&buffer, config)
-                {
-                    if let Some(value) = row.get(column) {
-                        let key = Self::value_to_index_key(value);
-                        index.entry(key).or_insert_with(Vec::new).push(row_id);
-                    }
-                }
-
-                row_id += 1;
+        let mut index: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+        let mut scanner = self.scan().await;
+        while let Some((row_id, row)) = scanner.next().await {
+            if let Some(value) = row.get(column) {
+                let key = Self::value_to_index_key(value);
+                index.entry(key).or_insert_with(Vec::new).push(row_id);
             }
         }
 
-        indexes.insert(column.to_string(), index);
-        drop(indexes); // Release lock before async operation
+        self.indexes
+            .write()
+            .unwrap()
+            .insert(column.to_string(), index);
 
         // Persist indexes
         self.save_indexes().await;
+        Ok(())
     }
 
     /// Drop an index on a specified column
-    pub async fn drop_index(&mut self, column: &str) {
+    pub async fn drop_index(&mut self, column: &str) -> Result<(), DbError> {
+        self.check_writable("drop_index")?;
         let mut indexes = self.indexes.write().unwrap();
         indexes.remove(column);
         drop(indexes); // Release lock before async operation
 
         // Persist indexes
         self.save_indexes().await;
+        Ok(())
     }
 
     /// List all indexed columns
@@ -126,40 +202,105 @@ impl TableRowSchemaless {
         indexes.keys().cloned().collect()
     }
 
-    async fn save_indexes(&self) {
-        let indexes = self.indexes.read().unwrap();
-        let index_path = format!("{}/{}.idx", self.settings.base_path, self.primary_key);
+    /// Writes the indexes to disk if inserts changed them since the last save.
+    /// Until then a reopened table re-indexes the unsaved rows on load.
+    pub async fn flush_indexes(&mut self) {
+        // Read-only handles keep what they re-indexed on open in memory
+        if self.indexes_dirty && self.settings.mode == OpenMode::ReadWrite {
+            self.save_indexes().await;
+        }
+    }
+
+    /// Flushes pending index changes. Call before dropping a table that had
+    /// inserts so reopening it does not have to re-index them.
+    pub async fn close(mut self) {
+        self.flush_indexes().await;
+    }
+
+    /// The file the rows are appended to.
+    fn data_path(&self) -> PathBuf {
+        Path::new(&self.settings.base_path).join(&self.primary_key)
+    }
+
+    fn index_path(&self) -> PathBuf {
+        Path::new(&self.settings.base_path).join(format!("{}.idx", self.primary_key))
+    }
+
+    /// The index file stores the number of rows it covers next to the indexes.
+    async fn save_indexes(&mut self) {
+        let index_path = self.index_path();
 
         let config = bincode::config::standard();
-        if let Ok(bytes) = bincode::encode_to_vec(&*indexes, config) {
+        let encoded = {
+            let indexes = self.indexes.read().unwrap();
+            let indexed_rows = *self.next_row_id.read().unwrap();
+            bincode::encode_to_vec((indexed_rows, &*indexes), config)
+        };
+        if let Ok(bytes) = encoded {
             if let Ok(mut file) = tokio::fs::File::create(&index_path).await {
-                let _ = file.write_all(&bytes).await;
+                if file.write_all(&bytes).await.is_ok() {
+                    self.indexes_dirty = false;
+                }
             }
         }
     }
 
-    async fn load_indexes(&mut self) {
-        let index_path = format!("{}/{}.idx", self.settings.base_path, self.primary_key);
+    /// Loads saved indexes and returns how many rows they cover.
+    async fn load_indexes(&mut self) -> u64 {
+        let index_path = self.index_path();
 
-        if let Ok(mut file) = tokio::fs::File::open(&index_path).await {
-            let mut buffer = Vec::new();
-            if file.read_to_end(&mut buffer).await.is_ok() {
-                let config = bincode::config::standard();
-                if let Ok((loaded_indexes, _)) = bincode::decode_from_slice::<
-                    HashMap<String, BTreeMap<String, Vec<u64>>>,
-                    _,
-                >(&buffer, config)
-                {
-                    *self.indexes.write().unwrap() = loaded_indexes;
+        let Ok(mut file) = tokio::fs::File::open(&index_path).await else {
+            return 0;
+        };
+        let mut buffer = Vec::new();
+        if file.read_to_end(&mut buffer).await.is_err() {
+            return 0;
+        }
+
+        let config = bincode::config::standard();
+        if let Ok(((indexed_rows, loaded_indexes), _)) =
+            bincode::decode_from_slice::<(u64, Indexes), _>(&buffer, config)
+        {
+            *self.indexes.write().unwrap() = loaded_indexes;
+            return indexed_rows;
+        }
+        // Files written before the row count was stored: keep the indexed
+        // columns and rebuild their contents
+        if let Ok((loaded_indexes, _)) = bincode::decode_from_slice::<Indexes, _>(&buffer, config) {
+            *self.indexes.write().unwrap() = loaded_indexes
+                .into_keys()
+                .map(|column| (column, BTreeMap::new()))
+                .collect();
+        }
+        0
+    }
+
+    /// Adds the rows from `row_id` on to every index.
+    async fn index_rows_from(&mut self, row_id: u64) {
+        if self.indexes.read().unwrap().is_empty() {
+            return;
+        }
+
+        let mut scanner = self.scan().await;
+        scanner.seek_to(row_id).await;
+        while let Some((row_id, row)) = scanner.next().await {
+            let mut indexes = self.indexes.write().unwrap();
+            for (column, index) in indexes.iter_mut() {
+                if let Some(value) = row.get(column) {
+                    let key = Self::value_to_index_key(value);
+                    index.entry(key).or_insert_with(Vec::new).push(row_id);
                 }
             }
         }
+        self.indexes_dirty = true;
     }
 
-    pub async fn insert(&mut self, data: HashMap<String, DBValue>) {
+    pub async fn insert(&mut self, mut data: HashMap<String, DBValue>) -> Result<(), DbError> {
+        self.check_writable("insert")?;
         for (k, v) in &data {
             self.known_columns.insert((k.to_owned(), v.vtype()));
         }
+        data.insert(VERSION_COLUMN.to_string(), DBValue::Number(1.0));
 
         // Get the row ID for this insert
         let row_id = {
@@ -184,7 +325,7 @@ impl TableRowSchemaless {
         let mut file = OpenOptions::new()
             .create(true)
             .append(true)
-            .open(format!("{}/{}", self.settings.base_path, self.primary_key))
+            .open(self.data_path())
             .await
             .unwrap();
 
@@ -198,13 +339,26 @@ impl TableRowSchemaless {
         file.write_all(&bytes).await.unwrap();
         file.flush().await.unwrap();
 
-        // Persist indexes if any exist
         if !self.indexes.read().unwrap().is_empty() {
-            self.save_indexes().await;
+            self.indexes_dirty = true;
         }
+        Ok(())
     }
 
-    pub async fn drop(&mut self) {
+    /// Inserts all rows, then flushes the indexes once.
+    pub async fn insert_many(
+        &mut self,
+        rows: impl IntoIterator<Item = HashMap<String, DBValue>>,
+    ) -> Result<(), DbError> {
+        for row in rows {
+            self.insert(row).await?;
+        }
+        self.flush_indexes().await;
+        Ok(())
+    }
+
+    pub async fn drop(&mut self) -> Result<(), DbError> {
+        self.check_writable("drop")?;
         // Clear indexes
         {
             let mut indexes = self.indexes.write().unwrap();
@@ -212,19 +366,22 @@ impl TableRowSchemaless {
         }
 
         // delete the file
-        tokio::fs::remove_file(format!("{}/{}", self.settings.base_path, self.primary_key))
+        tokio::fs::remove_file(self.data_path())
             .await
             .expect("Failed to remove file");
 
         // Delete index file
-        let index_path = format!("{}/{}.idx", self.settings.base_path, self.primary_key);
+        let index_path = self.index_path();
         let _ = tokio::fs::remove_file(&index_path).await;
 
         // Reset row counter
         *self.next_row_id.write().unwrap() = 0;
+        self.indexes_dirty = false;
+        Ok(())
     }
 
-    pub async fn truncate(&mut self) {
+    pub async fn truncate(&mut self) -> Result<(), DbError> {
+        self.check_writable("truncate")?;
         // Clear indexes but keep index definitions
         {
             let mut indexes = self.indexes.write().unwrap();
@@ -234,7 +391,7 @@ impl TableRowSchemaless {
         }
 
         // remove all rows
-        let path = format!("{}/{}", self.settings.base_path, self.primary_key);
+        let path = self.data_path();
         let _ = tokio::fs::remove_file(&path).await; // Ignore error if file doesn't exist
         tokio::fs::File::create(&path)
             .await
@@ -247,16 +404,142 @@ impl TableRowSchemaless {
         if !self.indexes.read().unwrap().is_empty() {
             self.save_indexes().await;
         }
+        Ok(())
     }
 
     pub async fn query(&self, query: FilterEntity) -> Vec<HashMap<String, DBValue>> {
+        self.query_with(query, &QueryOptions::default()).await
+    }
+
+    pub async fn query_with(
+        &self,
+        query: FilterEntity,
+        options: &QueryOptions,
+    ) -> Vec<HashMap<String, DBValue>> {
         // Try to use index if available for simple equality queries
-        if let Some((_column, _value, row_ids)) = self.try_use_index(&query) {
-            return self.query_by_row_ids(&row_ids, &query).await;
+        let mut rows = match self.try_use_index(&query) {
+            Some((_column, _value, row_ids)) => self.query_by_row_ids(&row_ids, &query).await,
+            // Fall back to full table scan
+            None => self.query_full_scan(query).await,
+        };
+
+        for row in &mut rows {
+            if options.include_versions {
+                let version = version_of(row);
+                row.insert(VERSION_COLUMN.to_string(), DBValue::Number(version as f64));
+            } else {
+                row.remove(VERSION_COLUMN);
+            }
         }
+        rows
+    }
+
+    /// Sets the columns in `changes` on row `row_id`, keeping the others,
+    /// and returns the row's new version. With `expected_version` the
+    /// update only happens if the row is still at that version, so of two
+    /// writers that read the same version only the first one wins; a row
+    /// at version 0 accepts any expected version.
+    ///
+    /// Rows are stored back to back, so this rewrites the table file.
+    pub async fn update(
+        &mut self,
+        row_id: u64,
+        changes: HashMap<String, DBValue>,
+        expected_version: Option<u64>,
+    ) -> Result<u64, DbError> {
+        self.check_writable("update")?;
+        let io_error = |e: std::io::Error| DbError::Io {
+            operation: "update",
+            table: self.primary_key.clone(),
+            message: e.to_string(),
+        };
+        let no_such_row = || DbError::NoSuchRow {
+            operation: "update",
+            table: self.primary_key.clone(),
+            row_id,
+        };
+
+        let path = self.data_path();
+        let file = match tokio::fs::read(&path).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(no_such_row()),
+            Err(e) => return Err(io_error(e)),
+        };
+        let record = record_range(&file, row_id).ok_or_else(no_such_row)?;
 
-        // Fall back to full table scan
-        self.query_full_scan(query).await
+        let config = bincode::config::standard();
+        let (mut row, _) = bincode::decode_from_slice::<HashMap<String, DBValue>, _>(
+            &file[record.clone()],
+            config,
+        )
+        .map_err(|_| no_such_row())?;
+        let actual = version_of(&row);
+        if let Some(expected) = expected_version {
+            if actual != 0 && actual != expected {
+                return Err(DbError::VersionConflict {
+                    row_id,
+                    expected,
+                    actual,
+                });
+            }
+        }
+
+        let old_row = row.clone();
+        for (k, v) in changes {
+            self.known_columns.insert((k.clone(), v.vtype()));
+            row.insert(k, v);
+        }
+        let version = actual + 1;
+        row.insert(VERSION_COLUMN.to_string(), DBValue::Number(version as f64));
+
+        let bytes = bincode::encode_to_vec(&row, config).map_err(|e| DbError::Io {
+            operation: "update",
+            table: self.primary_key.clone(),
+            message: e.to_string(),
+        })?;
+        let mut rewritten = Vec::with_capacity(file.len() + bytes.len());
+        rewritten.extend_from_slice(&file[..record.start - 4]);
+        rewritten.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        rewritten.extend_from_slice(&bytes);
+        rewritten.extend_from_slice(&file[record.end..]);
+
+        // Written next to the table and renamed over it, so a crash leaves
+        // either the old or the new file
+        let temp_path = path.with_extension("update.tmp");
+        tokio::fs::write(&temp_path, &rewritten)
+            .await
+            .map_err(io_error)?;
+        tokio::fs::rename(&temp_path, &path)
+            .await
+            .map_err(io_error)?;
+
+        self.reindex_row(row_id, &old_row, &row);
+        Ok(version)
+    }
+
+    /// Moves `row_id` from the index entries of `old` to those of `new`.
+    fn reindex_row(
+        &mut self,
+        row_id: u64,
+        old: &HashMap<String, DBValue>,
+        new: &HashMap<String, DBValue>,
+    ) {
+        let mut indexes = self.indexes.write().unwrap();
+        for (column, index) in indexes.iter_mut() {
+            if old.get(column) == new.get(column) {
+                continue;
+            }
+            if let Some(value) = old.get(column) {
+                if let Some(row_ids) = index.get_mut(&Self::value_to_index_key(value)) {
+                    row_ids.retain(|id| *id != row_id);
+                }
+            }
+            if let Some(value) = new.get(column) {
+                let key = Self::value_to_index_key(value);
+                index.entry(key).or_default().push(row_id);
+            }
+            self.indexes_dirty = true;
+        }
     }
 
     fn try_use_index(&self, query: &FilterEntity) -> Option<(String, DBValue, Vec<u64>)> {
@@ -287,6 +570,16 @@ impl TableRowSchemaless {
         None
     }
 
+    /// Iterates over every row in file order, with its row id.
+    pub async fn scan(&self) -> RowScanner {
+        RowScanner::open(&self.data_path()).await
+    }
+
+    /// Continues a scan from where `RowScanner::scan_position` said it was.
+    pub async fn scan_from(&self, position: ScanPosition) -> RowScanner {
+        RowScanner::open_at(&self.data_path(), position).await
+    }
+
     async fn query_by_row_ids(
         &self,
         row_ids: &[u64],
@@ -294,75 +587,28 @@ impl TableRowSchemaless {
     ) -> Vec<HashMap<String, DBValue>> {
         let mut result = Vec::new();
 
-        let file = OpenOptions::new()
-            .read(true)
-            .open(format!("{}/{}", self.settings.base_path, self.primary_key))
-            .await
-            .unwrap();
-        let mut reader = BufReader::new(file);
-
-        let mut current_row_id = 0u64;
-        let row_id_set: HashSet<u64> = row_ids.iter().copied().collect();
-
-        loop {
-            let mut len_bytes = [0u8; 4];
-            match reader.read_exact(&mut len_bytes).await {
-                Ok(_) => {}
-                Err(_) => break,
-            }
-            let len = u32::from_le_bytes(len_bytes) as usize;
-
-            let mut buffer = vec![0u8; len];
-            if reader.read_exact(&mut buffer).await.is_err() {
-                break;
-            }
+        let mut row_ids = row_ids.to_vec();
+        row_ids.sort_unstable();
+        row_ids.dedup();
 
-            if row_id_set.contains(&current_row_id) {
-                let config = bincode::config::standard();
-                if let Ok((row, _)) =
-                    bincode::decode_from_slice::<HashMap<String, DBValue>, _>(&buffer, config)
-                {
-                    if query_engine::execute_query(query, &row) {
-                        result.push(row);
-                    }
+        let mut scanner = self.scan().await;
+        for row_id in row_ids {
+            scanner.seek_to(row_id).await;
+            if let Some((found, row)) = scanner.next().await {
+                if found == row_id && query_engine::execute_query(query, &row) {
+                    result.push(row);
                 }
             }
-
-            current_row_id += 1;
         }
         result
     }
 
     async fn query_full_scan(&self, query: FilterEntity) -> Vec<HashMap<String, DBValue>> {
         let mut result = Vec::new();
-        let file = OpenOptions::new()
-            .read(true)
-            .open(format!("{}/{}", self.settings.base_path, self.primary_key))
-            .await
-            .unwrap();
-        let mut reader = BufReader::new(file);
-
-        // Read length-prefixed binary records
-        loop {
-            let mut len_bytes = [0u8; 4];
-            match reader.read_exact(&mut len_bytes).await {
-                Ok(_) => {}
-                Err(_) => break, // EOF or error
-            }
-            let len = u32::from_le_bytes(len_bytes) as usize;
-
-            let mut buffer = vec![0u8; len];
-            if reader.read_exact(&mut buffer).await.is_err() {
-                break;
-            }
-
-            let config = bincode::config::standard();
-            if let Ok((row, _)) =
-                bincode::decode_from_slice::<HashMap<String, DBValue>, _>(&buffer, config)
-            {
-                if query_engine::execute_query(&query, &row) {
-                    result.push(row);
-                }
+        let mut scanner = self.scan().await;
+        while let Some((_, row)) = scanner.next().await {
+            if query_engine::execute_query(&query, &row) {
+                result.push(row);
             }
         }
         result
@@ -370,10 +616,7 @@ impl TableRowSchemaless {
 
     /// returns false if file not exists
     pub async fn is_empty(&self) -> bool {
-        let file = OpenOptions::new()
-            .read(true)
-            .open(format!("{}/{}", self.settings.base_path, self.primary_key))
-            .await;
+        let file = OpenOptions::new().read(true).open(self.data_path()).await;
 
         match file {
             Ok(f) => {
@@ -389,35 +632,9 @@ impl TableRowSchemaless {
     }
 
     pub async fn size(&self) -> usize {
-        let file_result = OpenOptions::new()
-            .read(true)
-            .open(format!("{}/{}", self.settings.base_path, self.primary_key))
-            .await;
-
-        let file = match file_result {
-            Ok(f) => f,
-            Err(_) => return 0, // File doesn't exist, so size is 0
-        };
-
-        let mut reader = BufReader::new(file);
-
-        let mut count = 0;
-        loop {
-            let mut len_bytes = [0u8; 4];
-            match reader.read_exact(&mut len_bytes).await {
-                Ok(_) => {}
-                Err(_) => break, // EOF or error
-            }
-            let len = u32::from_le_bytes(len_bytes) as usize;
-
-            let mut buffer = vec![0u8; len];
-            if reader.read_exact(&mut buffer).await.is_err() {
-                break;
-            }
-
-            count += 1;
-        }
-        count
+        let mut scanner = self.scan().await;
+        scanner.seek_to(u64::MAX).await;
+        scanner.position() as usize
     }
 }
 
@@ -425,24 +642,35 @@ impl TableRowSchemaless {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tempfile::TempDir;
+
+    fn temp_settings(dir: &TempDir) -> Settings {
+        Settings {
+            base_path: dir.path().to_string_lossy().into_owned(),
+            ..Settings::default()
+        }
+    }
+
+    /// Opens an empty table in its own temporary directory, which is removed
+    /// when the returned `TempDir` is dropped.
+    async fn temp_table(pk: &str) -> (TableRowSchemaless, TempDir) {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let table = TableRowSchemaless::new(pk.to_string(), temp_settings(&dir)).await;
+        (table, dir)
+    }
 
     #[tokio::test]
     async fn test_insert() {
-        let mut table = TableRowSchemaless::new(
-            "id".to_string(),
-            Settings {
-                base_path: "test".to_string(),
-            },
-        )
-        .await;
-        table.truncate().await;
+        let (mut table, _dir) = temp_table("id").await;
+        table.truncate().await.unwrap();
         table
             .insert(HashMap::from([
                 ("id".to_string(), DBValue::Number(1.0)),
                 ("column1".to_string(), DBValue::String("value1".to_string())),
                 ("column2".to_string(), DBValue::String("value2".to_string())),
             ]))
-            .await;
+            .await
+            .unwrap();
 
         table
             .insert(HashMap::from([
@@ -450,26 +678,21 @@ mod tests {
                 ("column1".to_string(), DBValue::String("value3".to_string())),
                 ("column2".to_string(), DBValue::String("value4".to_string())),
             ]))
-            .await;
+            .await
+            .unwrap();
 
         assert!(table
             .known_columns
             .contains(&("column1".to_string(), DBValueType::String)));
 
-        table.drop().await;
+        table.drop().await.unwrap();
     }
 
     #[tokio::test]
     async fn test_query() {
-        let mut table = TableRowSchemaless::new(
-            "id".to_string(),
-            Settings {
-                base_path: "test_db/test_query".to_string(),
-            },
-        )
-        .await;
+        let (mut table, _dir) = temp_table("id").await;
 
-        table.truncate().await;
+        table.truncate().await.unwrap();
 
         table
             .insert(HashMap::from([
@@ -477,7 +700,8 @@ mod tests {
                 ("column1".to_string(), DBValue::String("value1".to_string())),
                 ("column2".to_string(), DBValue::String("value2".to_string())),
             ]))
-            .await;
+            .await
+            .unwrap();
 
         table
             .insert(HashMap::from([
@@ -485,7 +709,8 @@ mod tests {
                 ("column1".to_string(), DBValue::String("value3".to_string())),
                 ("column2".to_string(), DBValue::String("value4".to_string())),
             ]))
-            .await;
+            .await
+            .unwrap();
 
         let query = FilterEntity::And(
             Box::new(FilterEntity::Equals(
@@ -502,47 +727,41 @@ mod tests {
         assert_eq!(rows.len(), 1);
 
         let _result = rows[0].clone();
-        table.drop().await;
+        table.drop().await.unwrap();
 
         // assert_eq!(result.get("id").unwrap(), &DBValue::Number(1.0));
     }
 
-    async fn insert_test_data_if_not_exists(table: &mut TableRowSchemaless) {
-        if table.is_empty().await {
-            for i in 0..10_000 {
-                table
-                    .insert(HashMap::from([
-                        ("id".to_string(), DBValue::Number(i as f64)),
-                        (
-                            "column1".to_string(),
-                            DBValue::String(format!("value{}", i)),
-                        ),
-                        (
-                            "column2".to_string(),
-                            DBValue::String(format!("value{}- {}", i, i)),
-                        ),
-                        (
-                            "date".to_string(),
-                            DBValue::Timestamp(1672531200 + i * 86400),
-                        ),
-                        ("amount".to_string(), DBValue::Number((i * 2) as f64)),
-                    ]))
-                    .await;
-            }
+    /// Seeds 10,000 rows; tests call it on a fresh table so row counts are exact.
+    async fn insert_test_data(table: &mut TableRowSchemaless) {
+        for i in 0..10_000 {
+            table
+                .insert(HashMap::from([
+                    ("id".to_string(), DBValue::Number(i as f64)),
+                    (
+                        "column1".to_string(),
+                        DBValue::String(format!("value{}", i)),
+                    ),
+                    (
+                        "column2".to_string(),
+                        DBValue::String(format!("value{}- {}", i, i)),
+                    ),
+                    (
+                        "date".to_string(),
+                        DBValue::Timestamp(1672531200 + i * 86400),
+                    ),
+                    ("amount".to_string(), DBValue::Number((i * 2) as f64)),
+                ]))
+                .await
+                .unwrap();
         }
     }
 
     #[tokio::test]
     async fn test_query_performance() {
-        let mut table = TableRowSchemaless::new(
-            "id".to_string(),
-            Settings {
-                base_path: "test_db/test_performance".to_string(),
-            },
-        )
-        .await;
+        let (mut table, _dir) = temp_table("id").await;
 
-        insert_test_data_if_not_exists(&mut table).await;
+        insert_test_data(&mut table).await;
 
         let query = FilterEntity::Or(
             Box::new(FilterEntity::Or(
@@ -570,17 +789,11 @@ mod tests {
 
     #[tokio::test]
     async fn test_fuzzy_search() {
-        let mut table = TableRowSchemaless::new(
-            "id".to_string(),
-            Settings {
-                base_path: "test_db/test_fuzzy_search".to_string(),
-            },
-        )
-        .await;
+        let (mut table, _dir) = temp_table("id").await;
 
-        table.truncate().await;
+        table.truncate().await.unwrap();
 
-        insert_test_data_if_not_exists(&mut table).await;
+        insert_test_data(&mut table).await;
 
         table
             .insert(HashMap::from([
@@ -588,7 +801,8 @@ mod tests {
                 ("column1".to_string(), DBValue::String("Buch".to_string())),
                 ("column2".to_string(), DBValue::String("value2".to_string())),
             ]))
-            .await;
+            .await
+            .unwrap();
 
         let query = FilterEntity::FuzzyMatch(
             Box::new(FilterEntity::Column("column1".to_string())),
@@ -599,7 +813,7 @@ mod tests {
         println!("result: {:?}", rows);
         assert_eq!(rows.len(), 1);
 
-        table.drop().await;
+        table.drop().await.unwrap();
     }
 
     #[tokio::test]
@@ -665,17 +879,11 @@ mod tests {
 
     #[tokio::test]
     async fn test_debug_simple_insert_read() {
-        let mut table = TableRowSchemaless::new(
-            "id".to_string(),
-            Settings {
-                base_path: "test_db/test_debug".to_string(),
-            },
-        )
-        .await;
+        let (mut table, _dir) = temp_table("id").await;
 
-        table.truncate().await;
+        table.truncate().await.unwrap();
 
-        // Insert records in a loop - using exact same format as insert_test_data_if_not_exists
+        // Insert records in a loop - using exact same format as insert_test_data
         let num_records = 100000;
         println!("Inserting {} records...", num_records);
         for i in 0..num_records {
@@ -696,7 +904,8 @@ mod tests {
                     ),
                     ("amount".to_string(), DBValue::Number((i * 2) as f64)),
                 ]))
-                .await;
+                .await
+                .unwrap();
 
             if i % 1000 == 0 {
                 println!("Inserted {} records", i);
@@ -706,44 +915,31 @@ mod tests {
 
     #[tokio::test]
     async fn test_create_index() {
-        let settings = Settings {
-            base_path: "test_db/test_create_index".to_string(),
-        };
-
-        let _ = tokio::fs::remove_dir_all(&settings.base_path).await;
-
-        let mut table = TableRowSchemaless::new("test_table_idx".to_string(), settings).await;
-        table.truncate().await;
+        let (mut table, _dir) = temp_table("test_table_idx").await;
+        table.truncate().await.unwrap();
 
         // Insert test data
         for i in 0..10 {
             let mut data = HashMap::new();
             data.insert("id".to_string(), DBValue::Number(i as f64));
             data.insert("name".to_string(), DBValue::String(format!("Person{}", i)));
-            table.insert(data).await;
+            table.insert(data).await.unwrap();
         }
 
         // Create index on 'name' column
-        table.create_index("name").await;
+        table.create_index("name").await.unwrap();
 
         // Verify index exists
         let indexes = table.list_indexes();
         assert!(indexes.contains(&"name".to_string()));
 
-        table.drop().await;
-        let _ = tokio::fs::remove_dir_all("test_db/test_create_index").await;
+        table.drop().await.unwrap();
     }
 
     #[tokio::test]
     async fn test_query_with_index() {
-        let settings = Settings {
-            base_path: "test_db/test_query_with_index".to_string(),
-        };
-
-        let _ = tokio::fs::remove_dir_all(&settings.base_path).await;
-
-        let mut table = TableRowSchemaless::new("test_table_idx2".to_string(), settings).await;
-        table.truncate().await;
+        let (mut table, _dir) = temp_table("test_table_idx2").await;
+        table.truncate().await.unwrap();
 
         // Insert test data
         for i in 0..20 {
@@ -757,11 +953,11 @@ mod tests {
                     "inactive".to_string()
                 }),
             );
-            table.insert(data).await;
+            table.insert(data).await.unwrap();
         }
 
         // Create index on 'status' column
-        table.create_index("status").await;
+        table.create_index("status").await.unwrap();
 
         // Query using index
         let query = FilterEntity::Equals(
@@ -772,23 +968,19 @@ mod tests {
         let result = table.query(query).await;
         assert_eq!(result.len(), 10); // Should match 10 active entries
 
-        table.drop().await;
-        let _ = tokio::fs::remove_dir_all("test_db/test_query_with_index").await;
+        table.drop().await.unwrap();
     }
 
     #[tokio::test]
     async fn test_index_persistence() {
-        let settings = Settings {
-            base_path: "test_db/test_index_persistence".to_string(),
-        };
-
-        let _ = tokio::fs::remove_dir_all(&settings.base_path).await;
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let settings = temp_settings(&dir);
 
         // Create table and index
         {
             let mut table =
                 TableRowSchemaless::new("test_table_persist".to_string(), settings.clone()).await;
-            table.truncate().await;
+            table.truncate().await.unwrap();
 
             // Insert test data
             for i in 0..5 {
@@ -798,11 +990,11 @@ mod tests {
                     "category".to_string(),
                     DBValue::String(format!("cat{}", i % 3)),
                 );
-                table.insert(data).await;
+                table.insert(data).await.unwrap();
             }
 
             // Create index
-            table.create_index("category").await;
+            table.create_index("category").await.unwrap();
 
             // Don't call drop - let it go out of scope to test persistence
         }
@@ -822,35 +1014,27 @@ mod tests {
             let result = table.query(query).await;
             assert_eq!(result.len(), 2); // cat1 appears at indices 1 and 4
         }
-
-        let _ = tokio::fs::remove_dir_all("test_db/test_index_persistence").await;
     }
 
     #[tokio::test]
     async fn test_drop_index() {
-        let settings = Settings {
-            base_path: "test_db/test_drop_index".to_string(),
-        };
-
-        let _ = tokio::fs::remove_dir_all(&settings.base_path).await;
-
-        let mut table = TableRowSchemaless::new("test_table_drop_idx".to_string(), settings).await;
-        table.truncate().await;
+        let (mut table, _dir) = temp_table("test_table_drop_idx").await;
+        table.truncate().await.unwrap();
 
         // Insert test data
         for i in 0..5 {
             let mut data = HashMap::new();
             data.insert("id".to_string(), DBValue::Number(i as f64));
             data.insert("field".to_string(), DBValue::String(format!("value{}", i)));
-            table.insert(data).await;
+            table.insert(data).await.unwrap();
         }
 
         // Create index
-        table.create_index("field").await;
+        table.create_index("field").await.unwrap();
         assert!(table.list_indexes().contains(&"field".to_string()));
 
         // Drop index
-        table.drop_index("field").await;
+        table.drop_index("field").await.unwrap();
         assert!(!table.list_indexes().contains(&"field".to_string()));
 
         // Query should still work (using full scan)
@@ -861,20 +1045,13 @@ mod tests {
         let result = table.query(query).await;
         assert_eq!(result.len(), 1);
 
-        table.drop().await;
-        let _ = tokio::fs::remove_dir_all("test_db/test_drop_index").await;
+        table.drop().await.unwrap();
     }
 
     #[tokio::test]
     async fn test_index_performance() {
-        let settings = Settings {
-            base_path: "test_db/test_index_performance".to_string(),
-        };
-
-        let _ = tokio::fs::remove_dir_all(&settings.base_path).await;
-
-        let mut table = TableRowSchemaless::new("test_table_perf".to_string(), settings).await;
-        table.truncate().await;
+        let (mut table, _dir) = temp_table("test_table_perf").await;
+        table.truncate().await.unwrap();
 
         // Insert a larger dataset
         for i in 0..1000 {
@@ -884,7 +1061,7 @@ mod tests {
                 "email".to_string(),
                 DBValue::String(format!("user{}@example.com", i)),
             );
-            table.insert(data).await;
+            table.insert(data).await.unwrap();
         }
 
         // Query without index
@@ -900,7 +1077,7 @@ mod tests {
         let duration_no_index = start.elapsed();
 
         // Create index
-        table.create_index("email").await;
+        table.create_index("email").await.unwrap();
 
         // Query with index
         let start = std::time::Instant::now();
@@ -921,20 +1098,13 @@ mod tests {
             duration_no_index.as_secs_f64() / duration_with_index.as_secs_f64()
         );
 
-        table.drop().await;
-        let _ = tokio::fs::remove_dir_all("test_db/test_index_performance").await;
+        table.drop().await.unwrap();
     }
 
     #[tokio::test]
     async fn test_index_with_null_values() {
-        let settings = Settings {
-            base_path: "test_db/test_index_null".to_string(),
-        };
-
-        let _ = tokio::fs::remove_dir_all(&settings.base_path).await;
-
-        let mut table = TableRowSchemaless::new("test_table_null".to_string(), settings).await;
-        table.truncate().await;
+        let (mut table, _dir) = temp_table("test_table_null").await;
+        table.truncate().await.unwrap();
 
         // Insert data with null values
         for i in 0..5 {
@@ -948,11 +1118,11 @@ mod tests {
                     DBValue::String(format!("value{}", i)),
                 );
             }
-            table.insert(data).await;
+            table.insert(data).await.unwrap();
         }
 
         // Create index on field with nulls
-        table.create_index("optional_field").await;
+        table.create_index("optional_field").await.unwrap();
 
         // Query for null values
         let query = FilterEntity::Equals(
@@ -962,20 +1132,13 @@ mod tests {
         let result = table.query(query).await;
         assert_eq!(result.len(), 3); // Indices 0, 2, 4 have null
 
-        table.drop().await;
-        let _ = tokio::fs::remove_dir_all("test_db/test_index_null").await;
+        table.drop().await.unwrap();
     }
 
     #[tokio::test]
     async fn test_multiple_indexes() {
-        let settings = Settings {
-            base_path: "test_db/test_multiple_indexes".to_string(),
-        };
-
-        let _ = tokio::fs::remove_dir_all(&settings.base_path).await;
-
-        let mut table = TableRowSchemaless::new("test_table_multi".to_string(), settings).await;
-        table.truncate().await;
+        let (mut table, _dir) = temp_table("test_table_multi").await;
+        table.truncate().await.unwrap();
 
         // Insert test data
         for i in 0..10 {
@@ -983,12 +1146,12 @@ mod tests {
             data.insert("id".to_string(), DBValue::Number(i as f64));
             data.insert("name".to_string(), DBValue::String(format!("Person{}", i)));
             data.insert("age".to_string(), DBValue::Number((20 + i) as f64));
-            table.insert(data).await;
+            table.insert(data).await.unwrap();
         }
 
         // Create multiple indexes
-        table.create_index("name").await;
-        table.create_index("age").await;
+        table.create_index("name").await.unwrap();
+        table.create_index("age").await.unwrap();
 
         // Verify both indexes exist
         let indexes = table.list_indexes();
@@ -1012,7 +1175,465 @@ mod tests {
         let result2 = table.query(query2).await;
         assert_eq!(result2.len(), 1);
 
-        table.drop().await;
-        let _ = tokio::fs::remove_dir_all("test_db/test_multiple_indexes").await;
+        table.drop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scan_visits_every_row() {
+        let (mut table, _dir) = temp_table("id").await;
+        for i in 0..25 {
+            table
+                .insert(HashMap::from([(
+                    "id".to_string(),
+                    DBValue::Number(i as f64),
+                )]))
+                .await
+                .unwrap();
+        }
+
+        let mut scanner = table.scan().await;
+        let mut count = 0;
+        while let Some((row_id, row)) = scanner.next().await {
+            assert_eq!(row.get("id"), Some(&DBValue::Number(row_id as f64)));
+            count += 1;
+        }
+        assert_eq!(count, table.size().await);
+        assert_eq!(count, 25);
+    }
+
+    #[tokio::test]
+    async fn test_scan_seek_to() {
+        let (mut table, _dir) = temp_table("id").await;
+        for i in 0..10 {
+            table
+                .insert(HashMap::from([(
+                    "id".to_string(),
+                    DBValue::Number(i as f64),
+                )]))
+                .await
+                .unwrap();
+        }
+
+        let mut scanner = table.scan().await;
+        scanner.seek_to(5).await;
+        let (row_id, row) = scanner.next().await.unwrap();
+        assert_eq!(row_id, 5);
+        assert_eq!(row.get("id"), Some(&DBValue::Number(5.0)));
+
+        // Seeking backwards starts over from the beginning of the file
+        scanner.seek_to(2).await;
+        assert_eq!(scanner.next().await.map(|(row_id, _)| row_id), Some(2));
+
+        scanner.seek_to(100).await;
+        assert_eq!(scanner.position(), 10);
+        assert!(scanner.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_scan_seek_to_skips_rows_larger_than_the_read_buffer() {
+        let (mut table, _dir) = temp_table("id").await;
+        for i in 0..4 {
+            table
+                .insert(HashMap::from([
+                    ("id".to_string(), DBValue::Number(i as f64)),
+                    ("text".to_string(), DBValue::String("x".repeat(20_000))),
+                ]))
+                .await
+                .unwrap();
+        }
+
+        let mut scanner = table.scan().await;
+        scanner.seek_to(3).await;
+        let (row_id, row) = scanner.next().await.unwrap();
+        assert_eq!(row_id, 3);
+        assert_eq!(row.get("id"), Some(&DBValue::Number(3.0)));
+
+        // Cut the last row short: seeking stops before it
+        let len = tokio::fs::metadata(table.data_path()).await.unwrap().len();
+        let file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(table.data_path())
+            .await
+            .unwrap();
+        file.set_len(len - 10).await.unwrap();
+        let mut scanner = table.scan().await;
+        scanner.seek_to(100).await;
+        assert_eq!(scanner.position(), 3);
+        assert!(scanner.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_scan_empty_and_missing_file() {
+        let (mut table, _dir) = temp_table("id").await;
+        assert!(table.scan().await.next().await.is_none());
+
+        table.truncate().await.unwrap();
+        assert!(table.scan().await.next().await.is_none());
+        assert_eq!(table.size().await, 0);
+    }
+
+    fn category_row(i: usize) -> HashMap<String, DBValue> {
+        HashMap::from([
+            ("id".to_string(), DBValue::Number(i as f64)),
+            (
+                "category".to_string(),
+                DBValue::String(format!("cat{}", i % 3)),
+            ),
+        ])
+    }
+
+    fn category_query(category: &str) -> FilterEntity {
+        FilterEntity::Equals(
+            Box::new(FilterEntity::Column("category".to_string())),
+            Box::new(FilterEntity::Value(DBValue::String(category.to_string()))),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_unflushed_index_rows_are_reindexed_on_reopen() {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        {
+            let mut table = TableRowSchemaless::new("id".to_string(), temp_settings(&dir)).await;
+            table.insert_many((0..3).map(category_row)).await.unwrap();
+            table.create_index("category").await.unwrap();
+            // Not flushed: the saved index only covers the first 3 rows
+            for i in 3..9 {
+                table.insert(category_row(i)).await.unwrap();
+            }
+        }
+
+        let table = TableRowSchemaless::new("id".to_string(), temp_settings(&dir)).await;
+        assert!(table.list_indexes().contains(&"category".to_string()));
+        assert_eq!(table.query(category_query("cat1")).await.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_flushed_indexes_survive_reopen() {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let mut table = TableRowSchemaless::new("id".to_string(), temp_settings(&dir)).await;
+        table.create_index("category").await.unwrap();
+        for i in 0..9 {
+            table.insert(category_row(i)).await.unwrap();
+        }
+        table.close().await;
+
+        let mut table = TableRowSchemaless::new("id".to_string(), temp_settings(&dir)).await;
+        assert!(!table.indexes_dirty);
+        assert_eq!(table.query(category_query("cat2")).await.len(), 3);
+
+        // Rows added after reopening land in the persisted index too
+        table.insert_many((9..12).map(category_row)).await.unwrap();
+        table.close().await;
+        let table = TableRowSchemaless::new("id".to_string(), temp_settings(&dir)).await;
+        assert!(!table.indexes_dirty);
+        assert_eq!(table.query(category_query("cat2")).await.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_nested_base_path_with_trailing_separator() {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        // Windows users tend to write `C:\\data\\tables\\`; joining must not
+        // depend on the separator or a trailing one
+        let sep = std::path::MAIN_SEPARATOR;
+        let base_path = format!("{}{sep}data{sep}tables{sep}", dir.path().display());
+        let settings = || Settings {
+            base_path: base_path.clone(),
+            ..Settings::default()
+        };
+
+        let mut table = TableRowSchemaless::new("id".to_string(), settings()).await;
+        table.create_index("category").await.unwrap();
+        table.insert_many((0..6).map(category_row)).await.unwrap();
+        table.close().await;
+
+        let nested = dir.path().join("data").join("tables");
+        assert!(nested.join("id").is_file());
+        assert!(nested.join("id.idx").is_file());
+        let table = TableRowSchemaless::new("id".to_string(), settings()).await;
+        assert_eq!(table.size().await, 6);
+        assert_eq!(table.query(category_query("cat1")).await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_iso_date_literals_match_timestamps() {
+        let (mut table, _dir) = temp_table("id").await;
+        table
+            .insert_many((0..10).map(|i| {
+                HashMap::from([
+                    ("id".to_string(), DBValue::Number(i as f64)),
+                    (
+                        "date".to_string(),
+                        DBValue::Timestamp(1672531200 + i * 86400),
+                    ),
+                ])
+            }))
+            .await
+            .unwrap();
+
+        let after = |value: DBValue| {
+            FilterEntity::GreaterThan(
+                Box::new(FilterEntity::Column("date".to_string())),
+                Box::new(FilterEntity::Value(value)),
+            )
+        };
+        // 2023-01-05 is row 4
+        let by_iso = table
+            .query(after(DBValue::String("2023-01-05".to_string())))
+            .await;
+        let by_seconds = table.query(after(DBValue::Timestamp(1672876800))).await;
+        assert_eq!(by_iso.len(), 5);
+        assert_eq!(by_iso, by_seconds);
+
+        let on = FilterEntity::Equals(
+            Box::new(FilterEntity::Value(DBValue::String(
+                "2023-01-03T00:00:00Z".to_string(),
+            ))),
+            Box::new(FilterEntity::Column("date".to_string())),
+        );
+        let rows = table.query(on).await;
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].get("id"), Some(&DBValue::Number(2.0)));
+
+        let not_a_date = after(DBValue::String("soon".to_string()));
+        assert!(table.query(not_a_date).await.is_empty());
+    }
+
+    fn versioned_rows(rows: &[HashMap<String, DBValue>]) -> Vec<(DBValue, u64)> {
+        let mut rows: Vec<(DBValue, u64)> = rows
+            .iter()
+            .map(|row| (row["category"].clone(), version_of(row)))
+            .collect();
+        rows.sort_by_key(|(category, _)| category.to_string());
+        rows
+    }
+
+    fn change_category(category: &str) -> HashMap<String, DBValue> {
+        HashMap::from([(
+            "category".to_string(),
+            DBValue::String(category.to_string()),
+        )])
+    }
+
+    fn with_versions() -> QueryOptions {
+        QueryOptions {
+            include_versions: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_conditional_update_bumps_version() {
+        let (mut table, _dir) = temp_table("id").await;
+        table.create_index("category").await.unwrap();
+        table.insert_many((0..2).map(category_row)).await.unwrap();
+
+        let rows = table
+            .query_with(category_query("cat0"), &with_versions())
+            .await;
+        assert_eq!(versioned_rows(&rows), [(DBValue::String("cat0".into()), 1)]);
+
+        let version = table.update(0, change_category("done"), Some(1)).await;
+        assert_eq!(version, Ok(2));
+
+        // The index follows the new value
+        assert!(table.query(category_query("cat0")).await.is_empty());
+        let rows = table
+            .query_with(category_query("done"), &with_versions())
+            .await;
+        assert_eq!(versioned_rows(&rows), [(DBValue::String("done".into()), 2)]);
+        assert_eq!(rows[0]["id"], DBValue::Number(0.0));
+        // Other rows are untouched
+        assert_eq!(table.size().await, 2);
+        assert_eq!(table.query(category_query("cat1")).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_stale_update_conflicts() {
+        let (mut table, _dir) = temp_table("id").await;
+        table.insert(category_row(0)).await.unwrap();
+
+        // Two writers read version 1; the second one to write loses
+        assert_eq!(
+            table.update(0, change_category("first"), Some(1)).await,
+            Ok(2)
+        );
+        let stale = table.update(0, change_category("second"), Some(1)).await;
+        assert_eq!(
+            stale,
+            Err(DbError::VersionConflict {
+                row_id: 0,
+                expected: 1,
+                actual: 2
+            })
+        );
+        assert_eq!(table.query(category_query("first")).await.len(), 1);
+        assert!(table.query(category_query("second")).await.is_empty());
+
+        let missing = table.update(5, change_category("x"), None).await;
+        assert!(matches!(missing, Err(DbError::NoSuchRow { row_id: 5, .. })));
+    }
+
+    #[tokio::test]
+    async fn test_unconditional_updates_ignore_versions() {
+        let (mut table, _dir) = temp_table("id").await;
+        table.insert(category_row(0)).await.unwrap();
+
+        assert_eq!(table.update(0, change_category("a"), None).await, Ok(2));
+        assert_eq!(table.update(0, change_category("b"), None).await, Ok(3));
+
+        // Versions stay out of results unless asked for
+        let rows = table.query(category_query("b")).await;
+        assert_eq!(
+            rows,
+            [category_row(0)].map(|mut row| {
+                row.extend(change_category("b"));
+                row
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rows_without_version_accept_first_conditional_update() {
+        let (table, _dir) = temp_table("id").await;
+        // A row as written before versioning existed
+        let bytes = bincode::encode_to_vec(category_row(0), bincode::config::standard()).unwrap();
+        let mut file = (bytes.len() as u32).to_le_bytes().to_vec();
+        file.extend(bytes);
+        tokio::fs::write(table.data_path(), file).await.unwrap();
+        let settings = table.settings.clone();
+        drop(table);
+        let mut table = TableRowSchemaless::new("id".to_string(), settings).await;
+
+        let rows = table
+            .query_with(category_query("cat0"), &with_versions())
+            .await;
+        assert_eq!(versioned_rows(&rows), [(DBValue::String("cat0".into()), 0)]);
+
+        assert_eq!(
+            table.update(0, change_category("new"), Some(7)).await,
+            Ok(1)
+        );
+        let stale = table.update(0, change_category("newer"), Some(7)).await;
+        assert!(matches!(
+            stale,
+            Err(DbError::VersionConflict { actual: 1, .. })
+        ));
+    }
+
+    fn settings_in(path: &Path, mode: OpenMode) -> Settings {
+        Settings {
+            base_path: path.to_string_lossy().into_owned(),
+            mode,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_only_open_of_missing_table_creates_nothing() {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let base = dir.path().join("missing");
+        let table =
+            TableRowSchemaless::open("id".to_string(), settings_in(&base, OpenMode::ReadOnly))
+                .await
+                .unwrap();
+
+        assert_eq!(table.size().await, 0);
+        assert!(table.query(category_query("cat0")).await.is_empty());
+        table.close().await;
+        assert!(!base.exists());
+    }
+
+    #[tokio::test]
+    async fn test_read_only_handle_rejects_writes() {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let mut writer = TableRowSchemaless::open(
+            "id".to_string(),
+            settings_in(dir.path(), OpenMode::ReadWrite),
+        )
+        .await
+        .unwrap();
+        writer.insert(category_row(0)).await.unwrap();
+
+        // Readers don't need the writer's lock
+        let mut table = TableRowSchemaless::open(
+            "id".to_string(),
+            settings_in(dir.path(), OpenMode::ReadOnly),
+        )
+        .await
+        .unwrap();
+        let read_only = |operation| DbError::ReadOnly {
+            operation,
+            table: "id".to_string(),
+        };
+        assert_eq!(
+            table.insert(category_row(1)).await,
+            Err(read_only("insert"))
+        );
+        assert_eq!(
+            table.update(0, change_category("x"), None).await,
+            Err(read_only("update"))
+        );
+        assert_eq!(
+            table.create_index("category").await,
+            Err(read_only("create_index"))
+        );
+        assert_eq!(table.truncate().await, Err(read_only("truncate")));
+        assert_eq!(table.drop().await, Err(read_only("drop")));
+        assert_eq!(
+            read_only("insert").to_string(),
+            "db.insert on table 'id': the table is open read-only"
+        );
+
+        assert_eq!(table.size().await, 1);
+        assert_eq!(table.query(category_query("cat0")).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_second_writer_is_locked_out() {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let open = || {
+            TableRowSchemaless::open(
+                "id".to_string(),
+                settings_in(dir.path(), OpenMode::ReadWrite),
+            )
+        };
+
+        let first = open().await.unwrap();
+        let second = open().await;
+        assert!(matches!(
+            second,
+            Err(DbError::Locked { holder_pid }) if holder_pid == std::process::id()
+        ));
+
+        // Closing releases the lock
+        first.close().await;
+        assert!(!dir.path().join("id.lock").exists());
+        assert!(open().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_stale_lock_is_reclaimed() {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        // A process that has exited, standing in for a crashed writer
+        let mut child = std::process::Command::new(std::env::current_exe().unwrap())
+            .arg("--list")
+            .stdout(std::process::Stdio::null())
+            .spawn()
+            .unwrap();
+        let dead_pid = child.id();
+        child.wait().unwrap();
+        let lock_path = dir.path().join("id.lock");
+        std::fs::write(&lock_path, dead_pid.to_string()).unwrap();
+
+        let table = TableRowSchemaless::open(
+            "id".to_string(),
+            settings_in(dir.path(), OpenMode::ReadWrite),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&lock_path).unwrap(),
+            std::process::id().to_string()
+        );
+        drop(table);
+        assert!(!lock_path.exists());
     }
 }
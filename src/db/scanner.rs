@@ -0,0 +1,148 @@
+use crate::db::DBValue;
+use std::collections::HashMap;
+use std::io::SeekFrom;
+use std::path::Path;
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, BufReader};
+
+pub type Row = HashMap<String, DBValue>;
+
+/// Where a scan is in the table file, to resume it later with
+/// `TableRowSchemaless::scan_from` without reading the rows before it.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ScanPosition {
+    /// Id of the next row
+    pub row_id: u64,
+    /// Byte offset of the next row's length prefix
+    pub offset: u64,
+}
+
+/// Reads the length-prefixed rows of a table file in file order. A missing
+/// file reads as an empty table.
+pub struct RowScanner {
+    reader: Option<BufReader<File>>,
+    next_row_id: u64,
+    offset: u64,
+    buffer: Vec<u8>,
+}
+
+impl RowScanner {
+    pub(crate) async fn open(path: &Path) -> Self {
+        RowScanner::open_at(path, ScanPosition::default()).await
+    }
+
+    /// Opens the file at `position`, as returned by `scan_position`.
+    pub(crate) async fn open_at(path: &Path, position: ScanPosition) -> Self {
+        let mut reader = File::open(path).await.ok().map(BufReader::new);
+        if let Some(file) = reader.as_mut() {
+            if file.seek(SeekFrom::Start(position.offset)).await.is_err() {
+                reader = None;
+            }
+        }
+        RowScanner {
+            reader,
+            next_row_id: position.row_id,
+            offset: position.offset,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Id of the row the next call to `next` reads.
+    pub fn position(&self) -> u64 {
+        self.next_row_id
+    }
+
+    /// Where the next call to `next` reads, to resume the scan from later.
+    pub fn scan_position(&self) -> ScanPosition {
+        ScanPosition {
+            row_id: self.next_row_id,
+            offset: self.offset,
+        }
+    }
+
+    /// Returns the next row with its id. Rows that fail to decode are
+    /// skipped but still use up their id.
+    pub async fn next(&mut self) -> Option<(u64, Row)> {
+        while self.read_record().await {
+            let config = bincode::config::standard();
+            if let Ok((row, _)) = bincode::decode_from_slice::<Row, _>(&self.buffer, config) {
+                return Some((self.next_row_id - 1, row));
+            }
+        }
+        None
+    }
+
+    /// Moves to `row_id` by following the length prefixes, seeking over the
+    /// rows in between rather than reading them. Seeking past the last row
+    /// stops at the end.
+    pub async fn seek_to(&mut self, row_id: u64) {
+        if row_id < self.next_row_id {
+            if let Some(reader) = self.reader.as_mut() {
+                if reader.seek(SeekFrom::Start(0)).await.is_err() {
+                    self.reader = None;
+                }
+            }
+            self.next_row_id = 0;
+            self.offset = 0;
+        }
+        while self.next_row_id < row_id && self.skip_record().await {}
+    }
+
+    /// Moves past the next record. Records already in the read buffer are
+    /// dropped from it, longer ones are seeked over. Returns false at the
+    /// end of the file or on a truncated record.
+    async fn skip_record(&mut self) -> bool {
+        let Some(reader) = self.reader.as_mut() else {
+            return false;
+        };
+
+        let mut len_bytes = [0u8; 4];
+        if reader.read_exact(&mut len_bytes).await.is_err() {
+            return false;
+        }
+        let len = u32::from_le_bytes(len_bytes) as u64;
+        let end = self.offset + 4 + len;
+
+        if len <= reader.buffer().len() as u64 {
+            reader.consume(len as usize);
+        } else {
+            let file_len = match reader.get_ref().metadata().await {
+                Ok(metadata) => metadata.len(),
+                Err(_) => 0,
+            };
+            if end > file_len {
+                // A truncated record ends the table, as in `read_record`
+                let _ = reader.seek(SeekFrom::End(0)).await;
+                return false;
+            }
+            if reader.seek(SeekFrom::Start(end)).await.is_err() {
+                return false;
+            }
+        }
+        self.next_row_id += 1;
+        self.offset = end;
+        true
+    }
+
+    /// Reads the next record into `buffer`. Returns false at the end of the
+    /// file or on a truncated record.
+    async fn read_record(&mut self) -> bool {
+        let Some(reader) = self.reader.as_mut() else {
+            return false;
+        };
+
+        let mut len_bytes = [0u8; 4];
+        if reader.read_exact(&mut len_bytes).await.is_err() {
+            return false;
+        }
+        let len = u32::from_le_bytes(len_bytes) as usize;
+
+        self.buffer.resize(len, 0);
+        if reader.read_exact(&mut self.buffer).await.is_err() {
+            return false;
+        }
+        self.next_row_id += 1;
+        self.offset += 4 + len as u64;
+        true
+    }
+}
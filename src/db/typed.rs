@@ -0,0 +1,482 @@
+//! Rows as Rust types: `query_as` reads rows into any `Deserialize` type
+//! through a deserializer over `DBValue`, `insert_struct` stores any
+//! `Serialize` struct as a row.
+
+use super::row_schemaless::TableRowSchemaless;
+use super::{DBValue, DbError, FilterEntity, QueryOptions};
+use serde::de::{self, DeserializeOwned, DeserializeSeed, MapAccess, Visitor};
+use serde::Serialize;
+use std::collections::{hash_map, HashMap};
+use std::fmt;
+
+impl TableRowSchemaless {
+    /// Like `query_with`, but converts each row into a `T`. Null and missing
+    /// columns read as `None` for `Option` fields and fail for any other;
+    /// integer fields take whole numbers and timestamps that fit them.
+    pub async fn query_as<T: DeserializeOwned>(
+        &self,
+        query: FilterEntity,
+        options: &QueryOptions,
+    ) -> Result<Vec<T>, DbError> {
+        self.query_with(query, options)
+            .await
+            .into_iter()
+            .map(|row| {
+                T::deserialize(RowDeserializer { row }).map_err(|e| DbError::Conversion {
+                    operation: "query_as",
+                    table: self.primary_key.clone(),
+                    message: e.to_string(),
+                })
+            })
+            .collect()
+    }
+
+    /// Inserts `value`, a struct or map of strings, numbers, booleans
+    /// (stored as 1 or 0) and options (`None` is stored as null).
+    pub async fn insert_struct<T: Serialize>(&mut self, value: &T) -> Result<(), DbError> {
+        let row = to_row(value).map_err(|message| DbError::Conversion {
+            operation: "insert_struct",
+            table: self.primary_key.clone(),
+            message,
+        })?;
+        self.insert(row).await
+    }
+}
+
+fn to_row<T: Serialize>(value: &T) -> Result<HashMap<String, DBValue>, String> {
+    let fields = match serde_json::to_value(value).map_err(|e| e.to_string())? {
+        serde_json::Value::Object(fields) => fields,
+        other => return Err(format!("expected a struct, got {}", other)),
+    };
+    fields
+        .into_iter()
+        .map(|(column, value)| match to_column(value) {
+            Ok(value) => Ok((column, value)),
+            Err(message) => Err(format!("column '{}': {}", column, message)),
+        })
+        .collect()
+}
+
+fn to_column(value: serde_json::Value) -> Result<DBValue, String> {
+    match value {
+        serde_json::Value::Null => Ok(DBValue::Null),
+        serde_json::Value::Bool(b) => Ok(DBValue::Number(if b { 1.0 } else { 0.0 })),
+        serde_json::Value::Number(n) if n.is_f64() => Ok(DBValue::Number(n.as_f64().unwrap())),
+        serde_json::Value::Number(n) => n
+            .as_i64()
+            .and_then(DBValue::from_integer)
+            .ok_or_else(|| format!("{} is too large to store exactly", n)),
+        serde_json::Value::String(s) => Ok(DBValue::String(s)),
+        serde_json::Value::Array(_) => Err("an array cannot be stored in a column".to_string()),
+        serde_json::Value::Object(_) => Err("an object cannot be stored in a column".to_string()),
+    }
+}
+
+/// Why a row could not be read into a type, and the column at fault if
+/// it is known.
+#[derive(Debug)]
+struct RowError {
+    column: Option<String>,
+    message: String,
+}
+
+impl RowError {
+    /// Errors raised by serde's visitors don't know which column they
+    /// came from.
+    fn in_column(mut self, column: &str) -> Self {
+        self.column.get_or_insert_with(|| column.to_string());
+        self
+    }
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.column {
+            Some(column) => write!(f, "column '{}': {}", column, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for RowError {}
+
+impl de::Error for RowError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        RowError {
+            column: None,
+            message: msg.to_string(),
+        }
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        RowError {
+            column: Some(field.to_string()),
+            message: "missing, and the field is not an Option".to_string(),
+        }
+    }
+}
+
+fn describe(value: &DBValue) -> String {
+    match value {
+        DBValue::String(s) => format!("string {:?}", s),
+        DBValue::Number(n) => format!("number {}", n),
+        DBValue::Timestamp(_) => format!("timestamp {}", value),
+        DBValue::Null => "null".to_string(),
+    }
+}
+
+/// A whole row, read as a map from column name to value.
+struct RowDeserializer {
+    row: HashMap<String, DBValue>,
+}
+
+impl<'de> de::Deserializer<'de> for RowDeserializer {
+    type Error = RowError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RowError> {
+        visitor.visit_map(Columns {
+            columns: self.row.into_iter(),
+            pending: None,
+        })
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+struct Columns {
+    columns: hash_map::IntoIter<String, DBValue>,
+    /// The column whose name was just read, waiting for its value.
+    pending: Option<(String, DBValue)>,
+}
+
+impl<'de> MapAccess<'de> for Columns {
+    type Error = RowError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, RowError> {
+        let Some((name, value)) = self.columns.next() else {
+            return Ok(None);
+        };
+        let key = seed.deserialize(de::value::StrDeserializer::<RowError>::new(&name))?;
+        self.pending = Some((name, value));
+        Ok(Some(key))
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, RowError> {
+        let (name, value) = self
+            .pending
+            .take()
+            .ok_or_else(|| de::Error::custom("value read before its column"))?;
+        seed.deserialize(Column { name, value })
+    }
+}
+
+/// One column's value, read into a field.
+struct Column {
+    name: String,
+    value: DBValue,
+}
+
+impl Column {
+    fn error(&self, message: String) -> RowError {
+        RowError {
+            column: Some(self.name.clone()),
+            message,
+        }
+    }
+
+    fn mismatch(&self, expected: &str) -> RowError {
+        self.error(format!(
+            "expected {}, found {}",
+            expected,
+            describe(&self.value)
+        ))
+    }
+
+    /// Numbers narrow to an integer type only if they are whole and fit
+    /// it; timestamps only if they fit.
+    fn integer<T: TryFrom<i64>>(&self, target: &str) -> Result<T, RowError> {
+        let n = match &self.value {
+            DBValue::Number(n) if n.fract() != 0.0 => {
+                return Err(self.error(format!(
+                    "expected {}, found {} which is not a whole number",
+                    target, n
+                )))
+            }
+            DBValue::Number(n) => self
+                .value
+                .as_integer()
+                .ok_or_else(|| self.error(format!("{} is out of range for {}", n, target)))?,
+            DBValue::Timestamp(ts) => *ts,
+            _ => return Err(self.mismatch(target)),
+        };
+        T::try_from(n).map_err(|_| self.error(format!("{} is out of range for {}", n, target)))
+    }
+}
+
+macro_rules! deserialize_integer {
+    ($($method:ident => $visit:ident($ty:ty),)*) => {$(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RowError> {
+            let n: $ty = self.integer(stringify!($ty))?;
+            visitor.$visit::<RowError>(n).map_err(|e| e.in_column(&self.name))
+        }
+    )*};
+}
+
+impl<'de> de::Deserializer<'de> for Column {
+    type Error = RowError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RowError> {
+        let result: Result<V::Value, RowError> = match &self.value {
+            DBValue::String(s) => visitor.visit_str(s),
+            DBValue::Number(n) => match self.value.as_integer() {
+                Some(integer) => visitor.visit_i64(integer),
+                None => visitor.visit_f64(*n),
+            },
+            DBValue::Timestamp(ts) => visitor.visit_i64(*ts),
+            DBValue::Null => visitor.visit_unit(),
+        };
+        result.map_err(|e| e.in_column(&self.name))
+    }
+
+    /// Scripts use 1 and 0 for booleans.
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RowError> {
+        match self.value {
+            DBValue::Number(n) if n == 0.0 || n == 1.0 => visitor.visit_bool(n == 1.0),
+            _ => Err(self.mismatch("bool")),
+        }
+    }
+
+    deserialize_integer! {
+        deserialize_i8 => visit_i8(i8),
+        deserialize_i16 => visit_i16(i16),
+        deserialize_i32 => visit_i32(i32),
+        deserialize_i64 => visit_i64(i64),
+        deserialize_u8 => visit_u8(u8),
+        deserialize_u16 => visit_u16(u16),
+        deserialize_u32 => visit_u32(u32),
+        deserialize_u64 => visit_u64(u64),
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RowError> {
+        self.deserialize_f64(visitor)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RowError> {
+        match self.value {
+            DBValue::Number(n) => visitor.visit_f64(n),
+            _ => Err(self.mismatch("a float")),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RowError> {
+        match &self.value {
+            DBValue::String(s) => visitor.visit_str(s),
+            _ => Err(self.mismatch("a string")),
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RowError> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RowError> {
+        match self.value {
+            DBValue::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, RowError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// Unit variants are stored by name.
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, RowError> {
+        match &self.value {
+            DBValue::String(s) => visitor
+                .visit_enum(de::value::StrDeserializer::<RowError>::new(s))
+                .map_err(|e| e.in_column(&self.name)),
+            _ => Err(self.mismatch("a variant name")),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        i128 u128 char bytes byte_buf unit unit_struct seq tuple tuple_struct
+        map struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::row_schemaless::Settings;
+    use serde::Deserialize;
+    use tempfile::TempDir;
+
+    async fn temp_table() -> (TableRowSchemaless, TempDir) {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let settings = Settings {
+            base_path: dir.path().to_string_lossy().into_owned(),
+            ..Settings::default()
+        };
+        let table = TableRowSchemaless::new("id".to_string(), settings).await;
+        (table, dir)
+    }
+
+    fn id_is(id: f64) -> FilterEntity {
+        FilterEntity::Equals(
+            Box::new(FilterEntity::Column("id".to_string())),
+            Box::new(FilterEntity::Value(DBValue::Number(id))),
+        )
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct User {
+        id: i64,
+        name: String,
+        email: Option<String>,
+        score: f64,
+        active: bool,
+        joined: Option<i64>,
+    }
+
+    #[tokio::test]
+    async fn test_struct_round_trip() {
+        let (mut table, _dir) = temp_table().await;
+        let ada = User {
+            id: 1,
+            name: "Ada".to_string(),
+            email: Some("ada@example.com".to_string()),
+            score: 9.5,
+            active: true,
+            joined: None,
+        };
+        table.insert_struct(&ada).await.unwrap();
+        // Optional columns may also be missing altogether
+        table
+            .insert(HashMap::from([
+                ("id".to_string(), DBValue::Number(2.0)),
+                ("name".to_string(), DBValue::String("Bob".to_string())),
+                ("score".to_string(), DBValue::Number(7.0)),
+                ("active".to_string(), DBValue::Number(0.0)),
+                ("joined".to_string(), DBValue::Timestamp(1_700_000_000)),
+            ]))
+            .await
+            .unwrap();
+
+        let options = QueryOptions::default();
+        let users: Vec<User> = table.query_as(id_is(1.0), &options).await.unwrap();
+        assert_eq!(users, vec![ada]);
+        let users: Vec<User> = table.query_as(id_is(2.0), &options).await.unwrap();
+        assert_eq!(
+            users,
+            vec![User {
+                id: 2,
+                name: "Bob".to_string(),
+                email: None,
+                score: 7.0,
+                active: false,
+                joined: Some(1_700_000_000),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_missing_field_names_the_column() {
+        let (mut table, _dir) = temp_table().await;
+        table
+            .insert(HashMap::from([
+                ("id".to_string(), DBValue::Number(1.0)),
+                ("score".to_string(), DBValue::Number(1.0)),
+                ("active".to_string(), DBValue::Number(1.0)),
+            ]))
+            .await
+            .unwrap();
+
+        let result = table
+            .query_as::<User>(id_is(1.0), &QueryOptions::default())
+            .await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "db.query_as on table 'id': column 'name': missing, and the field is not an Option"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_numbers_narrow_only_when_they_fit() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Small {
+            n: i32,
+        }
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Byte {
+            n: u8,
+        }
+
+        let (mut table, _dir) = temp_table().await;
+        for (id, n) in [(1.0, 42.0), (2.0, 2.5), (3.0, 3e9), (4.0, -1.0)] {
+            table
+                .insert(HashMap::from([
+                    ("id".to_string(), DBValue::Number(id)),
+                    ("n".to_string(), DBValue::Number(n)),
+                ]))
+                .await
+                .unwrap();
+        }
+        let options = QueryOptions::default();
+        let error = |result: Result<Vec<Small>, DbError>| result.unwrap_err().to_string();
+
+        let rows: Vec<Small> = table.query_as(id_is(1.0), &options).await.unwrap();
+        assert_eq!(rows, vec![Small { n: 42 }]);
+        assert_eq!(
+            error(table.query_as(id_is(2.0), &options).await),
+            "db.query_as on table 'id': column 'n': expected i32, found 2.5 which is not a whole number"
+        );
+        assert_eq!(
+            error(table.query_as(id_is(3.0), &options).await),
+            "db.query_as on table 'id': column 'n': 3000000000 is out of range for i32"
+        );
+        let result = table.query_as::<Byte>(id_is(4.0), &options).await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "db.query_as on table 'id': column 'n': -1 is out of range for u8"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_insert_struct_rejects_nested_values() {
+        #[derive(Serialize)]
+        struct Tagged {
+            id: i64,
+            tags: Vec<String>,
+        }
+
+        let (mut table, _dir) = temp_table().await;
+        let result = table
+            .insert_struct(&Tagged {
+                id: 1,
+                tags: vec!["a".to_string()],
+            })
+            .await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "db.insert_struct on table 'id': column 'tags': an array cannot be stored in a column"
+        );
+    }
+}
@@ -1,10 +1,14 @@
 use crate::lexer::Token;
+use crate::style::{Severity, Style};
+use std::path::Path;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SyntaxError {
     UnexpectedToken(Option<Token>, String),
     UnimplementedToken(Token),
     UnexpectedEof,
+    /// Expressions, blocks or patterns nested deeper than the parser allows.
+    NestingTooDeep(usize),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -48,7 +52,7 @@ impl Error {
         Error {
             line: token.line,
             column: token.column,
-            error_type: ErrorTypes::LexicalError(format!("Unimplemented token: {:?}", token)),
+            error_type: ErrorTypes::SyntaxError(SyntaxError::UnimplementedToken(token.clone())),
             throw_location: throw_location.to_string(),
         }
     }
@@ -65,6 +69,15 @@ impl Error {
         }
     }
 
+    pub fn nesting_too_deep(token: &Token, limit: usize, throw_location: &str) -> Self {
+        Error {
+            line: token.line,
+            column: token.column,
+            error_type: ErrorTypes::SyntaxError(SyntaxError::NestingTooDeep(limit)),
+            throw_location: throw_location.to_string(),
+        }
+    }
+
     pub fn unexpected_eof(throw_location: &str) -> Self {
         Error {
             line: 0,
@@ -73,6 +86,19 @@ impl Error {
             throw_location: throw_location.to_string(),
         }
     }
+
+    /// The registered code for this error, see `explain`.
+    pub fn code(&self) -> Option<&'static ErrorCode> {
+        let code = match &self.error_type {
+            ErrorTypes::LexicalError(_) => "E0103",
+            ErrorTypes::SyntaxError(SyntaxError::UnexpectedToken(..)) => "E0100",
+            ErrorTypes::SyntaxError(SyntaxError::UnexpectedEof) => "E0101",
+            ErrorTypes::SyntaxError(SyntaxError::NestingTooDeep(_)) => "E0102",
+            ErrorTypes::SyntaxError(SyntaxError::UnimplementedToken(_)) => "E0104",
+            ErrorTypes::RuntimeError(_) => return None,
+        };
+        explain(code)
+    }
 }
 
 impl std::fmt::Display for Error {
@@ -84,3 +110,480 @@ impl std::fmt::Display for Error {
         )
     }
 }
+
+impl Error {
+    /// Renders the error for a terminal: a severity label, the location and,
+    /// when `source` is given, the offending line with a caret under the column.
+    pub fn render(&self, source: Option<&str>, path: Option<&Path>, style: Style) -> String {
+        let severity = Severity::Error;
+        let label = match self.code() {
+            Some(code) => format!("{}[{}]", severity.label(), code.code),
+            None => severity.label().to_string(),
+        };
+        let mut out = format!(
+            "{}: {}\n",
+            style.severity(severity, &label),
+            self.message(style)
+        );
+        self.render_location(&mut out, source, path, style);
+        if let Some(code) = self.code() {
+            out.push_str(&explain_hint(code, style));
+        }
+        out
+    }
+
+    fn render_location(
+        &self,
+        out: &mut String,
+        source: Option<&str>,
+        path: Option<&Path>,
+        style: Style,
+    ) {
+        let severity = Severity::Error;
+        let location = match (path, self.line) {
+            (Some(path), 0) => path.display().to_string(),
+            (Some(path), line) => format!("{}:{}:{}", path.display(), line, self.column),
+            (None, 0) => return,
+            (None, line) => format!("{}:{}", line, self.column),
+        };
+        out.push_str(&format!("  --> {}\n", style.dim(&location)));
+
+        let line = source
+            .filter(|_| self.line > 0)
+            .and_then(|source| source.lines().nth(self.line as usize - 1));
+        if let Some(line) = line {
+            let number = self.line.to_string();
+            let gutter = " ".repeat(number.len());
+            let padding = " ".repeat(self.column.saturating_sub(1) as usize);
+            out.push_str(&format!(
+                "{} {} {}\n",
+                style.dim(&number),
+                style.dim("|"),
+                line
+            ));
+            out.push_str(&format!(
+                "{} {} {}{}\n",
+                gutter,
+                style.dim("|"),
+                padding,
+                style.severity(severity, "^")
+            ));
+        }
+    }
+
+    fn message(&self, style: Style) -> String {
+        match &self.error_type {
+            ErrorTypes::SyntaxError(SyntaxError::UnexpectedToken(Some(token), expected)) => {
+                format!(
+                    "Syntax error: unexpected {}, expected {}",
+                    style.bold(&format!("{:?}", token.token)),
+                    expected
+                )
+            }
+            ErrorTypes::SyntaxError(SyntaxError::UnimplementedToken(token)) => format!(
+                "Syntax error: unimplemented token {}",
+                style.bold(&format!("{:?}", token.token))
+            ),
+            ErrorTypes::SyntaxError(SyntaxError::NestingTooDeep(limit)) => format!(
+                "Syntax error: expression nesting too deep (the limit is {} levels)",
+                limit
+            ),
+            other => other.to_string(),
+        }
+    }
+}
+
+/// A documented kind of error, printed by `mouse --explain CODE`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ErrorCode {
+    pub code: &'static str,
+    pub title: &'static str,
+    pub explanation: &'static str,
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: {}\n\n{}", self.code, self.title, self.explanation)
+    }
+}
+
+const UNDEFINED_VARIABLE: &str = "\
+A name was used before anything was bound to it, or assigned to without
+being declared first. Variables must be declared with `let` before they are
+read or assigned, and function names only exist once their `fn` statement
+has run.
+
+Failing example:
+
+    count = count + 1;
+
+Corrected:
+
+    let count = 0;
+    count = count + 1;
+";
+
+const ARITY_MISMATCH: &str = "\
+A function was called with a different number of arguments than it
+declares parameters. Every parameter must be passed; there are no default
+values.
+
+Failing example:
+
+    fn add(a, b) {
+        return a + b;
+    }
+    let sum = add(1);
+
+Corrected:
+
+    let sum = add(1, 2);
+";
+
+const UNDEFINED_FUNCTION: &str = "\
+A call names a function that is not defined, often because of a typo or
+because the call runs before the `fn` statement that defines it.
+
+Failing example:
+
+    let total = sum(1, 2);
+    fn sum(a, b) {
+        return a + b;
+    }
+
+Corrected:
+
+    fn sum(a, b) {
+        return a + b;
+    }
+    let total = sum(1, 2);
+";
+
+const DIVISION_BY_ZERO: &str = "\
+A number was divided by zero. Check the divisor first, or catch the error
+with `try`.
+
+Failing example:
+
+    let average = total / count;
+
+Corrected:
+
+    let average = 0;
+    if count != 0 {
+        average = total / count;
+    }
+";
+
+const CALL_DEPTH: &str = "\
+Function calls nested deeper than the interpreter allows, almost always a
+recursive function that never reaches its base case. The limit can be
+raised with `--max-call-depth`.
+
+Failing example:
+
+    fn countdown(n) {
+        return countdown(n - 1);
+    }
+
+Corrected:
+
+    fn countdown(n) {
+        if n > 0 {
+            return countdown(n - 1);
+        }
+        return 0;
+    }
+";
+
+const TYPE_MISMATCH: &str = "\
+An operator was used on values it does not support, such as subtracting a
+string or comparing a number with an array. Convert the values first.
+
+Failing example:
+
+    let label = \"total: \" - 5;
+
+Corrected:
+
+    let label = \"total: \" + 5;
+";
+
+const UNEXPECTED_TOKEN: &str = "\
+The parser found a token where the grammar does not allow it, such as a
+missing name after `let` or a missing `=`. The diagnostic names the token
+it found and what it expected instead.
+
+Failing example:
+
+    let = 5;
+
+Corrected:
+
+    let x = 5;
+";
+
+const UNEXPECTED_EOF: &str = "\
+The script ended in the middle of a statement, usually because of an
+unclosed bracket or an expression cut off at the end of the file.
+
+Failing example:
+
+    let x = add(1, 2
+
+Corrected:
+
+    let x = add(1, 2);
+";
+
+const NESTING_TOO_DEEP: &str = "\
+Expressions, blocks or patterns are nested deeper than the parser allows.
+Split the expression into several `let` statements.
+
+Failing example:
+
+    let x = ((((((((((1))))))))));   // nested hundreds of levels deep
+
+Corrected:
+
+    let inner = 1;
+    let x = inner;
+";
+
+const INVALID_TOKEN: &str = "\
+The lexer could not read a token, for example a number literal too large
+for a 64-bit integer.
+
+Failing example:
+
+    let big = 99999999999999999999;
+
+Corrected:
+
+    let big = 9999999999999999;
+";
+
+const UNSUPPORTED_TOKEN: &str = "\
+A statement starts with a token that cannot begin a statement, such as a
+stray `)` or a keyword the language does not support yet, like `else`.
+
+Failing example:
+
+    if x > 1 {
+        print(x);
+    } else {
+        print(0);
+    }
+
+Corrected:
+
+    if x > 1 {
+        print(x);
+    }
+    if x <= 1 {
+        print(0);
+    }
+";
+
+/// Every documented error, runtime errors first.
+pub const ERROR_CODES: &[ErrorCode] = &[
+    ErrorCode {
+        code: "E0001",
+        title: "undefined variable",
+        explanation: UNDEFINED_VARIABLE,
+    },
+    ErrorCode {
+        code: "E0002",
+        title: "wrong number of arguments",
+        explanation: ARITY_MISMATCH,
+    },
+    ErrorCode {
+        code: "E0003",
+        title: "undefined function",
+        explanation: UNDEFINED_FUNCTION,
+    },
+    ErrorCode {
+        code: "E0004",
+        title: "division by zero",
+        explanation: DIVISION_BY_ZERO,
+    },
+    ErrorCode {
+        code: "E0005",
+        title: "maximum call depth exceeded",
+        explanation: CALL_DEPTH,
+    },
+    ErrorCode {
+        code: "E0006",
+        title: "type mismatch",
+        explanation: TYPE_MISMATCH,
+    },
+    ErrorCode {
+        code: "E0100",
+        title: "unexpected token",
+        explanation: UNEXPECTED_TOKEN,
+    },
+    ErrorCode {
+        code: "E0101",
+        title: "unexpected end of input",
+        explanation: UNEXPECTED_EOF,
+    },
+    ErrorCode {
+        code: "E0102",
+        title: "nesting too deep",
+        explanation: NESTING_TOO_DEEP,
+    },
+    ErrorCode {
+        code: "E0103",
+        title: "invalid token",
+        explanation: INVALID_TOKEN,
+    },
+    ErrorCode {
+        code: "E0104",
+        title: "unsupported token",
+        explanation: UNSUPPORTED_TOKEN,
+    },
+];
+
+/// The error registered as `code`, e.g. `E0002`; case doesn't matter.
+pub fn explain(code: &str) -> Option<&'static ErrorCode> {
+    ERROR_CODES
+        .iter()
+        .find(|entry| entry.code.eq_ignore_ascii_case(code))
+}
+
+/// A runtime error as a script run reports it: the message scripts see and
+/// the registered code of its kind, if it has one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeError {
+    pub message: String,
+    pub code: Option<&'static ErrorCode>,
+}
+
+impl RuntimeError {
+    fn coded(code: &str, message: String) -> Self {
+        RuntimeError {
+            message,
+            code: explain(code),
+        }
+    }
+}
+
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+// The interpreter raises these with `Interpreter::raise`, which keeps the
+// code with the error on its way up.
+
+pub fn undefined_variable(name: &str) -> RuntimeError {
+    RuntimeError::coded("E0001", format!("Undefined identifier: {}", name))
+}
+
+pub fn assign_to_undefined(name: &str) -> RuntimeError {
+    RuntimeError::coded(
+        "E0001",
+        format!("Cannot assign to undefined variable: {}", name),
+    )
+}
+
+pub fn arity_mismatch(expected: usize, got: usize) -> RuntimeError {
+    RuntimeError::coded(
+        "E0002",
+        format!("Function expects {} arguments, got {}", expected, got),
+    )
+}
+
+pub fn unknown_argument(name: &str, suggestion: Option<&str>) -> RuntimeError {
+    let message = match suggestion {
+        Some(suggestion) => format!(
+            "Function expects no argument named '{}', did you mean '{}'?",
+            name, suggestion
+        ),
+        None => format!("Function expects no argument named '{}'", name),
+    };
+    RuntimeError::coded("E0002", message)
+}
+
+pub fn argument_given_twice(name: &str) -> RuntimeError {
+    RuntimeError::coded(
+        "E0002",
+        format!("Function expects argument '{}' once, got it twice", name),
+    )
+}
+
+pub fn argument_given_by_position_and_name(name: &str) -> RuntimeError {
+    RuntimeError::coded(
+        "E0002",
+        format!(
+            "Function expects argument '{}' once, got it by position and by name",
+            name
+        ),
+    )
+}
+
+pub fn missing_argument(name: &str) -> RuntimeError {
+    RuntimeError::coded(
+        "E0002",
+        format!("Function expects an argument for '{}'", name),
+    )
+}
+
+pub fn undefined_function(name: &str) -> RuntimeError {
+    RuntimeError::coded("E0003", format!("Undefined function: {}", name))
+}
+
+pub fn division_by_zero() -> RuntimeError {
+    RuntimeError::coded("E0004", "Division by zero".to_string())
+}
+
+pub fn call_depth_exceeded() -> RuntimeError {
+    RuntimeError::coded(
+        "E0005",
+        "Stack overflow: maximum call depth exceeded".to_string(),
+    )
+}
+
+/// `operands` describes what `op` was applied to, e.g. `strings`.
+pub fn unsupported_operation(op: &impl std::fmt::Debug, operands: &str) -> RuntimeError {
+    RuntimeError::coded(
+        "E0006",
+        format!("Unsupported operation {:?} for {}", op, operands),
+    )
+}
+
+pub fn type_mismatch() -> RuntimeError {
+    RuntimeError::coded("E0006", "Type mismatch in binary operation".to_string())
+}
+
+/// Has no code of its own.
+pub fn break_outside_loop() -> String {
+    "break outside of a loop".to_string()
+}
+
+/// Pointer to `mouse --explain`, the last line of a diagnostic.
+fn explain_hint(code: &ErrorCode, style: Style) -> String {
+    format!(
+        "  {} run `mouse --explain {}` for more about this error\n",
+        style.dim("= help:"),
+        code.code
+    )
+}
+
+/// Renders a runtime error for a terminal, with its code if it has one.
+pub fn render_runtime_error(error: &RuntimeError, style: Style) -> String {
+    match error.code {
+        Some(code) => format!(
+            "{} {}\n{}",
+            style.severity(Severity::Error, &format!("Runtime error[{}]:", code.code)),
+            error.message,
+            explain_hint(code, style)
+        ),
+        None => format!(
+            "{} {}\n",
+            style.severity(Severity::Error, "Runtime error:"),
+            error.message
+        ),
+    }
+}
@@ -0,0 +1,166 @@
+//! Calling script functions from Rust, and turning the values they return
+//! into Rust types.
+
+use super::{Interpreter, ObjectRef, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// Why `Interpreter::call` could not produce a result.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CallError {
+    /// Nothing is defined under the name.
+    NotFound(String),
+    /// The name is defined, but not as a function.
+    NotCallable {
+        name: String,
+        type_name: &'static str,
+    },
+    /// A user function was given the wrong number of arguments.
+    Arity {
+        name: String,
+        expected: usize,
+        got: usize,
+    },
+    /// The function ran and failed.
+    Runtime(String),
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CallError::NotFound(name) => write!(f, "no function named '{}'", name),
+            CallError::NotCallable { name, type_name } => {
+                write!(f, "'{}' is a {}, not a function", name, type_name)
+            }
+            CallError::Arity {
+                name,
+                expected,
+                got,
+            } => write!(f, "'{}' expects {} arguments, got {}", name, expected, got),
+            CallError::Runtime(msg) => write!(f, "Runtime error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for CallError {}
+
+impl Interpreter {
+    /// Calls the function bound to `name` with `args`. `name` is a global
+    /// like `"on_event"` or a path through objects like
+    /// `"handlers.on_event"` or `"std.str.len"`.
+    pub fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value, CallError> {
+        let (function, receiver) = self.resolve(name)?;
+        match &function {
+            Value::Function(_, params, _) if params.len() != args.len() => {
+                return Err(CallError::Arity {
+                    name: name.to_string(),
+                    expected: params.len(),
+                    got: args.len(),
+                })
+            }
+            Value::Function(..) | Value::NativeFunction(..) => {}
+            other => {
+                return Err(CallError::NotCallable {
+                    name: name.to_string(),
+                    type_name: other.type_name(),
+                })
+            }
+        }
+        match receiver {
+            Some(receiver) => self.call_method(&receiver, &function, args),
+            None => self.call_function(&function, args),
+        }
+        .map_err(CallError::Runtime)
+    }
+
+    /// Looks up `name`, along with the object it is a member of, if any.
+    fn resolve(&self, name: &str) -> Result<(Value, Option<ObjectRef>), CallError> {
+        let not_found = || CallError::NotFound(name.to_string());
+        let mut segments = name.split('.');
+        let first = segments.next().unwrap_or_default();
+        let mut value = match self.env.get_variable(first) {
+            Some(value) => value.clone(),
+            None => match self.env.get_object(first) {
+                Some(object) => Value::object(object.clone()),
+                None => return Err(not_found()),
+            },
+        };
+        let mut receiver = None;
+        for segment in segments {
+            let Value::Object(object) = value else {
+                return Err(not_found());
+            };
+            let member = object.borrow().get_property(segment).cloned();
+            value = member.ok_or_else(not_found)?;
+            receiver = Some(object);
+        }
+        Ok((value, receiver))
+    }
+}
+
+fn expected(what: &str, value: &Value) -> String {
+    format!("expected {}, got {}", what, value.type_name())
+}
+
+impl TryFrom<Value> for i64 {
+    type Error = String;
+
+    fn try_from(value: Value) -> Result<Self, String> {
+        match value {
+            Value::Number(n) => Ok(n),
+            other => Err(expected("a number", &other)),
+        }
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = String;
+
+    fn try_from(value: Value) -> Result<Self, String> {
+        match value {
+            Value::String(s) => Ok(s),
+            other => Err(expected("a string", &other)),
+        }
+    }
+}
+
+/// Comparisons produce 1 or 0, so any number converts: zero is false.
+impl TryFrom<Value> for bool {
+    type Error = String;
+
+    fn try_from(value: Value) -> Result<Self, String> {
+        match value {
+            Value::Number(n) => Ok(n != 0),
+            other => Err(expected("a number", &other)),
+        }
+    }
+}
+
+impl TryFrom<Value> for Vec<Value> {
+    type Error = String;
+
+    fn try_from(value: Value) -> Result<Self, String> {
+        match value {
+            Value::Array(array) => Ok(Rc::try_unwrap(array)
+                .map_or_else(|shared| shared.borrow().clone(), |a| a.into_inner())),
+            other => Err(expected("an array", &other)),
+        }
+    }
+}
+
+impl TryFrom<Value> for HashMap<String, Value> {
+    type Error = String;
+
+    fn try_from(value: Value) -> Result<Self, String> {
+        match value {
+            Value::Object(object) => Ok(object
+                .borrow()
+                .properties()
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect()),
+            other => Err(expected("an object", &other)),
+        }
+    }
+}
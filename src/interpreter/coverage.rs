@@ -0,0 +1,174 @@
+use crate::parser::{Program, Span, Stmt, StmtKind};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// How often each line of a script and the modules it imported ran.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoverageReport {
+    /// Hit count per line that holds a statement, by source file and in
+    /// line order. A line with several statements reports the most any of
+    /// them ran. Files are named as `meta.file` names them.
+    pub files: BTreeMap<String, BTreeMap<u32, u64>>,
+}
+
+impl CoverageReport {
+    pub fn hits(&self, file: &str, line: u32) -> Option<u64> {
+        self.files.get(file)?.get(&line).copied()
+    }
+
+    pub fn lines_found(&self) -> usize {
+        self.files.values().map(BTreeMap::len).sum()
+    }
+
+    pub fn lines_hit(&self) -> usize {
+        self.files
+            .values()
+            .flat_map(BTreeMap::values)
+            .filter(|hits| **hits > 0)
+            .count()
+    }
+
+    /// The report as an lcov tracefile with a record per source file,
+    /// readable by genhtml and editor coverage viewers.
+    pub fn to_lcov(&self) -> String {
+        let mut lcov = String::from("TN:\n");
+        for (file, lines) in &self.files {
+            lcov.push_str(&format!("SF:{}\n", file));
+            for (line, hits) in lines {
+                lcov.push_str(&format!("DA:{},{}\n", line, hits));
+            }
+            let hit = lines.values().filter(|hits| **hits > 0).count();
+            lcov.push_str(&format!("LF:{}\nLH:{}\nend_of_record\n", lines.len(), hit));
+        }
+        lcov
+    }
+}
+
+impl fmt::Display for CoverageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (file, lines) in &self.files {
+            writeln!(f, "{}", file)?;
+            writeln!(f, "{:>6}  {:>8}", "line", "hits")?;
+            for (line, hits) in lines {
+                writeln!(f, "{:>6}  {:>8}", line, hits)?;
+            }
+        }
+        let total = self.lines_found();
+        let percent = if total == 0 {
+            100.0
+        } else {
+            self.lines_hit() as f64 * 100.0 / total as f64
+        };
+        writeln!(
+            f,
+            "{} of {} lines covered ({:.1}%)",
+            self.lines_hit(),
+            total,
+            percent
+        )
+    }
+}
+
+/// Execution counts per statement, keyed by the file it is in and where
+/// in that file it starts.
+pub(crate) struct Coverage {
+    files: BTreeMap<String, BTreeMap<(u32, u32), u64>>,
+    /// File the statements being registered or run are from
+    current: String,
+    /// File each function was defined in, so its body is counted there
+    /// wherever it is called from
+    function_files: HashMap<String, String>,
+}
+
+impl Coverage {
+    pub(crate) fn new(file: String) -> Self {
+        Coverage {
+            files: BTreeMap::new(),
+            current: file,
+            function_files: HashMap::new(),
+        }
+    }
+
+    /// Makes `file` the file statements are registered and counted in, and
+    /// returns the one that was.
+    pub(crate) fn set_file(&mut self, file: String) -> String {
+        std::mem::replace(&mut self.current, file)
+    }
+
+    /// Switches to the file `function` was defined in, returning the file
+    /// to switch back to afterwards.
+    pub(crate) fn enter_function(&mut self, function: &str) -> Option<String> {
+        let file = self.function_files.get(function)?.clone();
+        Some(self.set_file(file))
+    }
+
+    /// Records every statement of `program` with no hits yet, so function
+    /// bodies and branches that never run still show up.
+    pub(crate) fn register(&mut self, program: &Program) {
+        self.register_block(&program.statements);
+    }
+
+    fn register_block(&mut self, statements: &[Stmt]) {
+        for stmt in statements {
+            self.statements()
+                .entry((stmt.span.line, stmt.span.column))
+                .or_insert(0);
+            match &stmt.kind {
+                StmtKind::Function { name, body, .. } => {
+                    self.function_files
+                        .insert(name.clone(), self.current.clone());
+                    self.register_block(body);
+                }
+                StmtKind::While { body, .. } | StmtKind::For { body, .. } => {
+                    self.register_block(body)
+                }
+                StmtKind::If {
+                    then_branch,
+                    else_branch,
+                    ..
+                } => {
+                    self.register_block(then_branch);
+                    if let Some(else_branch) = else_branch {
+                        self.register_block(else_branch);
+                    }
+                }
+                StmtKind::Try { body, handler, .. } => {
+                    self.register_block(body);
+                    self.register_block(handler);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Counts of the current file.
+    fn statements(&mut self) -> &mut BTreeMap<(u32, u32), u64> {
+        if !self.files.contains_key(&self.current) {
+            self.files.insert(self.current.clone(), BTreeMap::new());
+        }
+        self.files.get_mut(&self.current).unwrap()
+    }
+
+    pub(crate) fn hit(&mut self, span: Span) {
+        *self
+            .statements()
+            .entry((span.line, span.column))
+            .or_insert(0) += 1;
+    }
+
+    pub(crate) fn report(&self) -> CoverageReport {
+        let files = self
+            .files
+            .iter()
+            .map(|(file, statements)| {
+                let mut lines = BTreeMap::new();
+                for (&(line, _), &hits) in statements {
+                    let entry = lines.entry(line).or_insert(0);
+                    *entry = hits.max(*entry);
+                }
+                (file.clone(), lines)
+            })
+            .collect();
+        CoverageReport { files }
+    }
+}
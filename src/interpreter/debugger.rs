@@ -0,0 +1,98 @@
+use super::{Interpreter, Value};
+use crate::parser::Span;
+use std::collections::{BTreeSet, HashMap};
+
+/// What the host wants to happen after a pause.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugAction {
+    /// Pause again before the next statement.
+    Step,
+    /// Run until the next breakpoint.
+    Continue,
+    /// Stop the program with a runtime error.
+    Abort,
+}
+
+/// Callback invoked whenever execution pauses. It blocks the interpreter
+/// until it returns.
+pub type PauseHandler = Box<dyn FnMut(&mut Pause) -> DebugAction>;
+
+pub struct Debugger {
+    breakpoints: BTreeSet<u32>,
+    stepping: bool,
+    on_pause: PauseHandler,
+}
+
+impl Debugger {
+    pub fn new(on_pause: PauseHandler) -> Self {
+        Debugger {
+            breakpoints: BTreeSet::new(),
+            stepping: false,
+            on_pause,
+        }
+    }
+
+    /// Pause before the very first statement, as if stepping into the program.
+    pub fn stop_on_entry(mut self) -> Self {
+        self.stepping = true;
+        self
+    }
+
+    pub fn set_breakpoint(&mut self, line: u32) {
+        self.breakpoints.insert(line);
+    }
+
+    pub fn clear_breakpoint(&mut self, line: u32) {
+        self.breakpoints.remove(&line);
+    }
+
+    fn should_pause(&self, span: Span) -> bool {
+        self.stepping || self.breakpoints.contains(&span.line)
+    }
+
+    /// Runs the pause handler if the statement at `span` is a stop point.
+    pub(super) fn check(&mut self, interpreter: &Interpreter, span: Span) -> Result<(), String> {
+        if !self.should_pause(span) {
+            return Ok(());
+        }
+
+        let mut pause = Pause {
+            span,
+            interpreter,
+            breakpoints: &mut self.breakpoints,
+        };
+        match (self.on_pause)(&mut pause) {
+            DebugAction::Step => self.stepping = true,
+            DebugAction::Continue => self.stepping = false,
+            DebugAction::Abort => {
+                return Err(format!("Execution aborted by debugger at {}", span));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// State visible to the host while execution is paused.
+pub struct Pause<'a> {
+    pub span: Span,
+    interpreter: &'a Interpreter,
+    breakpoints: &'a mut BTreeSet<u32>,
+}
+
+impl Pause<'_> {
+    pub fn snapshot_scope(&self) -> HashMap<String, Value> {
+        self.interpreter.snapshot_scope()
+    }
+
+    pub fn set_breakpoint(&mut self, line: u32) {
+        self.breakpoints.insert(line);
+    }
+
+    pub fn clear_breakpoint(&mut self, line: u32) {
+        self.breakpoints.remove(&line);
+    }
+
+    pub fn breakpoints(&self) -> Vec<u32> {
+        self.breakpoints.iter().copied().collect()
+    }
+}
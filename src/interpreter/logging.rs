@@ -0,0 +1,75 @@
+use chrono::{SecondsFormat, Utc};
+use std::fmt;
+use std::str::FromStr;
+
+/// Severity of a log entry, from least to most severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum LogLevel {
+    Debug,
+    #[default]
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    /// Upper-case name used as the line prefix.
+    pub fn label(self) -> &'static str {
+        match self {
+            LogLevel::Debug => "DEBUG",
+            LogLevel::Info => "INFO",
+            LogLevel::Warn => "WARN",
+            LogLevel::Error => "ERROR",
+        }
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.label().to_lowercase())
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "debug" => Ok(LogLevel::Debug),
+            "info" => Ok(LogLevel::Info),
+            "warn" | "warning" => Ok(LogLevel::Warn),
+            "error" => Ok(LogLevel::Error),
+            _ => Err(format!(
+                "unknown log level \"{}\", expected debug, info, warn or error",
+                s
+            )),
+        }
+    }
+}
+
+/// Receives the log entries a script emits at or above the interpreter's
+/// log level. Closures taking `(LogLevel, &str)` are sinks too.
+pub trait LogSink {
+    fn log(&mut self, level: LogLevel, message: &str);
+}
+
+impl<F: FnMut(LogLevel, &str)> LogSink for F {
+    fn log(&mut self, level: LogLevel, message: &str) {
+        self(level, message)
+    }
+}
+
+/// The default sink: `<timestamp> <LEVEL> <message>` lines on stderr, so
+/// logs stay out of the script's printed output.
+pub struct StderrSink;
+
+impl LogSink for StderrSink {
+    fn log(&mut self, level: LogLevel, message: &str) {
+        eprintln!(
+            "{} {:<5} {}",
+            Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            level.label(),
+            message
+        );
+    }
+}
@@ -0,0 +1,126 @@
+//! Approximate memory accounting for `Interpreter::set_memory_limit`.
+//!
+//! Allocating operations (assignments, string concatenation, natives
+//! growing their array or object arguments) charge a rough byte count to a
+//! running total on a shared meter. Natives that build something from a
+//! size argument reserve it through `Interpreter::reserve_memory` before
+//! allocating, so an oversized request fails instead of running out of
+//! memory. Charges only ever add up, so before a charge turns into an error
+//! the meter recounts what the running scope can actually reach, which also
+//! drops whatever went out of scope or was overwritten since.
+
+use super::{Environment, Object, Value};
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::mem::size_of;
+
+/// Rough cost of one slot holding a value.
+pub const VALUE_SIZE: usize = size_of::<Value>();
+/// Rough cost of one object property: key, value and table overhead.
+const PROPERTY_SIZE: usize = VALUE_SIZE + size_of::<String>() + 16;
+
+/// See `MemoryMeter::charge`.
+const RECOUNT_FRACTION: usize = 64;
+
+/// Estimated size of `value` itself, not counting the values inside an
+/// array or object. Cheap enough to call on every assignment.
+pub(super) fn shallow_size(value: &Value) -> usize {
+    match value {
+        Value::String(s) => VALUE_SIZE + s.len(),
+        Value::Array(array) => VALUE_SIZE + array.borrow().len() * VALUE_SIZE,
+        Value::Object(object) => VALUE_SIZE + object.borrow().properties().len() * PROPERTY_SIZE,
+        Value::Function(_, params, body) => VALUE_SIZE * (1 + params.len() + body.len()),
+        _ => VALUE_SIZE,
+    }
+}
+
+/// Estimated size of everything reachable from `values`, counting arrays
+/// and objects shared between them once.
+fn reachable_size<'a>(values: impl Iterator<Item = &'a Value>) -> usize {
+    fn visit(value: &Value, seen: &mut HashSet<usize>) -> usize {
+        let mut size = shallow_size(value);
+        match value {
+            Value::Array(array) if seen.insert(array.as_ptr() as usize) => {
+                for element in array.borrow().iter() {
+                    size += visit(element, seen);
+                }
+            }
+            Value::Object(object) if seen.insert(object.as_ptr() as usize) => {
+                size += properties_size(&object.borrow(), seen);
+            }
+            _ => {}
+        }
+        size
+    }
+
+    fn properties_size(object: &Object, seen: &mut HashSet<usize>) -> usize {
+        object
+            .properties()
+            .iter()
+            .map(|(name, value)| name.len() + visit(value, seen))
+            .sum()
+    }
+
+    let mut seen = HashSet::new();
+    values.map(|value| visit(value, &mut seen)).sum()
+}
+
+/// Byte estimate shared by an interpreter and its function scopes.
+#[derive(Debug, Default)]
+pub(super) struct MemoryMeter {
+    /// Reachable bytes at the last recount.
+    counted: Cell<usize>,
+    /// Bytes charged since then.
+    charged: Cell<usize>,
+    limit: Cell<Option<usize>>,
+}
+
+impl MemoryMeter {
+    pub(super) fn set_limit(&self, limit: Option<usize>) {
+        self.limit.set(limit);
+    }
+
+    /// Recounts what `env` reaches; its variables are the whole scope chain,
+    /// function scopes start from a copy of their caller's.
+    pub(super) fn recount(&self, env: &Environment) -> usize {
+        let global = env
+            .get_object("global")
+            .map(|global| global.properties().values());
+        let used = reachable_size(global.into_iter().flatten().chain(env.variables.values()));
+        self.counted.set(used);
+        self.charged.set(0);
+        used
+    }
+
+    /// Adds `bytes` to the estimate, failing if that goes over the limit
+    /// even after a recount.
+    ///
+    /// A recount walks everything reachable, so one is only done once at
+    /// least 1/`RECOUNT_FRACTION` of the limit was charged since the last.
+    /// That keeps the cost of charges linear in the bytes charged when a
+    /// script runs close to its limit; until then the running total counts.
+    pub(super) fn charge(&self, bytes: usize, env: &Environment) -> Result<(), String> {
+        self.charged.set(self.charged.get().saturating_add(bytes));
+        let Some(limit) = self.limit.get() else {
+            return Ok(());
+        };
+        let mut estimate = self.counted.get().saturating_add(self.charged.get());
+        if estimate <= limit {
+            return Ok(());
+        }
+        if self.charged.get() >= limit / RECOUNT_FRACTION {
+            // What is being charged for is not reachable yet
+            estimate = self.recount(env).saturating_add(bytes);
+            self.charged.set(bytes);
+        }
+        if estimate > limit {
+            // Nothing allocated on this failed charge is kept
+            self.charged.set(self.charged.get().saturating_sub(bytes));
+            return Err(format!(
+                "Memory limit exceeded: about {} bytes in use, the limit is {}",
+                estimate, limit
+            ));
+        }
+        Ok(())
+    }
+}
@@ -1,30 +1,67 @@
 use crate::{
-    parser::{BinaryOp, Expr, Program, Stmt},
-    std_lib,
+    errors::{self, RuntimeError},
+    parser::{BinaryOp, Expr, Ident, Pattern, Program, Span, Stmt, StmtKind},
+    std_lib, PreludeError,
 };
-use std::collections::HashMap;
+use regex::Regex;
+use std::any::Any;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
-#[derive(Debug, Clone, PartialEq)]
+pub mod call;
+pub mod coverage;
+pub mod debugger;
+pub mod logging;
+pub mod memory;
+mod named_args;
+pub mod profiler;
+pub mod properties;
+pub mod resources;
+pub mod serialize;
+pub mod snapshot;
+mod unwind;
+pub mod watchdog;
+
+use coverage::{Coverage, CoverageReport};
+use debugger::{Debugger, PauseHandler};
+use logging::{LogLevel, LogSink, StderrSink};
+use memory::MemoryMeter;
+use profiler::{FunctionKind, ProfileReport, Profiler};
+use properties::Properties;
+use resources::Resources;
+use snapshot::StateSnapshot;
+use unwind::Unwinding;
+
+#[derive(Clone)]
 pub struct Object {
     name: String,
-    properties: HashMap<String, Value>,
+    properties: Properties,
 }
 
 impl Object {
     pub fn new(name: &str) -> Self {
         Object {
             name: name.to_string(),
-            properties: HashMap::new(),
+            properties: Properties::new(),
         }
     }
 
-    pub fn with_properties(name: &str, properties: HashMap<String, Value>) -> Self {
+    pub fn with_properties(name: &str, properties: Properties) -> Self {
         Object {
             name: name.to_string(),
             properties,
         }
     }
 
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     pub fn set_property(&mut self, key: String, value: Value) {
         self.properties.insert(key, value);
     }
@@ -33,6 +70,14 @@ impl Object {
         self.properties.get(name)
     }
 
+    pub fn get_property_mut(&mut self, name: &str) -> Option<&mut Value> {
+        self.properties.get_mut(name)
+    }
+
+    pub fn properties(&self) -> &Properties {
+        &self.properties
+    }
+
     pub fn register_native_fn(
         &mut self,
         name: &str,
@@ -45,75 +90,315 @@ impl Object {
     }
 }
 
+impl Object {
+    fn fmt_with_depth(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+        write!(f, "{{{}: ", self.name)?;
+        for (i, (key, value)) in self.properties.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}:", key)?;
+            value.fmt_with_depth(f, depth + 1)?;
+        }
+        write!(f, "}}")
+    }
+}
+
 impl std::fmt::Display for Object {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{{{}: {}}}",
-            self.name,
-            self.properties
-                .iter()
-                .map(|(k, v)| format!("{}:{}", k, v))
-                .collect::<Vec<String>>()
-                .join(", ")
-        )
+        self.fmt_with_depth(f, 0)
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// Nesting depth after which `Display` stops descending, so arrays and
+/// objects that contain themselves still print.
+const MAX_DISPLAY_DEPTH: usize = 32;
+
+/// Arrays and objects are shared: copying the value aliases the same data.
+pub type ArrayRef = Rc<RefCell<Vec<Value>>>;
+pub type ObjectRef = Rc<RefCell<Object>>;
+/// Signature of functions implemented in Rust and callable from scripts.
+pub type NativeFn = fn(&mut Interpreter, Vec<Value>) -> Result<Value, String>;
+
+#[derive(Clone)]
 pub enum Value {
-    Number(i32),
+    Number(i64),
+    Float(f64),
     String(String),
     Void,
-    Array(Vec<Value>),
+    Array(ArrayRef),
     Function(String, Vec<String>, Vec<Stmt>), // name, params, body
-    #[allow(unpredictable_function_pointer_comparisons)]
     NativeFunction(
         String,
         fn(&mut Interpreter, Vec<Value>) -> Result<Value, String>,
     ),
-    Object(Object),
+    Object(ObjectRef),
 }
 
 impl Value {
+    pub fn array(values: Vec<Value>) -> Value {
+        Value::Array(Rc::new(RefCell::new(values)))
+    }
+
+    pub fn object(object: Object) -> Value {
+        Value::Object(Rc::new(RefCell::new(object)))
+    }
+
+    /// Returns true if both values are the same array or object instance,
+    /// as opposed to `==` which compares contents.
+    pub fn same_ref(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Array(a), Value::Array(b)) => Rc::ptr_eq(a, b),
+            (Value::Object(a), Value::Object(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    /// Name of the value's type as used in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::Float(_) => "float",
+            Value::String(_) => "string",
+            Value::Void => "void",
+            Value::Array(_) => "array",
+            Value::Function(..) => "function",
+            Value::NativeFunction(..) => "native function",
+            Value::Object(_) => "object",
+        }
+    }
+
     pub fn to_bool(&self) -> bool {
         match self {
             Value::Number(n) => *n != 0,
+            Value::Float(n) => *n != 0.0,
             Value::String(s) => !s.is_empty(),
             Value::Void => false,
-            Value::Array(arr) => !arr.is_empty(),
+            Value::Array(arr) => !arr.borrow().is_empty(),
             Value::Function(_, _, _) => true,
             Value::NativeFunction(_, _) => true,
-            Value::Object(obj) => !obj.properties.is_empty(),
+            Value::Object(obj) => !obj.borrow().properties.is_empty(),
         }
     }
-}
 
-impl std::fmt::Display for Value {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt_with_depth(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
         match self {
             Value::Number(n) => write!(f, "{}", n),
+            // Debug keeps the `.0` of whole floats, so they don't read as integers
+            Value::Float(n) => write!(f, "{:?}", n),
             Value::String(s) => write!(f, "{}", s),
             Value::Void => write!(f, "()"),
-            Value::Array(arr) => write!(
-                f,
-                "[{}]",
-                arr.iter()
-                    .map(|v| format!("{}", v))
-                    .collect::<Vec<String>>()
-                    .join(", ")
-            ),
-            Value::Function(name, _, _) => write!(f, "<function {}>", name),
+            Value::Array(_) if depth >= MAX_DISPLAY_DEPTH => write!(f, "[...]"),
+            Value::Array(arr) => {
+                write!(f, "[")?;
+                for (i, value) in arr.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    value.fmt_with_depth(f, depth + 1)?;
+                }
+                write!(f, "]")
+            }
+            Value::Function(name, params, _) => {
+                write!(f, "<function {}({})>", name, params.join(", "))
+            }
             Value::NativeFunction(name, _) => write!(f, "<native function {}>", name),
-            Value::Object(obj) => write!(f, "{}", obj),
+            Value::Object(_) if depth >= MAX_DISPLAY_DEPTH => write!(f, "{{...}}"),
+            Value::Object(obj) => obj.borrow().fmt_with_depth(f, depth),
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_with_depth(f, 0)
+    }
+}
+
+/// Nesting depth after which `==` stops comparing contents, so arrays and
+/// objects that contain themselves still compare. Deeper than this only
+/// the same instance is equal.
+const MAX_COMPARE_DEPTH: usize = 256;
+
+impl Value {
+    fn eq_with_depth(&self, other: &Value, depth: usize) -> bool {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Void, Value::Void) => true,
+            (Value::Array(a), Value::Array(b)) => {
+                if Rc::ptr_eq(a, b) {
+                    return true;
+                }
+                let (a, b) = (a.borrow(), b.borrow());
+                depth < MAX_COMPARE_DEPTH
+                    && a.len() == b.len()
+                    && a.iter()
+                        .zip(b.iter())
+                        .all(|(a, b)| a.eq_with_depth(b, depth + 1))
+            }
+            (
+                Value::Function(a_name, a_params, a_body),
+                Value::Function(b_name, b_params, b_body),
+            ) => a_name == b_name && a_params == b_params && a_body == b_body,
+            (Value::NativeFunction(a_name, a), Value::NativeFunction(b_name, b)) => {
+                a_name == b_name && std::ptr::fn_addr_eq(*a, *b)
+            }
+            (Value::Object(a), Value::Object(b)) => {
+                Rc::ptr_eq(a, b)
+                    || (depth < MAX_COMPARE_DEPTH && a.borrow().eq_with_depth(&b.borrow(), depth))
+            }
+            _ => false,
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        self.eq_with_depth(other, 0)
+    }
+}
+
+impl Object {
+    fn eq_with_depth(&self, other: &Object, depth: usize) -> bool {
+        self.name == other.name
+            && self.properties.len() == other.properties.len()
+            && self.properties.iter().all(|(key, value)| {
+                other
+                    .properties
+                    .get(key)
+                    .is_some_and(|other| value.eq_with_depth(other, depth + 1))
+            })
+    }
+}
+
+impl PartialEq for Object {
+    fn eq(&self, other: &Object) -> bool {
+        self.eq_with_depth(other, 0)
+    }
+}
+
+/// `Debug` for a value `depth` levels into the value being printed, cut
+/// off at `MAX_DISPLAY_DEPTH` like `Display`.
+struct DebugValue<'a>(&'a Value, usize);
+
+/// `Debug` for an object `depth` levels into the value being printed.
+struct DebugObject<'a>(&'a Object, usize);
+
+impl std::fmt::Debug for DebugValue<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let DebugValue(value, depth) = *self;
+        match value {
+            Value::Number(n) => f.debug_tuple("Number").field(n).finish(),
+            Value::Float(n) => f.debug_tuple("Float").field(n).finish(),
+            Value::String(s) => f.debug_tuple("String").field(s).finish(),
+            Value::Void => write!(f, "Void"),
+            Value::Array(_) if depth >= MAX_DISPLAY_DEPTH => write!(f, "Array([...])"),
+            Value::Array(arr) => match arr.try_borrow() {
+                Ok(arr) => {
+                    write!(f, "Array(")?;
+                    f.debug_list()
+                        .entries(arr.iter().map(|value| DebugValue(value, depth + 1)))
+                        .finish()?;
+                    write!(f, ")")
+                }
+                Err(_) => write!(f, "Array(<borrowed>)"),
+            },
+            Value::Function(name, params, body) => f
+                .debug_tuple("Function")
+                .field(name)
+                .field(params)
+                .field(body)
+                .finish(),
+            Value::NativeFunction(name, func) => f
+                .debug_tuple("NativeFunction")
+                .field(name)
+                .field(func)
+                .finish(),
+            Value::Object(_) if depth >= MAX_DISPLAY_DEPTH => write!(f, "Object({{...}})"),
+            Value::Object(obj) => match obj.try_borrow() {
+                Ok(obj) => f
+                    .debug_tuple("Object")
+                    .field(&DebugObject(&obj, depth))
+                    .finish(),
+                Err(_) => write!(f, "Object(<borrowed>)"),
+            },
         }
     }
 }
 
+impl std::fmt::Debug for DebugObject<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let DebugObject(object, depth) = *self;
+        write!(f, "Object {{ name: {:?}, properties: ", object.name)?;
+        f.debug_map()
+            .entries(
+                object
+                    .properties
+                    .iter()
+                    .map(|(key, value)| (key, DebugValue(value, depth + 1))),
+            )
+            .finish()?;
+        write!(f, " }}")
+    }
+}
+
+impl std::fmt::Debug for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        DebugValue(self, 0).fmt(f)
+    }
+}
+
+impl std::fmt::Debug for Object {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        DebugObject(self, 0).fmt(f)
+    }
+}
+
+/// Events reported to an installed tracer while a program runs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceEvent {
+    StatementEntered {
+        span: Span,
+        kind: &'static str,
+    },
+    FunctionEntered {
+        name: String,
+        args: Vec<Value>,
+    },
+    FunctionExited {
+        name: String,
+        result: Result<Value, String>,
+    },
+    VariableAssigned {
+        name: String,
+        value: Value,
+    },
+}
+
+pub type Tracer = Box<dyn FnMut(TraceEvent)>;
+
+/// Receives each line a script prints, without the trailing newline.
+pub type Output = Box<dyn FnMut(&str)>;
+
+/// Host callbacks that follow execution into every function scope.
+#[derive(Default)]
+struct Hooks {
+    tracer: Option<Tracer>,
+    debugger: Option<Debugger>,
+    profiler: Option<Profiler>,
+    coverage: Option<Coverage>,
+    output: Option<Output>,
+    log_sink: Option<Box<dyn LogSink>>,
+    log_level: LogLevel,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum ControlFlow {
     None,
     Return(Value),
+    Break,
 }
 
 pub struct Environment {
@@ -142,6 +427,7 @@ impl Environment {
         // Register global print function
         if let Some(global) = self.objects.get_mut("global") {
             global.register_native_fn("print", std_lib::print::print);
+            global.register_native_fn("range", std_lib::range::new);
         }
 
         // Register std library
@@ -149,8 +435,212 @@ impl Environment {
         std_object.register_native_fn("print", std_lib::print::print);
         std_object.register_native_fn("sleep", std_lib::sleep::sleep);
         std_object.register_native_fn("split_str", std_lib::str_utils::split_string);
+        std_object.register_native_fn("printf", std_lib::format::printf);
+        std_object.register_native_fn("format_number", std_lib::format::format_number);
+        std_object.register_native_fn("pad_left", std_lib::format::pad_left);
+        std_object.register_native_fn("pad_right", std_lib::format::pad_right);
+        std_object.register_native_fn("len", std_lib::arr::len);
+        std_object.register_native_fn("levenshtein", std_lib::str_utils::levenshtein);
+        std_object.register_native_fn("similarity", std_lib::str_utils::similarity);
+        std_object.register_native_fn("closest", std_lib::str_utils::closest);
+        std_object.register_native_fn("counter", std_lib::counter::new);
+        std_object.register_native_fn("range", std_lib::range::new);
+        std_object.register_native_fn("memory_used", std_lib::memory::memory_used);
+
+        // std.str
+        let mut str_object = Object::new("str");
+        str_object.register_native_fn("escape_html", std_lib::str_utils::escape_html);
+        str_object.register_native_fn("unescape_html", std_lib::str_utils::unescape_html);
+        str_object.register_native_fn("escape_url", std_lib::str_utils::escape_url);
+        str_object.register_native_fn("base64_encode", std_lib::str_utils::base64_encode);
+        str_object.register_native_fn("base64_decode", std_lib::str_utils::base64_decode);
+        str_object.register_native_fn("pad_center", std_lib::str_utils::pad_center);
+        str_object.register_native_fn("center", std_lib::str_utils::center);
+        str_object.register_native_fn("left", std_lib::str_utils::left);
+        str_object.register_native_fn("right", std_lib::str_utils::right);
+        str_object.register_native_fn("truncate", std_lib::str_utils::truncate);
+        str_object.register_native_fn("truncate_start", std_lib::str_utils::truncate_start);
+        str_object.register_native_fn("mask", std_lib::str_utils::mask);
+        str_object.register_native_fn("mask_email", std_lib::str_utils::mask_email);
+        str_object.register_native_fn("camel_case", std_lib::str_utils::camel_case);
+        str_object.register_native_fn("snake_case", std_lib::str_utils::snake_case);
+        str_object.register_native_fn("kebab_case", std_lib::str_utils::kebab_case);
+        str_object.register_native_fn("wrap", std_lib::str_utils::wrap);
+        str_object.register_native_fn("wrap_join", std_lib::str_utils::wrap_join);
+        str_object.register_native_fn("levenshtein", std_lib::str_utils::levenshtein);
+        str_object.register_native_fn("similarity", std_lib::str_utils::similarity_ratio);
+        str_object.register_native_fn("jaro_winkler", std_lib::str_utils::jaro_winkler);
+        // Names matching the DB's `FuzzyMatch` filter
+        str_object.register_native_fn("levenshtein_distance", std_lib::str_utils::levenshtein);
+        str_object.register_native_fn("levenshtein_ratio", std_lib::str_utils::similarity_ratio);
+        str_object.register_native_fn("fuzzy_match", std_lib::str_utils::fuzzy_match);
+        str_object.register_native_fn("ngrams", std_lib::str_utils::ngrams);
+        str_object.register_native_fn("word_ngrams", std_lib::str_utils::word_ngrams);
+        str_object.register_native_fn("count_words", std_lib::str_utils::count_words);
+        str_object.register_native_fn("count_lines", std_lib::str_utils::count_lines);
+        str_object.register_native_fn("count_chars", std_lib::str_utils::count_chars);
+        str_object.register_native_fn("count_bytes", std_lib::str_utils::count_bytes);
+        str_object.register_native_fn("is_empty", std_lib::str_utils::is_empty);
+        str_object.register_native_fn("is_blank", std_lib::str_utils::is_blank);
+        str_object.register_native_fn("is_numeric", std_lib::str_utils::is_numeric);
+        str_object.register_native_fn("is_alpha", std_lib::str_utils::is_alpha);
+        str_object.register_native_fn("is_alphanumeric", std_lib::str_utils::is_alphanumeric);
+        std_object.set_property("str".to_string(), Value::object(str_object));
+
+        // std.time
+        let mut time_object = Object::new("time");
+        time_object.register_native_fn("parse", std_lib::time::parse);
+        time_object.register_native_fn("format", std_lib::time::format);
+        time_object.register_native_fn("diff", std_lib::time::diff);
+        time_object.register_native_fn("days", std_lib::time::days);
+        time_object.register_native_fn("hours", std_lib::time::hours);
+        time_object.register_native_fn("add_days", std_lib::time::add_days);
+        time_object.register_native_fn("add_hours", std_lib::time::add_hours);
+        time_object.register_native_fn("add_minutes", std_lib::time::add_minutes);
+        std_object.set_property("time".to_string(), Value::object(time_object));
+
+        // std.io
+        let mut io_object = Object::new("io");
+        io_object.register_native_fn("read_bytes", std_lib::io::read_bytes);
+        io_object.register_native_fn("write_bytes", std_lib::io::write_bytes);
+        io_object.register_native_fn("file_size", std_lib::io::file_size);
+
+        // std.io.path
+        let mut path_object = Object::new("path");
+        path_object.register_native_fn("join", std_lib::io::path_join);
+        path_object.register_native_fn("dirname", std_lib::io::path_dirname);
+        path_object.register_native_fn("basename", std_lib::io::path_basename);
+        path_object.register_native_fn("extension", std_lib::io::path_extension);
+        io_object.set_property("path".to_string(), Value::object(path_object));
+        std_object.set_property("io".to_string(), Value::object(io_object));
+
+        // std.arr
+        let mut arr_object = Object::new("arr");
+        arr_object.register_native_fn("push", std_lib::arr::push);
+        arr_object.register_native_fn("partition", std_lib::arr::partition);
+        arr_object.register_native_fn("all", std_lib::arr::all);
+        arr_object.register_native_fn("any", std_lib::arr::any);
+        arr_object.register_native_fn("none", std_lib::arr::none);
+        arr_object.register_native_fn("count_if", std_lib::arr::count_if);
+        arr_object.register_native_fn("scan", std_lib::arr::scan);
+        arr_object.register_native_fn("moving_average", std_lib::arr::moving_average);
+        arr_object.register_native_fn("flatten_map", std_lib::arr::flatten_map);
+        arr_object.register_native_fn("flatten_deep", std_lib::arr::flatten_deep);
+        arr_object.register_native_fn("range", std_lib::arr::range);
+        arr_object.register_native_fn("frequencies", std_lib::arr::frequencies);
+        arr_object.register_native_fn("tally", std_lib::arr::tally);
+        arr_object.register_native_fn("count_by", std_lib::arr::count_by);
+        arr_object.register_native_fn("chunk", std_lib::arr::chunk);
+        arr_object.register_native_fn("paginate", std_lib::arr::paginate);
+        arr_object.register_native_fn("paginate_count", std_lib::arr::paginate_count);
+        arr_object.register_native_fn("sliding_window", std_lib::arr::sliding_window);
+        arr_object.register_native_fn("window_reduce", std_lib::arr::window_reduce);
+        arr_object.register_native_fn("dedupe_by", std_lib::arr::dedupe_by);
+        arr_object.register_native_fn("sum_by", std_lib::arr::sum_by);
+        arr_object.register_native_fn("average_by", std_lib::arr::average_by);
+        arr_object.register_native_fn("flat_unique", std_lib::arr::flat_unique);
+        arr_object.register_native_fn("set_union", std_lib::arr::set_union);
+        arr_object.register_native_fn("set_intersection", std_lib::arr::set_intersection);
+        arr_object.register_native_fn("set_difference", std_lib::arr::set_difference);
+        arr_object.register_native_fn("sort_by", std_lib::arr::sort_by);
+        arr_object.register_native_fn("bisect_left", std_lib::arr::bisect_left);
+        arr_object.register_native_fn("bisect_right", std_lib::arr::bisect_right);
+        arr_object.register_native_fn("transpose", std_lib::arr::transpose);
+        arr_object.register_native_fn("first", std_lib::arr::first);
+        arr_object.register_native_fn("last", std_lib::arr::last);
+        arr_object.register_native_fn("first_or", std_lib::arr::first_or);
+        arr_object.register_native_fn("last_or", std_lib::arr::last_or);
+        arr_object.register_native_fn("nth", std_lib::arr::nth);
+        arr_object.register_native_fn("every_nth", std_lib::arr::every_nth);
+        arr_object.register_native_fn("rotate", std_lib::arr::rotate);
+        arr_object.register_native_fn("interleave", std_lib::arr::interleave);
+        arr_object.register_native_fn("zip_with", std_lib::arr::zip_with);
+        arr_object.register_native_fn("zip3", std_lib::arr::zip3);
+        arr_object.register_native_fn("cartesian_product", std_lib::arr::cartesian_product);
+        arr_object.register_native_fn("combinations", std_lib::arr::combinations);
+        arr_object.register_native_fn("unzip", std_lib::arr::unzip);
+        arr_object.register_native_fn("fill", std_lib::arr::fill);
+        arr_object.register_native_fn("new", std_lib::arr::new);
+        arr_object.register_native_fn("repeat", std_lib::arr::repeat);
+        arr_object.register_native_fn("cycle", std_lib::arr::cycle);
+        arr_object.register_native_fn("weighted_random", std_lib::arr::weighted_random);
+        arr_object.register_native_fn("sample", std_lib::arr::sample);
+        std_object.set_property("arr".to_string(), Value::object(arr_object));
+
+        // std.obj
+        let mut obj_object = Object::new("obj");
+        obj_object.register_native_fn("entries", std_lib::obj::entries);
+        obj_object.register_native_fn("from_entries", std_lib::obj::from_entries);
+        obj_object.register_native_fn("pick", std_lib::obj::pick);
+        obj_object.register_native_fn("omit", std_lib::obj::omit);
+        obj_object.register_native_fn("deep_get", std_lib::obj::deep_get);
+        obj_object.register_native_fn("deep_set", std_lib::obj::deep_set);
+        obj_object.register_native_fn("invert", std_lib::obj::invert);
+        obj_object.register_native_fn("has", std_lib::obj::has);
+        obj_object.register_native_fn("size", std_lib::obj::size);
+        obj_object.register_native_fn("is_empty", std_lib::obj::is_empty);
+        obj_object.register_native_fn("defaults", std_lib::obj::defaults);
+        obj_object.register_native_fn("defaults_deep", std_lib::obj::defaults_deep);
+        std_object.set_property("obj".to_string(), Value::object(obj_object));
+
+        // std.log
+        let mut log_object = Object::new("log");
+        log_object.register_native_fn("debug", std_lib::log::debug);
+        log_object.register_native_fn("info", std_lib::log::info);
+        log_object.register_native_fn("warn", std_lib::log::warn);
+        log_object.register_native_fn("error", std_lib::log::error);
+        log_object.register_native_fn("set_level", std_lib::log::set_level);
+        std_object.set_property("log".to_string(), Value::object(log_object));
+
+        // std.encode
+        let mut encode_object = Object::new("encode");
+        encode_object.register_native_fn("base64_encode", std_lib::str_utils::base64_encode);
+        encode_object.register_native_fn("base64_decode", std_lib::str_utils::base64_decode);
+        encode_object.register_native_fn("hex", std_lib::encode::hex);
+        #[cfg(feature = "crypto")]
+        {
+            encode_object.register_native_fn("sha256", std_lib::encode::sha256);
+            encode_object.register_native_fn("md5", std_lib::encode::md5);
+            encode_object.register_native_fn("uuid", std_lib::encode::uuid);
+        }
+        std_object.set_property("encode".to_string(), Value::object(encode_object));
+
+        // std.regex
+        let mut regex_object = Object::new("regex");
+        regex_object.register_native_fn("is_match", std_lib::regex::is_match);
+        regex_object.register_native_fn("find", std_lib::regex::find);
+        regex_object.register_native_fn("find_all", std_lib::regex::find_all);
+        regex_object.register_native_fn("replace", std_lib::regex::replace);
+        regex_object.register_native_fn("captures", std_lib::regex::captures);
+        std_object.set_property("regex".to_string(), Value::object(regex_object));
+
+        // std.http
+        let mut http_object = Object::new("http");
+        http_object.register_native_fn("serve", std_lib::http_server::serve);
+        http_object.register_native_fn("serve_background", std_lib::http_server::serve_background);
+        std_object.set_property("http".to_string(), Value::object(http_object));
+
+        // std.db
+        let mut db_object = Object::new("db");
+        db_object.register_native_fn("open", std_lib::db::open);
+        db_object.register_native_fn("insert", std_lib::db::insert);
+        db_object.register_native_fn("size", std_lib::db::size);
+        db_object.register_native_fn("query", std_lib::db::query);
+        db_object.register_native_fn("close", std_lib::db::close);
+        std_object.set_property("db".to_string(), Value::object(db_object));
 
         self.objects.insert("std".to_string(), std_object);
+
+        // Program metadata; `file` is filled in by whoever runs the script
+        let mut meta_object = Object::new("meta");
+        meta_object.set_property("file".to_string(), Value::String("<eval>".to_string()));
+        meta_object.set_property(
+            "version".to_string(),
+            Value::String(env!("CARGO_PKG_VERSION").to_string()),
+        );
+        meta_object.register_native_fn("line", std_lib::meta::line);
+        meta_object.register_native_fn("function", std_lib::meta::function);
+        self.objects.insert("meta".to_string(), meta_object);
     }
 
     pub fn create_child(&self) -> Environment {
@@ -165,9 +655,26 @@ impl Environment {
         self.objects.get("global")?.get_property(name)
     }
 
+    /// `get_variable` for an identifier, by the slot it was last found at
+    /// if that still holds its name.
+    pub fn resolve(&self, ident: &Ident) -> Option<&Value> {
+        self.objects.get("global")?.properties.get_ident(ident)
+    }
+
+    pub fn resolve_mut(&mut self, ident: &Ident) -> Option<&mut Value> {
+        self.objects
+            .get_mut("global")?
+            .properties
+            .get_ident_mut(ident)
+    }
+
+    pub fn get_variable_mut(&mut self, name: &str) -> Option<&mut Value> {
+        self.objects.get_mut("global")?.get_property_mut(name)
+    }
+
     pub fn set_variable(&mut self, name: String, value: Value) {
         if let Some(global) = self.objects.get_mut("global") {
-            global.set_property(name.clone(), value);
+            global.set_property(name, value);
         }
     }
 
@@ -179,8 +686,10 @@ impl Environment {
         self.objects.get_mut(name)
     }
 
+    /// Functions share the global scope with variables, so this is the same
+    /// lookup as `get_variable`.
     pub fn get_global_function(&self, name: &str) -> Option<&Value> {
-        self.objects.get("global")?.get_property(name)
+        self.get_variable(name)
     }
 
     pub fn set_global_function(&mut self, name: String, params: Vec<String>, body: Vec<Stmt>) {
@@ -192,59 +701,532 @@ impl Environment {
 
 pub struct Interpreter {
     pub env: Environment,
+    hooks: Hooks,
+    cancelled: Arc<AtomicBool>,
+    import_base: Option<PathBuf>,
+    max_call_depth: usize,
+    current_call_depth: usize,
+    // Shared with function scopes; closed once the last scope is dropped
+    resources: Rc<RefCell<Resources>>,
+    // Compiled `std.regex` patterns, shared with function scopes
+    regex_cache: Rc<RefCell<HashMap<String, Regex>>>,
+    // Value of the last top-level expression statement, see `eval_source`
+    last_result: Value,
+    capture_last_result: bool,
+    // Set once the script binds `_` itself, which then wins over capture
+    underscore_user_bound: bool,
+    // Object whose method is being called, see `current_receiver`
+    receiver: Option<ObjectRef>,
+    receiver_taken: bool,
+    // Shared with function scopes, see `set_memory_limit`
+    memory: Rc<MemoryMeter>,
+    // Statement being executed and the user function it belongs to, for `meta`
+    current_span: Span,
+    current_function: Option<String>,
+    // Expressions being evaluated inside each other in this scope
+    expression_depth: usize,
+    // Shared with function scopes, carries errors to `catch`
+    unwinding: Rc<RefCell<Unwinding>>,
+    // Modules run so far, shared with function scopes so each runs once
+    imported: Rc<RefCell<HashSet<PathBuf>>>,
 }
 
+/// Default for `Interpreter::set_max_call_depth`.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 500;
+
+/// Expressions evaluated inside each other before evaluation fails. Chains
+/// like `a + b + ...` are evaluated in a loop and count as one level, so
+/// this is above the parser's default nesting limit.
+const MAX_EXPRESSION_DEPTH: usize = 1_000;
+
+/// Operators in an `a + b + ...` chain from which it is evaluated in a loop
+/// rather than by recursion.
+const LONG_OPERATOR_CHAIN: usize = 16;
+
+/// Compiled patterns kept before the regex cache starts over, so scripts
+/// building patterns in a loop don't grow it without bound.
+const REGEX_CACHE_LIMIT: usize = 256;
+
 impl Interpreter {
     pub fn new() -> Self {
         Interpreter {
             env: Environment::new(),
+            hooks: Hooks::default(),
+            cancelled: Arc::new(AtomicBool::new(false)),
+            import_base: None,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            current_call_depth: 0,
+            resources: Rc::default(),
+            regex_cache: Rc::default(),
+            last_result: Value::Void,
+            capture_last_result: false,
+            underscore_user_bound: false,
+            receiver: None,
+            receiver_taken: false,
+            memory: Rc::default(),
+            current_span: Span::default(),
+            current_function: None,
+            expression_depth: 0,
+            unwinding: Rc::default(),
+            imported: Rc::default(),
         }
     }
 
     pub fn create_child(&self) -> Interpreter {
         Interpreter {
             env: self.env.create_child(),
+            hooks: Hooks::default(),
+            cancelled: self.cancelled.clone(),
+            import_base: self.import_base.clone(),
+            max_call_depth: self.max_call_depth,
+            current_call_depth: self.current_call_depth,
+            resources: self.resources.clone(),
+            regex_cache: self.regex_cache.clone(),
+            last_result: Value::Void,
+            capture_last_result: false,
+            underscore_user_bound: false,
+            receiver: None,
+            receiver_taken: false,
+            memory: self.memory.clone(),
+            current_span: self.current_span,
+            current_function: self.current_function.clone(),
+            expression_depth: 0,
+            unwinding: self.unwinding.clone(),
+            imported: self.imported.clone(),
+        }
+    }
+
+    /// Directory that relative module paths are resolved against, usually
+    /// the directory of the script being run.
+    pub fn set_import_base(&mut self, dir: PathBuf) {
+        self.import_base = Some(dir);
+    }
+
+    pub fn import_base(&self) -> Option<&Path> {
+        self.import_base.as_deref()
+    }
+
+    /// Canonical paths of the modules imported so far, including those
+    /// imported by other modules. A module whose run failed is left out.
+    pub fn imported_modules(&self) -> Vec<PathBuf> {
+        self.imported.borrow().iter().cloned().collect()
+    }
+
+    /// Registers a host resource and returns the handle scripts refer to it
+    /// by. `close` runs on `close_resource` or when the interpreter is dropped.
+    pub fn register_resource<T: Any>(&mut self, value: T, close: impl FnOnce(T) + 'static) -> u64 {
+        self.resources.borrow_mut().register(value, close)
+    }
+
+    /// Runs `f` on the open resource `id`; `None` if it is closed, unknown or
+    /// not a `T`.
+    pub fn with_resource<T: Any, R>(&mut self, id: u64, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        self.resources.borrow_mut().get_mut(id).map(f)
+    }
+
+    /// Closes resource `id`. Returns false if it was not open.
+    pub fn close_resource(&mut self, id: u64) -> bool {
+        self.resources.borrow_mut().close(id)
+    }
+
+    /// Runs shared helper code before the main program. Its definitions land
+    /// in the global scope, where the main program may shadow them; `name`
+    /// (usually the file path) identifies the prelude in errors and is what
+    /// `meta.file` reads while it runs.
+    pub fn load_prelude(&mut self, source: &str, name: &str) -> Result<(), PreludeError> {
+        let script_file = self.script_file();
+        self.set_script_file(name);
+        let result = crate::run_source_with(source, self);
+        self.set_script_file(&script_file);
+        result.map_err(|error| PreludeError {
+            name: name.to_string(),
+            error,
+        })
+    }
+
+    /// Sets what scripts read from `meta.file`, usually the path of the
+    /// script being run. Defaults to `"<eval>"`.
+    pub fn set_script_file(&mut self, file: &str) {
+        if let Some(meta) = self.env.get_object_mut("meta") {
+            meta.set_property("file".to_string(), Value::String(file.to_string()));
+        }
+        if let Some(coverage) = self.hooks.coverage.as_mut() {
+            coverage.set_file(file.to_string());
+        }
+    }
+
+    pub fn script_file(&self) -> String {
+        match self
+            .env
+            .get_object("meta")
+            .and_then(|meta| meta.get_property("file"))
+        {
+            Some(Value::String(file)) => file.clone(),
+            _ => "<eval>".to_string(),
+        }
+    }
+
+    /// Line of the statement being executed, 0 before the first one.
+    pub fn current_line(&self) -> u32 {
+        self.current_span.line
+    }
+
+    /// Name of the innermost user function being executed, `None` at the
+    /// top level.
+    pub fn current_function(&self) -> Option<&str> {
+        self.current_function.as_deref()
+    }
+
+    /// Compiles `pattern`, or returns the compiled form from an earlier call.
+    pub fn regex(&mut self, pattern: &str) -> Result<Regex, regex::Error> {
+        let mut cache = self.regex_cache.borrow_mut();
+        if let Some(regex) = cache.get(pattern) {
+            return Ok(regex.clone());
+        }
+        let regex = Regex::new(pattern)?;
+        if cache.len() >= REGEX_CACHE_LIMIT {
+            cache.clear();
+        }
+        cache.insert(pattern.to_string(), regex.clone());
+        Ok(regex)
+    }
+
+    /// Copies the global state (variables, functions, environment objects)
+    /// so it can be rolled back to with `restore`.
+    pub fn snapshot(&self) -> StateSnapshot {
+        StateSnapshot::capture(&self.env)
+    }
+
+    /// Replaces the global state with `snapshot`. Open resources, hooks and
+    /// settings are left as they are.
+    pub fn restore(&mut self, snapshot: StateSnapshot) {
+        snapshot.apply(&mut self.env);
+    }
+
+    /// Reads a snapshot serialized with serde, linking the natives it
+    /// refers to with the ones registered here.
+    pub fn deserialize_snapshot<'de, D: serde::Deserializer<'de>>(
+        &self,
+        deserializer: D,
+    ) -> Result<StateSnapshot, D::Error> {
+        StateSnapshot::deserialize(&self.env, deserializer)
+    }
+
+    /// Registers `std.exec` and `std.shell`, which run external programs
+    /// with the host's privileges. Off by default; only enable it for
+    /// scripts you would run from a shell yourself.
+    pub fn enable_process_spawning(&mut self) {
+        if let Some(std_object) = self.env.get_object_mut("std") {
+            std_object.register_native_fn("exec", std_lib::process::exec);
+            std_object.register_native_fn("shell", std_lib::process::shell);
+        }
+    }
+
+    /// Number of nested user function calls allowed before a call fails with
+    /// a stack overflow error instead of overflowing the Rust stack.
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
+    /// Makes an allocating operation (an assignment, a native growing an
+    /// array) fail with a runtime error once the script's values take up
+    /// more than about `bytes`. The estimate is rough: it counts string
+    /// lengths, array elements and object properties, not the allocator's
+    /// real usage.
+    pub fn set_memory_limit(&mut self, bytes: usize) {
+        self.memory.set_limit(Some(bytes));
+    }
+
+    /// Counts `bytes` a native is about to allocate against the memory
+    /// limit, failing before the allocation when it would not fit. Natives
+    /// that build arrays or strings from a size argument call this first.
+    pub fn reserve_memory(&mut self, bytes: usize) -> Result<(), String> {
+        self.memory.charge(bytes, &self.env)
+    }
+
+    /// Estimated bytes taken by the values the running scope can reach, as
+    /// `set_memory_limit` counts them.
+    pub fn memory_used(&self) -> usize {
+        self.memory.recount(&self.env)
+    }
+
+    /// Flag that stops execution with an error before the next statement or
+    /// loop iteration once set. It may be set from another thread.
+    pub fn cancel_handle(&self) -> Arc<AtomicBool> {
+        self.cancelled.clone()
+    }
+
+    /// Returns the message of `error` to pass up like any other error,
+    /// keeping its code for `runtime_error`.
+    fn raise(&self, error: RuntimeError) -> String {
+        self.unwinding.borrow_mut().coded(error)
+    }
+
+    /// Pairs the message of an error `interpret` returned with its code.
+    pub fn runtime_error(&self, message: String) -> RuntimeError {
+        RuntimeError {
+            code: self.unwinding.borrow().code(&message),
+            message,
+        }
+    }
+
+    fn check_cancelled(&self) -> Result<(), String> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            let message = "Execution cancelled".to_string();
+            return Err(self.unwinding.borrow_mut().uncatchable(message));
+        }
+        Ok(())
+    }
+
+    /// Installs a callback that receives a `TraceEvent` for every executed
+    /// statement, user function call and variable assignment.
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.hooks.tracer = Some(tracer);
+    }
+
+    pub fn clear_tracer(&mut self) {
+        self.hooks.tracer = None;
+    }
+
+    /// Only builds the event when a tracer is installed.
+    fn trace(&mut self, event: impl FnOnce() -> TraceEvent) {
+        if let Some(tracer) = self.hooks.tracer.as_mut() {
+            tracer(event());
+        }
+    }
+
+    /// Attaches a debugger whose handler is called, blocking execution,
+    /// whenever a breakpoint is hit or a step completes.
+    pub fn set_debugger(&mut self, debugger: Debugger) {
+        self.hooks.debugger = Some(debugger);
+    }
+
+    /// Shorthand for attaching a debugger with `on_pause` as its handler.
+    pub fn enable_debugger(&mut self, on_pause: PauseHandler) {
+        self.set_debugger(Debugger::new(on_pause));
+    }
+
+    pub fn debugger_mut(&mut self) -> Option<&mut Debugger> {
+        self.hooks.debugger.as_mut()
+    }
+
+    /// Breakpoints are line numbers; they require a debugger to be attached.
+    pub fn set_breakpoint(&mut self, line: u32) {
+        if let Some(debugger) = self.hooks.debugger.as_mut() {
+            debugger.set_breakpoint(line);
+        }
+    }
+
+    pub fn clear_breakpoint(&mut self, line: u32) {
+        if let Some(debugger) = self.hooks.debugger.as_mut() {
+            debugger.clear_breakpoint(line);
+        }
+    }
+
+    /// Values visible in the current scope, excluding functions.
+    pub fn snapshot_scope(&self) -> HashMap<String, Value> {
+        self.env
+            .get_object("global")
+            .map(|global| {
+                global
+                    .properties
+                    .iter()
+                    .filter(|(_, value)| {
+                        !matches!(value, Value::Function(..) | Value::NativeFunction(..))
+                    })
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn check_debugger(&mut self, span: Span) -> Result<(), String> {
+        // Taken out so the pause handler can inspect the interpreter.
+        if let Some(mut debugger) = self.hooks.debugger.take() {
+            let result = debugger.check(self, span);
+            self.hooks.debugger = Some(debugger);
+            result.map_err(|message| self.unwinding.borrow_mut().uncatchable(message))?;
+        }
+        Ok(())
+    }
+
+    /// Starts or stops recording per-function call counts and timings.
+    /// Disabling discards what was recorded so far.
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.hooks.profiler = if enabled {
+            Some(self.hooks.profiler.take().unwrap_or_default())
+        } else {
+            None
+        };
+    }
+
+    pub fn profile_report(&self) -> Option<ProfileReport> {
+        self.hooks.profiler.as_ref().map(Profiler::report)
+    }
+
+    /// Starts or stops counting how often each statement runs. Statements
+    /// of programs interpreted while enabled are listed even if they never
+    /// run. Disabling discards the counts.
+    pub fn set_coverage(&mut self, enabled: bool) {
+        self.hooks.coverage = if enabled {
+            let file = self.script_file();
+            Some(
+                self.hooks
+                    .coverage
+                    .take()
+                    .unwrap_or_else(|| Coverage::new(file)),
+            )
+        } else {
+            None
+        };
+    }
+
+    pub fn coverage_report(&self) -> Option<CoverageReport> {
+        self.hooks.coverage.as_ref().map(Coverage::report)
+    }
+
+    /// Counts the body of `function` in the file it was defined in,
+    /// returning the file to go back to once it returns.
+    fn enter_coverage_file(&mut self, function: &str) -> Option<String> {
+        self.hooks.coverage.as_mut()?.enter_function(function)
+    }
+
+    fn leave_coverage_file(&mut self, file: Option<String>) {
+        if let (Some(coverage), Some(file)) = (self.hooks.coverage.as_mut(), file) {
+            coverage.set_file(file);
+        }
+    }
+
+    /// Sends `print` output to `output` instead of stdout.
+    pub fn set_output(&mut self, output: Output) {
+        self.hooks.output = Some(output);
+    }
+
+    /// Writes one line of script output to the installed sink, or stdout.
+    pub fn write_line(&mut self, line: &str) {
+        match self.hooks.output.as_mut() {
+            Some(output) => output(line),
+            None => println!("{}", line),
         }
     }
 
+    /// Sends `std.log` entries to `sink` instead of stderr.
+    pub fn set_log_sink(&mut self, sink: Box<dyn LogSink>) {
+        self.hooks.log_sink = Some(sink);
+    }
+
+    /// Entries below `level` are dropped. Scripts can change it with
+    /// `std.log.set_level`.
+    pub fn set_log_level(&mut self, level: LogLevel) {
+        self.hooks.log_level = level;
+    }
+
+    pub fn log_level(&self) -> LogLevel {
+        self.hooks.log_level
+    }
+
+    /// Passes a log entry to the installed sink, or stderr, unless it is
+    /// below the log level.
+    pub fn log(&mut self, level: LogLevel, message: &str) {
+        if level < self.hooks.log_level {
+            return;
+        }
+        match self.hooks.log_sink.as_mut() {
+            Some(sink) => sink.log(level, message),
+            None => StderrSink.log(level, message),
+        }
+    }
+
+    /// When on, the value of every top-level expression statement is bound
+    /// to `_` in the global scope, as a REPL would. Off by default so scripts
+    /// don't see a variable they never declared. A script's own `let _`
+    /// takes precedence over the captured value.
+    pub fn set_capture_last_result(&mut self, capture: bool) {
+        self.capture_last_result = capture;
+    }
+
+    /// Takes the value of the last top-level expression statement run,
+    /// leaving `Void` behind.
+    pub fn take_last_result(&mut self) -> Value {
+        std::mem::replace(&mut self.last_result, Value::Void)
+    }
+
+    fn record_result(&mut self, value: Value) {
+        if self.capture_last_result && !self.underscore_user_bound {
+            self.env.set_variable("_".to_string(), value.clone());
+        }
+        self.last_result = value;
+    }
+
     pub fn interpret(&mut self, program: &Program) -> Result<(), String> {
+        if let Some(coverage) = self.hooks.coverage.as_mut() {
+            coverage.register(program);
+        }
         for stmt in &program.statements {
+            if let StmtKind::Expression(expr) = &stmt.kind {
+                self.enter_statement(stmt)?;
+                let value = self.evaluate_expression(expr)?;
+                self.record_result(value);
+                continue;
+            }
             match self.execute_statement(stmt)? {
                 ControlFlow::None => continue,
                 ControlFlow::Return(_) => {
                     // Top-level return, we can ignore or handle as needed
                     continue;
                 }
+                ControlFlow::Break => return Err(errors::break_outside_loop()),
             }
         }
         Ok(())
     }
 
+    /// Tracing, cancellation and breakpoints, run before every statement.
+    fn enter_statement(&mut self, stmt: &Stmt) -> Result<(), String> {
+        self.current_span = stmt.span;
+        self.trace(|| TraceEvent::StatementEntered {
+            span: stmt.span,
+            kind: stmt.kind.name(),
+        });
+        if let Some(coverage) = self.hooks.coverage.as_mut() {
+            coverage.hit(stmt.span);
+        }
+        self.check_cancelled()?;
+        self.check_debugger(stmt.span)
+    }
+
     pub fn execute_statement(&mut self, stmt: &Stmt) -> Result<ControlFlow, String> {
-        match stmt {
-            Stmt::Let { name, value } => {
+        self.enter_statement(stmt)?;
+
+        match &stmt.kind {
+            StmtKind::Let { name, value } => {
                 let val = self.evaluate_expression(value)?;
-                self.env.set_variable(name.clone(), val);
+                self.assign_variable(name, val)?;
                 Ok(ControlFlow::None)
             }
-            Stmt::Assign { name, value } => {
-                if self.env.get_variable(name).is_none() {
-                    return Err(format!("Cannot assign to undefined variable: {}", name));
+            StmtKind::LetPattern { pattern, value } => {
+                let val = self.evaluate_expression(value)?;
+                self.bind_pattern(pattern, val)?;
+                Ok(ControlFlow::None)
+            }
+            StmtKind::Assign { name, value } => {
+                if self.env.resolve(name).is_none() {
+                    return Err(self.raise(errors::assign_to_undefined(name)));
                 }
                 let val = self.evaluate_expression(value)?;
-                self.env.set_variable(name.clone(), val);
+                self.reassign_variable(name, val)?;
                 Ok(ControlFlow::None)
             }
-            Stmt::Function { name, params, body } => {
+            StmtKind::Function { name, params, body } => {
                 self.env
                     .set_global_function(name.clone(), params.clone(), body.clone());
                 Ok(ControlFlow::None)
             }
-            Stmt::Return(expr) => {
+            StmtKind::Return(expr) => {
                 let val = self.evaluate_expression(expr)?;
                 Ok(ControlFlow::Return(val))
             }
-            Stmt::If {
+            StmtKind::If {
                 condition,
                 then_branch,
                 else_branch,
@@ -259,59 +1241,292 @@ impl Interpreter {
                     Ok(ControlFlow::None)
                 }
             }
-            Stmt::While { condition, body } => {
-                while self.evaluate_expression(condition)?.to_bool() {
+            StmtKind::While { condition, body } => self.execute_while(condition, body),
+            StmtKind::For {
+                name,
+                iterable,
+                body,
+            } => self.execute_for(name, iterable, body),
+            StmtKind::Break => Ok(ControlFlow::Break),
+            StmtKind::Import(name) => self.execute_import(name),
+            StmtKind::Try {
+                body,
+                error_name,
+                handler,
+            } => self.execute_try(body, error_name, handler, stmt.span),
+            StmtKind::Throw(expr) => {
+                let value = self.evaluate_expression(expr)?;
+                Err(self.unwinding.borrow_mut().thrown(value, stmt.span.line))
+            }
+            StmtKind::Expression(expr) => {
+                self.evaluate_expression(expr)?;
+                Ok(ControlFlow::None)
+            }
+        }
+    }
+
+    /// Runs `body`; if it fails with an error a `catch` may stop, binds
+    /// the error to `error_name` and runs `handler` instead.
+    fn execute_try(
+        &mut self,
+        body: &[Stmt],
+        error_name: &str,
+        handler: &[Stmt],
+        span: Span,
+    ) -> Result<ControlFlow, String> {
+        let message = match self.execute_block(body) {
+            Ok(flow) => return Ok(flow),
+            Err(message) => message,
+        };
+        let caught = self.unwinding.borrow_mut().catch(&message, span.line);
+        match caught {
+            Some(error) => {
+                self.assign_variable(error_name, error)?;
+                self.execute_block(handler)
+            }
+            None => Err(message),
+        }
+    }
+
+    // Loops live outside `execute_statement`: every nested user function
+    // call pays for that function's stack frame, so it is kept small.
+    fn execute_while(&mut self, condition: &Expr, body: &[Stmt]) -> Result<ControlFlow, String> {
+        while self.evaluate_expression(condition)?.to_bool() {
+            // an empty body runs no statements, so check here as well
+            self.check_cancelled()?;
+            match self.execute_block(body)? {
+                ControlFlow::Return(value) => return Ok(ControlFlow::Return(value)),
+                ControlFlow::Break => break,
+                ControlFlow::None => continue,
+            }
+        }
+        Ok(ControlFlow::None)
+    }
+
+    /// Runs the module `name` resolves to from the import base (or the
+    /// working directory), unless it already ran. Its definitions land in
+    /// the current scope; its own imports resolve from its directory.
+    fn execute_import(&mut self, name: &str) -> Result<ControlFlow, String> {
+        let base = self.import_base.clone().unwrap_or_default();
+        let path = crate::modules::resolve(&base, name)?;
+        let key = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if !self.imported.borrow_mut().insert(key.clone()) {
+            return Ok(ControlFlow::None);
+        }
+        // Recorded while it runs, so imports going in a circle stop, but
+        // forgotten if it fails, so importing it again runs it again
+        let result = self.run_module(&path);
+        if result.is_err() {
+            self.imported.borrow_mut().remove(&key);
+        }
+        result
+    }
+
+    fn run_module(&mut self, path: &Path) -> Result<ControlFlow, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        let program = crate::lexer::tokenize(source)
+            .and_then(|tokens| crate::parser::parse(&tokens))
+            .map_err(|e| format!("in module {}: {}", path.display(), e))?;
+
+        let script_file = self.script_file();
+        self.set_script_file(&path.display().to_string());
+        if let Some(coverage) = self.hooks.coverage.as_mut() {
+            coverage.register(&program);
+        }
+        let outer_base =
+            std::mem::replace(&mut self.import_base, path.parent().map(Path::to_path_buf));
+        let result = self.execute_block(&program.statements);
+        self.import_base = outer_base;
+        self.set_script_file(&script_file);
+        match result? {
+            ControlFlow::Break => Err(errors::break_outside_loop()),
+            _ => Ok(ControlFlow::None),
+        }
+    }
+
+    /// Runs `body` once for each element of an array, or for each value of
+    /// an object with `has_next()` and `next()` methods, bound to `name`.
+    /// Arrays are iterated as they were when the loop started.
+    fn execute_for(
+        &mut self,
+        name: &str,
+        iterable: &Expr,
+        body: &[Stmt],
+    ) -> Result<ControlFlow, String> {
+        let iterable = self.evaluate_expression(iterable)?;
+        match &iterable {
+            Value::Array(items) => {
+                let items = items.borrow().clone();
+                for item in items {
+                    self.check_cancelled()?;
+                    self.assign_variable(name, item)?;
+                    match self.execute_block(body)? {
+                        ControlFlow::Return(value) => return Ok(ControlFlow::Return(value)),
+                        ControlFlow::Break => break,
+                        ControlFlow::None => continue,
+                    }
+                }
+            }
+            Value::Object(object) => {
+                let method = |name: &str| {
+                    object.borrow().get_property(name).cloned().ok_or_else(|| {
+                        format!("Cannot iterate over an object without a {}() method", name)
+                    })
+                };
+                let has_next = method("has_next")?;
+                let next = method("next")?;
+                loop {
+                    self.check_cancelled()?;
+                    if !self.call_method(object, &has_next, vec![])?.to_bool() {
+                        break;
+                    }
+                    let item = self.call_method(object, &next, vec![])?;
+                    self.assign_variable(name, item)?;
                     match self.execute_block(body)? {
                         ControlFlow::Return(value) => return Ok(ControlFlow::Return(value)),
+                        ControlFlow::Break => break,
                         ControlFlow::None => continue,
                     }
                 }
-                Ok(ControlFlow::None)
             }
-            Stmt::Expression(expr) => {
-                self.evaluate_expression(expr)?;
-                Ok(ControlFlow::None)
+            other => return Err(format!("Cannot iterate over a {}", other.type_name())),
+        }
+        Ok(ControlFlow::None)
+    }
+
+    fn assign_variable(&mut self, name: &str, value: Value) -> Result<(), String> {
+        self.prepare_assignment(name, &value)?;
+        // Reassignment, the common case in loops, reuses the existing entry
+        match self.env.get_variable_mut(name) {
+            Some(slot) => *slot = value,
+            None => self.env.set_variable(name.to_string(), value),
+        }
+        Ok(())
+    }
+
+    /// `assign_variable` for the target of an assignment statement, found
+    /// by the slot it was last assigned at.
+    fn reassign_variable(&mut self, name: &Ident, value: Value) -> Result<(), String> {
+        self.prepare_assignment(name, &value)?;
+        match self.env.resolve_mut(name) {
+            Some(slot) => *slot = value,
+            None => self.env.set_variable(name.to_string(), value),
+        }
+        Ok(())
+    }
+
+    fn prepare_assignment(&mut self, name: &str, value: &Value) -> Result<(), String> {
+        self.trace(|| TraceEvent::VariableAssigned {
+            name: name.to_string(),
+            value: value.clone(),
+        });
+        self.memory.charge(memory::shallow_size(value), &self.env)?;
+        if name == "_" {
+            self.underscore_user_bound = true;
+        }
+        Ok(())
+    }
+
+    /// Binds every name in `pattern` to the matching part of `value`.
+    /// Missing object fields and array elements are errors.
+    fn bind_pattern(&mut self, pattern: &Pattern, value: Value) -> Result<(), String> {
+        match pattern {
+            Pattern::Identifier(name) => self.assign_variable(name, value),
+            Pattern::Object(fields) => {
+                let object = match value {
+                    Value::Object(object) => object,
+                    other => return Err(format!("Cannot destructure {} as an object", other)),
+                };
+                for (field, field_pattern) in fields {
+                    let field_value = object
+                        .borrow()
+                        .get_property(field)
+                        .cloned()
+                        .ok_or_else(|| format!("Cannot destructure missing field '{}'", field))?;
+                    self.bind_pattern(field_pattern, field_value)?;
+                }
+                Ok(())
+            }
+            Pattern::Array(elements) => {
+                let values = match value {
+                    Value::Array(values) => values.borrow().clone(),
+                    other => return Err(format!("Cannot destructure {} as an array", other)),
+                };
+                if values.len() < elements.len() {
+                    return Err(format!(
+                        "Cannot destructure {} elements from an array of length {}",
+                        elements.len(),
+                        values.len()
+                    ));
+                }
+                for (element_pattern, element) in elements.iter().zip(values) {
+                    self.bind_pattern(element_pattern, element)?;
+                }
+                Ok(())
             }
         }
     }
 
     fn execute_block(&mut self, statements: &[Stmt]) -> Result<ControlFlow, String> {
         for stmt in statements {
-            match self.execute_statement(stmt)? {
-                ControlFlow::Return(value) => return Ok(ControlFlow::Return(value)),
+            let flow = match self.execute_statement(stmt) {
+                Ok(flow) => flow,
+                Err(message) => {
+                    self.unwinding.borrow_mut().raised(&message, stmt.span.line);
+                    return Err(message);
+                }
+            };
+            match flow {
                 ControlFlow::None => continue,
+                flow => return Ok(flow),
             }
         }
         Ok(ControlFlow::None)
     }
 
     fn evaluate_expression(&mut self, expr: &Expr) -> Result<Value, String> {
+        // The parser limits nesting, but ASTs built in Rust can be any depth
+        if self.expression_depth >= MAX_EXPRESSION_DEPTH {
+            return Err("Expression nesting too deep".to_string());
+        }
+        self.expression_depth += 1;
+        let result = self.evaluate_nested_expression(expr);
+        self.expression_depth -= 1;
+        result
+    }
+
+    fn evaluate_nested_expression(&mut self, expr: &Expr) -> Result<Value, String> {
         match expr {
             Expr::Number(n) => Ok(Value::Number(*n)),
             Expr::String(s) => Ok(Value::String(s.clone())),
             Expr::Identifier(name) => {
-                // First check variables
-                if let Some(value) = self.env.get_variable(name) {
+                // Variables and functions, whichever was bound last. A function
+                // is bound when its `fn` statement runs, so code that runs
+                // before that sees it as undefined.
+                if let Some(value) = self.env.resolve(name) {
                     return Ok(value.clone());
                 }
 
-                // Then check global functions
-                if let Some(func) = self.env.get_global_function(name) {
-                    return Ok(func.clone());
-                }
-
-                // Finally check if it's an object
+                // Then check if it's an object
                 if let Some(obj) = self.env.get_object(name) {
-                    return Ok(Value::Object(obj.clone()));
+                    return Ok(Value::object(obj.clone()));
                 }
 
-                Err(format!("Undefined identifier: {}", name))
+                Err(self.raise(errors::undefined_variable(name)))
             }
             Expr::Binary { left, op, right } => self.evaluate_binary_op(left, op, right),
-            Expr::FunctionCall { name, args } => self.evaluate_function_call(name, args),
+            Expr::FunctionCall {
+                name,
+                args,
+                arg_names,
+            } => self.evaluate_function_call(name, args, arg_names),
             Expr::ObjectCall(object_name, member_expr) => {
                 self.evaluate_object_call(object_name, member_expr)
             }
+            Expr::MemberAccess(target, member_expr) => {
+                self.evaluate_member_access(target, member_expr)
+            }
         }
     }
 
@@ -321,20 +1536,81 @@ impl Interpreter {
         op: &BinaryOp,
         right: &Expr,
     ) -> Result<Value, String> {
-        let left_val = self.evaluate_expression(left)?;
+        // Variables are read in place when neither side can run code that
+        // changes them, which is what loop conditions and counters look like
+        if let (Some(l), Some(r)) = (self.peek_operand(left), self.peek_operand(right)) {
+            return self.binary_values(&l, op, &r);
+        }
+        // `a + b + c` nests to the left, one level per operator. Short
+        // chains recurse, long ones are walked in a loop so that they run
+        // into neither `MAX_EXPRESSION_DEPTH` nor the end of the stack.
+        let mut first = left;
+        let mut length = 0;
+        while let Expr::Binary { left, .. } = first {
+            length += 1;
+            first = left;
+        }
+        if length < LONG_OPERATOR_CHAIN {
+            let left_val = self.evaluate_expression(left)?;
+            return self.apply_operator(left_val, op, right);
+        }
+
+        let mut rest = vec![(op, right)];
+        let mut first = left;
+        while let Expr::Binary { left, op, right } = first {
+            rest.push((op, right));
+            first = left;
+        }
+        let mut value = self.evaluate_expression(first)?;
+        for (op, right) in rest.into_iter().rev() {
+            value = self.apply_operator(value, op, right)?;
+        }
+        Ok(value)
+    }
+
+    /// `left op right` for an already evaluated `left`.
+    fn apply_operator(
+        &mut self,
+        left: Value,
+        op: &BinaryOp,
+        right: &Expr,
+    ) -> Result<Value, String> {
+        if let Some(right_val) = self.peek_operand(right) {
+            return self.binary_values(&left, op, &right_val);
+        }
         let right_val = self.evaluate_expression(right)?;
+        self.binary_values(&left, op, &right_val)
+    }
 
-        match (left_val, right_val) {
-            (Value::Number(l), Value::Number(r)) => {
+    /// The value of a bound identifier or a number literal, without
+    /// evaluating or cloning it.
+    fn peek_operand<'a>(&'a self, expr: &'a Expr) -> Option<Cow<'a, Value>> {
+        match expr {
+            Expr::Identifier(name) => self.env.resolve(name).map(Cow::Borrowed),
+            Expr::Number(n) => Some(Cow::Owned(Value::Number(*n))),
+            _ => None,
+        }
+    }
+
+    fn binary_values(&self, left: &Value, op: &BinaryOp, right: &Value) -> Result<Value, String> {
+        match (left, right) {
+            (&Value::Number(l), &Value::Number(r)) => {
                 let result = match op {
-                    BinaryOp::Add => l + r,
-                    BinaryOp::Subtract => l - r,
-                    BinaryOp::Multiply => l * r,
+                    BinaryOp::Add => l
+                        .checked_add(r)
+                        .ok_or_else(|| format!("Integer overflow in {} + {}", l, r))?,
+                    BinaryOp::Subtract => l
+                        .checked_sub(r)
+                        .ok_or_else(|| format!("Integer overflow in {} - {}", l, r))?,
+                    BinaryOp::Multiply => l
+                        .checked_mul(r)
+                        .ok_or_else(|| format!("Integer overflow in {} * {}", l, r))?,
                     BinaryOp::Divide => {
                         if r == 0 {
-                            return Err("Division by zero".to_string());
+                            return Err(self.raise(errors::division_by_zero()));
                         }
-                        l / r
+                        l.checked_div(r)
+                            .ok_or_else(|| format!("Integer overflow in {} / {}", l, r))?
                     }
                     BinaryOp::Equal => {
                         if l == r {
@@ -381,31 +1657,47 @@ impl Interpreter {
                 };
                 Ok(Value::Number(result))
             }
+            (&Value::Float(l), &Value::Float(r)) => float_op(l, op, r).map_err(|e| self.raise(e)),
+            (&Value::Number(l), &Value::Float(r)) => {
+                float_op(l as f64, op, r).map_err(|e| self.raise(e))
+            }
+            (&Value::Float(l), &Value::Number(r)) => {
+                float_op(l, op, r as f64).map_err(|e| self.raise(e))
+            }
             (Value::String(l), Value::String(r)) => match op {
-                BinaryOp::Add => Ok(Value::String(format!("{}{}", l, r))),
+                BinaryOp::Add => {
+                    self.memory
+                        .charge(l.len().saturating_add(r.len()), &self.env)?;
+                    Ok(Value::String(format!("{}{}", l, r)))
+                }
                 BinaryOp::Equal => Ok(Value::Number(if l == r { 1 } else { 0 })),
                 BinaryOp::NotEqual => Ok(Value::Number(if l != r { 1 } else { 0 })),
-                _ => Err(format!("Unsupported operation {:?} for strings", op)),
+                _ => Err(self.raise(errors::unsupported_operation(op, "strings"))),
             },
             (Value::String(l), r) => match op {
                 BinaryOp::Add => Ok(Value::String(format!("{}{}", l, r))),
-                _ => Err(format!(
-                    "Unsupported operation {:?} for string and {}",
-                    op, r
-                )),
+                _ => Err(self.raise(errors::unsupported_operation(
+                    op,
+                    &format!("string and {}", r),
+                ))),
             },
             (l, Value::String(r)) => match op {
                 BinaryOp::Add => Ok(Value::String(format!("{}{}", l, r))),
-                _ => Err(format!(
-                    "Unsupported operation {:?} for {} and string",
-                    op, l
-                )),
+                _ => Err(self.raise(errors::unsupported_operation(
+                    op,
+                    &format!("{} and string", l),
+                ))),
             },
-            _ => Err("Type mismatch in binary operation".to_string()),
+            _ => Err(self.raise(errors::type_mismatch())),
         }
     }
 
-    fn evaluate_function_call(&mut self, name: &str, args: &[Expr]) -> Result<Value, String> {
+    fn evaluate_function_call(
+        &mut self,
+        name: &str,
+        args: &[Expr],
+        arg_names: &[Option<String>],
+    ) -> Result<Value, String> {
         // Evaluate arguments
         let mut arg_values = Vec::new();
         for arg in args {
@@ -415,14 +1707,18 @@ impl Interpreter {
         // Check for global functions
         if let Some(func) = self.env.get_global_function(name).cloned() {
             match func {
-                Value::Function(_, params, body) => {
-                    self.call_user_function(&params, &body, arg_values)
+                Value::Function(fn_name, params, body) => {
+                    named_args::order(&params, &mut arg_values, arg_names)
+                        .map_err(|e| self.raise(e))?;
+                    self.call_user_function(&fn_name, &params, &body, arg_values)
+                }
+                Value::NativeFunction(fn_name, native_fn) => {
+                    self.call_native_function(&fn_name, native_fn, arg_values)
                 }
-                Value::NativeFunction(_, native_fn) => native_fn(self, arg_values),
                 _ => Err(format!("{} is not a function", name)),
             }
         } else {
-            Err(format!("Undefined function: {}", name))
+            Err(self.raise(errors::undefined_function(name)))
         }
     }
 
@@ -431,42 +1727,84 @@ impl Interpreter {
         object_name: &str,
         member_expr: &Expr,
     ) -> Result<Value, String> {
-        // Get the object
-        let object = self
-            .env
-            .get_object(object_name)
-            .ok_or_else(|| format!("Undefined object: {}", object_name))?
-            .clone();
+        // Variables first, as for plain identifiers, so a script's own
+        // `meta` or `global` shadows the built-in object of that name
+        if self.env.get_variable(object_name).is_none() {
+            if let Some(object) = self.env.get_object(object_name) {
+                // These are stored by value, so methods work on a copy that is
+                // stored back if a native took it as its receiver
+                let copy = Rc::new(RefCell::new(object.clone()));
+                let outer_taken = std::mem::replace(&mut self.receiver_taken, false);
+                let result = self.evaluate_member(&copy, member_expr);
+                if self.receiver_taken {
+                    if let Some(object) = self.env.get_object_mut(object_name) {
+                        *object = copy.borrow().clone();
+                    }
+                }
+                self.receiver_taken |= outer_taken;
+                return result;
+            }
+        }
+        let object = match self.env.get_variable(object_name) {
+            Some(Value::Object(object)) => object.clone(),
+            Some(other) => return Err(format!("'{}' is not an object: {}", object_name, other)),
+            None => return Err(format!("Undefined object: {}", object_name)),
+        };
+
+        self.evaluate_member(&object, member_expr)
+    }
+
+    /// Evaluates `target.member`, where `target` is any expression producing an object.
+    fn evaluate_member_access(
+        &mut self,
+        target: &Expr,
+        member_expr: &Expr,
+    ) -> Result<Value, String> {
+        match self.evaluate_expression(target)? {
+            Value::Object(object) => self.evaluate_member(&object, member_expr),
+            other => Err(format!("Cannot access a member of {}", other)),
+        }
+    }
 
-        // Handle the member expression
+    fn evaluate_member(&mut self, object: &ObjectRef, member_expr: &Expr) -> Result<Value, String> {
+        let object_name = object.borrow().name.clone();
         match member_expr {
             Expr::Identifier(prop_name) => {
                 // Simple property access: obj.prop
-                object.get_property(prop_name).cloned().ok_or_else(|| {
-                    format!(
-                        "Property '{}' not found on object '{}'",
-                        prop_name, object_name
-                    )
-                })
-            }
-            Expr::FunctionCall { name, args } => {
+                object
+                    .borrow()
+                    .properties
+                    .get_ident(prop_name)
+                    .cloned()
+                    .ok_or_else(|| {
+                        format!(
+                            "Property '{}' not found on object '{}'",
+                            prop_name, object_name
+                        )
+                    })
+            }
+            Expr::FunctionCall {
+                name,
+                args,
+                arg_names,
+            } => {
                 // Method call: obj.method(args)
-                let method = object.get_property(name).ok_or_else(|| {
+                let method = object.borrow().get_property(name).cloned().ok_or_else(|| {
                     format!("Method '{}' not found on object '{}'", name, object_name)
                 })?;
 
-                // Evaluate arguments
                 let mut arg_values = Vec::new();
                 for arg in args {
                     arg_values.push(self.evaluate_expression(arg)?);
                 }
 
-                // Call the method
-                match method.clone() {
-                    Value::Function(_, params, body) => {
-                        self.call_user_function(&params, &body, arg_values)
+                match &method {
+                    Value::Function(_, params, _) => {
+                        named_args::order(params, &mut arg_values, arg_names)
+                            .map_err(|e| self.raise(e))?;
+                        self.call_method(object, &method, arg_values)
                     }
-                    Value::NativeFunction(_, native_fn) => native_fn(self, arg_values),
+                    Value::NativeFunction(..) => self.call_method(object, &method, arg_values),
                     _ => Err(format!(
                         "'{}' is not a method on object '{}'",
                         name, object_name
@@ -475,18 +1813,20 @@ impl Interpreter {
             }
             Expr::ObjectCall(nested_obj, nested_member) => {
                 // Nested object call: obj.nested.member
-                // First get the nested object from the parent
-                let nested_value = object.get_property(nested_obj).ok_or_else(|| {
-                    format!(
-                        "Property '{}' not found on object '{}'",
-                        nested_obj, object_name
-                    )
-                })?;
+                let nested_value = object
+                    .borrow()
+                    .get_property(nested_obj)
+                    .cloned()
+                    .ok_or_else(|| {
+                        format!(
+                            "Property '{}' not found on object '{}'",
+                            nested_obj, object_name
+                        )
+                    })?;
 
                 match nested_value {
                     Value::Object(nested_object) => {
-                        // Recursively evaluate the nested member
-                        self.evaluate_nested_object_call(nested_object, nested_member)
+                        self.evaluate_member(&nested_object, nested_member)
                     }
                     _ => Err(format!(
                         "'{}' is not an object on '{}'",
@@ -498,55 +1838,124 @@ impl Interpreter {
         }
     }
 
-    fn evaluate_nested_object_call(
+    fn call_user_function(
         &mut self,
-        object: &Object,
-        member_expr: &Expr,
+        name: &str,
+        params: &[String],
+        body: &[Stmt],
+        arg_values: Vec<Value>,
     ) -> Result<Value, String> {
-        match member_expr {
-            Expr::Identifier(prop_name) => object
-                .get_property(prop_name)
-                .cloned()
-                .ok_or_else(|| format!("Property '{}' not found on object", prop_name)),
-            Expr::FunctionCall { name, args } => {
-                let method = object
-                    .get_property(name)
-                    .cloned()
-                    .ok_or_else(|| format!("Method '{}' not found on object", name))?;
+        if self.current_call_depth >= self.max_call_depth {
+            return Err(self.raise(errors::call_depth_exceeded()));
+        }
+        self.trace(|| TraceEvent::FunctionEntered {
+            name: name.to_string(),
+            args: arg_values.clone(),
+        });
+        if let Some(profiler) = self.hooks.profiler.as_mut() {
+            profiler.enter(name, FunctionKind::User);
+        }
 
-                let mut arg_values = Vec::new();
-                for arg in args {
-                    arg_values.push(self.evaluate_expression(arg)?);
-                }
+        self.current_call_depth += 1;
+        let result = self.execute_user_function(name, params, body, arg_values);
+        self.current_call_depth -= 1;
 
-                match method {
-                    Value::Function(_, params, body) => {
-                        self.call_user_function(&params, &body, arg_values)
-                    }
-                    Value::NativeFunction(_, native_fn) => native_fn(self, arg_values),
-                    _ => Err(format!("'{}' is not a method", name)),
-                }
+        if let Some(profiler) = self.hooks.profiler.as_mut() {
+            profiler.exit();
+        }
+
+        self.trace(|| TraceEvent::FunctionExited {
+            name: name.to_string(),
+            result: result.clone(),
+        });
+        result
+    }
+
+    /// The object the running native was called on as a method, e.g.
+    /// `counter` in `counter.increment()`. Natives use it to act on their
+    /// own object; changes made through it are what later reads of the
+    /// object see. `None` outside a method call.
+    pub fn current_receiver(&mut self) -> Option<ObjectRef> {
+        self.receiver_taken = true;
+        self.receiver.clone()
+    }
+
+    /// Calls `method` as a member of `receiver`, see `current_receiver`.
+    pub(crate) fn call_method(
+        &mut self,
+        receiver: &ObjectRef,
+        method: &Value,
+        args: Vec<Value>,
+    ) -> Result<Value, String> {
+        let previous = self.receiver.replace(receiver.clone());
+        let result = self.invoke(method, args);
+        self.receiver = previous;
+        result
+    }
+
+    /// Calls a function value (user defined or native) with already evaluated
+    /// arguments, e.g. a callback handed to a std library function.
+    pub fn call_function(&mut self, func: &Value, args: Vec<Value>) -> Result<Value, String> {
+        // A callback is not a method call, even when made from inside one
+        let previous = self.receiver.take();
+        let result = self.invoke(func, args);
+        self.receiver = previous;
+        result
+    }
+
+    fn invoke(&mut self, func: &Value, args: Vec<Value>) -> Result<Value, String> {
+        match func {
+            Value::Function(name, params, body) => {
+                self.call_user_function(name, params, body, args)
+            }
+            Value::NativeFunction(name, native_fn) => {
+                self.call_native_function(name, *native_fn, args)
             }
-            _ => Err("Invalid nested member access".to_string()),
+            other => Err(format!("{} is not a function", other)),
         }
     }
 
-    fn call_user_function(
+    fn call_native_function(
         &mut self,
+        name: &str,
+        native_fn: fn(&mut Interpreter, Vec<Value>) -> Result<Value, String>,
+        arg_values: Vec<Value>,
+    ) -> Result<Value, String> {
+        if let Some(profiler) = self.hooks.profiler.as_mut() {
+            profiler.enter(name, FunctionKind::Native);
+        }
+        // Natives like `arr.push` allocate by growing their arguments
+        let containers: Vec<Value> = arg_values
+            .iter()
+            .filter(|value| matches!(value, Value::Array(_) | Value::Object(_)))
+            .cloned()
+            .collect();
+        let size_before: usize = containers.iter().map(memory::shallow_size).sum();
+        let result = native_fn(self, arg_values);
+        if let Some(profiler) = self.hooks.profiler.as_mut() {
+            profiler.exit();
+        }
+        let size_after: usize = containers.iter().map(memory::shallow_size).sum();
+        if size_after > size_before {
+            self.memory.charge(size_after - size_before, &self.env)?;
+        }
+        result
+    }
+
+    fn execute_user_function(
+        &mut self,
+        name: &str,
         params: &[String],
         body: &[Stmt],
         arg_values: Vec<Value>,
     ) -> Result<Value, String> {
         if params.len() != arg_values.len() {
-            return Err(format!(
-                "Function expects {} arguments, got {}",
-                params.len(),
-                arg_values.len()
-            ));
+            return Err(self.raise(errors::arity_mismatch(params.len(), arg_values.len())));
         }
 
         // Create new interpreter scope for function
         let mut func_interpreter = self.create_child();
+        func_interpreter.current_function = Some(name.to_string());
 
         // Set parameters as local variables
         for (param, value) in params.iter().zip(arg_values.iter()) {
@@ -555,22 +1964,76 @@ impl Interpreter {
                 .set_variable(param.clone(), value.clone());
         }
 
-        // Execute function body
-        for stmt in body {
-            match func_interpreter.execute_statement(stmt)? {
-                ControlFlow::Return(value) => return Ok(value),
-                ControlFlow::None => continue,
-            }
+        // Hooks follow execution into the function scope and are handed
+        // back afterwards, even if the body fails.
+        let caller_file = self.enter_coverage_file(name);
+        func_interpreter.hooks = std::mem::take(&mut self.hooks);
+        let result = func_interpreter.execute_block(body);
+        self.hooks = std::mem::take(&mut func_interpreter.hooks);
+        self.leave_coverage_file(caller_file);
+
+        match result? {
+            ControlFlow::Return(value) => Ok(value),
+            ControlFlow::Break => Err(errors::break_outside_loop()),
+            ControlFlow::None => Ok(Value::Void),
         }
+    }
+}
 
-        Ok(Value::Void)
+/// Binary operation where at least one side is a float; the other has
+/// already been converted.
+fn float_op(l: f64, op: &BinaryOp, r: f64) -> Result<Value, RuntimeError> {
+    let truth = |holds: bool| Ok(Value::Number(holds as i64));
+    match op {
+        BinaryOp::Add => Ok(Value::Float(l + r)),
+        BinaryOp::Subtract => Ok(Value::Float(l - r)),
+        BinaryOp::Multiply => Ok(Value::Float(l * r)),
+        BinaryOp::Divide if r == 0.0 => Err(errors::division_by_zero()),
+        BinaryOp::Divide => Ok(Value::Float(l / r)),
+        BinaryOp::Equal => truth(l == r),
+        BinaryOp::NotEqual => truth(l != r),
+        BinaryOp::LessThan => truth(l < r),
+        BinaryOp::LessThanOrEqual => truth(l <= r),
+        BinaryOp::GreaterThan => truth(l > r),
+        BinaryOp::GreaterThanOrEqual => truth(l >= r),
     }
 }
 
 pub fn interpret(program: &Program) {
-    let mut interpreter = Interpreter::new();
+    interpret_with(&mut Interpreter::new(), program);
+}
+
+pub fn interpret_with(interpreter: &mut Interpreter, program: &Program) {
     match interpreter.interpret(program) {
         Ok(()) => println!("Program executed successfully."),
         Err(e) => eprintln!("Runtime error: {}", e),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patterns_are_cached() {
+        let mut interpreter = Interpreter::new();
+        let cached = |interpreter: &Interpreter| interpreter.regex_cache.borrow().len();
+        interpreter.regex("a+").unwrap();
+        interpreter.regex("a+").unwrap();
+        assert_eq!(cached(&interpreter), 1);
+        // Shared with function scopes
+        let mut child = interpreter.create_child();
+        child.regex("a+").unwrap();
+        child.regex("b+").unwrap();
+        assert_eq!(cached(&interpreter), 2);
+        assert!(interpreter.regex("(").is_err());
+        assert_eq!(cached(&interpreter), 2);
+
+        for n in 2..REGEX_CACHE_LIMIT {
+            interpreter.regex(&format!("a{{{}}}", n)).unwrap();
+        }
+        assert_eq!(cached(&interpreter), REGEX_CACHE_LIMIT);
+        interpreter.regex("c+").unwrap();
+        assert_eq!(cached(&interpreter), 1);
+    }
+}
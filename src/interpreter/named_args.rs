@@ -0,0 +1,59 @@
+//! `name: value` arguments at call sites, matched to a user function's
+//! parameters. Natives have no parameter names, so they get their
+//! arguments in call order.
+
+use super::Value;
+use crate::errors::{self, RuntimeError};
+
+/// Puts `args` in parameter order, where `names` holds the name each
+/// argument was given by, if any (see `Expr::FunctionCall`). Named
+/// arguments fill the parameters the positional ones left.
+pub(super) fn order(
+    params: &[String],
+    args: &mut Vec<Value>,
+    names: &[Option<String>],
+) -> Result<(), RuntimeError> {
+    if names.is_empty() {
+        return Ok(());
+    }
+    if args.len() > params.len() {
+        return Err(errors::arity_mismatch(params.len(), args.len()));
+    }
+
+    let positional = names.iter().take_while(|name| name.is_none()).count();
+    let mut slots: Vec<Option<Value>> = vec![None; params.len()];
+    for (slot, (value, name)) in args.drain(..).zip(names).enumerate() {
+        let index = match name {
+            None => slot,
+            Some(name) => params
+                .iter()
+                .position(|param| param == name)
+                .ok_or_else(|| errors::unknown_argument(name, closest(name, params)))?,
+        };
+        if slots[index].is_some() {
+            let param = &params[index];
+            return Err(if index < positional {
+                errors::argument_given_by_position_and_name(param)
+            } else {
+                errors::argument_given_twice(param)
+            });
+        }
+        slots[index] = Some(value);
+    }
+
+    for (value, param) in slots.into_iter().zip(params) {
+        args.push(value.ok_or_else(|| errors::missing_argument(param))?);
+    }
+    Ok(())
+}
+
+/// The parameter a mistyped argument name most likely meant, if any is
+/// close enough to suggest.
+fn closest<'a>(name: &str, params: &'a [String]) -> Option<&'a str> {
+    params
+        .iter()
+        .map(|param| (strsim::levenshtein(name, param), param))
+        .filter(|(distance, param)| *distance <= 2 && *distance < param.chars().count())
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, param)| param.as_str())
+}
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FunctionKind {
+    User,
+    Native,
+}
+
+/// Timing totals for a single function across the whole run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileEntry {
+    pub name: String,
+    pub kind: FunctionKind,
+    pub calls: u64,
+    /// Wall time from entry to exit, counted once even for recursive calls.
+    pub total: Duration,
+    /// Wall time spent in the function itself, excluding profiled callees.
+    pub self_time: Duration,
+}
+
+/// Per-function timings, sorted by total time (slowest first).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileReport {
+    pub entries: Vec<ProfileEntry>,
+}
+
+impl ProfileReport {
+    pub fn get(&self, name: &str) -> Option<&ProfileEntry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+}
+
+impl fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name_width = self
+            .entries
+            .iter()
+            .map(|entry| entry.name.len())
+            .max()
+            .unwrap_or(0)
+            .max("function".len());
+
+        writeln!(
+            f,
+            "{:<name_width$}  {:>6}  {:>8}  {:>12}  {:>12}",
+            "function", "kind", "calls", "total (ms)", "self (ms)"
+        )?;
+        for entry in &self.entries {
+            let kind = match entry.kind {
+                FunctionKind::User => "user",
+                FunctionKind::Native => "native",
+            };
+            writeln!(
+                f,
+                "{:<name_width$}  {:>6}  {:>8}  {:>12.3}  {:>12.3}",
+                entry.name,
+                kind,
+                entry.calls,
+                entry.total.as_secs_f64() * 1000.0,
+                entry.self_time.as_secs_f64() * 1000.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+struct Frame {
+    name: String,
+    started: Instant,
+    children: Duration,
+}
+
+#[derive(Default)]
+struct Stats {
+    kind: Option<FunctionKind>,
+    calls: u64,
+    total: Duration,
+    self_time: Duration,
+    /// Number of frames of this function currently on the stack.
+    active: u32,
+}
+
+/// Records call counts and wall time for every function call.
+#[derive(Default)]
+pub struct Profiler {
+    stack: Vec<Frame>,
+    stats: HashMap<String, Stats>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(super) fn enter(&mut self, name: &str, kind: FunctionKind) {
+        let stats = self.stats.entry(name.to_string()).or_default();
+        stats.kind = Some(kind);
+        stats.calls += 1;
+        stats.active += 1;
+        self.stack.push(Frame {
+            name: name.to_string(),
+            started: Instant::now(),
+            children: Duration::ZERO,
+        });
+    }
+
+    pub(super) fn exit(&mut self) {
+        let Some(frame) = self.stack.pop() else {
+            return;
+        };
+        let elapsed = frame.started.elapsed();
+        if let Some(parent) = self.stack.last_mut() {
+            parent.children += elapsed;
+        }
+
+        let stats = self.stats.entry(frame.name).or_default();
+        stats.active -= 1;
+        stats.self_time += elapsed.saturating_sub(frame.children);
+        // Only the outermost frame of a recursive function adds to its total
+        if stats.active == 0 {
+            stats.total += elapsed;
+        }
+    }
+
+    pub fn report(&self) -> ProfileReport {
+        let mut entries: Vec<ProfileEntry> = self
+            .stats
+            .iter()
+            .map(|(name, stats)| ProfileEntry {
+                name: name.clone(),
+                kind: stats.kind.unwrap_or(FunctionKind::User),
+                calls: stats.calls,
+                total: stats.total,
+                self_time: stats.self_time,
+            })
+            .collect();
+        entries.sort_by(|a, b| b.total.cmp(&a.total).then_with(|| a.name.cmp(&b.name)));
+        ProfileReport { entries }
+    }
+}
@@ -0,0 +1,156 @@
+//! Property storage of an `Object`, the global scope's variables included.
+//!
+//! Properties are kept in the order they were first set and are never
+//! removed, so a name keeps its slot for as long as the object lives, and
+//! function scopes, which start as copies of their caller's, keep the same
+//! slots too. That lets an `Expr::Identifier` remember the slot its name
+//! was found at and read it by index the next time.
+
+use super::Value;
+use crate::parser::Ident;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Default)]
+pub struct Properties {
+    index: HashMap<String, usize>,
+    slots: Vec<(String, Value)>,
+}
+
+impl Properties {
+    pub fn new() -> Self {
+        Properties::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.index.get(name).map(|&slot| &self.slots[slot].1)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Value> {
+        self.index.get(name).map(|&slot| &mut self.slots[slot].1)
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.index.contains_key(name)
+    }
+
+    /// Sets `name`, returning the value it replaced.
+    pub fn insert(&mut self, name: String, value: Value) -> Option<Value> {
+        if let Some(&slot) = self.index.get(&name) {
+            return Some(std::mem::replace(&mut self.slots[slot].1, value));
+        }
+        self.index.insert(name.clone(), self.slots.len());
+        self.slots.push((name, value));
+        None
+    }
+
+    /// Looks up the name of `ident`, starting at the slot it was last found
+    /// at. The slot is only a hint: it is checked against the name, so one
+    /// remembered from another object or an older scope is just a miss.
+    pub fn get_ident(&self, ident: &Ident) -> Option<&Value> {
+        if let Some((name, value)) = self.slots.get(ident.slot()) {
+            if name == ident.name() {
+                return Some(value);
+            }
+        }
+        let slot = *self.index.get(ident.name())?;
+        ident.remember_slot(slot);
+        Some(&self.slots[slot].1)
+    }
+
+    pub fn get_ident_mut(&mut self, ident: &Ident) -> Option<&mut Value> {
+        let slot = match self.slots.get(ident.slot()) {
+            Some((name, _)) if name == ident.name() => ident.slot(),
+            _ => {
+                let slot = *self.index.get(ident.name())?;
+                ident.remember_slot(slot);
+                slot
+            }
+        };
+        Some(&mut self.slots[slot].1)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.slots.iter().map(|(name, value)| (name, value))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.slots.iter().map(|(name, _)| name)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.slots.iter().map(|(_, value)| value)
+    }
+}
+
+impl<'a> IntoIterator for &'a Properties {
+    type Item = (&'a String, &'a Value);
+    type IntoIter = std::iter::Map<
+        std::slice::Iter<'a, (String, Value)>,
+        fn(&'a (String, Value)) -> (&'a String, &'a Value),
+    >;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.slots.iter().map(|(name, value)| (name, value))
+    }
+}
+
+impl FromIterator<(String, Value)> for Properties {
+    fn from_iter<I: IntoIterator<Item = (String, Value)>>(iter: I) -> Self {
+        let mut properties = Properties::new();
+        for (name, value) in iter {
+            properties.insert(name, value);
+        }
+        properties
+    }
+}
+
+impl From<HashMap<String, Value>> for Properties {
+    fn from(map: HashMap<String, Value>) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ident_slot_is_checked_against_the_name() {
+        let mut first = Properties::new();
+        first.insert("a".to_string(), Value::Number(1));
+        first.insert("b".to_string(), Value::Number(2));
+        let mut second = Properties::new();
+        second.insert("b".to_string(), Value::Number(3));
+        second.insert("a".to_string(), Value::Number(4));
+
+        let ident = Ident::new("b".to_string());
+        assert_eq!(first.get_ident(&ident), Some(&Value::Number(2)));
+        assert_eq!(ident.slot(), 1);
+        // Slot 1 holds `a` here
+        assert_eq!(second.get_ident(&ident), Some(&Value::Number(3)));
+        assert_eq!(ident.slot(), 0);
+        assert_eq!(first.get_ident(&Ident::new("c".to_string())), None);
+    }
+
+    #[test]
+    fn test_reassignment_keeps_the_slot() {
+        let mut properties = Properties::new();
+        properties.insert("a".to_string(), Value::Number(1));
+        properties.insert("b".to_string(), Value::Number(2));
+        assert_eq!(
+            properties.insert("a".to_string(), Value::Number(3)),
+            Some(Value::Number(1))
+        );
+        let names: Vec<&String> = properties.keys().collect();
+        assert_eq!(names, ["a", "b"]);
+        assert_eq!(properties.get("a"), Some(&Value::Number(3)));
+    }
+}
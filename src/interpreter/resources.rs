@@ -0,0 +1,68 @@
+use std::any::Any;
+
+type CloseHook = Box<dyn FnOnce(Box<dyn Any>)>;
+
+struct Entry {
+    id: u64,
+    value: Box<dyn Any>,
+    close: CloseHook,
+}
+
+/// Host resources (database tables, sockets, ...) handed to scripts as
+/// numeric handles. Whatever is still open when the registry is dropped is
+/// closed in reverse registration order.
+#[derive(Default)]
+pub struct Resources {
+    next_id: u64,
+    entries: Vec<Entry>,
+}
+
+impl Resources {
+    /// Stores `value` and returns its handle; `close` runs when the handle is
+    /// closed or the registry is dropped.
+    pub fn register<T: Any>(&mut self, value: T, close: impl FnOnce(T) + 'static) -> u64 {
+        self.next_id += 1;
+        self.entries.push(Entry {
+            id: self.next_id,
+            value: Box::new(value),
+            close: Box::new(move |value| {
+                if let Ok(value) = value.downcast::<T>() {
+                    close(*value);
+                }
+            }),
+        });
+        self.next_id
+    }
+
+    /// The resource behind `id`, if it is open and of type `T`.
+    pub fn get_mut<T: Any>(&mut self, id: u64) -> Option<&mut T> {
+        self.entries
+            .iter_mut()
+            .find(|entry| entry.id == id)
+            .and_then(|entry| entry.value.downcast_mut())
+    }
+
+    /// Runs the close hook of `id`. Returns false if it was not open.
+    pub fn close(&mut self, id: u64) -> bool {
+        match self.entries.iter().position(|entry| entry.id == id) {
+            Some(index) => {
+                let entry = self.entries.remove(index);
+                (entry.close)(entry.value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn close_all(&mut self) {
+        while let Some(entry) = self.entries.pop() {
+            (entry.close)(entry.value);
+        }
+    }
+}
+
+impl Drop for Resources {
+    fn drop(&mut self) {
+        self.close_all();
+    }
+}
@@ -0,0 +1,320 @@
+//! Serde support for `Value` and `Object`.
+//!
+//! Numbers, floats, strings, void (as unit/null), arrays and objects (as
+//! maps of their properties) map onto serde's data model directly. The rest
+//! use reserved map shapes:
+//!
+//! - a user function is `{"fn": name, "params": [...], "body": <AST>}`
+//! - a native function is `{"native": name}`, resolved by name when read
+//!   back, which fails if the reading interpreter has no such native
+//!
+//! An object whose properties happen to have exactly one of these shapes is
+//! read back as the function. Object names (`{user: ...}` in `Display`) are
+//! not kept; objects read back are named `object`.
+//!
+//! Reading goes through `serde_json::Value` for maps, so it needs a
+//! self-describing format (JSON, YAML, ...).
+
+use super::{Environment, Interpreter, NativeFn, Object, Value};
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{self, SerializeMap, SerializeSeq, Serializer};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// Arrays and objects nested deeper than this fail to serialize instead of
+/// overflowing the stack on values that contain themselves.
+const MAX_SERIALIZE_DEPTH: usize = 128;
+
+/// A value being serialized, with how deep it sits in the outermost one.
+struct Nested<'a> {
+    value: &'a Value,
+    depth: usize,
+}
+
+impl Serialize for Nested<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.value {
+            Value::Number(n) => serializer.serialize_i64(*n),
+            Value::Float(n) => serializer.serialize_f64(*n),
+            Value::String(s) => serializer.serialize_str(s),
+            Value::Void => serializer.serialize_unit(),
+            Value::Array(_) | Value::Object(_) if self.depth >= MAX_SERIALIZE_DEPTH => {
+                Err(ser::Error::custom(format!(
+                    "value is nested more than {} levels deep, does it contain itself?",
+                    MAX_SERIALIZE_DEPTH
+                )))
+            }
+            Value::Array(array) => {
+                let array = array.borrow();
+                let mut seq = serializer.serialize_seq(Some(array.len()))?;
+                for element in array.iter() {
+                    seq.serialize_element(&self.child(element))?;
+                }
+                seq.end()
+            }
+            Value::Object(object) => {
+                let object = object.borrow();
+                // Sorted, so the same object always serializes the same way
+                let mut properties: Vec<_> = object.properties().iter().collect();
+                properties.sort_by_key(|(name, _)| *name);
+                let mut map = serializer.serialize_map(Some(properties.len()))?;
+                for (name, value) in properties {
+                    map.serialize_entry(name, &self.child(value))?;
+                }
+                map.end()
+            }
+            Value::Function(name, params, body) => {
+                let mut map = serializer.serialize_map(Some(3))?;
+                map.serialize_entry("fn", name)?;
+                map.serialize_entry("params", params)?;
+                map.serialize_entry("body", body)?;
+                map.end()
+            }
+            Value::NativeFunction(name, _) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("native", name)?;
+                map.end()
+            }
+        }
+    }
+}
+
+impl<'a> Nested<'a> {
+    fn child(&self, value: &'a Value) -> Nested<'a> {
+        Nested {
+            value,
+            depth: self.depth + 1,
+        }
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Nested {
+            value: self,
+            depth: 0,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl Serialize for Object {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut properties: Vec<_> = self.properties().iter().collect();
+        properties.sort_by_key(|(name, _)| *name);
+        let mut map = serializer.serialize_map(Some(properties.len()))?;
+        for (name, value) in properties {
+            map.serialize_entry(name, &Nested { value, depth: 1 })?;
+        }
+        map.end()
+    }
+}
+
+/// Native functions reachable from an environment, by name.
+pub(super) fn native_functions(env: &Environment) -> HashMap<String, NativeFn> {
+    fn collect(object: &Object, natives: &mut HashMap<String, NativeFn>, depth: usize) {
+        for value in object.properties().values() {
+            match value {
+                Value::NativeFunction(name, native_fn) => {
+                    natives.entry(name.clone()).or_insert(*native_fn);
+                }
+                Value::Object(nested) if depth < MAX_SERIALIZE_DEPTH => {
+                    collect(&nested.borrow(), natives, depth + 1)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut natives = HashMap::new();
+    for object in env.objects.values() {
+        collect(object, &mut natives, 0);
+    }
+    natives
+}
+
+/// Natives of the standard library by name, collected the first time a
+/// value read with plain `Deserialize` names one.
+fn std_natives() -> &'static HashMap<String, NativeFn> {
+    static NATIVES: OnceLock<HashMap<String, NativeFn>> = OnceLock::new();
+    NATIVES.get_or_init(|| native_functions(&Environment::new()))
+}
+
+/// Deserializes a `Value`, resolving native function references against
+/// `natives`, or the standard library's if `None`.
+struct ValueSeed<'a> {
+    natives: Option<&'a HashMap<String, NativeFn>>,
+}
+
+impl<'a> ValueSeed<'a> {
+    fn new(natives: &'a HashMap<String, NativeFn>) -> Self {
+        ValueSeed {
+            natives: Some(natives),
+        }
+    }
+
+    fn natives(&self) -> &'a HashMap<String, NativeFn> {
+        match self.natives {
+            Some(natives) => natives,
+            None => std_natives(),
+        }
+    }
+
+    fn read_json<E: de::Error>(&self, json: serde_json::Value) -> Result<Value, E> {
+        ValueSeed {
+            natives: self.natives,
+        }
+        .deserialize(json)
+        .map_err(E::custom)
+    }
+
+    /// Reads back the reserved function and native shapes, or builds an
+    /// object from the map's entries.
+    fn read_entries<E: de::Error>(
+        &self,
+        mut entries: HashMap<String, serde_json::Value>,
+    ) -> Result<Value, E> {
+        let is_shape = |keys: &[&str]| {
+            entries.len() == keys.len() && keys.iter().all(|key| entries.contains_key(*key))
+        };
+
+        if is_shape(&["fn", "params", "body"]) {
+            if let Some(serde_json::Value::String(name)) = entries.get("fn") {
+                let name = name.clone();
+                let params = entries.remove("params").unwrap_or_default();
+                let body = entries.remove("body").unwrap_or_default();
+                let params = serde_json::from_value(params).map_err(E::custom)?;
+                let body = serde_json::from_value(body).map_err(E::custom)?;
+                return Ok(Value::Function(name, params, body));
+            }
+        }
+        if is_shape(&["native"]) {
+            if let Some(serde_json::Value::String(name)) = entries.get("native") {
+                return match self.natives().get(name) {
+                    Some(native_fn) => Ok(Value::NativeFunction(name.clone(), *native_fn)),
+                    None => Err(E::custom(format!("unknown native function '{}'", name))),
+                };
+            }
+        }
+
+        let mut object = Object::new("object");
+        for (name, json) in entries {
+            object.set_property(name, self.read_json(json)?);
+        }
+        Ok(Value::object(object))
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for ValueSeed<'_> {
+    type Value = Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ValueSeed<'_> {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a mouse value")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Number(v as i64))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::Number(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Value, E> {
+        i64::try_from(v)
+            .map(Value::Number)
+            .map_err(|_| E::custom(format!("number {} does not fit in an integer", v)))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Value, E> {
+        Ok(Value::Float(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Value, E> {
+        Ok(Value::String(v))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Void)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Void)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        self.deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut elements = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        let natives = self.natives;
+        while let Some(element) = seq.next_element_seed(ValueSeed { natives })? {
+            elements.push(element);
+        }
+        Ok(Value::array(elements))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        // Buffered: whether this is a function only shows once every key is known
+        let mut entries = HashMap::new();
+        while let Some((name, json)) = map.next_entry::<String, serde_json::Value>()? {
+            entries.insert(name, json);
+        }
+        self.read_entries(entries)
+    }
+}
+
+/// Converts already parsed JSON into a value, resolving natives by name.
+pub(super) fn value_from_json(
+    natives: &HashMap<String, NativeFn>,
+    json: serde_json::Value,
+) -> Result<Value, serde_json::Error> {
+    ValueSeed::new(natives).deserialize(json)
+}
+
+/// Reads natives back against the standard library. Natives a host added
+/// need `Interpreter::deserialize_value` instead.
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        ValueSeed { natives: None }.deserialize(deserializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Object {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Value::deserialize(deserializer)? {
+            Value::Object(object) => Ok(object.borrow().clone()),
+            other => Err(de::Error::custom(format!(
+                "expected an object, got {}",
+                other.type_name()
+            ))),
+        }
+    }
+}
+
+impl Interpreter {
+    /// Deserializes a value, linking native function references to the
+    /// natives registered in this interpreter.
+    pub fn deserialize_value<'de, D: Deserializer<'de>>(
+        &self,
+        deserializer: D,
+    ) -> Result<Value, D::Error> {
+        let natives = native_functions(&self.env);
+        ValueSeed::new(&natives).deserialize(deserializer)
+    }
+}
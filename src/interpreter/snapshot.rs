@@ -0,0 +1,125 @@
+use super::serialize::{native_functions, value_from_json};
+use super::{Environment, Object, Value};
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// A copy of an interpreter's global state: variables, functions and every
+/// environment object. Arrays and objects are copied rather than shared, so
+/// changes made after the snapshot don't leak into it; values that were
+/// aliased (or contained themselves) stay that way in the copy.
+///
+/// Snapshots serialize with serde; aliasing is not kept in that form, and
+/// reading one back needs the interpreter it is for, see
+/// `Interpreter::deserialize_snapshot`.
+#[derive(Debug, Clone, Serialize)]
+pub struct StateSnapshot {
+    variables: HashMap<String, Value>,
+    objects: HashMap<String, Object>,
+}
+
+impl StateSnapshot {
+    pub(super) fn capture(env: &Environment) -> Self {
+        let mut copier = DeepCopy::default();
+        StateSnapshot {
+            variables: copier.map(&env.variables),
+            objects: env
+                .objects
+                .iter()
+                .map(|(name, object)| (name.clone(), copier.object(object)))
+                .collect(),
+        }
+    }
+
+    /// Reads a serialized snapshot, linking natives to those in `env`.
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        env: &Environment,
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Serialized {
+            variables: HashMap<String, serde_json::Value>,
+            objects: HashMap<String, serde_json::Value>,
+        }
+
+        let serialized = Serialized::deserialize(deserializer)?;
+        let natives = native_functions(env);
+        let read = |json| value_from_json(&natives, json).map_err(de::Error::custom);
+
+        let mut variables = HashMap::new();
+        for (name, json) in serialized.variables {
+            variables.insert(name, read(json)?);
+        }
+        let mut objects = HashMap::new();
+        for (name, json) in serialized.objects {
+            let Value::Object(object) = read(json)? else {
+                return Err(de::Error::custom(format!("{} is not an object", name)));
+            };
+            let mut object = object.borrow().clone();
+            object.name = name.clone();
+            objects.insert(name, object);
+        }
+        Ok(StateSnapshot { variables, objects })
+    }
+
+    pub(super) fn apply(self, env: &mut Environment) {
+        env.variables = self.variables;
+        env.objects = self.objects;
+    }
+}
+
+/// Copies values while remembering which arrays and objects were already
+/// copied, keyed by their address.
+#[derive(Default)]
+struct DeepCopy {
+    arrays: HashMap<*const RefCell<Vec<Value>>, Value>,
+    objects: HashMap<*const RefCell<Object>, Value>,
+}
+
+impl DeepCopy {
+    fn value(&mut self, value: &Value) -> Value {
+        match value {
+            Value::Array(array) => {
+                if let Some(copy) = self.arrays.get(&Rc::as_ptr(array)) {
+                    return copy.clone();
+                }
+                // Registered before the elements are copied, so an array
+                // containing itself points at its copy
+                let copy = Rc::new(RefCell::new(Vec::new()));
+                self.arrays
+                    .insert(Rc::as_ptr(array), Value::Array(copy.clone()));
+                let elements = array.borrow().iter().map(|v| self.value(v)).collect();
+                *copy.borrow_mut() = elements;
+                Value::Array(copy)
+            }
+            Value::Object(object) => {
+                if let Some(copy) = self.objects.get(&Rc::as_ptr(object)) {
+                    return copy.clone();
+                }
+                let copy = Rc::new(RefCell::new(Object::new(&object.borrow().name)));
+                self.objects
+                    .insert(Rc::as_ptr(object), Value::Object(copy.clone()));
+                let copied = self.object(&object.borrow());
+                *copy.borrow_mut() = copied;
+                Value::Object(copy)
+            }
+            other => other.clone(),
+        }
+    }
+
+    fn object(&mut self, object: &Object) -> Object {
+        Object::with_properties(&object.name, self.map(&object.properties))
+    }
+
+    fn map<'a, T>(&mut self, values: impl IntoIterator<Item = (&'a String, &'a Value)>) -> T
+    where
+        T: FromIterator<(String, Value)>,
+    {
+        values
+            .into_iter()
+            .map(|(name, value)| (name.clone(), self.value(value)))
+            .collect()
+    }
+}
@@ -0,0 +1,133 @@
+//! Errors on their way up to a `catch`.
+//!
+//! Runtime errors and `throw`n values travel up as `Err(String)` like any
+//! other error. What a `catch` needs beyond the message, the thrown value
+//! and the line it came from, waits in a slot shared with function scopes.
+//! The slot is keyed by the message, so an entry left behind by an error
+//! that never reached a `catch` is not mistaken for the one being caught.
+
+use super::{Object, Value};
+use crate::errors::{ErrorCode, RuntimeError};
+
+struct Failure {
+    message: String,
+    // 0 until the error leaves a statement
+    line: u32,
+    thrown: Option<Value>,
+    catchable: bool,
+    code: Option<&'static ErrorCode>,
+}
+
+#[derive(Default)]
+pub(super) struct Unwinding {
+    failure: Option<Failure>,
+}
+
+impl Unwinding {
+    /// Notes that error `message` left a statement on `line`. Only the
+    /// innermost statement counts, the error passes through the others.
+    pub(super) fn raised(&mut self, message: &str, line: u32) {
+        match &mut self.failure {
+            Some(failure) if failure.message == message => {
+                if failure.line == 0 {
+                    failure.line = line;
+                }
+            }
+            failure => {
+                *failure = Some(Failure {
+                    message: message.to_string(),
+                    line,
+                    thrown: None,
+                    catchable: true,
+                    code: None,
+                })
+            }
+        }
+    }
+
+    /// Records `error` with its code and returns its message.
+    pub(super) fn coded(&mut self, error: RuntimeError) -> String {
+        self.failure = Some(Failure {
+            message: error.message.clone(),
+            line: 0,
+            thrown: None,
+            catchable: true,
+            code: error.code,
+        });
+        error.message
+    }
+
+    /// The code of error `message`, if it has one.
+    pub(super) fn code(&self, message: &str) -> Option<&'static ErrorCode> {
+        match &self.failure {
+            Some(failure) if failure.message == message => failure.code,
+            _ => None,
+        }
+    }
+
+    /// Records a `throw` of `value` on `line` and returns its message.
+    pub(super) fn thrown(&mut self, value: Value, line: u32) -> String {
+        let message = thrown_message(&value);
+        self.failure = Some(Failure {
+            message: message.clone(),
+            line,
+            thrown: Some(value),
+            catchable: true,
+            code: None,
+        });
+        message
+    }
+
+    /// Marks `message` as one no `catch` may stop, like a cancellation,
+    /// and returns it.
+    pub(super) fn uncatchable(&mut self, message: String) -> String {
+        self.failure = Some(Failure {
+            message: message.clone(),
+            line: 0,
+            thrown: None,
+            catchable: false,
+            code: None,
+        });
+        message
+    }
+
+    /// The value a `catch` binds for error `message`: the thrown value, or
+    /// an error object with `message` and `line`. `None` if the error must
+    /// keep going. `line` is used if the error's own line is unknown.
+    pub(super) fn catch(&mut self, message: &str, line: u32) -> Option<Value> {
+        match self.failure.take() {
+            Some(failure) if failure.message == message => {
+                if !failure.catchable {
+                    self.failure = Some(failure);
+                    return None;
+                }
+                Some(
+                    failure
+                        .thrown
+                        .unwrap_or_else(|| error_object(message, failure.line)),
+                )
+            }
+            _ => Some(error_object(message, line)),
+        }
+    }
+}
+
+/// Message of an uncaught `throw`: a string as is, an error object's
+/// `message`, anything else as printed.
+fn thrown_message(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Object(object) => match object.borrow().get_property("message") {
+            Some(Value::String(message)) => message.clone(),
+            _ => value.to_string(),
+        },
+        other => other.to_string(),
+    }
+}
+
+fn error_object(message: &str, line: u32) -> Value {
+    let mut error = Object::new("error");
+    error.set_property("message".to_string(), Value::String(message.to_string()));
+    error.set_property("line".to_string(), Value::Number(line as i64));
+    Value::object(error)
+}
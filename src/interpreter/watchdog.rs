@@ -0,0 +1,49 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Raises an interpreter's cancellation flag once `timeout` has passed.
+/// Dropping the watchdog before that stops it without cancelling.
+pub struct Watchdog {
+    fired: Arc<AtomicBool>,
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Watchdog {
+    pub fn spawn(cancel: Arc<AtomicBool>, timeout: Duration) -> Self {
+        let fired = Arc::new(AtomicBool::new(false));
+        let (stop, stopped) = mpsc::channel::<()>();
+
+        let thread_fired = fired.clone();
+        let thread = thread::spawn(move || {
+            if let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(timeout) {
+                thread_fired.store(true, Ordering::SeqCst);
+                cancel.store(true, Ordering::SeqCst);
+            }
+        });
+
+        Watchdog {
+            fired,
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    /// Whether the timeout elapsed and execution was cancelled.
+    pub fn fired(&self) -> bool {
+        self.fired.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        // closing the channel wakes the thread up early
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,41 @@
+//! ISO 8601 dates as scripts write them, shared by `std.time` and the
+//! database's timestamp columns.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+
+const EXPECTED: &str =
+    "expected an ISO 8601 date like 2023-01-01, 2023-01-01T12:00:00 or 2023-01-01T12:00:00Z";
+
+/// Parses an ISO 8601 date or date-time. Without an offset the time is taken
+/// as UTC, and a bare date means midnight.
+pub fn parse(text: &str) -> Result<DateTime<Utc>, String> {
+    let text = text.trim();
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(text) {
+        return Ok(datetime.to_utc());
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"] {
+        if let Ok(datetime) = NaiveDateTime::parse_from_str(text, format) {
+            return Ok(datetime.and_utc());
+        }
+    }
+    NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .map(|date| date.and_hms_opt(0, 0, 0).unwrap().and_utc())
+        .map_err(|_| format!("could not parse '{}': {}", text, EXPECTED))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offsets_and_bare_dates_are_utc() {
+        let midnight = parse("2023-01-01").unwrap();
+        assert_eq!(midnight.timestamp(), 1_672_531_200);
+        assert_eq!(parse(" 2023-01-01T01:00:00+01:00 "), Ok(midnight));
+        assert_eq!(
+            parse("2023-01-01 00:00:00.250").unwrap().timestamp_millis(),
+            1_672_531_200_250
+        );
+        assert!(parse("2023-13-01").unwrap_err().contains("'2023-13-01'"));
+    }
+}
@@ -1,3 +1,5 @@
+use crate::errors::{Error, ErrorTypes};
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum TokenType {
     KWLet,                  // let
@@ -5,8 +7,15 @@ pub enum TokenType {
     KWReturn,               // return
     KWIf,                   // if
     KWWhile,                // while
+    KWTry,                  // try
+    KWCatch,                // catch
+    KWThrow,                // throw
+    KWFor,                  // for
+    KWIn,                   // in
+    KWBreak,                // break
+    KWImport,               // import
     Identifier(String),     // identifier (e.g. a)
-    Number(i32),            // number literal
+    Number(i64),            // number literal
     String(String),         // string literal
     Operator(Operator),     // operator (e.g. +)
     Comparison(Comparison), // comparison (e.g. ==)
@@ -21,7 +30,10 @@ pub enum TokenType {
     BracketClose,       // )
     BraceOpen,          // {
     BraceClose,         // }
+    SquareOpen,         // [
+    SquareClose,        // ]
     Comma,              // ,
+    Colon,              // :
     Semicolon,          // ;
     Dot,                // .
     ObjectName(String), // object name (e.g. std::split_string() -> `std`)
@@ -36,6 +48,13 @@ impl From<TokenType> for String {
             TokenType::KWReturn => "return".to_string(),
             TokenType::KWIf => "if".to_string(),
             TokenType::KWWhile => "while".to_string(),
+            TokenType::KWTry => "try".to_string(),
+            TokenType::KWCatch => "catch".to_string(),
+            TokenType::KWThrow => "throw".to_string(),
+            TokenType::KWFor => "for".to_string(),
+            TokenType::KWIn => "in".to_string(),
+            TokenType::KWBreak => "break".to_string(),
+            TokenType::KWImport => "import".to_string(),
             TokenType::Identifier(name) => name,
             TokenType::Number(num) => num.to_string(),
             TokenType::String(str) => str,
@@ -52,7 +71,10 @@ impl From<TokenType> for String {
             TokenType::BracketClose => ")".to_string(),
             TokenType::BraceOpen => "{".to_string(),
             TokenType::BraceClose => "}".to_string(),
+            TokenType::SquareOpen => "[".to_string(),
+            TokenType::SquareClose => "]".to_string(),
             TokenType::Comma => ",".to_string(),
+            TokenType::Colon => ":".to_string(),
             TokenType::Semicolon => ";".to_string(),
             TokenType::Dot => ".".to_string(),
             TokenType::ObjectName(name) => name,
@@ -120,28 +142,135 @@ impl Token {
     }
 }
 
-pub fn tokenize(input: String) -> Vec<Token> {
+/// Where lexing stands at the start of a line: its number, and a string
+/// literal the lines before it left open.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LexState {
+    pub line: u32,
+    /// The string read so far, with the line and column it started at
+    open_string: Option<(String, u32, u32)>,
+}
+
+impl LexState {
+    /// The state for starting at line `line` (counting from 1).
+    pub fn at_line(line: u32) -> Self {
+        LexState {
+            line,
+            open_string: None,
+        }
+    }
+}
+
+impl Default for LexState {
+    fn default() -> Self {
+        LexState::at_line(1)
+    }
+}
+
+pub fn tokenize(input: String) -> Result<Vec<Token>, Error> {
     let mut tokens = Vec::new();
-    let mut chars = input.chars().peekable();
+    let mut state = LexState::default();
+    let mut source = input.as_str();
+
+    // A shebang (`#!/usr/bin/env mouse`) is only allowed on the first line
+    if source.starts_with("#!") {
+        source = match source.split_once('\n') {
+            Some((_, rest)) => {
+                state.line += 1;
+                rest
+            }
+            None => "",
+        };
+    }
+
+    lex(source, &mut state, &mut tokens)?;
+    // An unterminated string runs to the end of the input
+    if let Some((string_val, line, column)) = state.open_string {
+        tokens.push(Token::new(TokenType::String(string_val), line, column));
+    }
+    Ok(tokens)
+}
+
+/// Lexes one line (its trailing newline is optional) starting from `state`,
+/// and returns its tokens and the state for the next line. Starting from
+/// `LexState::default()` and feeding each returned state to the next line
+/// gives the tokens, lines and columns `tokenize` gives for the whole input,
+/// so editors can re-lex only the lines that changed. A string still open at
+/// the end of a line is carried over and comes out with the line closing it.
+pub fn tokenize_line(line: &str, mut state: LexState) -> Result<(Vec<Token>, LexState), Error> {
+    let mut tokens = Vec::new();
+    lex(
+        line.strip_suffix('\n').unwrap_or(line),
+        &mut state,
+        &mut tokens,
+    )?;
+    lex("\n", &mut state, &mut tokens)?;
+    Ok((tokens, state))
+}
 
-    let mut line = 0;
+/// Reads a string literal up to its closing quote, returning false when the
+/// input ends first.
+fn read_string(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+    string_val: &mut String,
+    line: &mut u32,
+    column: &mut u32,
+) -> bool {
+    for c in chars.by_ref() {
+        *column += 1;
+        if c == '"' {
+            return true;
+        }
+        if c == '\n' {
+            *line += 1;
+            *column = 0;
+        }
+        string_val.push(c);
+    }
+    false
+}
+
+/// Lexes `input`, which starts at the beginning of line `state.line`, and
+/// leaves `state` at the line after it.
+fn lex(input: &str, state: &mut LexState, tokens: &mut Vec<Token>) -> Result<(), Error> {
+    let mut chars = input.chars().peekable();
+    let mut line = state.line;
     let mut column = 0;
+
+    if let Some((mut string_val, start_line, start_column)) = state.open_string.take() {
+        if read_string(&mut chars, &mut string_val, &mut line, &mut column) {
+            tokens.push(Token::new(
+                TokenType::String(string_val),
+                start_line,
+                start_column,
+            ));
+        } else {
+            state.open_string = Some((string_val, start_line, start_column));
+        }
+    }
+
     while let Some(c) = chars.next() {
         column += 1;
+        let start_column = column;
         match c {
-            ' ' | '\t' | '\n' => {
+            // A `\r` only appears before `\n` in CRLF files, where the `\n`
+            // resets the column anyway
+            ' ' | '\t' | '\r' | '\n' => {
                 if c == '\n' {
                     line += 1;
                     column = 0;
                 }
             }
-            ';' => tokens.push(Token::new(TokenType::Semicolon, line, column)),
-            ',' => tokens.push(Token::new(TokenType::Comma, line, column)),
-            '.' => tokens.push(Token::new(TokenType::Dot, line, column)),
-            '(' => tokens.push(Token::new(TokenType::BracketOpen, line, column)),
-            ')' => tokens.push(Token::new(TokenType::BracketClose, line, column)),
-            '{' => tokens.push(Token::new(TokenType::BraceOpen, line, column)),
-            '}' => tokens.push(Token::new(TokenType::BraceClose, line, column)),
+            ';' => tokens.push(Token::new(TokenType::Semicolon, line, start_column)),
+            ',' => tokens.push(Token::new(TokenType::Comma, line, start_column)),
+            ':' => tokens.push(Token::new(TokenType::Colon, line, start_column)),
+            '.' => tokens.push(Token::new(TokenType::Dot, line, start_column)),
+            '(' => tokens.push(Token::new(TokenType::BracketOpen, line, start_column)),
+            ')' => tokens.push(Token::new(TokenType::BracketClose, line, start_column)),
+            '{' => tokens.push(Token::new(TokenType::BraceOpen, line, start_column)),
+            '}' => tokens.push(Token::new(TokenType::BraceClose, line, start_column)),
+            '[' => tokens.push(Token::new(TokenType::SquareOpen, line, start_column)),
+            ']' => tokens.push(Token::new(TokenType::SquareClose, line, start_column)),
             '/' => {
                 if let Some('/') = chars.peek() {
                     chars.next();
@@ -158,12 +287,11 @@ pub fn tokenize(input: String) -> Vec<Token> {
                     tokens.push(Token::new(
                         TokenType::Operator(Operator::Divide),
                         line,
-                        column,
+                        start_column,
                     ));
                 }
             }
             '#' => {
-                chars.next();
                 // Skip comment until end of line
                 while let Some(&c) = chars.peek() {
                     chars.next();
@@ -177,78 +305,88 @@ pub fn tokenize(input: String) -> Vec<Token> {
             '=' => {
                 if let Some('=') = chars.peek() {
                     chars.next();
+                    column += 1;
                     tokens.push(Token::new(
                         TokenType::Comparison(Comparison::Equal),
                         line,
-                        column,
+                        start_column,
                     ));
                 } else {
-                    tokens.push(Token::new(TokenType::Assign, line, column));
+                    tokens.push(Token::new(TokenType::Assign, line, start_column));
                 }
             }
             '!' => {
                 if let Some('=') = chars.peek() {
                     chars.next();
+                    column += 1;
                     tokens.push(Token::new(
                         TokenType::Comparison(Comparison::NotEqual),
                         line,
-                        column,
+                        start_column,
                     ));
                 }
             }
             '<' => {
                 if let Some('=') = chars.peek() {
                     chars.next();
+                    column += 1;
                     tokens.push(Token::new(
                         TokenType::Comparison(Comparison::LessThanOrEqual),
                         line,
-                        column,
+                        start_column,
                     ));
                 } else {
                     tokens.push(Token::new(
                         TokenType::Comparison(Comparison::LessThan),
                         line,
-                        column,
+                        start_column,
                     ));
                 }
             }
             '>' => {
                 if let Some('=') = chars.peek() {
                     chars.next();
+                    column += 1;
                     tokens.push(Token::new(
                         TokenType::Comparison(Comparison::GreaterThanOrEqual),
                         line,
-                        column,
+                        start_column,
                     ));
                 } else {
                     tokens.push(Token::new(
                         TokenType::Comparison(Comparison::GreaterThan),
                         line,
-                        column,
+                        start_column,
                     ));
                 }
             }
-            '+' => tokens.push(Token::new(TokenType::Operator(Operator::Add), line, column)),
+            '+' => tokens.push(Token::new(
+                TokenType::Operator(Operator::Add),
+                line,
+                start_column,
+            )),
             '-' => tokens.push(Token::new(
                 TokenType::Operator(Operator::Subtract),
                 line,
-                column,
+                start_column,
             )),
             '*' => tokens.push(Token::new(
                 TokenType::Operator(Operator::Multiply),
                 line,
-                column,
+                start_column,
             )),
             '"' => {
+                let start_line = line;
                 let mut string_val = String::new();
-                while let Some(&c) = chars.peek() {
-                    chars.next();
-                    if c == '"' {
-                        break;
-                    }
-                    string_val.push(c);
+                if read_string(&mut chars, &mut string_val, &mut line, &mut column) {
+                    tokens.push(Token::new(
+                        TokenType::String(string_val),
+                        start_line,
+                        start_column,
+                    ));
+                } else {
+                    state.open_string = Some((string_val, start_line, start_column));
                 }
-                tokens.push(Token::new(TokenType::String(string_val), line, column));
             }
             _ => {
                 if c.is_ascii_digit() {
@@ -257,53 +395,93 @@ pub fn tokenize(input: String) -> Vec<Token> {
                     while let Some(&c) = chars.peek() {
                         if c.is_ascii_digit() {
                             number.push(chars.next().unwrap());
+                            column += 1;
                         } else {
                             break;
                         }
                     }
-                    if let Ok(n) = number.parse::<i32>() {
-                        tokens.push(Token::new(TokenType::Number(n), line, column));
-                    }
+                    let n = number.parse::<i64>().map_err(|_| {
+                        Error::new(
+                            line,
+                            start_column,
+                            ErrorTypes::LexicalError(format!(
+                                "number literal {} is out of range",
+                                number
+                            )),
+                            "tokenize",
+                        )
+                    })?;
+                    tokens.push(Token::new(TokenType::Number(n), line, start_column));
                 } else if c.is_alphabetic() || c == '_' {
                     let mut identifier = String::new();
                     identifier.push(c);
+                    // Keywords only count on their own, `meta.function` is a member
+                    let mut member = false;
                     while let Some(&c) = chars.peek() {
                         if c.is_alphanumeric() || c == '_' {
                             identifier.push(chars.next().unwrap());
+                            column += 1;
                         } else if c == '.' {
                             tokens.push(Token::new(
                                 TokenType::ObjectName(identifier.clone()),
                                 line,
-                                column,
+                                start_column,
                             ));
                             identifier.clear();
+                            member = true;
                             chars.next();
+                            column += 1;
                         } else {
                             break;
                         }
                     }
                     let token = match identifier.as_str() {
+                        _ if member => TokenType::Identifier(identifier),
                         "let" | "var" | "const" => TokenType::KWLet,
                         "fn" | "function" | "def" => TokenType::KWFn,
                         "return" => TokenType::KWReturn,
                         "if" => TokenType::KWIf,
                         "while" => TokenType::KWWhile,
+                        "try" => TokenType::KWTry,
+                        "catch" => TokenType::KWCatch,
+                        "throw" => TokenType::KWThrow,
+                        "for" => TokenType::KWFor,
+                        "in" => TokenType::KWIn,
+                        "break" => TokenType::KWBreak,
+                        "import" => TokenType::KWImport,
                         _ => TokenType::Identifier(identifier),
                     };
-                    tokens.push(Token::new(token, line, column));
+                    tokens.push(Token::new(token, line, start_column));
                 }
             }
         }
     }
 
-    tokens
+    state.line = line;
+    Ok(())
 }
 
 /// fixes issues like missing semicolons at the end of lines
+///
+/// The input's line endings (`\n` or `\r\n`, going by the first line) and
+/// its trailing newline, or lack of one, are kept, so a file that needs no
+/// fixes comes back byte for byte.
 pub fn autofix(input: &str) -> String {
+    let newline = match input.find('\n') {
+        Some(i) if input[..i].ends_with('\r') => "\r\n",
+        _ => "\n",
+    };
     let mut output = String::new();
     let mut lines = input.lines().peekable();
 
+    // Leave a shebang line exactly as it is, the OS loader reads it
+    if input.starts_with("#!") {
+        if let Some(shebang) = lines.next() {
+            output.push_str(shebang);
+            output.push_str(newline);
+        }
+    }
+
     while let Some(line) = lines.next() {
         let trimmed = line.trim_end();
         // println!("Autofix processing line: '{}'", line);
@@ -314,15 +492,60 @@ pub fn autofix(input: &str) -> String {
             && !trimmed.ends_with('}')
             && !trimmed.ends_with(',')
             && !trimmed.ends_with('(')
+            && !trimmed.ends_with('[')
         {
             output.push_str(trimmed);
-            output.push_str(";\n");
+            output.push(';');
         } else {
             output.push_str(line);
-            output.push('\n');
         }
+        output.push_str(newline);
     }
     // println!("Autofix output:\n{}", output);
 
+    if !input.ends_with('\n') && output.ends_with(newline) {
+        output.truncate(output.len() - newline.len());
+    }
     output
 }
+
+/// Checks that `fixed`, the output of `autofix`, has the same tokens as
+/// `original` apart from added semicolons, so writing it back can't change
+/// what the script means. The error names where the two first differ.
+pub fn verify_autofix(original: &str, fixed: &str) -> Result<(), String> {
+    let before = tokenize(original.to_string()).map_err(|e| {
+        format!(
+            "the original does not tokenize (line {}, column {}), so the fix can't be checked",
+            e.line, e.column
+        )
+    })?;
+    let after = tokenize(fixed.to_string()).map_err(|e| {
+        format!(
+            "the fixed code does not tokenize at line {}, column {}",
+            e.line, e.column
+        )
+    })?;
+
+    let mut expected = before.iter().peekable();
+    for token in &after {
+        match expected.peek() {
+            Some(next) if next.token == token.token => {
+                expected.next();
+            }
+            _ if token.token == TokenType::Semicolon => {}
+            _ => {
+                return Err(format!(
+                    "the fix changes more than semicolons at line {}, column {}",
+                    token.line, token.column
+                ))
+            }
+        }
+    }
+    match expected.next() {
+        Some(missing) => Err(format!(
+            "the fix drops code at line {}, column {}",
+            missing.line, missing.column
+        )),
+        None => Ok(()),
+    }
+}
@@ -1 +1,112 @@
 pub mod db;
+pub mod errors;
+pub mod interpreter;
+pub mod iso8601;
+pub mod lexer;
+pub mod modules;
+pub mod parser;
+pub mod std_lib;
+pub mod style;
+pub mod tests;
+pub mod watch;
+
+use interpreter::watchdog::Watchdog;
+use interpreter::{Interpreter, Value};
+use std::path::Path;
+use std::time::Duration;
+
+/// Why a script run through the library API did not complete.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RunError {
+    /// The source could not be tokenized or parsed.
+    Syntax(errors::Error),
+    Runtime(errors::RuntimeError),
+    /// The script could not be read.
+    Io(String),
+    /// The deadline passed before the script finished.
+    TimedOut(Duration),
+}
+
+impl std::fmt::Display for RunError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RunError::Syntax(error) => write!(f, "{}", error),
+            RunError::Runtime(msg) => write!(f, "Runtime error: {}", msg),
+            RunError::Io(msg) => write!(f, "{}", msg),
+            RunError::TimedOut(deadline) => {
+                write!(f, "execution timed out after {}s", deadline.as_secs_f64())
+            }
+        }
+    }
+}
+
+/// A prelude that failed to load, with the name it was loaded under so
+/// diagnostics point at the prelude rather than the main script.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreludeError {
+    pub name: String,
+    pub error: RunError,
+}
+
+impl std::fmt::Display for PreludeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "in prelude {}: {}", self.name, self.error)
+    }
+}
+
+/// Runs `code` in a fresh interpreter and cancels it once `deadline` has
+/// passed. Blocks the calling thread; async hosts can call it from
+/// `spawn_blocking`, the result is `Send`.
+pub fn run_source_with_deadline(code: &str, deadline: Duration) -> Result<(), RunError> {
+    let mut interpreter = Interpreter::new();
+    let watchdog = Watchdog::spawn(interpreter.cancel_handle(), deadline);
+    let result = run_source_with(code, &mut interpreter);
+
+    if watchdog.fired() {
+        return Err(RunError::TimedOut(deadline));
+    }
+    result
+}
+
+/// Tokenizes, parses and runs `code` in an interpreter the caller has
+/// already configured (output sink, tracer, import base, ...).
+pub fn run_source_with(code: &str, interpreter: &mut Interpreter) -> Result<(), RunError> {
+    let tokens = lexer::tokenize(code.to_string()).map_err(RunError::Syntax)?;
+    let program = parser::parse(&tokens).map_err(RunError::Syntax)?;
+    interpreter
+        .interpret(&program)
+        .map_err(|message| RunError::Runtime(interpreter.runtime_error(message)))
+}
+
+/// Reads and runs the script at `path`, which scripts see as `meta.file`.
+/// To run source under another name, call `Interpreter::set_script_file`
+/// and then `run_source_with`.
+pub fn run_file_with(path: &Path, interpreter: &mut Interpreter) -> Result<(), RunError> {
+    let code = std::fs::read_to_string(path)
+        .map_err(|e| RunError::Io(format!("Could not read {}: {}", path.display(), e)))?;
+    interpreter.set_script_file(&path.display().to_string());
+    run_source_with(&code, interpreter)
+}
+
+/// Like `run_source_with`, but returns the value of the last top-level
+/// expression statement in `code` (`Void` if it has none), for REPLs and
+/// embedders evaluating one line at a time. Pair it with
+/// `Interpreter::set_capture_last_result` to make earlier results
+/// available to later lines as `_`.
+///
+/// The line is parsed a statement at a time with `parser::parse_statement`,
+/// the entry point editor tooling uses, and runs only if all of it parses.
+pub fn eval_source(code: &str, interpreter: &mut Interpreter) -> Result<Value, RunError> {
+    let tokens = lexer::tokenize(code.to_string()).map_err(RunError::Syntax)?;
+    let mut statements = Vec::new();
+    let mut idx = 0;
+    while idx < tokens.len() {
+        statements.push(parser::parse_statement(&tokens, &mut idx).map_err(RunError::Syntax)?);
+    }
+
+    interpreter.take_last_result();
+    interpreter
+        .interpret(&parser::Program { statements })
+        .map_err(|message| RunError::Runtime(interpreter.runtime_error(message)))?;
+    Ok(interpreter.take_last_result())
+}
@@ -1,27 +1,207 @@
-pub mod db;
-pub mod errors;
-pub mod interpreter;
-pub mod lexer;
-pub mod parser;
-pub mod std_lib;
-pub mod tests;
-
 use clap::Parser;
-use lexer::tokenize;
-use parser::parse;
+use mouse_lang::errors;
+use mouse_lang::interpreter::debugger::{DebugAction, Debugger, Pause};
+use mouse_lang::interpreter::logging::LogLevel;
+use mouse_lang::interpreter::watchdog::Watchdog;
+use mouse_lang::interpreter::{Interpreter, TraceEvent, Tracer, DEFAULT_MAX_CALL_DEPTH};
+use mouse_lang::lexer::{self, tokenize};
+use mouse_lang::modules;
+use mouse_lang::parser::parse;
+use mouse_lang::style::{Severity, Style};
+use mouse_lang::watch::{watch, WatchOptions};
+use mouse_lang::{PreludeError, RunError};
+use std::cell::RefCell;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Exit code used when `--timeout` stops a script, as with coreutils `timeout`.
+const TIMEOUT_EXIT_CODE: i32 = 124;
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
-    /// Name of the file to process
-    #[arg(short, long)]
-    filename: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// File or directory of `.mouse` files to process, may be repeated
+    #[arg(short, long, required_unless_present_any = ["scripts", "eval", "explain"])]
+    filename: Vec<PathBuf>,
+
+    /// Files to process, given positionally so `#!/usr/bin/env mouse` scripts run directly
+    scripts: Vec<PathBuf>,
 
-    #[arg(short, long, default_value_t = false)]
+    /// Run CODE instead of a script file
+    #[arg(short, long, value_name = "CODE", conflicts_with_all = ["filename", "scripts", "watch"])]
+    eval: Option<String>,
+
+    /// Explain error CODE (e.g. E0002) with an example and its fix
+    #[arg(long, value_name = "CODE", conflicts_with_all = ["filename", "scripts", "eval", "watch"])]
+    explain: Option<String>,
+
+    /// Run FILE before every script so its functions are available there; defaults to `MOUSE_PRELUDE`
+    #[arg(long, value_name = "FILE", global = true)]
+    prelude: Option<PathBuf>,
+
+    #[arg(short, long, default_value_t = false, global = true)]
     debug: bool,
 
-    #[arg(short, long, default_value_t = true)]
+    #[arg(short, long, default_value_t = true, global = true)]
     autofix: bool,
+
+    /// Keep the original of an autofixed script as `<file>.bak`
+    #[arg(long, default_value_t = false, global = true)]
+    autofix_backup: bool,
+
+    /// Print every executed statement, function call and assignment
+    #[arg(short, long, default_value_t = false, global = true)]
+    trace: bool,
+
+    /// Pause before the first statement and read debugger commands from stdin
+    #[arg(long, default_value_t = false, global = true)]
+    debug_interactive: bool,
+
+    /// Print a per-function timing report after the run
+    #[arg(long, default_value_t = false, global = true)]
+    profile: bool,
+
+    /// Print how often each line ran after the run
+    #[arg(long, default_value_t = false, global = true)]
+    coverage: bool,
+
+    /// Write line coverage to FILE in lcov format
+    #[arg(long, value_name = "FILE", global = true)]
+    coverage_lcov: Option<PathBuf>,
+
+    /// Stop each script after this many seconds
+    #[arg(long, value_name = "SECONDS", global = true, value_parser = parse_timeout)]
+    timeout: Option<f64>,
+
+    /// Maximum number of nested function calls before a stack overflow error
+    #[arg(long, value_name = "DEPTH", default_value_t = DEFAULT_MAX_CALL_DEPTH, global = true)]
+    max_call_depth: usize,
+
+    /// Register `std.exec` and `std.shell` so scripts can run external programs
+    #[arg(long, default_value_t = false, global = true)]
+    allow_exec: bool,
+
+    /// Lowest `std.log` level shown: debug, info, warn or error
+    #[arg(long, value_name = "LEVEL", default_value_t = LogLevel::Info, global = true)]
+    log_level: LogLevel,
+
+    /// Re-run the script every time it changes, until interrupted with Ctrl-C
+    #[arg(short, long, default_value_t = false, global = true)]
+    watch: bool,
+
+    /// Print diagnostics without colors; also disabled by `NO_COLOR` or a non-terminal stderr
+    #[arg(long, default_value_t = false, global = true)]
+    no_color: bool,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Run the project in DIR through its `main.mouse` entry point
+    Run { dir: PathBuf },
+    /// Copy or clone a library into the project's `mouse_modules/`, the nearest one
+    /// above the current directory, so scripts can `import` it by name
+    Install {
+        /// Directory or git URL of the library
+        source: String,
+    },
+}
+
+/// Accepts a `--timeout` that is a usable duration: not negative, NaN or
+/// too large for `Duration`.
+fn parse_timeout(value: &str) -> Result<f64, String> {
+    let seconds: f64 = value
+        .parse()
+        .map_err(|_| format!("'{}' is not a number of seconds", value))?;
+    Duration::try_from_secs_f64(seconds)
+        .map_err(|_| format!("'{}' is not a usable number of seconds", value))?;
+    Ok(seconds)
+}
+
+/// Entry point looked up by `mouse run <dir>`.
+const PROJECT_ENTRY: &str = "main.mouse";
+
+/// Environment variable naming a prelude when `--prelude` is not given.
+const PRELUDE_ENV: &str = "MOUSE_PRELUDE";
+
+/// Helper code run before each script, read once up front.
+struct Prelude {
+    path: PathBuf,
+    source: String,
+}
+
+impl Prelude {
+    fn from_args(args: &Args) -> Result<Option<Prelude>, String> {
+        let path = match &args.prelude {
+            Some(path) => path.clone(),
+            None => match std::env::var_os(PRELUDE_ENV) {
+                Some(path) if !path.is_empty() => PathBuf::from(path),
+                _ => return Ok(None),
+            },
+        };
+        let source = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Could not read prelude {}: {}", path.display(), e))?;
+        Ok(Some(Prelude { path, source }))
+    }
+
+    /// Loads the prelude into `interpreter`, printing any error against the
+    /// prelude file. Returns false if it failed.
+    fn load(&self, interpreter: &mut Interpreter, style: Style) -> bool {
+        let name = self.path.display().to_string();
+        match interpreter.load_prelude(&self.source, &name) {
+            Ok(()) => true,
+            Err(PreludeError {
+                error: RunError::Syntax(e),
+                ..
+            }) => {
+                eprint!("{}", e.render(Some(&self.source), Some(&self.path), style));
+                false
+            }
+            Err(e) => {
+                eprintln!("{} {}", style.severity(Severity::Error, "error:"), e);
+                false
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Success,
+    Failed,
+    TimedOut,
+}
+
+/// Replaces `filename` with `fixed` through a temporary file and a rename,
+/// so an interrupted write never leaves the script half written. With
+/// `backup`, `original` is kept next to it as `<file>.bak` first.
+fn write_fixed(filename: &Path, original: &str, fixed: &str, backup: bool) -> std::io::Result<()> {
+    let with_suffix = |suffix: &str| {
+        let mut name = filename.as_os_str().to_owned();
+        name.push(suffix);
+        PathBuf::from(name)
+    };
+    if backup {
+        std::fs::write(with_suffix(".bak"), original)?;
+    }
+    let temp = with_suffix(".autofix.tmp");
+    std::fs::write(&temp, fixed)?;
+    // Keep the mode, e.g. the executable bit of a shebang script
+    std::fs::set_permissions(&temp, std::fs::metadata(filename)?.permissions())?;
+    std::fs::rename(&temp, filename).inspect_err(|_| {
+        let _ = std::fs::remove_file(&temp);
+    })
 }
 
 fn debug_print(debug: &bool, msg: &str) {
@@ -30,29 +210,233 @@ fn debug_print(debug: &bool, msg: &str) {
     }
 }
 
+/// Prints trace events as a compact log, indented by call depth.
+fn trace_printer() -> Tracer {
+    let mut depth = 0usize;
+    Box::new(move |event| {
+        let indent = "  ".repeat(depth);
+        match event {
+            TraceEvent::StatementEntered { span, kind } => {
+                println!("[TRACE] {}{} {}", indent, span, kind)
+            }
+            TraceEvent::FunctionEntered { name, args } => {
+                let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+                println!("[TRACE] {}-> {}({})", indent, name, args.join(", "));
+                depth += 1;
+            }
+            TraceEvent::FunctionExited { name, result } => {
+                depth = depth.saturating_sub(1);
+                let indent = "  ".repeat(depth);
+                match result {
+                    Ok(value) => println!("[TRACE] {}<- {} = {}", indent, name, value),
+                    Err(e) => println!("[TRACE] {}<- {} failed: {}", indent, name, e),
+                }
+            }
+            TraceEvent::VariableAssigned { name, value } => {
+                println!("[TRACE] {}{} = {}", indent, name, value)
+            }
+        }
+    })
+}
+
+/// Reads debugger commands from stdin until one resumes execution.
+fn prompt_debugger(pause: &mut Pause) -> DebugAction {
+    println!("[DEBUGGER] paused at {}", pause.span);
+    let stdin = std::io::stdin();
+    loop {
+        print!("(mouse-db) ");
+        let _ = std::io::stdout().flush();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            // stdin closed, let the program finish
+            return DebugAction::Continue;
+        }
+
+        let mut parts = line.split_whitespace();
+        match (parts.next(), parts.next()) {
+            (Some("step") | Some("s"), _) => return DebugAction::Step,
+            (Some("continue") | Some("c"), _) => return DebugAction::Continue,
+            (Some("quit") | Some("q"), _) => return DebugAction::Abort,
+            (Some("break") | Some("b"), Some(line)) => match line.parse() {
+                Ok(line) => {
+                    pause.set_breakpoint(line);
+                    println!("breakpoint set at line {}", line);
+                }
+                Err(_) => println!("invalid line number: {}", line),
+            },
+            (Some("clear"), Some(line)) => match line.parse() {
+                Ok(line) => pause.clear_breakpoint(line),
+                Err(_) => println!("invalid line number: {}", line),
+            },
+            (Some("print") | Some("p"), Some(name)) => match pause.snapshot_scope().get(name) {
+                Some(value) => println!("{} = {}", name, value),
+                None => println!("{} is not defined", name),
+            },
+            (Some("vars"), _) => {
+                for (name, value) in pause.snapshot_scope() {
+                    println!("{} = {}", name, value);
+                }
+            }
+            _ => println!(
+                "commands: step (s), continue (c), break <line> (b), clear <line>, print <var> (p), vars, quit (q)"
+            ),
+        }
+    }
+}
+
+/// Expands directories into the `.mouse` files they contain, sorted by name.
+fn collect_files(paths: &[PathBuf]) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let mut entries: Vec<PathBuf> = std::fs::read_dir(path)?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|entry| entry.is_file() && entry.extension() == Some("mouse".as_ref()))
+                .collect();
+            entries.sort();
+            files.extend(entries);
+        } else {
+            files.push(path.clone());
+        }
+    }
+    Ok(files)
+}
+
 #[tokio::main]
 async fn main() {
     let args = Args::parse();
+    if let Some(code) = &args.explain {
+        match errors::explain(code) {
+            Some(entry) => print!("{}", entry),
+            None => {
+                eprintln!("{} is not a known error code", code);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    let prelude = match Prelude::from_args(&args) {
+        Ok(prelude) => prelude,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
 
-    let code = std::fs::read_to_string(&args.filename).expect("Could not read file");
+    let files = match &args.command {
+        Some(Command::Install { source }) => {
+            match modules::install(source, &modules::project_root(Path::new("."))) {
+                Ok(manifest) => println!("installed {} {}", manifest.name, manifest.version),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some(Command::Run { dir }) => {
+            let entry = dir.join(PROJECT_ENTRY);
+            if !entry.is_file() {
+                eprintln!("no {} found in {}", PROJECT_ENTRY, dir.display());
+                std::process::exit(1);
+            }
+            vec![entry]
+        }
+        None => {
+            let paths: Vec<PathBuf> = args.filename.iter().chain(&args.scripts).cloned().collect();
+            match collect_files(&paths) {
+                Ok(files) => files,
+                Err(e) => {
+                    eprintln!("Could not read input files: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    };
+
+    if args.watch {
+        let [file] = files.as_slice() else {
+            eprintln!("--watch takes exactly one script");
+            std::process::exit(1);
+        };
+        std::process::exit(watch_file(file, &args, prelude.as_ref()));
+    }
+
+    let mut outcomes = Vec::with_capacity(files.len());
+    if let Some(code) = &args.eval {
+        outcomes.push(run_code(code.clone(), None, &args, prelude.as_ref()));
+    }
+    for file in &files {
+        if files.len() > 1 {
+            println!("=== {} ===", file.display());
+        }
+        outcomes.push(run_file(file, &args, prelude.as_ref()));
+    }
+
+    let _ = std::io::stdout().flush();
+    if outcomes.contains(&Outcome::TimedOut) {
+        std::process::exit(TIMEOUT_EXIT_CODE);
+    }
+    if outcomes.contains(&Outcome::Failed) {
+        std::process::exit(1);
+    }
+}
+
+/// Runs one script in a fresh interpreter, with its directory as the import base.
+fn run_file(filename: &Path, args: &Args, prelude: Option<&Prelude>) -> Outcome {
+    let code = match std::fs::read_to_string(filename) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Could not read {}: {}", filename.display(), e);
+            return Outcome::Failed;
+        }
+    };
+    run_code(code, Some(filename), args, prelude)
+}
+
+/// Runs `code` in a fresh interpreter. `filename` is where the code came
+/// from, `None` for `--eval`; only files are autofixed in place.
+fn run_code(
+    code: String,
+    filename: Option<&Path>,
+    args: &Args,
+    prelude: Option<&Prelude>,
+) -> Outcome {
     let debug = args.debug;
     let autofix = args.autofix;
 
     debug_print(&debug, "Starting interpretation process...");
     debug_print(&debug, "Reading input code...");
 
-    let code = if autofix {
+    let style = Style::for_stderr(args.no_color);
+    let code = if let (true, Some(filename)) = (autofix, filename) {
         debug_print(&debug, "Autofix enabled, fixing code...");
         let fixed_code = lexer::autofix(&code);
-        if fixed_code != code {
-            debug_print(&debug, "Code was modified by autofix.");
-            // write the fixed code back to the file
-            std::fs::write(&args.filename, &fixed_code)
-                .expect("Could not write fixed code back to file");
-        } else {
+        if fixed_code == code {
             debug_print(&debug, "No changes made by autofix.");
+            code
+        } else if let Err(reason) = lexer::verify_autofix(&code, &fixed_code) {
+            // Running the unchecked fix could misbehave just like saving it
+            eprintln!(
+                "{} not applying autofix to {}: {}",
+                style.severity(Severity::Warning, "warning:"),
+                filename.display(),
+                reason
+            );
+            code
+        } else {
+            debug_print(&debug, "Code was modified by autofix.");
+            if let Err(e) = write_fixed(filename, &code, &fixed_code, args.autofix_backup) {
+                eprintln!(
+                    "{} could not write autofix changes to {}: {}",
+                    style.severity(Severity::Warning, "warning:"),
+                    filename.display(),
+                    e
+                );
+            }
+            fixed_code
         }
-        fixed_code
     } else {
         code
     };
@@ -61,7 +445,13 @@ async fn main() {
     debug_print(&debug, code.as_str());
 
     // Tokenize
-    let tokens = tokenize(code.to_string());
+    let tokens = match tokenize(code.to_string()) {
+        Ok(tokens) => tokens,
+        Err(e) => {
+            eprint!("{}", e.render(Some(&code), filename, style));
+            return Outcome::Failed;
+        }
+    };
     debug_print(&debug, "\nTokens:");
     let _tokens_as_tokentype: Vec<_> = tokens.iter().map(|token| token.token.to_owned()).collect();
 
@@ -73,7 +463,13 @@ async fn main() {
         }
     }
 
-    let parse_result = parse(&tokens).unwrap();
+    let parse_result = match parse(&tokens) {
+        Ok(program) => program,
+        Err(e) => {
+            eprint!("{}", e.render(Some(&code), filename, style));
+            return Outcome::Failed;
+        }
+    };
     debug_print(&debug, "\nParsed successfully.");
     debug_print(&debug, format!("AST: {:#?}", parse_result).as_str());
 
@@ -83,9 +479,161 @@ async fn main() {
         &debug,
         "-------------------------------------------------------------",
     );
-    interpreter::interpret(&parse_result);
+    let mut interpreter = Interpreter::new();
+    if let Some(dir) = filename.and_then(Path::parent) {
+        interpreter.set_import_base(dir.to_path_buf());
+    }
+    if let Some(filename) = filename {
+        interpreter.set_script_file(&filename.display().to_string());
+    }
+    interpreter.set_max_call_depth(args.max_call_depth);
+    interpreter.set_log_level(args.log_level);
+    if args.allow_exec {
+        interpreter.enable_process_spawning();
+    }
+    // Loaded before the hooks are installed so tracing and the debugger
+    // start at the script itself
+    if let Some(prelude) = prelude {
+        if !prelude.load(&mut interpreter, style) {
+            return Outcome::Failed;
+        }
+    }
+    if args.trace {
+        interpreter.set_tracer(trace_printer());
+    }
+    if args.debug_interactive {
+        interpreter.set_debugger(Debugger::new(Box::new(prompt_debugger)).stop_on_entry());
+    }
+    if args.profile {
+        interpreter.set_profiling(true);
+    }
+    if args.coverage || args.coverage_lcov.is_some() {
+        interpreter.set_coverage(true);
+    }
+    let watchdog = args.timeout.map(|seconds| {
+        Watchdog::spawn(
+            interpreter.cancel_handle(),
+            Duration::from_secs_f64(seconds),
+        )
+    });
+
+    let result = interpreter.interpret(&parse_result);
+
+    if let (Some(watchdog), Some(seconds)) = (&watchdog, args.timeout) {
+        if watchdog.fired() {
+            let _ = std::io::stdout().flush();
+            eprintln!("execution timed out after {}s", seconds);
+            return Outcome::TimedOut;
+        }
+    }
+    let outcome = match result {
+        Ok(()) => {
+            println!("Program executed successfully.");
+            Outcome::Success
+        }
+        Err(e) => {
+            let error = interpreter.runtime_error(e);
+            eprint!("{}", errors::render_runtime_error(&error, style));
+            Outcome::Failed
+        }
+    };
+    if let Some(report) = interpreter.profile_report() {
+        println!("{}", report);
+    }
+    if let Some(report) = interpreter.coverage_report() {
+        if args.coverage {
+            println!("{}", report);
+        }
+        if let Some(lcov_path) = &args.coverage_lcov {
+            if let Err(e) = std::fs::write(lcov_path, report.to_lcov()) {
+                eprintln!("Could not write {}: {}", lcov_path.display(), e);
+            }
+        }
+    }
     debug_print(
         &debug,
         "-------------------------------------------------------------",
     );
+    outcome
+}
+
+/// Runs `filename` until Ctrl-C, re-running it after every change.
+fn watch_file(filename: &Path, args: &Args, prelude: Option<&Prelude>) -> i32 {
+    let options = WatchOptions::default();
+    // Cancel handle of the run in progress, so Ctrl-C also stops a long run
+    let running: Arc<Mutex<Option<Arc<AtomicBool>>>> = Arc::default();
+    tokio::spawn({
+        let stop = options.stop.clone();
+        let running = running.clone();
+        async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                stop.store(true, Ordering::Relaxed);
+                if let Some(cancel) = running.lock().unwrap().as_ref() {
+                    cancel.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+    });
+
+    let style = Style::for_stderr(args.no_color);
+    let watchdog = RefCell::new(None);
+    let make_interpreter = || {
+        let mut interpreter = Interpreter::new();
+        if let Some(dir) = filename.parent() {
+            interpreter.set_import_base(dir.to_path_buf());
+        }
+        interpreter.set_max_call_depth(args.max_call_depth);
+        interpreter.set_log_level(args.log_level);
+        if args.allow_exec {
+            interpreter.enable_process_spawning();
+        }
+        // The prelude is not watched; a broken one is reported and the
+        // script runs without it
+        if let Some(prelude) = prelude {
+            prelude.load(&mut interpreter, style);
+        }
+        if args.trace {
+            interpreter.set_tracer(trace_printer());
+        }
+        if args.debug_interactive {
+            interpreter.set_debugger(Debugger::new(Box::new(prompt_debugger)).stop_on_entry());
+        }
+        *watchdog.borrow_mut() = args.timeout.map(|seconds| {
+            Watchdog::spawn(
+                interpreter.cancel_handle(),
+                Duration::from_secs_f64(seconds),
+            )
+        });
+        *running.lock().unwrap() = Some(interpreter.cancel_handle());
+        interpreter
+    };
+    let on_result = |_run: usize, result: Result<(), RunError>| {
+        let timed_out = watchdog.take().is_some_and(|watchdog| watchdog.fired());
+        if options.stop.load(Ordering::Relaxed) {
+            return;
+        }
+        match result {
+            _ if timed_out => eprintln!(
+                "execution timed out after {}s",
+                args.timeout.unwrap_or_default()
+            ),
+            Ok(()) => println!("Program executed successfully."),
+            Err(RunError::Syntax(e)) => eprint!("{}", e.render(None, Some(filename), style)),
+            Err(RunError::Runtime(e)) => eprint!("{}", errors::render_runtime_error(&e, style)),
+            Err(e) => eprintln!("{}", e),
+        }
+        println!(
+            "--- watching {} for changes (Ctrl-C to stop) ---",
+            filename.display()
+        );
+        let _ = std::io::stdout().flush();
+    };
+
+    match watch(filename, &options, make_interpreter, on_result) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
 }
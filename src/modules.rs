@@ -0,0 +1,318 @@
+//! Finding the files `import "name";` refers to, and installing libraries
+//! into a project's `mouse_modules/` directory with `mouse install`.
+//!
+//! A library is a directory with a `lib.mouse` entry point and a
+//! `mouse.json` manifest naming it, e.g. `{"name": "greet", "version": "1.0.0"}`.
+//! It is installed as `mouse_modules/<name>/` of the project, and found
+//! from scripts in any of the project's directories and from other
+//! installed libraries.
+
+use serde::Deserialize;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Directory next to a project's scripts that installed libraries live in.
+pub const MODULES_DIR: &str = "mouse_modules";
+
+/// Entry point of an installed library, run by `import "name";`.
+pub const LIBRARY_ENTRY: &str = "lib.mouse";
+
+/// Manifest every library carries at its root.
+pub const MANIFEST_FILE: &str = "mouse.json";
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Manifest {
+    pub name: String,
+    pub version: String,
+}
+
+/// Places `import "name";` looks in, in order: a file relative to `base`
+/// (with `.mouse` added unless given), then an installed library in the
+/// `mouse_modules/` of `base` or of the nearest of its parent directories.
+/// So a library installed next to another finds it, as does a script in a
+/// subdirectory of the project.
+pub fn candidates(base: &Path, name: &str) -> Vec<PathBuf> {
+    let file = if name.ends_with(".mouse") {
+        base.join(name)
+    } else {
+        base.join(format!("{}.mouse", name))
+    };
+    let mut candidates = vec![file];
+    for dir in absolute(base).ancestors() {
+        // `mouse_modules/mouse_modules/` is never where libraries go
+        if dir.file_name() == Some(OsStr::new(MODULES_DIR)) {
+            continue;
+        }
+        candidates.push(dir.join(MODULES_DIR).join(name).join(LIBRARY_ENTRY));
+    }
+    candidates
+}
+
+/// Where `mouse install` run in `dir` installs to: the nearest of `dir` and
+/// its parents that has a `mouse_modules/` already, else `dir` itself.
+pub fn project_root(dir: &Path) -> PathBuf {
+    let dir = absolute(dir);
+    dir.ancestors()
+        .find(|ancestor| ancestor.join(MODULES_DIR).is_dir())
+        .unwrap_or(&dir)
+        .to_path_buf()
+}
+
+/// `path` made absolute, without resolving symlinks; the empty path is the
+/// current directory.
+fn absolute(path: &Path) -> PathBuf {
+    let path = if path.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        path
+    };
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// The first of `candidates` that exists, or an error listing every place
+/// that was searched.
+pub fn resolve(base: &Path, name: &str) -> Result<PathBuf, String> {
+    let candidates = candidates(base, name);
+    if let Some(found) = candidates.iter().find(|path| path.is_file()) {
+        return Ok(found.clone());
+    }
+    let searched: Vec<String> = candidates
+        .iter()
+        .map(|path| format!("  {}", path.display()))
+        .collect();
+    Err(format!(
+        "Cannot find module '{}', searched:\n{}",
+        name,
+        searched.join("\n")
+    ))
+}
+
+/// Reads and checks the manifest of the library in `dir`.
+pub fn read_manifest(dir: &Path) -> Result<Manifest, String> {
+    let path = dir.join(MANIFEST_FILE);
+    let text = fs::read_to_string(&path)
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    let manifest: Manifest = serde_json::from_str(&text)
+        .map_err(|e| format!("Invalid manifest {}: {}", path.display(), e))?;
+    let valid_name = !manifest.name.is_empty()
+        && manifest.name != "."
+        && manifest.name != ".."
+        && !manifest.name.contains(['/', '\\']);
+    if !valid_name {
+        return Err(format!(
+            "Invalid manifest {}: '{}' is not a usable library name",
+            path.display(),
+            manifest.name
+        ));
+    }
+    if !dir.join(LIBRARY_ENTRY).is_file() {
+        return Err(format!(
+            "{} has no {} entry point",
+            dir.display(),
+            LIBRARY_ENTRY
+        ));
+    }
+    Ok(manifest)
+}
+
+/// Copies a library from a local directory or clones it from a git URL
+/// into `project`'s `mouse_modules/`, replacing an installed library of
+/// the same name, and returns its manifest.
+pub fn install(source: &str, project: &Path) -> Result<Manifest, String> {
+    let modules = project.join(MODULES_DIR);
+    fs::create_dir_all(&modules)
+        .map_err(|e| format!("Could not create {}: {}", modules.display(), e))?;
+
+    // Fetched next to its final place, so moving it there is a rename
+    let staging = modules.join(format!(".install-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&staging);
+    let result = fetch(source, &staging).and_then(|()| {
+        let manifest = read_manifest(&staging)?;
+        let target = modules.join(&manifest.name);
+        if target.exists() {
+            fs::remove_dir_all(&target)
+                .map_err(|e| format!("Could not replace {}: {}", target.display(), e))?;
+        }
+        fs::rename(&staging, &target)
+            .map_err(|e| format!("Could not install into {}: {}", target.display(), e))?;
+        Ok(manifest)
+    });
+    if result.is_err() {
+        let _ = fs::remove_dir_all(&staging);
+    }
+    result
+}
+
+fn fetch(source: &str, target: &Path) -> Result<(), String> {
+    let path = Path::new(source);
+    if path.is_dir() {
+        return copy_dir(path, target)
+            .map_err(|e| format!("Could not copy {}: {}", path.display(), e));
+    }
+    if !is_git_url(source) {
+        return Err(format!("{} is neither a directory nor a git URL", source));
+    }
+    let status = Command::new("git")
+        .args(["clone", "--quiet", "--depth", "1", source])
+        .arg(target)
+        .status()
+        .map_err(|e| format!("Could not run git: {}", e))?;
+    if !status.success() {
+        return Err(format!("git clone {} failed", source));
+    }
+    Ok(())
+}
+
+fn is_git_url(source: &str) -> bool {
+    source.contains("://") || source.starts_with("git@") || source.ends_with(".git")
+}
+
+/// Copies `from` into a new directory `to`, leaving out version control data.
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn library(dir: &Path, name: &str) -> PathBuf {
+        let library = dir.join(format!("{}-src", name));
+        fs::create_dir_all(library.join("helpers")).unwrap();
+        fs::write(
+            library.join(MANIFEST_FILE),
+            format!(r#"{{"name": "{}", "version": "0.1.0"}}"#, name),
+        )
+        .unwrap();
+        fs::write(library.join(LIBRARY_ENTRY), "fn hello() { return 1; }").unwrap();
+        fs::write(library.join("helpers").join("more.mouse"), "let more = 1;").unwrap();
+        library
+    }
+
+    #[test]
+    fn test_install_from_local_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = library(dir.path(), "greet");
+        let project = dir.path().join("project");
+
+        let manifest = install(source.to_str().unwrap(), &project).unwrap();
+        assert_eq!(
+            manifest,
+            Manifest {
+                name: "greet".to_string(),
+                version: "0.1.0".to_string()
+            }
+        );
+        let installed = project.join(MODULES_DIR).join("greet");
+        assert!(installed.join(LIBRARY_ENTRY).is_file());
+        assert!(installed.join("helpers").join("more.mouse").is_file());
+        assert_eq!(
+            resolve(&project, "greet"),
+            Ok(installed.join(LIBRARY_ENTRY))
+        );
+
+        // Installing again replaces the library
+        fs::write(source.join(LIBRARY_ENTRY), "fn hello() { return 2; }").unwrap();
+        install(source.to_str().unwrap(), &project).unwrap();
+        assert_eq!(
+            fs::read_to_string(installed.join(LIBRARY_ENTRY)).unwrap(),
+            "fn hello() { return 2; }"
+        );
+        assert_eq!(fs::read_dir(project.join(MODULES_DIR)).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_install_rejects_libraries_without_manifest_or_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = library(dir.path(), "broken");
+        fs::remove_file(source.join(LIBRARY_ENTRY)).unwrap();
+        let project = dir.path().join("project");
+
+        let err = install(source.to_str().unwrap(), &project).unwrap_err();
+        assert!(err.ends_with("has no lib.mouse entry point"), "{}", err);
+        fs::remove_file(source.join(MANIFEST_FILE)).unwrap();
+        let err = install(source.to_str().unwrap(), &project).unwrap_err();
+        assert!(err.starts_with("Could not read"), "{}", err);
+        // Nothing is left behind
+        assert_eq!(fs::read_dir(project.join(MODULES_DIR)).unwrap().count(), 0);
+
+        let err = install("no/such/dir", &project).unwrap_err();
+        assert_eq!(err, "no/such/dir is neither a directory nor a git URL");
+    }
+
+    #[test]
+    fn test_relative_file_comes_before_installed_library() {
+        let dir = tempfile::tempdir().unwrap();
+        let library = dir.path().join(MODULES_DIR).join("util");
+        fs::create_dir_all(&library).unwrap();
+        fs::write(library.join(LIBRARY_ENTRY), "").unwrap();
+        assert_eq!(resolve(dir.path(), "util"), Ok(library.join(LIBRARY_ENTRY)));
+
+        fs::write(dir.path().join("util.mouse"), "").unwrap();
+        assert_eq!(
+            resolve(dir.path(), "util"),
+            Ok(dir.path().join("util.mouse"))
+        );
+        assert_eq!(
+            resolve(dir.path(), "util.mouse"),
+            Ok(dir.path().join("util.mouse"))
+        );
+    }
+
+    #[test]
+    fn test_unresolved_import_lists_searched_locations() {
+        let base = Path::new("project");
+        let cwd = std::env::current_dir().unwrap();
+        let mut expected = vec![format!("  {}", base.join("missing.mouse").display())];
+        for dir in cwd.join(base).ancestors() {
+            if dir.file_name() != Some(OsStr::new(MODULES_DIR)) {
+                let library = dir.join("mouse_modules/missing/lib.mouse");
+                expected.push(format!("  {}", library.display()));
+            }
+        }
+        assert_eq!(
+            resolve(base, "missing"),
+            Err(format!(
+                "Cannot find module 'missing', searched:\n{}",
+                expected.join("\n")
+            ))
+        );
+    }
+
+    #[test]
+    fn test_libraries_are_found_from_parent_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let modules = dir.path().join(MODULES_DIR);
+        for name in ["util", "other"] {
+            fs::create_dir_all(modules.join(name)).unwrap();
+            fs::write(modules.join(name).join(LIBRARY_ENTRY), "").unwrap();
+        }
+        let nested = dir.path().join("src").join("deeper");
+        fs::create_dir_all(&nested).unwrap();
+
+        let found = resolve(&nested, "util").unwrap();
+        assert_eq!(found, modules.join("util").join(LIBRARY_ENTRY));
+        // From inside an installed library, its neighbours
+        assert_eq!(
+            resolve(&modules.join("util"), "other"),
+            Ok(modules.join("other").join(LIBRARY_ENTRY))
+        );
+        assert_eq!(project_root(&nested), dir.path());
+        assert_eq!(project_root(&modules.join("util")), dir.path());
+    }
+}
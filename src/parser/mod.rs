@@ -2,10 +2,13 @@ use crate::{
     errors::Error,
     lexer::{Comparison, Operator, Token, TokenType},
 };
-#[derive(Debug, Clone, PartialEq)]
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Expr {
-    Identifier(String),
-    Number(i32),
+    Identifier(Ident),
+    Number(i64),
     String(String),
     Binary {
         left: Box<Expr>,
@@ -15,11 +18,95 @@ pub enum Expr {
     FunctionCall {
         name: String,
         args: Vec<Expr>,
+        /// Names of `name: value` arguments, one entry per argument. Empty
+        /// if every argument is positional.
+        #[serde(default)]
+        arg_names: Vec<Option<String>>,
     },
     ObjectCall(String, Box<Expr>),
+    /// Member of a computed value, e.g. `make_user().name` or `a.b(x).c`.
+    MemberAccess(Box<Expr>, Box<Expr>),
+}
+
+/// Name read by an `Expr::Identifier`, along with the property slot the
+/// interpreter last found it at (see `interpreter::properties`). The slot
+/// is a cache only: it is not compared, printed or serialized.
+#[derive(Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub struct Ident {
+    name: String,
+    slot: AtomicUsize,
+}
+
+impl Ident {
+    pub fn new(name: String) -> Self {
+        Ident {
+            name,
+            slot: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn slot(&self) -> usize {
+        self.slot.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn remember_slot(&self, slot: usize) {
+        self.slot.store(slot, Ordering::Relaxed);
+    }
+}
+
+impl std::ops::Deref for Ident {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Clone for Ident {
+    fn clone(&self) -> Self {
+        Ident {
+            name: self.name.clone(),
+            slot: AtomicUsize::new(self.slot()),
+        }
+    }
+}
+
+impl PartialEq for Ident {
+    fn eq(&self, other: &Ident) -> bool {
+        self.name == other.name
+    }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+impl std::fmt::Debug for Ident {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.name.fmt(f)
+    }
+}
+
+impl std::fmt::Display for Ident {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.name.fmt(f)
+    }
+}
+
+impl From<String> for Ident {
+    fn from(name: String) -> Self {
+        Ident::new(name)
+    }
+}
+
+impl From<Ident> for String {
+    fn from(ident: Ident) -> Self {
+        ident.name
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum BinaryOp {
     Add,
     Subtract,
@@ -33,14 +120,66 @@ pub enum BinaryOp {
     GreaterThanOrEqual,
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum Stmt {
+/// Source position of the first token of a statement (1-based line and column).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Span {
+    pub line: u32,
+    pub column: u32,
+}
+
+impl Span {
+    pub fn new(line: u32, column: u32) -> Self {
+        Span { line, column }
+    }
+}
+
+impl From<&Token> for Span {
+    fn from(token: &Token) -> Self {
+        Span::new(token.line, token.column)
+    }
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Destructuring target on the left side of a `let`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Pattern {
+    Identifier(String),
+    /// `{ name, age }` binds each field to a pattern, shorthand fields bind
+    /// to a variable of the same name.
+    Object(Vec<(String, Pattern)>),
+    /// `[first, second]` binds elements by position, extra elements are ignored.
+    Array(Vec<Pattern>),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+impl Stmt {
+    pub fn new(kind: StmtKind, span: Span) -> Self {
+        Stmt { kind, span }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum StmtKind {
     Let {
         name: String,
         value: Expr,
     },
+    LetPattern {
+        pattern: Pattern,
+        value: Expr,
+    },
     Assign {
-        name: String,
+        name: Ident,
         value: Expr,
     },
     Function {
@@ -59,10 +198,46 @@ pub enum Stmt {
         condition: Expr,
         body: Vec<Stmt>,
     },
+    /// `try { body } catch (error_name) { handler }`
+    Try {
+        body: Vec<Stmt>,
+        error_name: String,
+        handler: Vec<Stmt>,
+    },
+    Throw(Expr),
+    /// `for name in iterable { body }`
+    For {
+        name: String,
+        iterable: Expr,
+        body: Vec<Stmt>,
+    },
+    Break,
+    /// `import "name";`, see `crate::modules::resolve`
+    Import(String),
     Expression(Expr), // e.g. let x = 5;
 }
 
-#[derive(Debug, Clone, PartialEq)]
+impl StmtKind {
+    /// Short keyword-like name used in traces and diagnostics.
+    pub fn name(&self) -> &'static str {
+        match self {
+            StmtKind::Let { .. } | StmtKind::LetPattern { .. } => "let",
+            StmtKind::Assign { .. } => "assign",
+            StmtKind::Function { .. } => "fn",
+            StmtKind::Return(_) => "return",
+            StmtKind::If { .. } => "if",
+            StmtKind::While { .. } => "while",
+            StmtKind::Try { .. } => "try",
+            StmtKind::Throw(_) => "throw",
+            StmtKind::For { .. } => "for",
+            StmtKind::Break => "break",
+            StmtKind::Import(_) => "import",
+            StmtKind::Expression(_) => "expression",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Program {
     pub statements: Vec<Stmt>,
 }
@@ -91,8 +266,51 @@ impl From<&Comparison> for BinaryOp {
     }
 }
 
-fn parse_fn_call_params(tokens: &[Token], idx: usize) -> Result<(Vec<Expr>, u8), Error> {
+/// Default for `parse_with_max_nesting`.
+pub const DEFAULT_MAX_NESTING: usize = 256;
+
+/// How deep the parser is in nested expressions, blocks and patterns. Each
+/// level recurses, so a limit keeps hostile input like 50k `(` from
+/// overflowing the stack.
+#[derive(Debug, Clone, Copy)]
+struct Nesting {
+    depth: usize,
+    limit: usize,
+}
+
+impl Nesting {
+    fn new(limit: usize) -> Self {
+        Nesting { depth: 0, limit }
+    }
+
+    /// One level deeper, for the construct starting at `idx`.
+    fn deeper(self, tokens: &[Token], idx: usize) -> Result<Nesting, Error> {
+        if self.depth >= self.limit {
+            return Err(match tokens.get(idx).or(tokens.last()) {
+                Some(token) => Error::nesting_too_deep(token, self.limit, "parse"),
+                None => Error::unexpected_eof("parse"),
+            });
+        }
+        Ok(Nesting {
+            depth: self.depth + 1,
+            limit: self.limit,
+        })
+    }
+}
+
+/// Call arguments, their names as in `Expr::FunctionCall`, and the number
+/// of tokens consumed.
+type CallParams = (Vec<Expr>, Vec<Option<String>>, usize);
+
+/// Parses call arguments up to the closing bracket. Positional arguments
+/// come first, then any `name: value` ones.
+fn parse_fn_call_params(
+    tokens: &[Token],
+    idx: usize,
+    nesting: Nesting,
+) -> Result<CallParams, Error> {
     let mut params = Vec::new();
+    let mut names: Vec<Option<String>> = Vec::new();
     let mut idx2 = idx;
     loop {
         let token = tokens
@@ -107,18 +325,36 @@ fn parse_fn_call_params(tokens: &[Token], idx: usize) -> Result<(Vec<Expr>, u8),
                 idx2 += 1;
             }
             TokenType::BracketClose => {
-                return Ok((params, (idx2 - idx) as u8));
+                if names.iter().all(Option::is_none) {
+                    names.clear();
+                }
+                return Ok((params, names, idx2 - idx));
             }
             _ => {
-                let (expr, len) = parse_expr(tokens, idx2)?;
+                let name = match (&token.token, tokens.get(idx2 + 1).map(|t| &t.token)) {
+                    (TokenType::Identifier(name), Some(TokenType::Colon)) => {
+                        idx2 += 2;
+                        Some(name.to_owned())
+                    }
+                    _ if names.iter().any(Option::is_some) => {
+                        return Err(Error::syntax_error(
+                            token,
+                            "named argument (positional ones go first)",
+                            "parse_fn_call_params",
+                        ))
+                    }
+                    _ => None,
+                };
+                let (expr, len) = parse_expr(tokens, idx2, nesting)?;
                 params.push(expr);
-                idx2 += len as usize;
+                names.push(name);
+                idx2 += len;
             }
         }
     }
 }
 
-fn parse_params(tokens: &[Token], idx: usize) -> Result<(Vec<String>, u8), Error> {
+fn parse_params(tokens: &[Token], idx: usize) -> Result<(Vec<String>, usize), Error> {
     let mut params: Vec<String> = Vec::new();
     let mut idx2 = idx;
     // only strings
@@ -135,7 +371,7 @@ fn parse_params(tokens: &[Token], idx: usize) -> Result<(Vec<String>, u8), Error
                 idx2 += 1;
             }
             TokenType::BracketClose => {
-                return Ok((params, (idx2 - idx) as u8));
+                return Ok((params, idx2 - idx));
             }
             _ => {
                 return Err(Error::syntax_error(
@@ -149,21 +385,21 @@ fn parse_params(tokens: &[Token], idx: usize) -> Result<(Vec<String>, u8), Error
     Err(Error::unexpected_eof("parse_params"))
 }
 
-fn parse_fn(tokens: &[Token], idx: usize) -> Result<(Stmt, u8), Error> {
+fn parse_fn(tokens: &[Token], idx: usize, nesting: Nesting) -> Result<(StmtKind, usize), Error> {
     let token = tokens
         .get(idx + 1)
         .ok_or(Error::unexpected_eof("parse_fn"))?;
     match &token.token {
         TokenType::Identifier(name) => {
             let params = parse_params(tokens, idx + 3)?;
-            let body = parse_block(tokens, idx + params.1 as usize + 5)?;
+            let body = parse_block(tokens, idx + params.1 + 5, nesting)?;
             Ok((
-                Stmt::Function {
+                StmtKind::Function {
                     name: name.to_owned(),
                     params: params.0,
                     body: body.0,
                 },
-                5 + params.1 + body.1 as u8,
+                5 + params.1 + body.1,
             ))
         }
         _ => Err(Error::syntax_error(token, "function name", "parse_fn")),
@@ -171,17 +407,21 @@ fn parse_fn(tokens: &[Token], idx: usize) -> Result<(Stmt, u8), Error> {
 }
 
 /// Parse an identifier token. (e.g. `let x = 1;`)
-fn parse_identifier(tokens: &[Token], name: String, idx: usize) -> Result<(Stmt, u8), Error> {
-    println!("parse identifier");
+fn parse_identifier(
+    tokens: &[Token],
+    name: String,
+    idx: usize,
+    nesting: Nesting,
+) -> Result<(StmtKind, usize), Error> {
     let token = tokens
         .get(idx + 1)
         .ok_or(Error::unexpected_eof("parse_identifier"))?;
     match token.token {
         TokenType::Assign => {
-            let value = parse_expr(tokens, idx + 2)?;
+            let value = parse_expr(tokens, idx + 2, nesting)?;
             Ok((
-                Stmt::Assign {
-                    name,
+                StmtKind::Assign {
+                    name: Ident::new(name),
                     value: value.0,
                 },
                 2 + value.1,
@@ -189,20 +429,39 @@ fn parse_identifier(tokens: &[Token], name: String, idx: usize) -> Result<(Stmt,
         }
         TokenType::BracketOpen => {
             // parse expression
-            let expr = parse_expr(tokens, idx)?;
-            Ok((Stmt::Expression(expr.0), expr.1))
+            let expr = parse_expr(tokens, idx, nesting)?;
+            Ok((StmtKind::Expression(expr.0), expr.1))
+        }
+        _ => parse_expression_statement(tokens, idx, nesting),
+    }
+}
+
+/// Parses a bare expression used as a statement, e.g. `x;` or `2 + 3;`,
+/// whose value a REPL shows. It must end the statement, so a typo like
+/// `x 5;` is still a syntax error.
+fn parse_expression_statement(
+    tokens: &[Token],
+    idx: usize,
+    nesting: Nesting,
+) -> Result<(StmtKind, usize), Error> {
+    let (expr, consumed) = parse_expr(tokens, idx, nesting)?;
+    match tokens.get(idx + consumed) {
+        None => {}
+        Some(token) if matches!(token.token, TokenType::Semicolon | TokenType::BraceClose) => {}
+        Some(token) => {
+            return Err(Error::syntax_error(
+                token,
+                "assignment operator or end of statement",
+                "parse_expression_statement",
+            ))
         }
-        _ => Err(Error::syntax_error(
-            token,
-            "assignment operator",
-            "parse_identifier",
-        )),
     }
+    Ok((StmtKind::Expression(expr), consumed))
 }
 
 /// Parses a primary expression: number, string, identifier, or function call
 /// Returns the parsed expression and the number of tokens consumed
-fn parse_primary(tokens: &[Token], idx: usize) -> Result<(Expr, u8), Error> {
+fn parse_primary(tokens: &[Token], idx: usize, nesting: Nesting) -> Result<(Expr, usize), Error> {
     let token = tokens
         .get(idx)
         .ok_or(Error::unexpected_eof("parse_primary"))?;
@@ -211,7 +470,7 @@ fn parse_primary(tokens: &[Token], idx: usize) -> Result<(Expr, u8), Error> {
         TokenType::Number(num) => Ok((Expr::Number(*num), 1)),
         TokenType::String(str) => Ok((Expr::String(str.clone()), 1)),
         TokenType::ObjectName(name) => {
-            let object_member = parse_primary(tokens, idx + 1)?;
+            let object_member = parse_primary(tokens, idx + 1, nesting.deeper(tokens, idx)?)?;
             Ok((
                 Expr::ObjectCall(name.clone(), Box::new(object_member.0)),
                 object_member.1 + 1,
@@ -221,17 +480,29 @@ fn parse_primary(tokens: &[Token], idx: usize) -> Result<(Expr, u8), Error> {
             // Check if this is a function call
             if let Some(next_token) = tokens.get(idx + 1) {
                 if next_token.token == TokenType::BracketOpen {
-                    let args = parse_fn_call_params(tokens, idx + 2)?;
+                    let args = parse_fn_call_params(tokens, idx + 2, nesting)?;
                     return Ok((
                         Expr::FunctionCall {
                             name: ident.clone(),
                             args: args.0,
+                            arg_names: args.1,
                         },
-                        3 + args.1,
+                        3 + args.2,
                     ));
                 }
             }
-            Ok((Expr::Identifier(ident.clone()), 1))
+            Ok((Expr::Identifier(Ident::new(ident.clone())), 1))
+        }
+        TokenType::BracketOpen => {
+            // Grouping, e.g. `(a + b) * c`
+            let (inner, consumed) = parse_expr(tokens, idx + 1, nesting)?;
+            let close = tokens
+                .get(idx + 1 + consumed)
+                .ok_or(Error::unexpected_eof("parse_primary"))?;
+            if close.token != TokenType::BracketClose {
+                return Err(Error::syntax_error(close, ")", "parse_primary"));
+            }
+            Ok((inner, consumed + 2))
         }
         _ => Err(Error::syntax_error(
             token,
@@ -241,13 +512,31 @@ fn parse_primary(tokens: &[Token], idx: usize) -> Result<(Expr, u8), Error> {
     }
 }
 
+/// Parses a primary expression followed by any `.member` accesses
+/// Returns the parsed expression and the number of tokens consumed
+fn parse_postfix(tokens: &[Token], idx: usize, nesting: Nesting) -> Result<(Expr, usize), Error> {
+    let (mut expr, mut consumed) = parse_primary(tokens, idx, nesting)?;
+
+    while let Some(Token {
+        token: TokenType::Dot,
+        ..
+    }) = tokens.get(idx + consumed)
+    {
+        let (member, member_consumed) = parse_primary(tokens, idx + consumed + 1, nesting)?;
+        expr = Expr::MemberAccess(Box::new(expr), Box::new(member));
+        consumed += 1 + member_consumed;
+    }
+
+    Ok((expr, consumed))
+}
+
 /// Parses multiplication and division (higher precedence)
 /// Returns the parsed expression and the number of tokens consumed
-fn parse_term(tokens: &[Token], idx: usize) -> Result<(Expr, u8), Error> {
-    let (mut left, mut consumed) = parse_primary(tokens, idx)?;
+fn parse_term(tokens: &[Token], idx: usize, nesting: Nesting) -> Result<(Expr, usize), Error> {
+    let (mut left, mut consumed) = parse_postfix(tokens, idx, nesting)?;
 
     loop {
-        let next_idx = idx + consumed as usize;
+        let next_idx = idx + consumed;
         if let Some(next_token) = tokens.get(next_idx) {
             match &next_token.token {
                 TokenType::Operator(Operator::Multiply) | TokenType::Operator(Operator::Divide) => {
@@ -255,7 +544,7 @@ fn parse_term(tokens: &[Token], idx: usize) -> Result<(Expr, u8), Error> {
                         TokenType::Operator(op) => op,
                         _ => unreachable!(),
                     });
-                    let (right, right_consumed) = parse_primary(tokens, next_idx + 1)?;
+                    let (right, right_consumed) = parse_postfix(tokens, next_idx + 1, nesting)?;
                     left = Expr::Binary {
                         left: Box::new(left),
                         op,
@@ -275,20 +564,20 @@ fn parse_term(tokens: &[Token], idx: usize) -> Result<(Expr, u8), Error> {
 
 /// Parses addition, subtraction, and comparisons (lower precedence)
 /// Returns the parsed expression and the number of tokens consumed
-fn parse_expr(tokens: &[Token], idx: usize) -> Result<(Expr, u8), Error> {
-    let (mut left, mut consumed) = parse_term(tokens, idx)?;
+fn parse_expr(tokens: &[Token], idx: usize, nesting: Nesting) -> Result<(Expr, usize), Error> {
+    let nesting = nesting.deeper(tokens, idx)?;
+    let (mut left, mut consumed) = parse_term(tokens, idx, nesting)?;
 
     loop {
-        let next_idx = idx + consumed as usize;
+        let next_idx = idx + consumed;
         if let Some(next_token) = tokens.get(next_idx) {
-            println!("Parse expr: Next token: {:?}", next_token);
             match &next_token.token {
                 TokenType::Operator(Operator::Add) | TokenType::Operator(Operator::Subtract) => {
                     let op = BinaryOp::from(match &next_token.token {
                         TokenType::Operator(op) => op,
                         _ => unreachable!(),
                     });
-                    let (right, right_consumed) = parse_term(tokens, next_idx + 1)?;
+                    let (right, right_consumed) = parse_term(tokens, next_idx + 1, nesting)?;
                     left = Expr::Binary {
                         left: Box::new(left),
                         op,
@@ -298,7 +587,7 @@ fn parse_expr(tokens: &[Token], idx: usize) -> Result<(Expr, u8), Error> {
                 }
                 TokenType::Comparison(cmp) => {
                     let op = BinaryOp::from(cmp);
-                    let (right, right_consumed) = parse_term(tokens, next_idx + 1)?;
+                    let (right, right_consumed) = parse_term(tokens, next_idx + 1, nesting)?;
                     left = Expr::Binary {
                         left: Box::new(left),
                         op,
@@ -307,7 +596,6 @@ fn parse_expr(tokens: &[Token], idx: usize) -> Result<(Expr, u8), Error> {
                     consumed += 1 + right_consumed;
                 }
                 _ => {
-                    println!("End of expression: {:?}", next_token);
                     break;
                 }
             }
@@ -319,7 +607,80 @@ fn parse_expr(tokens: &[Token], idx: usize) -> Result<(Expr, u8), Error> {
     Ok((left, consumed))
 }
 
-fn parse_let(tokens: &[Token], current_token: &Token, idx: usize) -> Result<(Stmt, u8), Error> {
+/// Parses a binding pattern: `name`, `{ a, b }` or `[a, b]`.
+/// Returns the parsed pattern and the number of tokens consumed
+fn parse_pattern(
+    tokens: &[Token],
+    idx: usize,
+    nesting: Nesting,
+) -> Result<(Pattern, usize), Error> {
+    let token = tokens
+        .get(idx)
+        .ok_or(Error::unexpected_eof("parse_pattern"))?;
+    let close = match &token.token {
+        TokenType::Identifier(name) => return Ok((Pattern::Identifier(name.to_owned()), 1)),
+        TokenType::BraceOpen => TokenType::BraceClose,
+        TokenType::SquareOpen => TokenType::SquareClose,
+        _ => return Err(Error::syntax_error(token, "identifier", "parse_pattern")),
+    };
+
+    let mut fields = Vec::new();
+    let mut elements = Vec::new();
+    let mut idx2 = idx + 1;
+    loop {
+        let token = tokens
+            .get(idx2)
+            .ok_or(Error::unexpected_eof("parse_pattern"))?;
+        if token.token == close {
+            idx2 += 1;
+            break;
+        }
+
+        if close == TokenType::BraceClose {
+            match &token.token {
+                TokenType::Identifier(name) => {
+                    fields.push((name.to_owned(), Pattern::Identifier(name.to_owned())));
+                    idx2 += 1;
+                }
+                _ => return Err(Error::syntax_error(token, "field name", "parse_pattern")),
+            }
+        } else {
+            let (element, len) = parse_pattern(tokens, idx2, nesting.deeper(tokens, idx2)?)?;
+            elements.push(element);
+            idx2 += len;
+        }
+
+        let separator = tokens
+            .get(idx2)
+            .ok_or(Error::unexpected_eof("parse_pattern"))?;
+        match &separator.token {
+            TokenType::Comma => idx2 += 1,
+            t if *t == close => {}
+            _ => {
+                let expected: String = close.into();
+                return Err(Error::syntax_error(
+                    separator,
+                    &format!(", or {}", expected),
+                    "parse_pattern",
+                ));
+            }
+        }
+    }
+
+    let pattern = if close == TokenType::BraceClose {
+        Pattern::Object(fields)
+    } else {
+        Pattern::Array(elements)
+    };
+    Ok((pattern, idx2 - idx))
+}
+
+fn parse_let(
+    tokens: &[Token],
+    current_token: &Token,
+    idx: usize,
+    nesting: Nesting,
+) -> Result<(StmtKind, usize), Error> {
     let name_token = tokens.get(idx + 1).ok_or(Error::syntax_error(
         current_token,
         "identifier",
@@ -327,6 +688,9 @@ fn parse_let(tokens: &[Token], current_token: &Token, idx: usize) -> Result<(Stm
     ))?;
     let name = match &name_token.token {
         TokenType::Identifier(name) => name,
+        TokenType::BraceOpen | TokenType::SquareOpen => {
+            return parse_let_pattern(tokens, current_token, idx, nesting)
+        }
         _ => return Err(Error::syntax_error(name_token, "identifier", "parse_let")),
     };
 
@@ -340,51 +704,77 @@ fn parse_let(tokens: &[Token], current_token: &Token, idx: usize) -> Result<(Stm
     }
 
     // value can be a value or an expression
-    let value = parse_expr(tokens, idx + 3)?;
+    let value = parse_expr(tokens, idx + 3, nesting)?;
 
-    let let_stmt = Stmt::Let {
+    let let_stmt = StmtKind::Let {
         name: name.to_owned(),
         value: value.0,
     };
     Ok((let_stmt, value.1 + 3))
 }
 
-fn parse_while(tokens: &[Token], idx: usize) -> Result<(Stmt, u8), Error> {
-    let condition = parse_expr(tokens, idx + 1)?;
-    println!("condition: {:?}", condition);
+/// Parse a destructuring let. (e.g. `let { name, age } = user;`)
+fn parse_let_pattern(
+    tokens: &[Token],
+    current_token: &Token,
+    idx: usize,
+    nesting: Nesting,
+) -> Result<(StmtKind, usize), Error> {
+    let (pattern, len) = parse_pattern(tokens, idx + 1, nesting)?;
+
+    let equal_idx = idx + 1 + len;
+    let equal_token =
+        tokens
+            .get(equal_idx)
+            .ok_or(Error::syntax_error(current_token, "=", "parse_let"))?;
+    if equal_token.token != TokenType::Assign {
+        return Err(Error::syntax_error(equal_token, "=", "parse_let"));
+    }
+
+    let value = parse_expr(tokens, equal_idx + 1, nesting)?;
+    Ok((
+        StmtKind::LetPattern {
+            pattern,
+            value: value.0,
+        },
+        len + value.1 + 2,
+    ))
+}
+
+fn parse_while(tokens: &[Token], idx: usize, nesting: Nesting) -> Result<(StmtKind, usize), Error> {
+    let condition = parse_expr(tokens, idx + 1, nesting)?;
     // expect {
     let open_brace_token = tokens
-        .get(idx + condition.1 as usize + 1)
+        .get(idx + condition.1 + 1)
         .ok_or(Error::unexpected_eof("parse_while"))?;
     if open_brace_token.token != TokenType::BraceOpen {
         return Err(Error::syntax_error(open_brace_token, "{", "parse_while"));
     }
 
     // then
-    let then_branch = parse_block(tokens, idx + condition.1 as usize + 2)?;
+    let then_branch = parse_block(tokens, idx + condition.1 + 2, nesting)?;
 
-    let while_stmt = Stmt::While {
+    let while_stmt = StmtKind::While {
         condition: condition.0,
         body: then_branch.0,
     };
     Ok((while_stmt, 2 + condition.1 + then_branch.1))
 }
 
-fn parse_if(tokens: &[Token], idx: usize) -> Result<(Stmt, u8), Error> {
-    let condition = parse_expr(tokens, idx + 1)?;
-    println!("condition: {:?}", condition);
+fn parse_if(tokens: &[Token], idx: usize, nesting: Nesting) -> Result<(StmtKind, usize), Error> {
+    let condition = parse_expr(tokens, idx + 1, nesting)?;
     // expect {
     let open_brace_token = tokens
-        .get(idx + condition.1 as usize + 1)
+        .get(idx + condition.1 + 1)
         .ok_or(Error::unexpected_eof("parse_if"))?;
     if open_brace_token.token != TokenType::BraceOpen {
         return Err(Error::syntax_error(open_brace_token, "{", "parse_if"));
     }
 
     // then
-    let then_branch = parse_block(tokens, idx + condition.1 as usize + 2)?;
+    let then_branch = parse_block(tokens, idx + condition.1 + 2, nesting)?;
 
-    let if_stmt = Stmt::If {
+    let if_stmt = StmtKind::If {
         condition: condition.0,
         then_branch: then_branch.0,
         else_branch: Option::None, // TODO
@@ -392,15 +782,136 @@ fn parse_if(tokens: &[Token], idx: usize) -> Result<(Stmt, u8), Error> {
     Ok((if_stmt, 2 + condition.1 + then_branch.1))
 }
 
-pub fn parse_block(tokens: &[Token], mut idx: usize) -> Result<(Vec<Stmt>, u8), Error> {
+/// Parses `try { ... } catch (name) { ... }`.
+fn parse_try(tokens: &[Token], idx: usize, nesting: Nesting) -> Result<(StmtKind, usize), Error> {
+    let expect = |idx: usize, expected: &TokenType| -> Result<(), Error> {
+        let token = tokens.get(idx).ok_or(Error::unexpected_eof("parse_try"))?;
+        if token.token != *expected {
+            let expected: String = expected.clone().into();
+            return Err(Error::syntax_error(token, &expected, "parse_try"));
+        }
+        Ok(())
+    };
+
+    expect(idx + 1, &TokenType::BraceOpen)?;
+    let body = parse_block(tokens, idx + 2, nesting)?;
+
+    let catch_idx = idx + 2 + body.1;
+    expect(catch_idx, &TokenType::KWCatch)?;
+    expect(catch_idx + 1, &TokenType::BracketOpen)?;
+    let name_token = tokens
+        .get(catch_idx + 2)
+        .ok_or(Error::unexpected_eof("parse_try"))?;
+    let error_name = match &name_token.token {
+        TokenType::Identifier(name) => name.to_owned(),
+        _ => return Err(Error::syntax_error(name_token, "identifier", "parse_try")),
+    };
+    expect(catch_idx + 3, &TokenType::BracketClose)?;
+    expect(catch_idx + 4, &TokenType::BraceOpen)?;
+    let handler = parse_block(tokens, catch_idx + 5, nesting)?;
+
+    let try_stmt = StmtKind::Try {
+        body: body.0,
+        error_name,
+        handler: handler.0,
+    };
+    Ok((try_stmt, 7 + body.1 + handler.1))
+}
+
+/// Parses `for name in iterable { ... }`.
+fn parse_for(tokens: &[Token], idx: usize, nesting: Nesting) -> Result<(StmtKind, usize), Error> {
+    let name_token = tokens
+        .get(idx + 1)
+        .ok_or(Error::unexpected_eof("parse_for"))?;
+    let name = match &name_token.token {
+        TokenType::Identifier(name) => name.to_owned(),
+        _ => return Err(Error::syntax_error(name_token, "identifier", "parse_for")),
+    };
+    let in_token = tokens
+        .get(idx + 2)
+        .ok_or(Error::unexpected_eof("parse_for"))?;
+    if in_token.token != TokenType::KWIn {
+        return Err(Error::syntax_error(in_token, "in", "parse_for"));
+    }
+
+    let iterable = parse_expr(tokens, idx + 3, nesting)?;
+    let open_brace_token = tokens
+        .get(idx + 3 + iterable.1)
+        .ok_or(Error::unexpected_eof("parse_for"))?;
+    if open_brace_token.token != TokenType::BraceOpen {
+        return Err(Error::syntax_error(open_brace_token, "{", "parse_for"));
+    }
+    let body = parse_block(tokens, idx + 4 + iterable.1, nesting)?;
+
+    let for_stmt = StmtKind::For {
+        name,
+        iterable: iterable.0,
+        body: body.0,
+    };
+    Ok((for_stmt, 4 + iterable.1 + body.1))
+}
+
+/// Parses the statement starting at `tokens[idx]`, returning it and the
+/// number of tokens it takes up.
+fn parse_statement_at(
+    tokens: &[Token],
+    idx: usize,
+    nesting: Nesting,
+) -> Result<(Stmt, usize), Error> {
+    let Some(token) = tokens.get(idx) else {
+        return Err(Error::unexpected_eof("parse_statement"));
+    };
+    let stmt = match &token.token {
+        TokenType::KWLet => parse_let(tokens, token, idx, nesting),
+        TokenType::Identifier(name) => parse_identifier(tokens, name.to_owned(), idx, nesting),
+        TokenType::ObjectName(_) => {
+            let expr = parse_expr(tokens, idx, nesting)?;
+            Ok((StmtKind::Expression(expr.0), expr.1))
+        }
+        TokenType::Number(_) | TokenType::String(_) | TokenType::BracketOpen => {
+            parse_expression_statement(tokens, idx, nesting)
+        }
+        TokenType::KWFn => parse_fn(tokens, idx, nesting),
+        TokenType::KWIf => parse_if(tokens, idx, nesting),
+        TokenType::KWWhile => parse_while(tokens, idx, nesting),
+        TokenType::KWTry => parse_try(tokens, idx, nesting),
+        TokenType::KWFor => parse_for(tokens, idx, nesting),
+        TokenType::KWBreak => Ok((StmtKind::Break, 1)),
+        TokenType::KWImport => match tokens.get(idx + 1) {
+            Some(Token {
+                token: TokenType::String(name),
+                ..
+            }) => Ok((StmtKind::Import(name.to_owned()), 2)),
+            Some(token) => Err(Error::syntax_error(token, "module name", "parse_block")),
+            None => Err(Error::unexpected_eof("parse_block")),
+        },
+        TokenType::KWThrow => {
+            let value = parse_expr(tokens, idx + 1, nesting)?;
+            Ok((StmtKind::Throw(value.0), value.1 + 1))
+        }
+        TokenType::KWReturn => {
+            let value = parse_expr(tokens, idx + 1, nesting)?;
+
+            let return_stmt = StmtKind::Return(value.0);
+            Ok((return_stmt, value.1 + 1))
+        }
+        _ => Err(Error::unimplemented_token(token, "parse_block")),
+    }?;
+    Ok((Stmt::new(stmt.0, Span::from(token)), stmt.1))
+}
+
+fn parse_block(
+    tokens: &[Token],
+    mut idx: usize,
+    nesting: Nesting,
+) -> Result<(Vec<Stmt>, usize), Error> {
+    let nesting = nesting.deeper(tokens, idx)?;
     let mut body = Vec::new();
     let initial_idx = idx;
 
     while idx < tokens.len() {
         let current_token = tokens.get(idx);
         if let Some(token) = current_token {
-            println!("{:?}", token);
-
             // tokens to ignore
             if token.token == TokenType::Semicolon {
                 idx += 1;
@@ -409,37 +920,55 @@ pub fn parse_block(tokens: &[Token], mut idx: usize) -> Result<(Vec<Stmt>, u8),
 
             // end of block
             if token.token == TokenType::BraceClose {
-                return Ok((body, (idx - initial_idx + 1) as u8));
+                return Ok((body, idx - initial_idx + 1));
             }
 
-            let stmt = match &token.token {
-                TokenType::KWLet => parse_let(tokens, token, idx),
-                TokenType::Identifier(name) => parse_identifier(tokens, name.to_owned(), idx),
-                TokenType::KWFn => parse_fn(tokens, idx),
-                TokenType::KWIf => parse_if(tokens, idx),
-                TokenType::KWWhile => parse_while(tokens, idx),
-                TokenType::KWReturn => {
-                    let value = parse_expr(tokens, idx + 1)?;
-
-                    let return_stmt = Stmt::Return(value.0);
-                    Ok((return_stmt, value.1 + 1))
-                }
-                _ => Err(Error::unimplemented_token(token, "parse_block")),
-            }?;
+            let stmt = parse_statement_at(tokens, idx, nesting)?;
             body.push(stmt.0);
-            println!("idx: {} + {}", idx, stmt.1);
-            idx += stmt.1 as usize;
+            idx += stmt.1;
         } else {
             break;
         }
     }
-    Ok((body, (idx - initial_idx) as u8))
+    Ok((body, idx - initial_idx))
 }
 
 pub fn parse(tokens: &[Token]) -> Result<Program, Error> {
+    parse_with_max_nesting(tokens, DEFAULT_MAX_NESTING)
+}
+
+/// Like `parse`, but with a custom limit on how deeply expressions, blocks
+/// and patterns may nest before parsing fails with a syntax error.
+pub fn parse_with_max_nesting(tokens: &[Token], max_nesting: usize) -> Result<Program, Error> {
     let program = Program {
-        statements: parse_block(tokens, 0)?.0,
+        statements: parse_block(tokens, 0, Nesting::new(max_nesting))?.0,
     };
 
     Ok(program)
 }
+
+/// Parses one top-level statement starting at `*idx` and moves `*idx` past
+/// it and any semicolons following it, so calling this until `*idx` reaches
+/// the end of `tokens` gives the statements `parse` returns, one at a time.
+pub fn parse_statement(tokens: &[Token], idx: &mut usize) -> Result<Stmt, Error> {
+    let skip_semicolons = |idx: &mut usize| {
+        while tokens
+            .get(*idx)
+            .is_some_and(|t| t.token == TokenType::Semicolon)
+        {
+            *idx += 1;
+        }
+    };
+    skip_semicolons(idx);
+    if let Some(token) = tokens
+        .get(*idx)
+        .filter(|t| t.token == TokenType::BraceClose)
+    {
+        return Err(Error::syntax_error(token, "statement", "parse_statement"));
+    }
+    let nesting = Nesting::new(DEFAULT_MAX_NESTING).deeper(tokens, *idx)?;
+    let (stmt, consumed) = parse_statement_at(tokens, *idx, nesting)?;
+    *idx += consumed;
+    skip_semicolons(idx);
+    Ok(stmt)
+}
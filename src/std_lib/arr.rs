@@ -0,0 +1,2857 @@
+use crate::interpreter::{Interpreter, Object, Value};
+use crate::std_lib::{
+    expect_array, expect_array_ref, expect_function, expect_number, reserve_elements,
+    MAX_BUILD_SIZE,
+};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+/// Splits `array` into `[matching, rest]` according to `predicate(element)`.
+pub fn partition(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let array = expect_array(&args, 0, "arr.partition")?;
+    let predicate = expect_function(&args, 1, "arr.partition")?;
+
+    let mut matching = Vec::new();
+    let mut rest = Vec::new();
+    for element in array {
+        if interpreter
+            .call_function(&predicate, vec![element.clone()])?
+            .to_bool()
+        {
+            matching.push(element);
+        } else {
+            rest.push(element);
+        }
+    }
+
+    Ok(Value::array(vec![
+        Value::array(matching),
+        Value::array(rest),
+    ]))
+}
+
+/// Calls `predicate` on elements in order until one returns `stop_on`.
+fn find_truthiness(
+    interpreter: &mut Interpreter,
+    args: &[Value],
+    stop_on: bool,
+    fn_name: &str,
+) -> Result<bool, String> {
+    let array = expect_array(args, 0, fn_name)?;
+    let predicate = expect_function(args, 1, fn_name)?;
+    for element in array {
+        if interpreter
+            .call_function(&predicate, vec![element])?
+            .to_bool()
+            == stop_on
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// 1 if `predicate` is truthy for every element, stopping at the first
+/// falsy one. Like "for all", an empty array gives 1.
+pub fn all(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let found_falsy = find_truthiness(interpreter, &args, false, "arr.all")?;
+    Ok(Value::Number(!found_falsy as i64))
+}
+
+/// 1 if `predicate` is truthy for some element, stopping at the first one.
+/// An empty array gives 0.
+pub fn any(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let found_truthy = find_truthiness(interpreter, &args, true, "arr.any")?;
+    Ok(Value::Number(found_truthy as i64))
+}
+
+/// 1 if `predicate` is truthy for no element, stopping at the first one
+/// that matches.
+pub fn none(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let found_truthy = find_truthiness(interpreter, &args, true, "arr.none")?;
+    Ok(Value::Number(!found_truthy as i64))
+}
+
+/// Number of elements `predicate` is truthy for.
+pub fn count_if(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let array = expect_array(&args, 0, "arr.count_if")?;
+    let predicate = expect_function(&args, 1, "arr.count_if")?;
+
+    let mut count = 0;
+    for element in array {
+        if interpreter
+            .call_function(&predicate, vec![element])?
+            .to_bool()
+        {
+            count += 1;
+        }
+    }
+    Ok(Value::Number(count))
+}
+
+/// Like a reduce that keeps every step: `initial` followed by each
+/// `reducer(accumulator, element)` in turn.
+pub fn scan(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let array = expect_array(&args, 0, "arr.scan")?;
+    let initial = args
+        .get(1)
+        .cloned()
+        .ok_or("arr.scan is missing argument 2")?;
+    let reducer = expect_function(&args, 2, "arr.scan")?;
+
+    let mut steps = Vec::with_capacity(array.len() + 1);
+    let mut accumulator = initial;
+    for element in array {
+        let next = interpreter.call_function(&reducer, vec![accumulator.clone(), element])?;
+        steps.push(std::mem::replace(&mut accumulator, next));
+    }
+    steps.push(accumulator);
+    Ok(Value::array(steps))
+}
+
+/// Averages of each run of `window` consecutive numbers, as floats. The
+/// result is `window - 1` elements shorter than `array`.
+pub fn moving_average(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let array = expect_array(&args, 0, "arr.moving_average")?;
+    let window = expect_number(&args, 1, "arr.moving_average")?;
+    if window < 1 || window as usize > array.len() {
+        return Err(format!(
+            "arr.moving_average: window must be between 1 and the array length {}, got {}",
+            array.len(),
+            window
+        ));
+    }
+
+    let mut values = Vec::with_capacity(array.len());
+    for (index, element) in array.iter().enumerate() {
+        values.push(match element {
+            Value::Number(n) => *n as f64,
+            Value::Float(n) => *n,
+            other => {
+                return Err(format!(
+                    "arr.moving_average expects an array of numbers, element {} is a {}",
+                    index,
+                    other.type_name()
+                ))
+            }
+        });
+    }
+
+    let window = window as usize;
+    Ok(Value::array(
+        values
+            .windows(window)
+            .map(|run| Value::Float(run.iter().sum::<f64>() / window as f64))
+            .collect(),
+    ))
+}
+
+/// Returns the number of elements of an array, characters of a string or
+/// properties of an object.
+pub fn len(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let len = match args.first() {
+        Some(Value::Array(array)) => array.borrow().len(),
+        Some(Value::String(s)) => s.chars().count(),
+        Some(Value::Object(object)) => object.borrow().properties().len(),
+        Some(other) => {
+            return Err(format!(
+                "len expects an array, string or object, got {}",
+                other
+            ))
+        }
+        None => return Err("len is missing argument 1".to_string()),
+    };
+    Ok(Value::Number(len as i64))
+}
+
+/// Appends `value` to `array` in place and returns the new length.
+pub fn push(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let array = expect_array_ref(&args, 0, "arr.push")?;
+    let value = args
+        .get(1)
+        .cloned()
+        .ok_or("arr.push is missing argument 2")?;
+
+    let mut array = array.borrow_mut();
+    array.push(value);
+    Ok(Value::Number(array.len() as i64))
+}
+
+/// Maps every element with `func` and flattens array results by one level.
+pub fn flatten_map(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let array = expect_array(&args, 0, "arr.flatten_map")?;
+    let func = expect_function(&args, 1, "arr.flatten_map")?;
+
+    let mut result = Vec::with_capacity(array.len());
+    for element in array {
+        match interpreter.call_function(&func, vec![element])? {
+            Value::Array(values) => result.extend(values.borrow().iter().cloned()),
+            value => result.push(value),
+        }
+    }
+
+    Ok(Value::array(result))
+}
+
+/// Default for `flatten_deep`'s `max_depth`.
+const FLATTEN_MAX_DEPTH: i64 = 1000;
+
+/// Every non-array value inside `array`, however deeply nested, in order.
+/// Arrays nested more than `max_depth` (default 1000) levels deep, which
+/// includes an array that contains itself, are an error.
+pub fn flatten_deep(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let array = expect_array(&args, 0, "arr.flatten_deep")?;
+    let max_depth = match args.get(1) {
+        Some(_) => expect_number(&args, 1, "arr.flatten_deep")?,
+        None => FLATTEN_MAX_DEPTH,
+    };
+
+    // Walked with a stack of its own, so deep nesting can't overflow ours
+    let mut result = Vec::new();
+    let mut levels = vec![array.into_iter()];
+    while let Some(level) = levels.last_mut() {
+        match level.next() {
+            Some(Value::Array(inner)) => {
+                if levels.len() as i64 >= max_depth {
+                    return Err(format!(
+                        "arr.flatten_deep: arrays are nested more than {} levels deep",
+                        max_depth
+                    ));
+                }
+                let inner = inner.borrow().clone();
+                levels.push(inner.into_iter());
+            }
+            Some(value) => result.push(value),
+            None => {
+                levels.pop();
+            }
+        }
+    }
+    Ok(Value::array(result))
+}
+
+/// Returns the numbers from `start` up to (excluding) `end`, advancing by
+/// `step` (default 1). A negative step counts down.
+pub fn range(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let start = expect_number(&args, 0, "arr.range")?;
+    let end = expect_number(&args, 1, "arr.range")?;
+    let step = if args.len() > 2 {
+        expect_number(&args, 2, "arr.range")?
+    } else {
+        1
+    };
+    if step == 0 {
+        return Err("arr.range step must not be 0".to_string());
+    }
+
+    let mut values = Vec::new();
+    let mut current = Some(start);
+    while let Some(n) = current {
+        if (step > 0 && n >= end) || (step < 0 && n <= end) {
+            break;
+        }
+        values.push(Value::Number(n));
+        current = n.checked_add(step);
+    }
+
+    Ok(Value::array(values))
+}
+
+/// Splits `array` into consecutive chunks of `size` elements; the last chunk
+/// may be shorter.
+pub fn chunk(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let array = expect_array(&args, 0, "arr.chunk")?;
+    let size = expect_number(&args, 1, "arr.chunk")?;
+    if size <= 0 {
+        return Err(format!("arr.chunk size must be positive, got {}", size));
+    }
+
+    Ok(Value::array(
+        array
+            .chunks(size as usize)
+            .map(|chunk| Value::array(chunk.to_vec()))
+            .collect(),
+    ))
+}
+
+fn page_size(args: &[Value], index: usize, fn_name: &str) -> Result<usize, String> {
+    let size = expect_number(args, index, fn_name)?;
+    if size <= 0 {
+        return Err(format!(
+            "{}: page_size must be positive, got {}",
+            fn_name, size
+        ));
+    }
+    Ok(size as usize)
+}
+
+/// Page `page` (counting from 1) of `array` split into pages of
+/// `page_size`, as `{items, total, page, total_pages, has_next, has_prev}`.
+/// A page past the end has no items.
+pub fn paginate(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let array = expect_array_ref(&args, 0, "arr.paginate")?;
+    let page = expect_number(&args, 1, "arr.paginate")?;
+    let size = page_size(&args, 2, "arr.paginate")?;
+    if page < 1 {
+        return Err(format!(
+            "arr.paginate: page must be at least 1, got {}",
+            page
+        ));
+    }
+
+    let array = array.borrow();
+    let total = array.len();
+    let total_pages = total.div_ceil(size);
+    let start = usize::try_from(page - 1)
+        .ok()
+        .and_then(|index| index.checked_mul(size))
+        .unwrap_or(usize::MAX)
+        .min(total);
+    let end = start.saturating_add(size).min(total);
+
+    let mut result = Object::new("page");
+    result.set_property(
+        "items".to_string(),
+        Value::array(array[start..end].to_vec()),
+    );
+    result.set_property("total".to_string(), Value::Number(total as i64));
+    result.set_property("page".to_string(), Value::Number(page));
+    result.set_property("total_pages".to_string(), Value::Number(total_pages as i64));
+    let has_next = (page as u64) < total_pages as u64;
+    result.set_property("has_next".to_string(), Value::Number(has_next as i64));
+    result.set_property("has_prev".to_string(), Value::Number((page > 1) as i64));
+    Ok(Value::object(result))
+}
+
+/// Number of pages of `page_size` elements `array` fills.
+pub fn paginate_count(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let array = expect_array_ref(&args, 0, "arr.paginate_count")?;
+    let size = page_size(&args, 1, "arr.paginate_count")?;
+    let pages = array.borrow().len().div_ceil(size);
+    Ok(Value::Number(pages as i64))
+}
+
+/// Returns every window of `size` consecutive elements, starting a new
+/// window each `step` elements (default 1).
+pub fn sliding_window(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let array = expect_array(&args, 0, "arr.sliding_window")?;
+    let size = expect_number(&args, 1, "arr.sliding_window")?;
+    let step = if args.len() > 2 {
+        expect_number(&args, 2, "arr.sliding_window")?
+    } else {
+        1
+    };
+    if size <= 0 || step <= 0 {
+        return Err(format!(
+            "arr.sliding_window size and step must be positive, got {} and {}",
+            size, step
+        ));
+    }
+
+    Ok(Value::array(
+        array
+            .windows(size as usize)
+            .step_by(step as usize)
+            .map(|window| Value::array(window.to_vec()))
+            .collect(),
+    ))
+}
+
+/// Calls `reduce(window)` for every run of `window` consecutive elements and
+/// returns the results. Without `initial` the result is `window - 1`
+/// elements shorter than `array`; with it, the positions before the first
+/// full window are filled with `initial`.
+pub fn window_reduce(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let array = expect_array(&args, 0, "arr.window_reduce")?;
+    let window = expect_number(&args, 1, "arr.window_reduce")?;
+    let reduce = expect_function(&args, 2, "arr.window_reduce")?;
+    if window < 1 {
+        return Err(format!(
+            "arr.window_reduce: window must be at least 1, got {}",
+            window
+        ));
+    }
+    let window = usize::try_from(window).unwrap_or(usize::MAX);
+
+    let mut results = Vec::with_capacity(array.len());
+    if let Some(initial) = args.get(3) {
+        let padding = array.len().min(window - 1);
+        results.resize(padding, initial.clone());
+    }
+    for run in array.windows(window) {
+        results.push(interpreter.call_function(&reduce, vec![Value::array(run.to_vec())])?);
+    }
+    Ok(Value::array(results))
+}
+
+/// Nesting depth after which `set_key` stops describing arrays and objects
+/// by content and uses their identity instead, so cyclic values still get
+/// a key.
+const MAX_KEY_DEPTH: usize = 256;
+
+/// Identity of an element in the set functions and `tally`: equal
+/// elements, as `==` compares them, get the same key, so `1` and `1.0` are
+/// one element but `1` and `"1"` are two. The key is type-tagged, strings
+/// are quoted and object properties sorted, so arrays and objects are
+/// compared by content and `[1]`, `["1"]` and `["a, b"]` stay apart.
+fn set_key(value: &Value) -> String {
+    let mut key = String::new();
+    write_key(&mut key, value, 0);
+    key
+}
+
+fn write_key(key: &mut String, value: &Value, depth: usize) {
+    use std::fmt::Write;
+
+    match value {
+        // Only top-level numbers compare across types; inside arrays and
+        // objects `==` keeps `1` and `1.0` apart
+        Value::Float(f) if depth == 0 && f.fract() == 0.0 && f.abs() < i64::MAX as f64 => {
+            let _ = write!(key, "n{}", *f as i64);
+        }
+        Value::Number(n) => {
+            let _ = write!(key, "n{}", n);
+        }
+        Value::Float(f) => {
+            let _ = write!(key, "f{}", f);
+        }
+        Value::String(s) => {
+            let _ = write!(key, "s{:?}", s);
+        }
+        Value::Void => key.push('v'),
+        // Too deep to be anything but a cycle: fall back to identity
+        Value::Array(items) if depth >= MAX_KEY_DEPTH => {
+            let _ = write!(key, "a@{:p}", Rc::as_ptr(items));
+        }
+        Value::Object(object) if depth >= MAX_KEY_DEPTH => {
+            let _ = write!(key, "o@{:p}", Rc::as_ptr(object));
+        }
+        Value::Array(items) => {
+            key.push_str("a[");
+            for (i, item) in items.borrow().iter().enumerate() {
+                if i > 0 {
+                    key.push(',');
+                }
+                write_key(key, item, depth + 1);
+            }
+            key.push(']');
+        }
+        Value::Object(object) => {
+            let object = object.borrow();
+            let mut properties: Vec<_> = object.properties().iter().collect();
+            properties.sort_by(|a, b| a.0.cmp(b.0));
+            let _ = write!(key, "o{:?}{{", object.name());
+            for (i, (name, property)) in properties.into_iter().enumerate() {
+                if i > 0 {
+                    key.push(',');
+                }
+                let _ = write!(key, "{:?}:", name);
+                write_key(key, property, depth + 1);
+            }
+            key.push('}');
+        }
+        Value::Function(..) | Value::NativeFunction(..) => {
+            let _ = write!(key, "{}:{:?}", value.type_name(), value);
+        }
+    }
+}
+
+/// Elements in order of first appearance, each once.
+#[derive(Default)]
+struct OrderedSet {
+    keys: HashSet<String>,
+    values: Vec<Value>,
+}
+
+impl OrderedSet {
+    fn insert(&mut self, value: Value) {
+        if self.keys.insert(set_key(&value)) {
+            self.values.push(value);
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::array(self.values)
+    }
+}
+
+fn key_set(values: &[Value]) -> HashSet<String> {
+    values.iter().map(set_key).collect()
+}
+
+/// Flattens nested arrays one level and drops repeated elements.
+pub fn flat_unique(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let array = expect_array(&args, 0, "arr.flat_unique")?;
+    let mut set = OrderedSet::default();
+    for element in array {
+        match element {
+            Value::Array(inner) => inner.borrow().iter().cloned().for_each(|v| set.insert(v)),
+            other => set.insert(other),
+        }
+    }
+    Ok(set.into_value())
+}
+
+/// Every distinct element of `a` or `b`, `a`'s first.
+pub fn set_union(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let a = expect_array(&args, 0, "arr.set_union")?;
+    let b = expect_array(&args, 1, "arr.set_union")?;
+    let mut set = OrderedSet::default();
+    a.into_iter().chain(b).for_each(|v| set.insert(v));
+    Ok(set.into_value())
+}
+
+/// The distinct elements of `a` that are also in `b`.
+pub fn set_intersection(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let a = expect_array(&args, 0, "arr.set_intersection")?;
+    let b = key_set(&expect_array(&args, 1, "arr.set_intersection")?);
+    let mut set = OrderedSet::default();
+    a.into_iter()
+        .filter(|v| b.contains(&set_key(v)))
+        .for_each(|v| set.insert(v));
+    Ok(set.into_value())
+}
+
+/// The distinct elements of `a` that are not in `b`.
+pub fn set_difference(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let a = expect_array(&args, 0, "arr.set_difference")?;
+    let b = key_set(&expect_array(&args, 1, "arr.set_difference")?);
+    let mut set = OrderedSet::default();
+    a.into_iter()
+        .filter(|v| !b.contains(&set_key(v)))
+        .for_each(|v| set.insert(v));
+    Ok(set.into_value())
+}
+
+/// Computes the key of `element` for the `*_by` functions: a function key is
+/// called with the element, a string key names a property of an object element.
+fn key_of(interpreter: &mut Interpreter, key: &Value, element: &Value) -> Result<Value, String> {
+    match key {
+        Value::String(property) => match element {
+            Value::Object(object) => Ok(object
+                .borrow()
+                .get_property(property)
+                .cloned()
+                .unwrap_or(Value::Void)),
+            other => Err(format!(
+                "cannot read property '{}' of {}, it is not an object",
+                property, other
+            )),
+        },
+        func => interpreter.call_function(func, vec![element.clone()]),
+    }
+}
+
+/// Keeps the first element for every distinct key, where the key is computed
+/// by `key_fn(element)` or read from the property named by a string `key_fn`.
+pub fn dedupe_by(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let array = expect_array(&args, 0, "arr.dedupe_by")?;
+    let key = match args.get(1) {
+        Some(Value::String(_)) => args[1].clone(),
+        _ => expect_function(&args, 1, "arr.dedupe_by")?,
+    };
+
+    let mut seen = HashSet::new();
+    let mut result = Vec::new();
+    for element in array {
+        let element_key = key_of(interpreter, &key, &element)?.to_string();
+        if seen.insert(element_key) {
+            result.push(element);
+        }
+    }
+
+    Ok(Value::array(result))
+}
+
+/// Sum of the keys of all elements and how many there were, for `sum_by`
+/// and `average_by`. Keys are computed as in `dedupe_by` and must be numbers.
+fn key_total(
+    interpreter: &mut Interpreter,
+    args: &[Value],
+    fn_name: &str,
+) -> Result<(f64, usize), String> {
+    let array = expect_array(args, 0, fn_name)?;
+    let key = match args.get(1) {
+        Some(Value::String(_)) => args[1].clone(),
+        _ => expect_function(args, 1, fn_name)?,
+    };
+
+    let mut total = 0.0;
+    for (index, element) in array.iter().enumerate() {
+        total += match key_of(interpreter, &key, element)? {
+            Value::Number(n) => n as f64,
+            Value::Float(n) => n,
+            other => {
+                return Err(format!(
+                    "{}: key of element {} is a {}, expected a number",
+                    fn_name,
+                    index,
+                    other.type_name()
+                ))
+            }
+        };
+    }
+    Ok((total, array.len()))
+}
+
+/// Sum of `key_fn(element)` over the array as a float, where a string
+/// `key_fn` names a property. Void for an empty array.
+pub fn sum_by(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    Ok(match key_total(interpreter, &args, "arr.sum_by")? {
+        (_, 0) => Value::Void,
+        (total, _) => Value::Float(total),
+    })
+}
+
+/// Mean of `key_fn(element)` over the array, see `sum_by`.
+pub fn average_by(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    Ok(match key_total(interpreter, &args, "arr.average_by")? {
+        (_, 0) => Value::Void,
+        (total, count) => Value::Float(total / count as f64),
+    })
+}
+
+/// Sorts by the key `key_fn(element)` returns, or by the property a string
+/// `key_fn` names. Keys must be all numbers, ints and floats alike, or all
+/// strings. Elements with equal keys keep their order; pass 0 as
+/// `ascending` to sort descending.
+pub fn sort_by(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let array = expect_array(&args, 0, "arr.sort_by")?;
+    let key = match args.get(1) {
+        Some(Value::String(_)) => args[1].clone(),
+        _ => expect_function(&args, 1, "arr.sort_by")?,
+    };
+    let ascending = args.get(2).is_none_or(Value::to_bool);
+
+    let mut keyed = Vec::with_capacity(array.len());
+    for element in array {
+        let element_key = key_of(interpreter, &key, &element)?;
+        match element_key {
+            Value::Number(_) | Value::String(_) => {}
+            Value::Float(f) if !f.is_nan() => {}
+            Value::Float(_) => return Err("arr.sort_by: a key is NaN".to_string()),
+            _ => {
+                return Err(format!(
+                    "arr.sort_by: keys must be numbers or strings, got {}",
+                    element_key.type_name()
+                ))
+            }
+        }
+        // Ints and floats compare with each other, strings only with strings
+        if let Some((first, _)) = keyed.first() {
+            if compare_values(first, &element_key).is_none() {
+                return Err(format!(
+                    "arr.sort_by: cannot compare a {} key with a {} key",
+                    first.type_name(),
+                    element_key.type_name()
+                ));
+            }
+        }
+        keyed.push((element_key, element));
+    }
+
+    keyed.sort_by(|(a, _), (b, _)| {
+        // Every pair compares: same kind as the first key, and no NaN
+        let order = compare_values(a, b).unwrap_or(std::cmp::Ordering::Equal);
+        if ascending {
+            order
+        } else {
+            order.reverse()
+        }
+    });
+    Ok(Value::array(
+        keyed.into_iter().map(|(_, element)| element).collect(),
+    ))
+}
+
+/// Orders two values the way `sort_by` orders keys: numbers (ints and
+/// floats alike) numerically, strings lexicographically.
+fn compare_values(a: &Value, b: &Value) -> Option<std::cmp::Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => Some(a.cmp(b)),
+        (Value::Number(a), Value::Float(b)) => (*a as f64).partial_cmp(b),
+        (Value::Float(a), Value::Number(b)) => a.partial_cmp(&(*b as f64)),
+        (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+/// Binary search for the insertion point of `args[1]` in the sorted array
+/// `args[0]`. `after_equal` picks the position after any equal elements.
+fn bisect(args: &[Value], after_equal: bool, fn_name: &str) -> Result<Value, String> {
+    let array = expect_array_ref(args, 0, fn_name)?;
+    let value = args
+        .get(1)
+        .ok_or_else(|| format!("{} is missing argument 2", fn_name))?;
+    let array = array.borrow();
+
+    let (mut low, mut high) = (0, array.len());
+    while low < high {
+        let mid = low + (high - low) / 2;
+        let order = compare_values(&array[mid], value).ok_or_else(|| {
+            format!(
+                "{}: cannot compare a {} with a {}",
+                fn_name,
+                array[mid].type_name(),
+                value.type_name()
+            )
+        })?;
+        let goes_left = match order {
+            std::cmp::Ordering::Less => true,
+            std::cmp::Ordering::Equal => after_equal,
+            std::cmp::Ordering::Greater => false,
+        };
+        if goes_left {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    Ok(Value::Number(low as i64))
+}
+
+/// Index at which `value` would be inserted into the sorted array to keep
+/// it sorted, before any elements equal to it. The array is not checked
+/// for being sorted.
+pub fn bisect_left(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    bisect(&args, false, "arr.bisect_left")
+}
+
+/// Like `bisect_left`, but after any elements equal to `value`.
+pub fn bisect_right(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    bisect(&args, true, "arr.bisect_right")
+}
+
+/// Swaps rows and columns of an array of arrays. Rows shorter than the
+/// longest one are padded with void.
+pub fn transpose(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let matrix = expect_array(&args, 0, "arr.transpose")?;
+
+    let mut rows = Vec::with_capacity(matrix.len());
+    for (index, row) in matrix.iter().enumerate() {
+        match row {
+            Value::Array(row) => rows.push(row.borrow().clone()),
+            other => {
+                return Err(format!(
+                    "arr.transpose: row {} is not an array, got {}",
+                    index, other
+                ))
+            }
+        }
+    }
+
+    let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let columns = (0..width)
+        .map(|column| {
+            Value::array(
+                rows.iter()
+                    .map(|row| row.get(column).cloned().unwrap_or(Value::Void))
+                    .collect(),
+            )
+        })
+        .collect();
+    Ok(Value::array(columns))
+}
+
+/// Returns the first element of `array`, or void when it is empty.
+pub fn first(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let array = expect_array_ref(&args, 0, "arr.first")?;
+    let first = array.borrow().first().cloned();
+    Ok(first.unwrap_or(Value::Void))
+}
+
+/// Returns the last element of `array`, or void when it is empty.
+pub fn last(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let array = expect_array_ref(&args, 0, "arr.last")?;
+    let last = array.borrow().last().cloned();
+    Ok(last.unwrap_or(Value::Void))
+}
+
+/// Like `first`, but returns `default` when `array` is empty.
+pub fn first_or(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let array = expect_array_ref(&args, 0, "arr.first_or")?;
+    let default = args
+        .get(1)
+        .cloned()
+        .ok_or("arr.first_or is missing argument 2")?;
+    let first = array.borrow().first().cloned();
+    Ok(first.unwrap_or(default))
+}
+
+/// Like `last`, but returns `default` when `array` is empty.
+pub fn last_or(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let array = expect_array_ref(&args, 0, "arr.last_or")?;
+    let default = args
+        .get(1)
+        .cloned()
+        .ok_or("arr.last_or is missing argument 2")?;
+    let last = array.borrow().last().cloned();
+    Ok(last.unwrap_or(default))
+}
+
+/// Returns element `n` of `array`, or void when `n` is out of range.
+pub fn nth(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let array = expect_array_ref(&args, 0, "arr.nth")?;
+    let n = expect_number(&args, 1, "arr.nth")?;
+    let element = usize::try_from(n)
+        .ok()
+        .and_then(|n| array.borrow().get(n).cloned());
+    Ok(element.unwrap_or(Value::Void))
+}
+
+/// Every `n`-th element of `array`, starting at index `offset` (0 by default).
+pub fn every_nth(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let array = expect_array_ref(&args, 0, "arr.every_nth")?;
+    let n = expect_number(&args, 1, "arr.every_nth")?;
+    if n < 1 {
+        return Err(format!("arr.every_nth: n must be at least 1, got {}", n));
+    }
+    let offset = match args.get(2) {
+        Some(_) => expect_number(&args, 2, "arr.every_nth")?,
+        None => 0,
+    };
+    if offset < 0 {
+        return Err(format!(
+            "arr.every_nth: offset must not be negative, got {}",
+            offset
+        ));
+    }
+
+    let picked = array
+        .borrow()
+        .iter()
+        .skip(offset as usize)
+        .step_by(n as usize)
+        .cloned()
+        .collect();
+    Ok(Value::array(picked))
+}
+
+/// Rotates `array` right by `n` positions, or left when `n` is negative.
+/// `n` wraps around the array length.
+pub fn rotate(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let mut array = expect_array(&args, 0, "arr.rotate")?;
+    let n = expect_number(&args, 1, "arr.rotate")?;
+
+    if array.len() > 1 {
+        let shift = n.rem_euclid(array.len() as i64) as usize;
+        array.rotate_right(shift);
+    }
+    Ok(Value::array(array))
+}
+
+/// Alternates elements of `first` and `second`, stopping at the shorter
+/// array unless `pad` is truthy, in which case missing elements are void.
+pub fn interleave(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let first = expect_array(&args, 0, "arr.interleave")?;
+    let second = expect_array(&args, 1, "arr.interleave")?;
+    let pad = args.get(2).is_some_and(Value::to_bool);
+
+    let len = if pad {
+        first.len().max(second.len())
+    } else {
+        first.len().min(second.len())
+    };
+    let mut result = Vec::with_capacity(len * 2);
+    for index in 0..len {
+        result.push(first.get(index).cloned().unwrap_or(Value::Void));
+        result.push(second.get(index).cloned().unwrap_or(Value::Void));
+    }
+    Ok(Value::array(result))
+}
+
+/// Calls `f(a, b)` on elements at the same index of both arrays and
+/// collects the results, stopping at the shorter array.
+pub fn zip_with(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let first = expect_array(&args, 0, "arr.zip_with")?;
+    let second = expect_array(&args, 1, "arr.zip_with")?;
+    let f = expect_function(&args, 2, "arr.zip_with")?;
+
+    let mut result = Vec::with_capacity(first.len().min(second.len()));
+    for (a, b) in first.into_iter().zip(second) {
+        result.push(interpreter.call_function(&f, vec![a, b])?);
+    }
+    Ok(Value::array(result))
+}
+
+/// Groups elements at the same index of three arrays into `[a, b, c]`
+/// triples, stopping at the shortest array.
+pub fn zip3(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let first = expect_array(&args, 0, "arr.zip3")?;
+    let second = expect_array(&args, 1, "arr.zip3")?;
+    let third = expect_array(&args, 2, "arr.zip3")?;
+
+    Ok(Value::array(
+        first
+            .into_iter()
+            .zip(second)
+            .zip(third)
+            .map(|((a, b), c)| Value::array(vec![a, b, c]))
+            .collect(),
+    ))
+}
+
+/// Every `[a, b]` pair with `a` from the first array and `b` from the
+/// second, ordered by `a` first.
+pub fn cartesian_product(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let first = expect_array(&args, 0, "arr.cartesian_product")?;
+    let second = expect_array(&args, 1, "arr.cartesian_product")?;
+    let count = first.len().checked_mul(second.len()).ok_or_else(|| {
+        format!(
+            "arr.cartesian_product: more elements than the limit of {}",
+            MAX_BUILD_SIZE
+        )
+    })?;
+    reserve_elements(interpreter, count, "arr.cartesian_product")?;
+
+    let mut pairs = Vec::with_capacity(count);
+    for a in &first {
+        for b in &second {
+            pairs.push(Value::array(vec![a.clone(), b.clone()]));
+        }
+    }
+    Ok(Value::array(pairs))
+}
+
+/// All `k` element subsets of the array, each keeping the original order,
+/// in lexicographic order of their indices.
+pub fn combinations(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let array = expect_array(&args, 0, "arr.combinations")?;
+    let k = expect_number(&args, 1, "arr.combinations")?;
+    if k < 0 {
+        return Err(format!(
+            "arr.combinations: k must not be negative, got {}",
+            k
+        ));
+    }
+    let k = k as usize;
+    if k > array.len() {
+        return Ok(Value::array(Vec::new()));
+    }
+    let count = binomial(array.len(), k).ok_or_else(|| {
+        format!(
+            "arr.combinations: more elements than the limit of {}",
+            MAX_BUILD_SIZE
+        )
+    })?;
+    reserve_elements(interpreter, count, "arr.combinations")?;
+
+    // Indices of the current subset, advanced like an odometer
+    let mut indices: Vec<usize> = (0..k).collect();
+    let mut result = Vec::with_capacity(count);
+    loop {
+        result.push(Value::array(
+            indices.iter().map(|&i| array[i].clone()).collect(),
+        ));
+        let Some(position) = (0..k).rev().find(|&p| indices[p] != p + array.len() - k) else {
+            return Ok(Value::array(result));
+        };
+        indices[position] += 1;
+        for next in position + 1..k {
+            indices[next] = indices[next - 1] + 1;
+        }
+    }
+}
+
+/// `n` choose `k` for `k <= n`, or `None` if it does not fit a `usize`.
+fn binomial(n: usize, k: usize) -> Option<usize> {
+    let k = k.min(n - k);
+    // Each step is C(n, i + 1) = C(n, i) * (n - i) / (i + 1), exactly
+    (0..k).try_fold(1usize, |count, i| Some(count.checked_mul(n - i)? / (i + 1)))
+}
+
+/// Splits an array of pairs into `[firsts, seconds]`.
+pub fn unzip(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let pairs = expect_array(&args, 0, "arr.unzip")?;
+
+    let mut firsts = Vec::with_capacity(pairs.len());
+    let mut seconds = Vec::with_capacity(pairs.len());
+    for (index, pair) in pairs.iter().enumerate() {
+        match pair {
+            Value::Array(pair) if pair.borrow().len() == 2 => {
+                let pair = pair.borrow();
+                firsts.push(pair[0].clone());
+                seconds.push(pair[1].clone());
+            }
+            other => {
+                return Err(format!(
+                    "arr.unzip: element {} is not a two-element array, got {}",
+                    index, other
+                ))
+            }
+        }
+    }
+    Ok(Value::array(vec![
+        Value::array(firsts),
+        Value::array(seconds),
+    ]))
+}
+
+/// Builds an array of `size` elements: copies of `value`, or `value(index)`
+/// for each index when it is a function.
+pub fn fill(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let size = expect_number(&args, 0, "arr.fill")?;
+    let value = args
+        .get(1)
+        .cloned()
+        .ok_or("arr.fill is missing argument 2")?;
+    if size < 0 {
+        return Err(format!("arr.fill: size must not be negative, got {}", size));
+    }
+
+    reserve_elements(interpreter, size as usize, "arr.fill")?;
+    let mut result = Vec::with_capacity(size as usize);
+    for index in 0..size {
+        match &value {
+            Value::Function(..) | Value::NativeFunction(..) => {
+                result.push(interpreter.call_function(&value, vec![Value::Number(index)])?)
+            }
+            other => result.push(other.clone()),
+        }
+    }
+    Ok(Value::array(result))
+}
+
+/// An array of `size` void elements.
+pub fn new(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let size = expect_number(&args, 0, "arr.new")?;
+    if size < 0 {
+        return Err(format!("arr.new: size must not be negative, got {}", size));
+    }
+    reserve_elements(interpreter, size as usize, "arr.new")?;
+    Ok(Value::array(vec![Value::Void; size as usize]))
+}
+
+/// `array`'s elements `n` times over.
+pub fn repeat(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let array = expect_array(&args, 0, "arr.repeat")?;
+    let n = expect_number(&args, 1, "arr.repeat")?;
+    if n < 0 {
+        return Err(format!("arr.repeat: n must not be negative, got {}", n));
+    }
+    let total = array
+        .len()
+        .checked_mul(n as usize)
+        .ok_or("arr.repeat: the result would be too large")?;
+    cycle_to(interpreter, &array, total, "arr.repeat")
+}
+
+/// `array`'s elements over and over until there are `length` of them. An
+/// empty array stays empty.
+pub fn cycle(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let array = expect_array(&args, 0, "arr.cycle")?;
+    let length = expect_number(&args, 1, "arr.cycle")?;
+    if length < 0 {
+        return Err(format!(
+            "arr.cycle: length must not be negative, got {}",
+            length
+        ));
+    }
+    cycle_to(interpreter, &array, length as usize, "arr.cycle")
+}
+
+fn cycle_to(
+    interpreter: &mut Interpreter,
+    array: &[Value],
+    length: usize,
+    fn_name: &str,
+) -> Result<Value, String> {
+    let length = if array.is_empty() { 0 } else { length };
+    reserve_elements(interpreter, length, fn_name)?;
+    Ok(Value::array(
+        array.iter().cycle().take(length).cloned().collect(),
+    ))
+}
+
+/// Small splitmix64 generator for the random picks below: good enough for
+/// simulations and reproducible from a seed, not for anything secret.
+struct Rng(u64);
+
+impl Rng {
+    /// Seeded from `args[index]` if given, otherwise from the clock and a
+    /// per-process random key.
+    fn from_arg(args: &[Value], index: usize, fn_name: &str) -> Result<Rng, String> {
+        use std::hash::{BuildHasher, Hasher};
+
+        if args.get(index).is_some() {
+            return Ok(Rng(expect_number(args, index, fn_name)? as u64));
+        }
+        let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+        if let Ok(now) = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+            hasher.write_u128(now.as_nanos());
+        }
+        Ok(Rng(hasher.finish()))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0.0..1.0`.
+    fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform in `0..n`, `n` must not be 0.
+    fn below(&mut self, n: usize) -> usize {
+        ((self.next_u64() as u128 * n as u128) >> 64) as usize
+    }
+}
+
+/// One of `items`, each picked with probability proportional to the
+/// weight at the same position. Weights must be positive numbers. An
+/// optional `seed` makes the pick reproducible.
+pub fn weighted_random(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let items = expect_array(&args, 0, "arr.weighted_random")?;
+    let weights = expect_array(&args, 1, "arr.weighted_random")?;
+    let mut rng = Rng::from_arg(&args, 2, "arr.weighted_random")?;
+    if items.len() != weights.len() {
+        return Err(format!(
+            "arr.weighted_random: items and weights must have the same length, got {} and {}",
+            items.len(),
+            weights.len()
+        ));
+    }
+    if items.is_empty() {
+        return Err("arr.weighted_random: there are no items to pick from".to_string());
+    }
+
+    let mut cumulative = Vec::with_capacity(weights.len());
+    let mut total = 0.0;
+    for (index, weight) in weights.iter().enumerate() {
+        let weight = match weight {
+            Value::Number(n) if *n > 0 => *n as f64,
+            Value::Float(f) if *f > 0.0 && f.is_finite() => *f,
+            other => {
+                return Err(format!(
+                    "arr.weighted_random: weight {} must be a positive number, got {}",
+                    index, other
+                ))
+            }
+        };
+        total += weight;
+        cumulative.push(total);
+    }
+
+    let target = rng.unit() * total;
+    let picked = cumulative
+        .iter()
+        .position(|bound| target < *bound)
+        .unwrap_or(items.len() - 1);
+    Ok(items[picked].clone())
+}
+
+/// `n` distinct elements of `array` (distinct by position) in random
+/// order. An optional `seed` makes the sample reproducible.
+pub fn sample(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let mut array = expect_array(&args, 0, "arr.sample")?;
+    let n = expect_number(&args, 1, "arr.sample")?;
+    let mut rng = Rng::from_arg(&args, 2, "arr.sample")?;
+    if n < 0 || n as usize > array.len() {
+        return Err(format!(
+            "arr.sample: n must be between 0 and the array length {}, got {}",
+            array.len(),
+            n
+        ));
+    }
+
+    // Fisher-Yates, stopped once the first `n` places are drawn
+    let n = n as usize;
+    for i in 0..n {
+        let j = i + rng.below(array.len() - i);
+        array.swap(i, j);
+    }
+    array.truncate(n);
+    Ok(Value::array(array))
+}
+
+fn count_keys(keys: impl IntoIterator<Item = Value>) -> Value {
+    let mut counts = Object::new("object");
+    for key in keys {
+        let key = key.to_string();
+        let count = match counts.get_property(&key) {
+            Some(Value::Number(n)) => n + 1,
+            _ => 1,
+        };
+        counts.set_property(key, Value::Number(count));
+    }
+    Value::object(counts)
+}
+
+/// Counts how often each element occurs, keyed by its string form.
+pub fn frequencies(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let array = expect_array(&args, 0, "arr.frequencies")?;
+    Ok(count_keys(array))
+}
+
+/// Counts elements by the key `key_fn(element)` returns.
+pub fn count_by(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let array = expect_array(&args, 0, "arr.count_by")?;
+    let key_fn = expect_function(&args, 1, "arr.count_by")?;
+
+    let mut keys = Vec::with_capacity(array.len());
+    for element in array {
+        keys.push(interpreter.call_function(&key_fn, vec![element])?);
+    }
+    Ok(count_keys(keys))
+}
+
+/// Tie-break order for `tally`: numbers before strings before anything
+/// else, each group in ascending order.
+fn tally_order(a: &Value, b: &Value) -> std::cmp::Ordering {
+    let rank = |value: &Value| match value {
+        Value::Number(_) | Value::Float(_) => 0,
+        Value::String(_) => 1,
+        _ => 2,
+    };
+    let as_float = |value: &Value| match value {
+        Value::Number(n) => *n as f64,
+        Value::Float(f) => *f,
+        _ => 0.0,
+    };
+    rank(a).cmp(&rank(b)).then_with(|| match rank(a) {
+        0 => compare_values(a, b).unwrap_or_else(|| as_float(a).total_cmp(&as_float(b))),
+        _ => a.to_string().cmp(&b.to_string()),
+    })
+}
+
+/// A frequency table of the array's elements: `{value, count}` objects,
+/// most common first, equal counts ordered by value.
+pub fn tally(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let array = expect_array(&args, 0, "arr.tally")?;
+
+    let mut index: HashMap<String, usize> = HashMap::new();
+    let mut counts: Vec<(Value, i64)> = Vec::new();
+    for element in array {
+        let key = set_key(&element);
+        match index.get(&key) {
+            Some(&i) => counts[i].1 += 1,
+            None => {
+                index.insert(key, counts.len());
+                counts.push((element, 1));
+            }
+        }
+    }
+    counts
+        .sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| tally_order(a, b)));
+
+    let rows = counts
+        .into_iter()
+        .map(|(value, count)| {
+            let mut row = Object::new("object");
+            row.set_property("value".to_string(), value);
+            row.set_property("count".to_string(), Value::Number(count));
+            Value::object(row)
+        })
+        .collect();
+    Ok(Value::array(rows))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::NativeFn;
+    use crate::lexer::tokenize;
+    use crate::parser::parse;
+
+    /// Runs `source` and returns the global function `name` it defines.
+    fn define(interpreter: &mut Interpreter, source: &str, name: &str) -> Value {
+        let program = parse(&tokenize(source.to_string()).unwrap()).unwrap();
+        interpreter.interpret(&program).unwrap();
+        interpreter.env.get_global_function(name).unwrap().clone()
+    }
+
+    fn numbers(values: &[i64]) -> Value {
+        Value::array(values.iter().map(|n| Value::Number(*n)).collect())
+    }
+
+    #[test]
+    fn test_partition_even_odd() {
+        let mut interpreter = Interpreter::new();
+        let is_even = define(
+            &mut interpreter,
+            "fn is_even(x) { return x / 2 * 2 == x; }",
+            "is_even",
+        );
+
+        let result = partition(&mut interpreter, vec![numbers(&[1, 2, 3, 4]), is_even]);
+        assert_eq!(
+            result,
+            Ok(Value::array(vec![numbers(&[2, 4]), numbers(&[1, 3])]))
+        );
+    }
+
+    #[test]
+    fn test_partition_empty() {
+        let mut interpreter = Interpreter::new();
+        let is_even = define(
+            &mut interpreter,
+            "fn is_even(x) { return x / 2 * 2 == x; }",
+            "is_even",
+        );
+
+        let result = partition(&mut interpreter, vec![numbers(&[]), is_even]);
+        assert_eq!(result, Ok(Value::array(vec![numbers(&[]), numbers(&[])])));
+    }
+
+    #[test]
+    fn test_partition_propagates_predicate_error() {
+        let mut interpreter = Interpreter::new();
+        let broken = define(
+            &mut interpreter,
+            "fn broken(x) { return x + missing; }",
+            "broken",
+        );
+
+        let result = partition(&mut interpreter, vec![numbers(&[1]), broken]);
+        assert!(result.unwrap_err().contains("missing"));
+    }
+
+    #[test]
+    fn test_partition_requires_function() {
+        let mut interpreter = Interpreter::new();
+        let result = partition(&mut interpreter, vec![numbers(&[1]), Value::Number(1)]);
+        assert!(result.is_err());
+    }
+
+    fn with_double(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+        let x = expect_number(&args, 0, "with_double")?;
+        Ok(Value::array(vec![Value::Number(x), Value::Number(x * 2)]))
+    }
+
+    #[test]
+    fn test_flatten_map_arrays() {
+        let mut interpreter = Interpreter::new();
+        let func = Value::NativeFunction("with_double".to_string(), with_double);
+
+        let result = flatten_map(&mut interpreter, vec![numbers(&[1, 2, 3]), func]);
+        assert_eq!(result, Ok(numbers(&[1, 2, 2, 4, 3, 6])));
+    }
+
+    #[test]
+    fn test_flatten_map_single_values() {
+        let mut interpreter = Interpreter::new();
+        let inc = define(&mut interpreter, "fn inc(x) { return x + 1; }", "inc");
+
+        let result = flatten_map(&mut interpreter, vec![numbers(&[1, 2]), inc]);
+        assert_eq!(result, Ok(numbers(&[2, 3])));
+    }
+
+    #[test]
+    fn test_flatten_map_only_one_level() {
+        let mut interpreter = Interpreter::new();
+        let nested = define(&mut interpreter, "fn nested(x) { return x; }", "nested");
+        let input = Value::array(vec![Value::array(vec![numbers(&[1])])]);
+
+        let result = flatten_map(&mut interpreter, vec![input, nested]);
+        assert_eq!(result, Ok(Value::array(vec![numbers(&[1])])));
+    }
+
+    #[test]
+    fn test_range() {
+        let mut interpreter = Interpreter::new();
+        let result = range(&mut interpreter, vec![Value::Number(0), Value::Number(5)]);
+        assert_eq!(result, Ok(numbers(&[0, 1, 2, 3, 4])));
+    }
+
+    #[test]
+    fn test_range_negative_step() {
+        let mut interpreter = Interpreter::new();
+        let result = range(
+            &mut interpreter,
+            vec![Value::Number(10), Value::Number(0), Value::Number(-3)],
+        );
+        assert_eq!(result, Ok(numbers(&[10, 7, 4, 1])));
+    }
+
+    #[test]
+    fn test_range_empty_when_step_points_away() {
+        let mut interpreter = Interpreter::new();
+        let result = range(
+            &mut interpreter,
+            vec![Value::Number(0), Value::Number(5), Value::Number(-1)],
+        );
+        assert_eq!(result, Ok(numbers(&[])));
+    }
+
+    #[test]
+    fn test_range_zero_step_fails() {
+        let mut interpreter = Interpreter::new();
+        let result = range(
+            &mut interpreter,
+            vec![Value::Number(0), Value::Number(5), Value::Number(0)],
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_push_mutates_shared_array() {
+        let mut interpreter = Interpreter::new();
+        let array = numbers(&[1]);
+        let alias = array.clone();
+
+        let result = push(&mut interpreter, vec![array, Value::Number(2)]);
+        assert_eq!(result, Ok(Value::Number(2)));
+        assert_eq!(alias, numbers(&[1, 2]));
+    }
+
+    fn strings(values: &[&str]) -> Value {
+        Value::array(
+            values
+                .iter()
+                .map(|s| Value::String(s.to_string()))
+                .collect(),
+        )
+    }
+
+    fn counts(entries: &[(&str, i64)]) -> Value {
+        let mut object = Object::new("object");
+        for (key, count) in entries {
+            object.set_property(key.to_string(), Value::Number(*count));
+        }
+        Value::object(object)
+    }
+
+    #[test]
+    fn test_frequencies() {
+        let mut interpreter = Interpreter::new();
+        let result = frequencies(
+            &mut interpreter,
+            vec![strings(&["a", "b", "a", "c", "b", "a"])],
+        );
+        assert_eq!(result, Ok(counts(&[("a", 3), ("b", 2), ("c", 1)])));
+    }
+
+    fn tally_rows(rows: Vec<(Value, i64)>) -> Value {
+        Value::array(
+            rows.into_iter()
+                .map(|(value, count)| {
+                    let mut row = Object::new("object");
+                    row.set_property("value".to_string(), value);
+                    row.set_property("count".to_string(), Value::Number(count));
+                    Value::object(row)
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_tally() {
+        let mut interpreter = Interpreter::new();
+        let result = tally(
+            &mut interpreter,
+            vec![strings(&["a", "b", "a", "c", "b", "a"])],
+        );
+        let s = |s: &str| Value::String(s.to_string());
+        assert_eq!(
+            result,
+            Ok(tally_rows(vec![(s("a"), 3), (s("b"), 2), (s("c"), 1)]))
+        );
+    }
+
+    #[test]
+    fn test_tally_ties_and_types() {
+        let mut interpreter = Interpreter::new();
+        let values = Value::array(vec![
+            Value::String("b".to_string()),
+            Value::Number(10),
+            Value::String("1".to_string()),
+            Value::Number(2),
+            Value::Number(1),
+            Value::String("a".to_string()),
+            Value::Number(10),
+        ]);
+        let s = |s: &str| Value::String(s.to_string());
+        assert_eq!(
+            tally(&mut interpreter, vec![values]),
+            Ok(tally_rows(vec![
+                (Value::Number(10), 2),
+                (Value::Number(1), 1),
+                (Value::Number(2), 1),
+                (s("1"), 1),
+                (s("a"), 1),
+                (s("b"), 1),
+            ]))
+        );
+        assert_eq!(
+            tally(&mut interpreter, vec![Value::array(vec![])]),
+            Ok(Value::array(vec![]))
+        );
+    }
+
+    #[test]
+    fn test_count_by() {
+        let mut interpreter = Interpreter::new();
+        let parity = define(
+            &mut interpreter,
+            "fn parity(x) { return x - x / 2 * 2; }",
+            "parity",
+        );
+
+        let result = count_by(&mut interpreter, vec![numbers(&[1, 2, 3, 4, 5]), parity]);
+        assert_eq!(result, Ok(counts(&[("0", 2), ("1", 3)])));
+    }
+
+    #[test]
+    fn test_len() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(
+            len(&mut interpreter, vec![numbers(&[1, 2, 3])]),
+            Ok(Value::Number(3))
+        );
+        assert_eq!(
+            len(&mut interpreter, vec![Value::String("héllo".to_string())]),
+            Ok(Value::Number(5))
+        );
+        assert!(len(&mut interpreter, vec![Value::Number(1)]).is_err());
+    }
+
+    fn nested(groups: &[&[i64]]) -> Value {
+        Value::array(groups.iter().map(|group| numbers(group)).collect())
+    }
+
+    #[test]
+    fn test_chunk() {
+        let mut interpreter = Interpreter::new();
+        let result = chunk(
+            &mut interpreter,
+            vec![numbers(&[1, 2, 3, 4, 5]), Value::Number(2)],
+        );
+        assert_eq!(result, Ok(nested(&[&[1, 2], &[3, 4], &[5]])));
+
+        let result = chunk(&mut interpreter, vec![numbers(&[1, 2]), Value::Number(5)]);
+        assert_eq!(result, Ok(nested(&[&[1, 2]])));
+
+        let result = chunk(&mut interpreter, vec![numbers(&[]), Value::Number(2)]);
+        assert_eq!(result, Ok(nested(&[])));
+    }
+
+    fn page_of(array: Value, page: i64, size: i64) -> Result<Value, String> {
+        let mut interpreter = Interpreter::new();
+        paginate(
+            &mut interpreter,
+            vec![array, Value::Number(page), Value::Number(size)],
+        )
+    }
+
+    fn page_object(
+        items: &[i64],
+        total: i64,
+        page: i64,
+        pages: i64,
+        next: i64,
+        prev: i64,
+    ) -> Value {
+        let mut object = Object::new("page");
+        for (name, value) in [
+            ("items", numbers(items)),
+            ("total", Value::Number(total)),
+            ("page", Value::Number(page)),
+            ("total_pages", Value::Number(pages)),
+            ("has_next", Value::Number(next)),
+            ("has_prev", Value::Number(prev)),
+        ] {
+            object.set_property(name.to_string(), value);
+        }
+        Value::object(object)
+    }
+
+    #[test]
+    fn test_paginate() {
+        let items: Vec<i64> = (0..25).collect();
+        let array = || numbers(&items);
+        assert_eq!(
+            page_of(array(), 1, 10),
+            Ok(page_object(&items[0..10], 25, 1, 3, 1, 0))
+        );
+        assert_eq!(
+            page_of(array(), 2, 10),
+            Ok(page_object(&items[10..20], 25, 2, 3, 1, 1))
+        );
+        assert_eq!(
+            page_of(array(), 3, 10),
+            Ok(page_object(&items[20..25], 25, 3, 3, 0, 1))
+        );
+        assert_eq!(
+            page_of(array(), 4, 10),
+            Ok(page_object(&[], 25, 4, 3, 0, 1))
+        );
+        assert_eq!(
+            page_of(numbers(&[]), 1, 10),
+            Ok(page_object(&[], 0, 1, 0, 0, 0))
+        );
+        assert_eq!(
+            page_of(array(), i64::MAX, 10),
+            Ok(page_object(&[], 25, i64::MAX, 3, 0, 1))
+        );
+    }
+
+    #[test]
+    fn test_paginate_rejects_bad_pages() {
+        assert_eq!(
+            page_of(numbers(&[1]), 0, 10),
+            Err("arr.paginate: page must be at least 1, got 0".to_string())
+        );
+        assert_eq!(
+            page_of(numbers(&[1]), 1, 0),
+            Err("arr.paginate: page_size must be positive, got 0".to_string())
+        );
+    }
+
+    #[test]
+    fn test_paginate_count() {
+        let mut interpreter = Interpreter::new();
+        let mut count = |items: &[i64], size: i64| {
+            paginate_count(&mut interpreter, vec![numbers(items), Value::Number(size)])
+        };
+        assert_eq!(count(&[1, 2, 3, 4], 2), Ok(Value::Number(2)));
+        assert_eq!(count(&[1, 2, 3, 4, 5], 2), Ok(Value::Number(3)));
+        assert_eq!(count(&[], 2), Ok(Value::Number(0)));
+        assert!(count(&[1], -1).is_err());
+    }
+
+    #[test]
+    fn test_chunk_rejects_non_positive_size() {
+        let mut interpreter = Interpreter::new();
+        assert!(chunk(&mut interpreter, vec![numbers(&[1]), Value::Number(0)]).is_err());
+        assert!(chunk(&mut interpreter, vec![numbers(&[1]), Value::Number(-1)]).is_err());
+    }
+
+    #[test]
+    fn test_sliding_window() {
+        let mut interpreter = Interpreter::new();
+        let result = sliding_window(
+            &mut interpreter,
+            vec![numbers(&[1, 2, 3, 4]), Value::Number(2)],
+        );
+        assert_eq!(result, Ok(nested(&[&[1, 2], &[2, 3], &[3, 4]])));
+
+        let result = sliding_window(
+            &mut interpreter,
+            vec![
+                numbers(&[1, 2, 3, 4, 5]),
+                Value::Number(3),
+                Value::Number(2),
+            ],
+        );
+        assert_eq!(result, Ok(nested(&[&[1, 2, 3], &[3, 4, 5]])));
+
+        let result = sliding_window(&mut interpreter, vec![numbers(&[1]), Value::Number(2)]);
+        assert_eq!(result, Ok(nested(&[])));
+
+        let result = sliding_window(&mut interpreter, vec![numbers(&[]), Value::Number(1)]);
+        assert_eq!(result, Ok(nested(&[])));
+    }
+
+    fn window_sum(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+        let window = expect_array(&args, 0, "window_sum")?;
+        let total = window
+            .iter()
+            .map(|value| match value {
+                Value::Number(n) => *n,
+                _ => 0,
+            })
+            .sum();
+        Ok(Value::Number(total))
+    }
+
+    fn window_max(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+        let window = expect_array(&args, 0, "window_max")?;
+        Ok(window
+            .into_iter()
+            .max_by(|a, b| compare_values(a, b).unwrap_or(std::cmp::Ordering::Equal))
+            .unwrap_or(Value::Void))
+    }
+
+    #[test]
+    fn test_window_reduce_rolling_sum() {
+        let mut interpreter = Interpreter::new();
+        let sum = Value::NativeFunction("window_sum".to_string(), window_sum);
+        let result = window_reduce(
+            &mut interpreter,
+            vec![numbers(&[1, 2, 3, 4, 5]), Value::Number(3), sum.clone()],
+        );
+        assert_eq!(result, Ok(numbers(&[6, 9, 12])));
+
+        let result = window_reduce(
+            &mut interpreter,
+            vec![
+                numbers(&[1, 2, 3, 4, 5]),
+                Value::Number(3),
+                sum,
+                Value::Number(0),
+            ],
+        );
+        assert_eq!(result, Ok(numbers(&[0, 0, 6, 9, 12])));
+    }
+
+    #[test]
+    fn test_window_reduce_rolling_max() {
+        let mut interpreter = Interpreter::new();
+        let max = Value::NativeFunction("window_max".to_string(), window_max);
+        let result = window_reduce(
+            &mut interpreter,
+            vec![numbers(&[3, 1, 4, 1, 5, 9, 2]), Value::Number(2), max],
+        );
+        assert_eq!(result, Ok(numbers(&[3, 4, 4, 5, 9, 9])));
+    }
+
+    #[test]
+    fn test_window_reduce_window_larger_than_array() {
+        let mut interpreter = Interpreter::new();
+        let sum = Value::NativeFunction("window_sum".to_string(), window_sum);
+        let result = window_reduce(
+            &mut interpreter,
+            vec![numbers(&[1, 2]), Value::Number(5), sum.clone()],
+        );
+        assert_eq!(result, Ok(numbers(&[])));
+
+        let result = window_reduce(
+            &mut interpreter,
+            vec![numbers(&[1, 2]), Value::Number(5), sum.clone(), Value::Void],
+        );
+        assert_eq!(result, Ok(Value::array(vec![Value::Void, Value::Void])));
+
+        let result = window_reduce(
+            &mut interpreter,
+            vec![numbers(&[1, 2]), Value::Number(0), sum],
+        );
+        assert_eq!(
+            result,
+            Err("arr.window_reduce: window must be at least 1, got 0".to_string())
+        );
+    }
+
+    fn mixed(values: &[&str]) -> Value {
+        // Numbers as digits, `1.0`-style as floats, anything else a string
+        Value::array(
+            values
+                .iter()
+                .map(|v| {
+                    if let Ok(n) = v.parse::<i64>() {
+                        Value::Number(n)
+                    } else if let Ok(f) = v.parse::<f64>() {
+                        Value::Float(f)
+                    } else {
+                        Value::String(v.to_string())
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_flat_unique() {
+        let mut interpreter = Interpreter::new();
+        let input = Value::array(vec![
+            numbers(&[1, 2]),
+            Value::Number(2),
+            numbers(&[3, 1]),
+            nested(&[&[4]]),
+        ]);
+        assert_eq!(
+            flat_unique(&mut interpreter, vec![input]),
+            Ok(Value::array(vec![
+                Value::Number(1),
+                Value::Number(2),
+                Value::Number(3),
+                numbers(&[4]),
+            ]))
+        );
+        assert_eq!(
+            flat_unique(&mut interpreter, vec![numbers(&[])]),
+            Ok(numbers(&[]))
+        );
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let mut interpreter = Interpreter::new();
+        let a = numbers(&[1, 2, 2, 3, 4]);
+        let b = numbers(&[4, 3, 5, 5]);
+        assert_eq!(
+            set_union(&mut interpreter, vec![a.clone(), b.clone()]),
+            Ok(numbers(&[1, 2, 3, 4, 5]))
+        );
+        assert_eq!(
+            set_intersection(&mut interpreter, vec![a.clone(), b.clone()]),
+            Ok(numbers(&[3, 4]))
+        );
+        assert_eq!(
+            set_difference(&mut interpreter, vec![a.clone(), b]),
+            Ok(numbers(&[1, 2]))
+        );
+    }
+
+    #[test]
+    fn test_set_operations_with_empty_sets() {
+        let mut interpreter = Interpreter::new();
+        let a = numbers(&[1, 1, 2]);
+        let empty = numbers(&[]);
+        let call = |f: NativeFn, interpreter: &mut Interpreter, x: &Value, y: &Value| {
+            f(interpreter, vec![x.clone(), y.clone()])
+        };
+        assert_eq!(
+            call(set_union, &mut interpreter, &a, &empty),
+            Ok(numbers(&[1, 2]))
+        );
+        assert_eq!(
+            call(set_union, &mut interpreter, &empty, &empty),
+            Ok(numbers(&[]))
+        );
+        assert_eq!(
+            call(set_intersection, &mut interpreter, &a, &empty),
+            Ok(numbers(&[]))
+        );
+        assert_eq!(
+            call(set_difference, &mut interpreter, &a, &empty),
+            Ok(numbers(&[1, 2]))
+        );
+        assert_eq!(
+            call(set_difference, &mut interpreter, &empty, &a),
+            Ok(numbers(&[]))
+        );
+    }
+
+    #[test]
+    fn test_set_operations_mixed_types() {
+        let mut interpreter = Interpreter::new();
+        // 1 and 1.0 are the same element, 1 and "1" are not
+        let a = mixed(&["1", "1.0", "x", "2.5"]);
+        let b = mixed(&["1.0", "y", "x"]);
+        assert_eq!(
+            set_union(&mut interpreter, vec![a.clone(), b.clone()]),
+            Ok(mixed(&["1", "x", "2.5", "y"]))
+        );
+        assert_eq!(
+            set_intersection(&mut interpreter, vec![a.clone(), b.clone()]),
+            Ok(mixed(&["1", "x"]))
+        );
+        let strings_only = strings(&["1", "x"]);
+        assert_eq!(
+            set_difference(&mut interpreter, vec![a, strings_only]),
+            Ok(mixed(&["1", "2.5"]))
+        );
+    }
+
+    #[test]
+    fn test_set_operations_compare_arrays_and_objects_by_content() {
+        let mut interpreter = Interpreter::new();
+        let s = |s: &str| Value::String(s.to_string());
+        let object = |entries: &[(&str, Value)]| {
+            let mut object = Object::new("object");
+            for (key, value) in entries {
+                object.set_property(key.to_string(), value.clone());
+            }
+            Value::object(object)
+        };
+        let a = Value::array(vec![
+            Value::array(vec![Value::Number(1)]),
+            Value::array(vec![s("a, b")]),
+            object(&[("x", Value::Number(1)), ("y", s("2"))]),
+            Value::array(vec![Value::array(vec![Value::Number(1)])]),
+        ]);
+        let b = Value::array(vec![
+            Value::array(vec![s("1")]),
+            Value::array(vec![s("a"), s("b")]),
+            object(&[("y", s("2")), ("x", Value::Number(1))]),
+            Value::array(vec![Value::array(vec![Value::Number(1)])]),
+        ]);
+        // Only the object and the nested array are equal by content
+        assert_eq!(
+            set_intersection(&mut interpreter, vec![a.clone(), b.clone()]),
+            Ok(Value::array(vec![
+                object(&[("x", Value::Number(1)), ("y", s("2"))]),
+                Value::array(vec![Value::array(vec![Value::Number(1)])]),
+            ]))
+        );
+        match set_union(&mut interpreter, vec![a, b]) {
+            Ok(Value::Array(union)) => assert_eq!(union.borrow().len(), 6),
+            other => panic!("expected an array, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_tally_keeps_look_alike_arrays_apart() {
+        let mut interpreter = Interpreter::new();
+        let s = |s: &str| Value::String(s.to_string());
+        let values = Value::array(vec![
+            Value::array(vec![Value::Number(1)]),
+            Value::array(vec![s("1")]),
+            Value::array(vec![Value::Number(1)]),
+        ]);
+        assert_eq!(
+            tally(&mut interpreter, vec![values]),
+            Ok(tally_rows(vec![
+                (Value::array(vec![Value::Number(1)]), 2),
+                (Value::array(vec![s("1")]), 1),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_set_key_terminates_on_cyclic_arrays() {
+        let items = Value::array(vec![]);
+        if let Value::Array(inner) = &items {
+            inner.borrow_mut().push(items.clone());
+        }
+        assert!(set_key(&items).starts_with("a[a[a["));
+        if let Value::Array(inner) = &items {
+            inner.borrow_mut().clear();
+        }
+    }
+
+    #[test]
+    fn test_sliding_window_rejects_zero_step() {
+        let mut interpreter = Interpreter::new();
+        let result = sliding_window(
+            &mut interpreter,
+            vec![numbers(&[1, 2]), Value::Number(1), Value::Number(0)],
+        );
+        assert!(result.is_err());
+    }
+
+    fn record(id: i64, v: &str) -> Value {
+        let mut object = Object::new("object");
+        object.set_property("id".to_string(), Value::Number(id));
+        object.set_property("v".to_string(), Value::String(v.to_string()));
+        Value::object(object)
+    }
+
+    #[test]
+    fn test_dedupe_by_property_name() {
+        let mut interpreter = Interpreter::new();
+        let records = vec![record(1, "a"), record(1, "b"), record(2, "c")];
+
+        let result = dedupe_by(
+            &mut interpreter,
+            vec![
+                Value::array(records.clone()),
+                Value::String("id".to_string()),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            result,
+            Value::array(vec![records[0].clone(), records[2].clone()])
+        );
+    }
+
+    #[test]
+    fn test_dedupe_by_function() {
+        let mut interpreter = Interpreter::new();
+        let half = define(&mut interpreter, "fn half(x) { return x / 2; }", "half");
+
+        let result = dedupe_by(&mut interpreter, vec![numbers(&[1, 2, 3, 4, 5]), half]);
+        assert_eq!(result, Ok(numbers(&[1, 2, 4])));
+    }
+
+    #[test]
+    fn test_dedupe_by_property_of_non_object_fails() {
+        let mut interpreter = Interpreter::new();
+        let result = dedupe_by(
+            &mut interpreter,
+            vec![numbers(&[1]), Value::String("id".to_string())],
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_sum_by_property_name() {
+        let mut interpreter = Interpreter::new();
+        let records = Value::array(vec![record(1, "a"), record(2, "b"), record(3, "c")]);
+        let id = Value::String("id".to_string());
+
+        let result = sum_by(&mut interpreter, vec![records.clone(), id.clone()]);
+        assert_eq!(result, Ok(Value::Float(6.0)));
+        let result = average_by(&mut interpreter, vec![records, id]);
+        assert_eq!(result, Ok(Value::Float(2.0)));
+    }
+
+    #[test]
+    fn test_sum_by_function() {
+        let mut interpreter = Interpreter::new();
+        let double = define(&mut interpreter, "fn double(x) { return x * 2; }", "double");
+
+        let result = sum_by(&mut interpreter, vec![numbers(&[2, 4, 7]), double.clone()]);
+        assert_eq!(result, Ok(Value::Float(26.0)));
+        let result = average_by(&mut interpreter, vec![numbers(&[1, 2]), double]);
+        assert_eq!(result, Ok(Value::Float(3.0)));
+    }
+
+    #[test]
+    fn test_sum_by_empty_array_is_void() {
+        let mut interpreter = Interpreter::new();
+        let id = Value::String("id".to_string());
+        assert_eq!(
+            sum_by(&mut interpreter, vec![numbers(&[]), id.clone()]),
+            Ok(Value::Void)
+        );
+        assert_eq!(
+            average_by(&mut interpreter, vec![numbers(&[]), id]),
+            Ok(Value::Void)
+        );
+    }
+
+    #[test]
+    fn test_sum_by_rejects_non_numeric_keys() {
+        let mut interpreter = Interpreter::new();
+        let records = Value::array(vec![record(1, "a"), record(2, "b")]);
+        let result = sum_by(
+            &mut interpreter,
+            vec![records, Value::String("v".to_string())],
+        );
+        assert_eq!(
+            result,
+            Err("arr.sum_by: key of element 0 is a string, expected a number".to_string())
+        );
+    }
+
+    #[test]
+    fn test_transpose() {
+        let mut interpreter = Interpreter::new();
+        let result = transpose(&mut interpreter, vec![nested(&[&[1, 2, 3], &[4, 5, 6]])]);
+        assert_eq!(result, Ok(nested(&[&[1, 4], &[2, 5], &[3, 6]])));
+    }
+
+    #[test]
+    fn test_transpose_pads_ragged_rows() {
+        let mut interpreter = Interpreter::new();
+        let result = transpose(&mut interpreter, vec![nested(&[&[1, 2], &[3]])]);
+        assert_eq!(
+            result,
+            Ok(Value::array(vec![
+                numbers(&[1, 3]),
+                Value::array(vec![Value::Number(2), Value::Void]),
+            ]))
+        );
+
+        let result = transpose(&mut interpreter, vec![nested(&[])]);
+        assert_eq!(result, Ok(nested(&[])));
+    }
+
+    #[test]
+    fn test_transpose_rejects_non_array_rows() {
+        let mut interpreter = Interpreter::new();
+        let result = transpose(&mut interpreter, vec![numbers(&[1, 2])]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_first_and_last() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(first(&mut interpreter, vec![numbers(&[])]), Ok(Value::Void));
+        assert_eq!(last(&mut interpreter, vec![numbers(&[])]), Ok(Value::Void));
+        assert_eq!(
+            first(&mut interpreter, vec![numbers(&[1, 2, 3])]),
+            Ok(Value::Number(1))
+        );
+        assert_eq!(
+            last(&mut interpreter, vec![numbers(&[1, 2, 3])]),
+            Ok(Value::Number(3))
+        );
+    }
+
+    #[test]
+    fn test_first_or_and_last_or() {
+        let mut interpreter = Interpreter::new();
+        let default = Value::Number(42);
+        assert_eq!(
+            first_or(&mut interpreter, vec![numbers(&[]), default.clone()]),
+            Ok(Value::Number(42))
+        );
+        assert_eq!(
+            last_or(&mut interpreter, vec![numbers(&[]), default.clone()]),
+            Ok(Value::Number(42))
+        );
+        assert_eq!(
+            last_or(&mut interpreter, vec![numbers(&[1, 2, 3]), default]),
+            Ok(Value::Number(3))
+        );
+        assert!(first_or(&mut interpreter, vec![numbers(&[1])]).is_err());
+    }
+
+    #[test]
+    fn test_nth() {
+        let mut interpreter = Interpreter::new();
+        let mut nth_of = |n: i64| {
+            nth(
+                &mut interpreter,
+                vec![numbers(&[10, 20, 30]), Value::Number(n)],
+            )
+        };
+        assert_eq!(nth_of(0), Ok(Value::Number(10)));
+        assert_eq!(nth_of(2), Ok(Value::Number(30)));
+        assert_eq!(nth_of(3), Ok(Value::Void));
+        assert_eq!(nth_of(-1), Ok(Value::Void));
+    }
+
+    #[test]
+    fn test_every_nth() {
+        let mut interpreter = Interpreter::new();
+        let mut pick = |values: &[i64], args: &[i64]| {
+            let mut call_args = vec![numbers(values)];
+            call_args.extend(args.iter().map(|n| Value::Number(*n)));
+            every_nth(&mut interpreter, call_args)
+        };
+        let nine = [0, 1, 2, 3, 4, 5, 6, 7, 8];
+        assert_eq!(pick(&nine, &[3]), Ok(numbers(&[0, 3, 6])));
+        assert_eq!(pick(&nine, &[3, 1]), Ok(numbers(&[1, 4, 7])));
+        assert_eq!(pick(&nine, &[1]), Ok(numbers(&nine)));
+        assert_eq!(
+            pick(&[0, 1, 2, 3, 4, 5, 6, 7], &[3]),
+            Ok(numbers(&[0, 3, 6]))
+        );
+        assert_eq!(pick(&[0, 1, 2, 3, 4, 5, 6], &[3, 2]), Ok(numbers(&[2, 5])));
+        // An offset past the step starts further in, not at offset % n
+        assert_eq!(pick(&nine, &[3, 5]), Ok(numbers(&[5, 8])));
+        assert_eq!(pick(&nine, &[3, 9]), Ok(numbers(&[])));
+        assert_eq!(pick(&nine, &[100]), Ok(numbers(&[0])));
+        assert_eq!(pick(&[], &[2]), Ok(numbers(&[])));
+        assert_eq!(
+            pick(&nine, &[0]),
+            Err("arr.every_nth: n must be at least 1, got 0".to_string())
+        );
+        assert_eq!(
+            pick(&nine, &[2, -1]),
+            Err("arr.every_nth: offset must not be negative, got -1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_rotate() {
+        let mut interpreter = Interpreter::new();
+        let mut rotate_by = |n: i64| {
+            rotate(
+                &mut interpreter,
+                vec![numbers(&[1, 2, 3, 4, 5]), Value::Number(n)],
+            )
+        };
+        assert_eq!(rotate_by(2), Ok(numbers(&[4, 5, 1, 2, 3])));
+        assert_eq!(rotate_by(-2), Ok(numbers(&[3, 4, 5, 1, 2])));
+        assert_eq!(rotate_by(0), Ok(numbers(&[1, 2, 3, 4, 5])));
+        assert_eq!(rotate_by(7), Ok(numbers(&[4, 5, 1, 2, 3])));
+        assert_eq!(rotate_by(-6), Ok(numbers(&[2, 3, 4, 5, 1])));
+    }
+
+    #[test]
+    fn test_rotate_short_arrays() {
+        let mut interpreter = Interpreter::new();
+        let result = rotate(&mut interpreter, vec![numbers(&[]), Value::Number(3)]);
+        assert_eq!(result, Ok(numbers(&[])));
+        let result = rotate(&mut interpreter, vec![numbers(&[9]), Value::Number(-3)]);
+        assert_eq!(result, Ok(numbers(&[9])));
+    }
+
+    #[test]
+    fn test_interleave() {
+        let mut interpreter = Interpreter::new();
+        let result = interleave(&mut interpreter, vec![numbers(&[1, 2]), numbers(&[3, 4])]);
+        assert_eq!(result, Ok(numbers(&[1, 3, 2, 4])));
+
+        let result = interleave(
+            &mut interpreter,
+            vec![numbers(&[1, 2, 3]), strings(&["a", "b", "c"])],
+        );
+        assert_eq!(
+            result,
+            Ok(Value::array(vec![
+                Value::Number(1),
+                Value::String("a".to_string()),
+                Value::Number(2),
+                Value::String("b".to_string()),
+                Value::Number(3),
+                Value::String("c".to_string()),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_interleave_stops_at_shorter_unless_padded() {
+        let mut interpreter = Interpreter::new();
+        let result = interleave(&mut interpreter, vec![numbers(&[1, 2, 3]), numbers(&[4])]);
+        assert_eq!(result, Ok(numbers(&[1, 4])));
+
+        let result = interleave(
+            &mut interpreter,
+            vec![numbers(&[1, 2]), numbers(&[4]), Value::Number(1)],
+        );
+        assert_eq!(
+            result,
+            Ok(Value::array(vec![
+                Value::Number(1),
+                Value::Number(4),
+                Value::Number(2),
+                Value::Void,
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_unzip() {
+        let mut interpreter = Interpreter::new();
+        let pairs = Value::array(vec![
+            Value::array(vec![Value::Number(1), Value::String("a".to_string())]),
+            Value::array(vec![Value::Number(2), Value::String("b".to_string())]),
+        ]);
+        let result = unzip(&mut interpreter, vec![pairs]);
+        assert_eq!(
+            result,
+            Ok(Value::array(vec![numbers(&[1, 2]), strings(&["a", "b"])]))
+        );
+
+        let result = unzip(&mut interpreter, vec![nested(&[&[1, 2, 3]])]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_fill() {
+        let mut interpreter = Interpreter::new();
+        let result = fill(&mut interpreter, vec![Value::Number(3), Value::Number(0)]);
+        assert_eq!(result, Ok(numbers(&[0, 0, 0])));
+
+        let result = fill(&mut interpreter, vec![Value::Number(0), Value::Number(1)]);
+        assert_eq!(result, Ok(numbers(&[])));
+
+        let result = fill(&mut interpreter, vec![Value::Number(-1), Value::Number(0)]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_fill_with_function() {
+        let mut interpreter = Interpreter::new();
+        let double = define(&mut interpreter, "fn double(i) { return i * 2; }", "double");
+        let result = fill(&mut interpreter, vec![Value::Number(3), double]);
+        assert_eq!(result, Ok(numbers(&[0, 2, 4])));
+    }
+
+    #[test]
+    fn test_new() {
+        let mut interpreter = Interpreter::new();
+        let result = new(&mut interpreter, vec![Value::Number(2)]);
+        assert_eq!(result, Ok(Value::array(vec![Value::Void, Value::Void])));
+    }
+
+    #[test]
+    fn test_fill_and_new_reject_huge_sizes() {
+        let mut interpreter = Interpreter::new();
+        let result = new(&mut interpreter, vec![Value::Number(i64::MAX)]);
+        assert_eq!(
+            result,
+            Err(format!(
+                "arr.new: {} elements is more than the limit of 16777216",
+                i64::MAX
+            ))
+        );
+        let result = new(&mut interpreter, vec![Value::Number(-1)]);
+        assert_eq!(
+            result,
+            Err("arr.new: size must not be negative, got -1".to_string())
+        );
+
+        // Within the hard limit, the memory limit is checked before allocating
+        interpreter.set_memory_limit(1024 * 1024);
+        let err = fill(
+            &mut interpreter,
+            vec![Value::Number(10_000_000), Value::Number(0)],
+        )
+        .unwrap_err();
+        assert!(err.contains("Memory limit exceeded"), "{}", err);
+        let err = new(&mut interpreter, vec![Value::Number(10_000_000)]).unwrap_err();
+        assert!(err.contains("Memory limit exceeded"), "{}", err);
+        let result = fill(&mut interpreter, vec![Value::Number(3), Value::Number(0)]);
+        assert_eq!(result, Ok(numbers(&[0, 0, 0])));
+    }
+
+    #[test]
+    fn test_repeat() {
+        let mut interpreter = Interpreter::new();
+        let result = repeat(&mut interpreter, vec![numbers(&[0]), Value::Number(5)]);
+        assert_eq!(result, Ok(numbers(&[0, 0, 0, 0, 0])));
+        let result = repeat(
+            &mut interpreter,
+            vec![numbers(&[1, 2, 3]), Value::Number(2)],
+        );
+        assert_eq!(result, Ok(numbers(&[1, 2, 3, 1, 2, 3])));
+        let result = repeat(&mut interpreter, vec![numbers(&[1, 2]), Value::Number(0)]);
+        assert_eq!(result, Ok(numbers(&[])));
+        assert_eq!(
+            repeat(&mut interpreter, vec![numbers(&[1]), Value::Number(-1)]),
+            Err("arr.repeat: n must not be negative, got -1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_cycle() {
+        let mut interpreter = Interpreter::new();
+        let result = cycle(&mut interpreter, vec![numbers(&[1, 2]), Value::Number(5)]);
+        assert_eq!(result, Ok(numbers(&[1, 2, 1, 2, 1])));
+        let result = cycle(
+            &mut interpreter,
+            vec![numbers(&[1, 2, 3]), Value::Number(2)],
+        );
+        assert_eq!(result, Ok(numbers(&[1, 2])));
+        let result = cycle(&mut interpreter, vec![numbers(&[]), Value::Number(3)]);
+        assert_eq!(result, Ok(numbers(&[])));
+        assert!(cycle(&mut interpreter, vec![numbers(&[1]), Value::Number(-2)]).is_err());
+    }
+
+    #[test]
+    fn test_repeat_and_cycle_reject_huge_results() {
+        let mut interpreter = Interpreter::new();
+        let result = cycle(
+            &mut interpreter,
+            vec![numbers(&[1]), Value::Number(i64::MAX)],
+        );
+        assert_eq!(
+            result,
+            Err(format!(
+                "arr.cycle: {} elements is more than the limit of 16777216",
+                i64::MAX
+            ))
+        );
+        let result = repeat(
+            &mut interpreter,
+            vec![numbers(&[1, 2]), Value::Number(10_000_000)],
+        );
+        assert_eq!(
+            result,
+            Err("arr.repeat: 20000000 elements is more than the limit of 16777216".to_string())
+        );
+        // An empty array builds nothing, however often it is repeated
+        let result = cycle(
+            &mut interpreter,
+            vec![numbers(&[]), Value::Number(i64::MAX)],
+        );
+        assert_eq!(result, Ok(numbers(&[])));
+
+        interpreter.set_memory_limit(1024 * 1024);
+        let err = repeat(
+            &mut interpreter,
+            vec![numbers(&[1]), Value::Number(1_000_000)],
+        )
+        .unwrap_err();
+        assert!(err.contains("Memory limit exceeded"), "{}", err);
+    }
+
+    #[test]
+    fn test_flatten_deep() {
+        let mut interpreter = Interpreter::new();
+        let nested = Value::array(vec![
+            Value::Number(1),
+            Value::array(vec![
+                Value::Number(2),
+                Value::array(vec![Value::Number(3), Value::array(vec![Value::Number(4)])]),
+            ]),
+            numbers(&[]),
+        ]);
+        let result = flatten_deep(&mut interpreter, vec![nested.clone()]);
+        assert_eq!(result, Ok(numbers(&[1, 2, 3, 4])));
+        assert_eq!(
+            flatten_deep(&mut interpreter, vec![nested, Value::Number(3)]),
+            Err("arr.flatten_deep: arrays are nested more than 3 levels deep".to_string())
+        );
+
+        let mut deep = numbers(&[7]);
+        for _ in 0..1000 {
+            deep = Value::array(vec![deep]);
+        }
+        assert!(flatten_deep(&mut interpreter, vec![deep]).is_err());
+
+        // An array containing itself is nested without end
+        let Value::Array(cyclic) = numbers(&[1]) else {
+            unreachable!()
+        };
+        cyclic.borrow_mut().push(Value::Array(cyclic.clone()));
+        let result = flatten_deep(&mut interpreter, vec![Value::Array(cyclic.clone())]);
+        assert_eq!(
+            result,
+            Err("arr.flatten_deep: arrays are nested more than 1000 levels deep".to_string())
+        );
+        cyclic.borrow_mut().clear();
+    }
+
+    #[test]
+    fn test_weighted_random() {
+        let mut interpreter = Interpreter::new();
+        let items = strings(&["a", "b", "c"]);
+        let weights = numbers(&[1, 2, 3]);
+        let mut counts = HashMap::new();
+        for seed in 0..6000 {
+            let picked = weighted_random(
+                &mut interpreter,
+                vec![items.clone(), weights.clone(), Value::Number(seed)],
+            )
+            .unwrap();
+            *counts.entry(picked.to_string()).or_insert(0) += 1;
+        }
+        // Expected 1000, 2000 and 3000
+        assert!((850..1150).contains(&counts["a"]), "{:?}", counts);
+        assert!((1800..2200).contains(&counts["b"]), "{:?}", counts);
+        assert!((2800..3200).contains(&counts["c"]), "{:?}", counts);
+
+        let pick = |interpreter: &mut Interpreter| {
+            weighted_random(
+                interpreter,
+                vec![items.clone(), weights.clone(), Value::Number(42)],
+            )
+        };
+        assert_eq!(pick(&mut interpreter), pick(&mut interpreter));
+        let only = weighted_random(&mut interpreter, vec![items.clone(), numbers(&[0, 0, 0])]);
+        assert_eq!(
+            only,
+            Err("arr.weighted_random: weight 0 must be a positive number, got 0".to_string())
+        );
+        let unequal = weighted_random(&mut interpreter, vec![items.clone(), numbers(&[1])]);
+        assert_eq!(
+            unequal,
+            Err(
+                "arr.weighted_random: items and weights must have the same length, got 3 and 1"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_sample() {
+        let mut interpreter = Interpreter::new();
+        let array = numbers(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        let draw = |interpreter: &mut Interpreter, n: i64, seed: i64| {
+            sample(
+                interpreter,
+                vec![array.clone(), Value::Number(n), Value::Number(seed)],
+            )
+        };
+
+        let Ok(Value::Array(drawn)) = draw(&mut interpreter, 5, 7) else {
+            panic!("sample should return an array");
+        };
+        let drawn = drawn.borrow().clone();
+        assert_eq!(drawn.len(), 5);
+        assert_eq!(key_set(&drawn).len(), 5);
+        assert!(drawn.iter().all(|n| matches!(n, Value::Number(1..=8))));
+        assert_eq!(draw(&mut interpreter, 5, 7), draw(&mut interpreter, 5, 7));
+
+        let Ok(Value::Array(all)) = draw(&mut interpreter, 8, 3) else {
+            panic!("sample should return an array");
+        };
+        let mut all: Vec<Value> = all.borrow().clone();
+        all.sort_by_key(|n| n.to_string());
+        assert_eq!(Value::array(all), array);
+        assert_eq!(draw(&mut interpreter, 0, 1), Ok(numbers(&[])));
+        assert_eq!(
+            draw(&mut interpreter, 9, 1),
+            Err("arr.sample: n must be between 0 and the array length 8, got 9".to_string())
+        );
+    }
+
+    /// `small(x)` is `x < 3` and records every element it was called with.
+    fn recording_small(interpreter: &mut Interpreter) -> Value {
+        define(
+            interpreter,
+            "let seen = std.arr.new(0);\nfn small(x) { std.arr.push(seen, x); return x < 3; }",
+            "small",
+        )
+    }
+
+    fn seen(interpreter: &Interpreter) -> Value {
+        interpreter.env.get_variable("seen").unwrap().clone()
+    }
+
+    #[test]
+    fn test_all_stops_at_first_falsy() {
+        let mut interpreter = Interpreter::new();
+        let small = recording_small(&mut interpreter);
+
+        let result = all(
+            &mut interpreter,
+            vec![numbers(&[1, 5, 2, 7]), small.clone()],
+        );
+        assert_eq!(result, Ok(Value::Number(0)));
+        assert_eq!(seen(&interpreter), numbers(&[1, 5]));
+
+        let result = all(&mut interpreter, vec![numbers(&[1, 2]), small.clone()]);
+        assert_eq!(result, Ok(Value::Number(1)));
+        let result = all(&mut interpreter, vec![numbers(&[]), small]);
+        assert_eq!(result, Ok(Value::Number(1)));
+    }
+
+    #[test]
+    fn test_any_stops_at_first_truthy() {
+        let mut interpreter = Interpreter::new();
+        let small = recording_small(&mut interpreter);
+
+        let result = any(&mut interpreter, vec![numbers(&[5, 2, 1]), small.clone()]);
+        assert_eq!(result, Ok(Value::Number(1)));
+        assert_eq!(seen(&interpreter), numbers(&[5, 2]));
+
+        let result = any(&mut interpreter, vec![numbers(&[5, 7]), small.clone()]);
+        assert_eq!(result, Ok(Value::Number(0)));
+        let result = any(&mut interpreter, vec![numbers(&[]), small]);
+        assert_eq!(result, Ok(Value::Number(0)));
+    }
+
+    #[test]
+    fn test_all_any_require_a_function() {
+        let mut interpreter = Interpreter::new();
+        let args = vec![numbers(&[1]), Value::Number(1)];
+        assert!(all(&mut interpreter, args.clone()).is_err());
+        assert!(any(&mut interpreter, args).is_err());
+    }
+
+    #[test]
+    fn test_none_stops_at_first_truthy() {
+        let mut interpreter = Interpreter::new();
+        let small = recording_small(&mut interpreter);
+
+        let result = none(&mut interpreter, vec![numbers(&[5, 1, 2]), small.clone()]);
+        assert_eq!(result, Ok(Value::Number(0)));
+        assert_eq!(seen(&interpreter), numbers(&[5, 1]));
+
+        let result = none(&mut interpreter, vec![numbers(&[5, 7]), small.clone()]);
+        assert_eq!(result, Ok(Value::Number(1)));
+        let result = none(&mut interpreter, vec![numbers(&[]), small]);
+        assert_eq!(result, Ok(Value::Number(1)));
+    }
+
+    #[test]
+    fn test_count_if() {
+        let mut interpreter = Interpreter::new();
+        let is_even = define(
+            &mut interpreter,
+            "fn is_even(x) { return x / 2 * 2 == x; }",
+            "is_even",
+        );
+
+        let result = count_if(
+            &mut interpreter,
+            vec![numbers(&[1, 2, 3, 4, 5]), is_even.clone()],
+        );
+        assert_eq!(result, Ok(Value::Number(2)));
+        let result = count_if(&mut interpreter, vec![numbers(&[]), is_even]);
+        assert_eq!(result, Ok(Value::Number(0)));
+
+        let result = count_if(&mut interpreter, vec![numbers(&[1]), Value::Void]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_sort_by_property() {
+        let mut interpreter = Interpreter::new();
+        let records = vec![record(3, "c"), record(1, "a"), record(2, "b")];
+
+        let by_id = vec![
+            Value::array(records.clone()),
+            Value::String("id".to_string()),
+        ];
+        let result = sort_by(&mut interpreter, by_id.clone());
+        let ascending = vec![records[1].clone(), records[2].clone(), records[0].clone()];
+        assert_eq!(result, Ok(Value::array(ascending.clone())));
+
+        let mut descending = by_id;
+        descending.push(Value::Number(0));
+        let result = sort_by(&mut interpreter, descending);
+        let reversed = ascending.into_iter().rev().collect();
+        assert_eq!(result, Ok(Value::array(reversed)));
+    }
+
+    #[test]
+    fn test_sort_by_function_is_stable() {
+        let mut interpreter = Interpreter::new();
+        let half = define(&mut interpreter, "fn half(x) { return x / 2; }", "half");
+
+        let result = sort_by(
+            &mut interpreter,
+            vec![numbers(&[5, 1, 4, 0, 3]), half.clone()],
+        );
+        assert_eq!(result, Ok(numbers(&[1, 0, 3, 5, 4])));
+
+        let args = vec![numbers(&[5, 1, 4, 0, 3]), half, Value::Number(0)];
+        let result = sort_by(&mut interpreter, args);
+        assert_eq!(result, Ok(numbers(&[5, 4, 3, 1, 0])));
+    }
+
+    #[test]
+    fn test_sort_by_float_keys() {
+        let mut interpreter = Interpreter::new();
+        let id = define(&mut interpreter, "fn id(x) { return x; }", "id");
+
+        let averages = moving_average(
+            &mut interpreter,
+            vec![numbers(&[6, 0, 2, 1]), Value::Number(2)],
+        )
+        .unwrap();
+        let result = sort_by(&mut interpreter, vec![averages, id.clone()]);
+        let sorted = vec![Value::Float(1.0), Value::Float(1.5), Value::Float(3.0)];
+        assert_eq!(result, Ok(Value::array(sorted)));
+
+        // Ints and floats sort together
+        let mixed = Value::array(vec![Value::Number(3), Value::Float(2.5), Value::Number(1)]);
+        let result = sort_by(&mut interpreter, vec![mixed, id.clone()]);
+        let sorted = vec![Value::Number(1), Value::Float(2.5), Value::Number(3)];
+        assert_eq!(result, Ok(Value::array(sorted)));
+
+        let nan = Value::array(vec![Value::Float(1.0), Value::Float(f64::NAN)]);
+        let err = sort_by(&mut interpreter, vec![nan, id]).unwrap_err();
+        assert_eq!(err, "arr.sort_by: a key is NaN");
+    }
+
+    #[test]
+    fn test_sort_by_strings_and_mixed_keys() {
+        let mut interpreter = Interpreter::new();
+        let records = vec![record(1, "b"), record(2, "a")];
+        let args = vec![
+            Value::array(records.clone()),
+            Value::String("v".to_string()),
+        ];
+        let result = sort_by(&mut interpreter, args);
+        let sorted = vec![records[1].clone(), records[0].clone()];
+        assert_eq!(result, Ok(Value::array(sorted)));
+
+        let mixed = Value::array(vec![numbers(&[1]), strings(&["a"])]);
+        let first = define(
+            &mut interpreter,
+            "fn head(x) { return std.arr.first(x); }",
+            "head",
+        );
+        let err = sort_by(&mut interpreter, vec![mixed, first]).unwrap_err();
+        assert_eq!(
+            err,
+            "arr.sort_by: cannot compare a number key with a string key"
+        );
+
+        let err = sort_by(
+            &mut interpreter,
+            vec![
+                Value::array(vec![record(1, "a")]),
+                Value::String("missing".to_string()),
+            ],
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            "arr.sort_by: keys must be numbers or strings, got void"
+        );
+    }
+
+    #[test]
+    fn test_scan_running_total() {
+        let mut interpreter = Interpreter::new();
+        let add = define(
+            &mut interpreter,
+            "fn add(acc, x) { return acc + x; }",
+            "add",
+        );
+
+        let args = vec![numbers(&[1, 2, 3, 4]), Value::Number(0), add.clone()];
+        assert_eq!(scan(&mut interpreter, args), Ok(numbers(&[0, 1, 3, 6, 10])));
+
+        let args = vec![numbers(&[]), Value::Number(7), add];
+        assert_eq!(scan(&mut interpreter, args), Ok(numbers(&[7])));
+    }
+
+    #[test]
+    fn test_scan_stops_on_error() {
+        let mut interpreter = Interpreter::new();
+        let div = define(
+            &mut interpreter,
+            "fn div(acc, x) { return acc / x; }",
+            "div",
+        );
+
+        let args = vec![numbers(&[2, 0, 1]), Value::Number(8), div];
+        let err = scan(&mut interpreter, args).unwrap_err();
+        assert!(err.contains("Division by zero"), "{}", err);
+    }
+
+    fn floats(values: &[f64]) -> Value {
+        Value::array(values.iter().map(|n| Value::Float(*n)).collect())
+    }
+
+    #[test]
+    fn test_moving_average() {
+        let mut interpreter = Interpreter::new();
+        let args = vec![numbers(&[1, 2, 3, 4, 6]), Value::Number(2)];
+        let result = moving_average(&mut interpreter, args);
+        assert_eq!(result, Ok(floats(&[1.5, 2.5, 3.5, 5.0])));
+
+        let args = vec![numbers(&[3, 5, 10]), Value::Number(3)];
+        assert_eq!(moving_average(&mut interpreter, args), Ok(floats(&[6.0])));
+
+        let mixed = Value::array(vec![Value::Float(0.5), Value::Number(1)]);
+        let args = vec![mixed, Value::Number(1)];
+        assert_eq!(
+            moving_average(&mut interpreter, args),
+            Ok(floats(&[0.5, 1.0]))
+        );
+    }
+
+    #[test]
+    fn test_moving_average_errors() {
+        let mut interpreter = Interpreter::new();
+        for window in [0, 4] {
+            let args = vec![numbers(&[1, 2, 3]), Value::Number(window)];
+            assert!(moving_average(&mut interpreter, args).is_err());
+        }
+        let args = vec![strings(&["1", "2"]), Value::Number(1)];
+        let err = moving_average(&mut interpreter, args).unwrap_err();
+        assert!(err.contains("element 0 is a string"));
+    }
+
+    #[test]
+    fn test_bisect() {
+        let mut interpreter = Interpreter::new();
+        let sorted = numbers(&[1, 3, 3, 3, 7]);
+        let cases = [(0, 0, 0), (1, 0, 1), (3, 1, 4), (5, 4, 4), (9, 5, 5)];
+        for (value, left, right) in cases {
+            let args = vec![sorted.clone(), Value::Number(value)];
+            assert_eq!(
+                bisect_left(&mut interpreter, args.clone()),
+                Ok(Value::Number(left))
+            );
+            assert_eq!(
+                bisect_right(&mut interpreter, args),
+                Ok(Value::Number(right))
+            );
+        }
+
+        let args = vec![strings(&["ant", "cat", "dog"]), Value::String("cow".into())];
+        assert_eq!(bisect_left(&mut interpreter, args), Ok(Value::Number(2)));
+        let args = vec![numbers(&[1, 2]), Value::Float(1.5)];
+        assert_eq!(bisect_right(&mut interpreter, args), Ok(Value::Number(1)));
+        let args = vec![numbers(&[]), Value::Number(1)];
+        assert_eq!(bisect_left(&mut interpreter, args), Ok(Value::Number(0)));
+    }
+
+    #[test]
+    fn test_bisect_rejects_mixed_types() {
+        let mut interpreter = Interpreter::new();
+        let args = vec![numbers(&[1, 2, 3]), Value::String("2".into())];
+        let err = bisect_left(&mut interpreter, args).unwrap_err();
+        assert_eq!(
+            err,
+            "arr.bisect_left: cannot compare a number with a string"
+        );
+    }
+
+    #[test]
+    fn test_zip_with() {
+        let mut interpreter = Interpreter::new();
+        let multiply = define(
+            &mut interpreter,
+            "fn multiply(a, b) { return a * b; }",
+            "multiply",
+        );
+        let args = vec![numbers(&[1, 2]), numbers(&[3, 4, 5]), multiply];
+        assert_eq!(zip_with(&mut interpreter, args), Ok(numbers(&[3, 8])));
+    }
+
+    #[test]
+    fn test_zip_with_stops_at_first_error() {
+        let mut interpreter = Interpreter::new();
+        let divide = define(
+            &mut interpreter,
+            "let seen = std.arr.new(0);\nfn divide(a, b) { std.arr.push(seen, a); return a / b; }",
+            "divide",
+        );
+        let args = vec![numbers(&[1, 5, 2]), numbers(&[1, 0, 1]), divide];
+        assert!(zip_with(&mut interpreter, args).is_err());
+        assert_eq!(seen(&interpreter), numbers(&[1, 5]));
+    }
+
+    #[test]
+    fn test_zip3() {
+        let mut interpreter = Interpreter::new();
+        let args = vec![numbers(&[1, 2]), strings(&["a", "b"]), numbers(&[1, 0, 1])];
+        let expected = Value::array(vec![
+            Value::array(vec![
+                Value::Number(1),
+                Value::String("a".into()),
+                Value::Number(1),
+            ]),
+            Value::array(vec![
+                Value::Number(2),
+                Value::String("b".into()),
+                Value::Number(0),
+            ]),
+        ]);
+        assert_eq!(zip3(&mut interpreter, args), Ok(expected));
+    }
+
+    #[test]
+    fn test_cartesian_product() {
+        let mut interpreter = Interpreter::new();
+        let args = vec![numbers(&[1, 2]), strings(&["a", "b"])];
+        let result = cartesian_product(&mut interpreter, args).unwrap();
+        let pair = |n: i64, s: &str| Value::array(vec![Value::Number(n), Value::String(s.into())]);
+        assert_eq!(
+            result,
+            Value::array(vec![pair(1, "a"), pair(1, "b"), pair(2, "a"), pair(2, "b")])
+        );
+
+        let args = vec![numbers(&[1, 2, 3]), numbers(&[])];
+        assert_eq!(cartesian_product(&mut interpreter, args), Ok(numbers(&[])));
+
+        let large = Value::array(vec![Value::Number(0); 5_000]);
+        let err = cartesian_product(&mut interpreter, vec![large.clone(), large]).unwrap_err();
+        assert_eq!(
+            err,
+            format!(
+                "arr.cartesian_product: 25000000 elements is more than the limit of {}",
+                MAX_BUILD_SIZE
+            )
+        );
+    }
+
+    #[test]
+    fn test_combinations() {
+        let mut interpreter = Interpreter::new();
+        let result = combinations(
+            &mut interpreter,
+            vec![numbers(&[1, 2, 3]), Value::Number(2)],
+        );
+        assert_eq!(result, Ok(nested(&[&[1, 2], &[1, 3], &[2, 3]])));
+
+        let result = combinations(
+            &mut interpreter,
+            vec![numbers(&[1, 2, 3, 4]), Value::Number(3)],
+        );
+        assert_eq!(
+            result,
+            Ok(nested(&[&[1, 2, 3], &[1, 2, 4], &[1, 3, 4], &[2, 3, 4]]))
+        );
+
+        let result = combinations(&mut interpreter, vec![numbers(&[1, 2]), Value::Number(0)]);
+        assert_eq!(result, Ok(nested(&[&[]])));
+        let result = combinations(&mut interpreter, vec![numbers(&[1, 2]), Value::Number(3)]);
+        assert_eq!(result, Ok(nested(&[])));
+        assert!(combinations(&mut interpreter, vec![numbers(&[1]), Value::Number(-1)]).is_err());
+
+        // 60 choose 30 is about 1.2e17, 200 choose 100 does not fit a usize
+        let sixty = Value::array(vec![Value::Number(0); 60]);
+        let err = combinations(&mut interpreter, vec![sixty, Value::Number(30)]).unwrap_err();
+        assert_eq!(
+            err,
+            format!(
+                "arr.combinations: 118264581564861424 elements is more than the limit of {}",
+                MAX_BUILD_SIZE
+            )
+        );
+        let two_hundred = Value::array(vec![Value::Number(0); 200]);
+        let err = combinations(&mut interpreter, vec![two_hundred, Value::Number(100)]);
+        assert_eq!(
+            err,
+            Err(format!(
+                "arr.combinations: more elements than the limit of {}",
+                MAX_BUILD_SIZE
+            ))
+        );
+        assert_eq!(binomial(5, 2), Some(10));
+        assert_eq!(binomial(5, 5), Some(1));
+    }
+}
@@ -0,0 +1,125 @@
+//! `std.counter`: an object whose methods change the object they are called
+//! on, through `Interpreter::current_receiver`.
+
+use crate::interpreter::{Interpreter, Object, ObjectRef, Value};
+use crate::std_lib::expect_number;
+
+/// The object a counter method was called on.
+fn receiver(interpreter: &mut Interpreter, fn_name: &str) -> Result<ObjectRef, String> {
+    interpreter.current_receiver().ok_or_else(|| {
+        format!(
+            "{} must be called on a counter, e.g. c.{}()",
+            fn_name, fn_name
+        )
+    })
+}
+
+/// Adds `delta` to the receiver's `value` and returns the new value.
+fn add(interpreter: &mut Interpreter, delta: i64, fn_name: &str) -> Result<Value, String> {
+    let counter = receiver(interpreter, fn_name)?;
+    let mut counter = counter.borrow_mut();
+    let value = match counter.get_property("value") {
+        Some(Value::Number(n)) => n
+            .checked_add(delta)
+            .ok_or_else(|| format!("{}: counter overflowed", fn_name))?,
+        Some(other) => {
+            return Err(format!(
+                "{}: counter value must be a number, got {}",
+                fn_name,
+                other.type_name()
+            ))
+        }
+        None => delta,
+    };
+    counter.set_property("value".to_string(), Value::Number(value));
+    Ok(Value::Number(value))
+}
+
+/// `counter(start?)` makes a counter at `start` (default 0) with
+/// `increment(by?)`, `decrement(by?)` and `reset()` methods.
+pub fn new(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let start = match args.first() {
+        Some(_) => expect_number(&args, 0, "counter")?,
+        None => 0,
+    };
+    let mut counter = Object::new("counter");
+    counter.set_property("value".to_string(), Value::Number(start));
+    counter.set_property("start".to_string(), Value::Number(start));
+    counter.register_native_fn("increment", increment);
+    counter.register_native_fn("decrement", decrement);
+    counter.register_native_fn("reset", reset);
+    Ok(Value::object(counter))
+}
+
+fn step(args: &[Value], fn_name: &str) -> Result<i64, String> {
+    match args.first() {
+        Some(_) => expect_number(args, 0, fn_name),
+        None => Ok(1),
+    }
+}
+
+/// Adds `by` (default 1) and returns the new value.
+pub fn increment(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let by = step(&args, "increment")?;
+    add(interpreter, by, "increment")
+}
+
+/// Subtracts `by` (default 1) and returns the new value.
+pub fn decrement(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let by = step(&args, "decrement")?;
+    let by = by.checked_neg().ok_or("decrement: step is out of range")?;
+    add(interpreter, by, "decrement")
+}
+
+/// Sets the value back to where the counter started.
+pub fn reset(interpreter: &mut Interpreter, _args: Vec<Value>) -> Result<Value, String> {
+    let counter = receiver(interpreter, "reset")?;
+    let mut counter = counter.borrow_mut();
+    let start = counter
+        .get_property("start")
+        .cloned()
+        .unwrap_or(Value::Number(0));
+    counter.set_property("value".to_string(), start);
+    Ok(Value::Void)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counter_methods_change_their_receiver() {
+        let mut interpreter = Interpreter::new();
+        let counter = new(&mut interpreter, vec![Value::Number(5)]).unwrap();
+        let Value::Object(object) = &counter else {
+            panic!("counter should be an object");
+        };
+        let method = |name: &str| object.borrow().get_property(name).cloned().unwrap();
+
+        let result = interpreter.call_method(object, &method("increment"), vec![]);
+        assert_eq!(result, Ok(Value::Number(6)));
+        let result = interpreter.call_method(object, &method("decrement"), vec![Value::Number(3)]);
+        assert_eq!(result, Ok(Value::Number(3)));
+        assert_eq!(
+            object.borrow().get_property("value"),
+            Some(&Value::Number(3))
+        );
+
+        interpreter
+            .call_method(object, &method("reset"), vec![])
+            .unwrap();
+        assert_eq!(
+            object.borrow().get_property("value"),
+            Some(&Value::Number(5))
+        );
+    }
+
+    #[test]
+    fn test_counter_method_needs_receiver() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(
+            increment(&mut interpreter, vec![]),
+            Err("increment must be called on a counter, e.g. c.increment()".to_string())
+        );
+    }
+}
@@ -0,0 +1,489 @@
+use crate::db::row_schemaless::{OpenMode, Settings, TableRowSchemaless, VERSION_COLUMN};
+use crate::db::scanner::{Row, ScanPosition};
+use crate::db::{query_engine, DBValue, DbError, FilterEntity};
+use crate::interpreter::{Interpreter, Object, ObjectRef, Value};
+use crate::std_lib::{expect_number, expect_object, expect_string};
+use std::collections::HashMap;
+use std::future::Future;
+
+/// An open table, registered as an interpreter resource.
+struct TableHandle {
+    name: String,
+    table: TableRowSchemaless,
+}
+
+/// Natives are synchronous but may be called from inside the CLI's tokio
+/// runtime, where blocking on a future directly panics, so table operations
+/// run on a short-lived runtime in a scoped thread.
+fn block_on<F>(future: F) -> Result<F::Output, String>
+where
+    F: Future + Send,
+    F::Output: Send,
+{
+    std::thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .map(|runtime| runtime.block_on(future))
+                    .map_err(|e| e.to_string())
+            })
+            .join()
+            .map_err(|_| "the table file could not be accessed".to_string())?
+    })
+}
+
+fn expect_handle(args: &[Value], fn_name: &str) -> Result<u64, String> {
+    let handle = expect_number(args, 0, fn_name)?;
+    u64::try_from(handle).map_err(|_| format!("{}: invalid table handle {}", fn_name, handle))
+}
+
+/// Runs `f` on the table behind the handle in argument 1.
+fn with_table<R>(
+    interpreter: &mut Interpreter,
+    args: &[Value],
+    operation: &'static str,
+    f: impl FnOnce(&mut TableHandle) -> Result<R, DbError>,
+) -> Result<R, String> {
+    let handle = expect_handle(args, &format!("db.{}", operation))?;
+    interpreter
+        .with_resource(handle, f)
+        .unwrap_or(Err(DbError::Closed { operation, handle }))
+        .map_err(String::from)
+}
+
+fn to_db_value(value: &Value, operation: &'static str, table: &str) -> Result<DBValue, DbError> {
+    let unsupported = || DbError::UnsupportedValue {
+        operation,
+        table: table.to_string(),
+        value: value.to_string(),
+    };
+    match value {
+        Value::String(s) => Ok(DBValue::String(s.clone())),
+        Value::Number(n) => DBValue::from_integer(*n).ok_or_else(unsupported),
+        Value::Float(n) => Ok(DBValue::Number(*n)),
+        Value::Void => Ok(DBValue::Null),
+        _ => Err(unsupported()),
+    }
+}
+
+/// Opens (creating if needed) table `name` stored in directory `dir` and
+/// returns a handle for the other `db` functions. `mode` is `"write"`
+/// (default), which locks the table until the handle is closed, or
+/// `"read"`, which never changes the table or its directory.
+pub fn open(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let dir = expect_string(&args, 0, "db.open")?;
+    let name = expect_string(&args, 1, "db.open")?;
+    let mode = match args.get(2) {
+        None => OpenMode::ReadWrite,
+        Some(_) => match expect_string(&args, 2, "db.open")?.as_str() {
+            "write" => OpenMode::ReadWrite,
+            "read" => OpenMode::ReadOnly,
+            other => {
+                return Err(format!(
+                    "db.open: mode must be \"read\" or \"write\", got \"{}\"",
+                    other
+                ))
+            }
+        },
+    };
+
+    let settings = Settings {
+        base_path: dir,
+        mode,
+    };
+    let table =
+        block_on(TableRowSchemaless::open(name.clone(), settings)).map_err(|message| {
+            DbError::Io {
+                operation: "open",
+                table: name.clone(),
+                message,
+            }
+        })??;
+
+    let handle = interpreter.register_resource(TableHandle { name, table }, |mut handle| {
+        let _ = block_on(handle.table.flush_indexes());
+    });
+    Ok(Value::Number(handle as i64))
+}
+
+/// Inserts an object as a row.
+pub fn insert(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let row = expect_object(&args, 1, "db.insert")?;
+    with_table(interpreter, &args, "insert", |handle| {
+        let mut data = HashMap::new();
+        for (column, value) in row.borrow().properties() {
+            data.insert(column.clone(), to_db_value(value, "insert", &handle.name)?);
+        }
+        block_on(handle.table.insert(data)).map_err(|message| DbError::Io {
+            operation: "insert",
+            table: handle.name.clone(),
+            message,
+        })?
+    })?;
+    Ok(Value::Void)
+}
+
+/// Returns the number of rows in the table.
+pub fn size(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let size = with_table(interpreter, &args, "size", |handle| {
+        block_on(handle.table.size()).map_err(|message| DbError::Io {
+            operation: "size",
+            table: handle.name.clone(),
+            message,
+        })
+    })?;
+    Ok(Value::Number(size as i64))
+}
+
+fn from_db_value(value: &DBValue) -> Value {
+    match value {
+        DBValue::String(s) => Value::String(s.clone()),
+        DBValue::Number(n) => match value.as_integer() {
+            Some(n) => Value::Number(n),
+            None => Value::Float(*n),
+        },
+        DBValue::Timestamp(ts) => Value::Number(*ts),
+        DBValue::Null => Value::Void,
+    }
+}
+
+/// A filter matching the rows whose columns equal the properties of
+/// `columns`; `None` matches every row.
+fn equality_filter(
+    columns: &Object,
+    operation: &'static str,
+    table: &str,
+) -> Result<Option<FilterEntity>, DbError> {
+    let mut filter = None;
+    for (column, value) in columns.properties() {
+        let equals = FilterEntity::Equals(
+            Box::new(FilterEntity::Column(column.clone())),
+            Box::new(FilterEntity::Value(to_db_value(value, operation, table)?)),
+        );
+        filter = Some(match filter {
+            Some(filter) => FilterEntity::And(Box::new(filter), Box::new(equals)),
+            None => equals,
+        });
+    }
+    Ok(filter)
+}
+
+/// `db.query(handle, columns?)` returns a cursor over the rows whose
+/// columns equal those of the `columns` object, or over every row without
+/// it. Rows are read one at a time as its `has_next()` and `next()` are
+/// called, so `for row in db.query(t) { ... }` never holds the whole table.
+pub fn query(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let handle = expect_handle(&args, "db.query")?;
+    let columns = match args.get(1) {
+        Some(_) => Value::Object(expect_object(&args, 1, "db.query")?),
+        None => Value::Void,
+    };
+    // Checked now so a bad filter fails here rather than in the loop
+    with_table(interpreter, &args, "query", |table| match &columns {
+        Value::Object(columns) => equality_filter(&columns.borrow(), "query", &table.name),
+        _ => Ok(None),
+    })?;
+
+    let mut cursor = Object::new("cursor");
+    cursor.set_property("table".to_string(), Value::Number(handle as i64));
+    cursor.set_property("columns".to_string(), columns);
+    cursor.set_property("row_id".to_string(), Value::Number(0));
+    cursor.set_property("offset".to_string(), Value::Number(0));
+    cursor.register_native_fn("has_next", cursor_has_next);
+    cursor.register_native_fn("next", cursor_next);
+    Ok(Value::object(cursor))
+}
+
+/// The cursor a method was called on.
+fn cursor_receiver(interpreter: &mut Interpreter, fn_name: &str) -> Result<ObjectRef, String> {
+    interpreter.current_receiver().ok_or_else(|| {
+        format!(
+            "cursor.{} must be called on a cursor from db.query, e.g. c.{}()",
+            fn_name, fn_name
+        )
+    })
+}
+
+/// Where the cursor is and the table it reads, as `args` for `with_table`.
+fn cursor_state(cursor: &Object, fn_name: &str) -> Result<(Vec<Value>, ScanPosition), String> {
+    let number = |property: &str| match cursor.get_property(property) {
+        Some(Value::Number(n)) if *n >= 0 => Ok(*n as u64),
+        _ => Err(format!(
+            "cursor.{}: `{}` must be a number that is not negative",
+            fn_name, property
+        )),
+    };
+    let table = Value::Number(number("table")? as i64);
+    let position = ScanPosition {
+        row_id: number("row_id")?,
+        offset: number("offset")?,
+    };
+    Ok((vec![table], position))
+}
+
+fn set_position(cursor: &mut Object, position: ScanPosition) {
+    cursor.set_property("row_id".to_string(), Value::Number(position.row_id as i64));
+    cursor.set_property("offset".to_string(), Value::Number(position.offset as i64));
+}
+
+/// Finds the cursor's next matching row. Returns where that row starts, or
+/// the end of the table if there is none, and the row with the position
+/// after it.
+fn next_match(
+    interpreter: &mut Interpreter,
+    cursor: &ObjectRef,
+    fn_name: &str,
+) -> Result<(ScanPosition, Option<(Row, ScanPosition)>), String> {
+    let (table, position) = cursor_state(&cursor.borrow(), fn_name)?;
+    let columns = cursor.borrow().get_property("columns").cloned();
+    with_table(interpreter, &table, "query", |handle| {
+        let filter = match &columns {
+            Some(Value::Object(columns)) => {
+                equality_filter(&columns.borrow(), "query", &handle.name)?
+            }
+            _ => None,
+        };
+        let table = &handle.table;
+        let scan = async {
+            let mut scanner = table.scan_from(position).await;
+            loop {
+                let start = scanner.scan_position();
+                match scanner.next().await {
+                    Some((_, row))
+                        if filter
+                            .as_ref()
+                            .is_none_or(|filter| query_engine::execute_query(filter, &row)) =>
+                    {
+                        return (start, Some((row, scanner.scan_position())));
+                    }
+                    Some(_) => continue,
+                    None => return (start, None),
+                }
+            }
+        };
+        block_on(scan).map_err(|message| DbError::Io {
+            operation: "query",
+            table: handle.name.clone(),
+            message,
+        })
+    })
+}
+
+/// 1 while the cursor has rows left, then 0.
+pub fn cursor_has_next(interpreter: &mut Interpreter, _args: Vec<Value>) -> Result<Value, String> {
+    let cursor = cursor_receiver(interpreter, "has_next")?;
+    let (start, found) = next_match(interpreter, &cursor, "has_next")?;
+    // Rows that didn't match are not read again
+    set_position(&mut cursor.borrow_mut(), start);
+    Ok(Value::Number(found.is_some() as i64))
+}
+
+/// The next matching row as an object; an error once the cursor is used up.
+pub fn cursor_next(interpreter: &mut Interpreter, _args: Vec<Value>) -> Result<Value, String> {
+    let cursor = cursor_receiver(interpreter, "next")?;
+    let (start, found) = next_match(interpreter, &cursor, "next")?;
+    let Some((row, after)) = found else {
+        set_position(&mut cursor.borrow_mut(), start);
+        return Err("cursor.next: the cursor is used up".to_string());
+    };
+    set_position(&mut cursor.borrow_mut(), after);
+
+    let mut object = Object::new("object");
+    for (column, value) in row {
+        if column != VERSION_COLUMN {
+            object.set_property(column, from_db_value(&value));
+        }
+    }
+    Ok(Value::object(object))
+}
+
+/// Flushes and releases a table handle; using it afterwards is an error.
+pub fn close(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let handle = expect_handle(&args, "db.close")?;
+    if !interpreter.close_resource(handle) {
+        return Err(DbError::Closed {
+            operation: "close",
+            handle,
+        }
+        .into());
+    }
+    Ok(Value::Void)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Object;
+
+    fn open_table(interpreter: &mut Interpreter, dir: &tempfile::TempDir) -> Value {
+        let args = vec![
+            Value::String(dir.path().to_string_lossy().into_owned()),
+            Value::String("users".to_string()),
+        ];
+        open(interpreter, args).unwrap()
+    }
+
+    fn user(name: Value) -> Value {
+        let mut row = Object::new("object");
+        row.set_property("name".to_string(), name);
+        Value::object(row)
+    }
+
+    #[test]
+    fn test_insert_and_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut interpreter = Interpreter::new();
+        let handle = open_table(&mut interpreter, &dir);
+
+        for name in ["ada", "grace"] {
+            let row = user(Value::String(name.to_string()));
+            insert(&mut interpreter, vec![handle.clone(), row]).unwrap();
+        }
+        assert_eq!(size(&mut interpreter, vec![handle]), Ok(Value::Number(2)));
+    }
+
+    #[test]
+    fn test_closed_handle_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut interpreter = Interpreter::new();
+        let handle = open_table(&mut interpreter, &dir);
+
+        assert_eq!(
+            close(&mut interpreter, vec![handle.clone()]),
+            Ok(Value::Void)
+        );
+        let row = user(Value::String("ada".to_string()));
+        let err = insert(&mut interpreter, vec![handle.clone(), row]).unwrap_err();
+        assert_eq!(err, "db.insert: table handle 1 is closed");
+        assert!(close(&mut interpreter, vec![handle]).is_err());
+    }
+
+    #[test]
+    fn test_unsupported_value_names_table_and_operation() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut interpreter = Interpreter::new();
+        let handle = open_table(&mut interpreter, &dir);
+
+        let row = user(Value::array(vec![]));
+        let err = insert(&mut interpreter, vec![handle, row]).unwrap_err();
+        assert!(err.starts_with("db.insert on table 'users': cannot store"));
+    }
+
+    /// Whether every file in `dir` can be opened and locked exclusively,
+    /// as platforms that refuse to delete open files need.
+    fn files_lockable(dir: &std::path::Path) -> bool {
+        std::fs::read_dir(dir).unwrap().all(|entry| {
+            let path = entry.unwrap().path();
+            std::fs::File::options()
+                .read(true)
+                .write(true)
+                .open(path)
+                .is_ok_and(|file| file.try_lock().is_ok())
+        })
+    }
+
+    #[test]
+    fn test_dropping_interpreter_releases_tables() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut interpreter = Interpreter::new();
+        let handle = open_table(&mut interpreter, &dir);
+        let row = user(Value::String("ada".to_string()));
+        insert(&mut interpreter, vec![handle, row]).unwrap();
+        assert!(!files_lockable(dir.path()));
+
+        drop(interpreter);
+        assert!(files_lockable(dir.path()));
+
+        // Nothing holds the table any more: reopen it, then delete it
+        let mut interpreter = Interpreter::new();
+        let handle = open_table(&mut interpreter, &dir);
+        assert_eq!(size(&mut interpreter, vec![handle]), Ok(Value::Number(1)));
+        drop(interpreter);
+        let path = dir.path().to_path_buf();
+        dir.close().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_query_cursor_in_for_loop() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut interpreter = Interpreter::new();
+        let handle = open_table(&mut interpreter, &dir);
+        for name in ["ada", "grace", "ada", "linus"] {
+            let row = user(Value::String(name.to_string()));
+            insert(&mut interpreter, vec![handle.clone(), row]).unwrap();
+        }
+        interpreter.env.set_variable("t".to_string(), handle);
+
+        let code = "let names = \"\";\n\
+                    for row in std.db.query(t) { names = names + row.name + \",\"; }\n\
+                    let only = std.obj.from_entries(std.arr.new(0));\n\
+                    std.obj.deep_set(only, \"name\", \"ada\");\n\
+                    let adas = 0;\n\
+                    for row in std.db.query(t, only) { adas = adas + 1; }\n\
+                    let first = \"\";\n\
+                    for row in std.db.query(t) { first = row.name; break; }";
+        crate::run_source_with(code, &mut interpreter).unwrap();
+        let variable = |name: &str| interpreter.env.get_variable(name).cloned();
+        assert_eq!(
+            variable("names"),
+            Some(Value::String("ada,grace,ada,linus,".to_string()))
+        );
+        assert_eq!(variable("adas"), Some(Value::Number(2)));
+        assert_eq!(variable("first"), Some(Value::String("ada".to_string())));
+    }
+
+    #[test]
+    fn test_used_up_cursor_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut interpreter = Interpreter::new();
+        let handle = open_table(&mut interpreter, &dir);
+        interpreter.env.set_variable("t".to_string(), handle);
+
+        let code = "let c = std.db.query(t);\nlet more = c.has_next();\nc.next();";
+        let err = crate::run_source_with(code, &mut interpreter).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("cursor.next: the cursor is used up"));
+        assert_eq!(
+            interpreter.env.get_variable("more"),
+            Some(&Value::Number(0))
+        );
+    }
+
+    #[test]
+    fn test_open_modes() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut interpreter = Interpreter::new();
+        let writer = open_table(&mut interpreter, &dir);
+
+        let path = Value::String(dir.path().to_string_lossy().into_owned());
+        let users = Value::String("users".to_string());
+        let err = open(&mut interpreter, vec![path.clone(), users.clone()]).unwrap_err();
+        assert_eq!(
+            err,
+            format!(
+                "the table is open for writing in process {}",
+                std::process::id()
+            )
+        );
+
+        let mode = |mode: &str| Value::String(mode.to_string());
+        let reader = open(
+            &mut interpreter,
+            vec![path.clone(), users.clone(), mode("read")],
+        )
+        .unwrap();
+        let row = user(Value::String("ada".to_string()));
+        let err = insert(&mut interpreter, vec![reader, row]).unwrap_err();
+        assert_eq!(
+            err,
+            "db.insert on table 'users': the table is open read-only"
+        );
+
+        assert!(open(&mut interpreter, vec![path, users, mode("append")]).is_err());
+        close(&mut interpreter, vec![writer]).unwrap();
+    }
+}
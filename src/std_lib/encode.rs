@@ -0,0 +1,109 @@
+//! Hashes, hex and ids for cache keys, signatures and the like. Hashing and
+//! `uuid` are only built with the `crypto` feature.
+
+use crate::interpreter::{Interpreter, Value};
+use crate::std_lib::expect_string;
+#[cfg(feature = "crypto")]
+use md5::Md5;
+#[cfg(feature = "crypto")]
+use sha2::{Digest, Sha256};
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// The UTF-8 bytes of the string as lowercase hex.
+pub fn hex(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let string = expect_string(&args, 0, "encode.hex")?;
+    Ok(Value::String(to_hex(string.as_bytes())))
+}
+
+/// SHA-256 of the string's UTF-8 bytes, as lowercase hex.
+#[cfg(feature = "crypto")]
+pub fn sha256(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let string = expect_string(&args, 0, "encode.sha256")?;
+    Ok(Value::String(to_hex(&Sha256::digest(string.as_bytes()))))
+}
+
+/// MD5 of the string's UTF-8 bytes, as lowercase hex. Fine for checksums
+/// and cache keys, not for anything security related.
+#[cfg(feature = "crypto")]
+pub fn md5(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let string = expect_string(&args, 0, "encode.md5")?;
+    Ok(Value::String(to_hex(&Md5::digest(string.as_bytes()))))
+}
+
+/// A random (version 4) UUID in the usual hyphenated form.
+#[cfg(feature = "crypto")]
+pub fn uuid(_interpreter: &mut Interpreter, _args: Vec<Value>) -> Result<Value, String> {
+    Ok(Value::String(uuid::Uuid::new_v4().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::std_lib::str_utils::{base64_decode, base64_encode};
+
+    fn s(value: &str) -> Vec<Value> {
+        vec![Value::String(value.to_string())]
+    }
+
+    fn string(result: Result<Value, String>) -> String {
+        match result {
+            Ok(Value::String(s)) => s,
+            other => panic!("expected a string, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_hex() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(string(hex(&mut interpreter, s("Maus!"))), "4d61757321");
+        assert_eq!(string(hex(&mut interpreter, s("ü"))), "c3bc");
+        assert!(hex(&mut interpreter, vec![Value::Number(1)]).is_err());
+    }
+
+    #[test]
+    fn test_base64_roundtrip_unicode() {
+        let mut interpreter = Interpreter::new();
+        let encoded = base64_encode(&mut interpreter, s("grüße, 世界")).unwrap();
+        let decoded = base64_decode(&mut interpreter, vec![encoded]);
+        assert_eq!(string(decoded), "grüße, 世界");
+        assert_eq!(string(base64_encode(&mut interpreter, s("abc"))), "YWJj");
+        assert!(base64_decode(&mut interpreter, s("not base64!")).is_err());
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_hash_known_answers() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(
+            string(sha256(&mut interpreter, s("abc"))),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            string(sha256(&mut interpreter, s(""))),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            string(md5(&mut interpreter, s("abc"))),
+            "900150983cd24fb0d6963f7d28e17f72"
+        );
+        assert!(md5(&mut interpreter, vec![Value::Void]).is_err());
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_uuid_format_and_uniqueness() {
+        let mut interpreter = Interpreter::new();
+        let first = string(uuid(&mut interpreter, vec![]));
+        let second = string(uuid(&mut interpreter, vec![]));
+        assert_ne!(first, second);
+
+        let groups: Vec<&str> = first.split('-').collect();
+        let lengths: Vec<usize> = groups.iter().map(|group| group.len()).collect();
+        assert_eq!(lengths, vec![8, 4, 4, 4, 12]);
+        assert!(groups[2].starts_with('4'));
+        assert!(first.chars().all(|c| c == '-' || c.is_ascii_hexdigit()));
+    }
+}
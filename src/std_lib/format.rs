@@ -0,0 +1,430 @@
+//! Padding, number formatting and `printf`, whose placeholders take a
+//! format spec: `{}` or `{:[[fill]align][0][width][.precision]}`, with
+//! `align` one of `<`, `>` and `^`. Numbers are right aligned by default,
+//! everything else left aligned; content wider than the field is never cut.
+//! Widths and precisions above `MAX_BUILD_SIZE` are an error.
+
+use super::str_utils::{pad, pad_char_arg, Align};
+use super::{expect_number, expect_string, MAX_BUILD_SIZE};
+use crate::interpreter::{Interpreter, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+struct Spec {
+    fill: char,
+    align: Option<Align>,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+fn align_of(c: char) -> Option<Align> {
+    match c {
+        '<' => Some(Align::Left),
+        '>' => Some(Align::Right),
+        '^' => Some(Align::Center),
+        _ => None,
+    }
+}
+
+/// Parses what follows the `:` of a placeholder, `None` if it isn't a spec.
+fn parse_spec(spec: &str) -> Option<Spec> {
+    let chars: Vec<char> = spec.chars().collect();
+    let mut parsed = Spec {
+        fill: ' ',
+        align: None,
+        zero: false,
+        width: 0,
+        precision: None,
+    };
+    let mut i = 0;
+    if let Some(align) = chars.get(1).copied().and_then(align_of) {
+        parsed.fill = chars[0];
+        parsed.align = Some(align);
+        i = 2;
+    } else if let Some(align) = chars.first().copied().and_then(align_of) {
+        parsed.align = Some(align);
+        i = 1;
+    }
+    if chars.get(i) == Some(&'0') {
+        parsed.zero = true;
+        i += 1;
+    }
+    let digits = |i: &mut usize| {
+        let start = *i;
+        while chars.get(*i).is_some_and(char::is_ascii_digit) {
+            *i += 1;
+        }
+        chars[start..*i].iter().collect::<String>()
+    };
+    let width = digits(&mut i);
+    // Too many digits for a usize is as much over the limit as any other
+    // huge width
+    if !width.is_empty() {
+        parsed.width = width.parse().unwrap_or(usize::MAX);
+    }
+    if chars.get(i) == Some(&'.') {
+        i += 1;
+        let precision = digits(&mut i);
+        if precision.is_empty() {
+            return None;
+        }
+        parsed.precision = Some(precision.parse().unwrap_or(usize::MAX));
+    }
+    (i == chars.len()).then_some(parsed)
+}
+
+/// Right aligns a formatted number, keeping a minus sign in front of zeros.
+fn pad_number(
+    interpreter: &mut Interpreter,
+    text: &str,
+    width: usize,
+    fill: char,
+    fn_name: &str,
+) -> Result<String, String> {
+    match text.strip_prefix('-') {
+        // Too wide fields fail in `pad` below, naming the width given
+        Some(digits) if fill == '0' && width <= MAX_BUILD_SIZE => {
+            let width = width.saturating_sub(1);
+            let digits = pad(interpreter, digits, width, '0', Align::Right, fn_name)?;
+            Ok(format!("-{}", digits))
+        }
+        _ => pad(interpreter, text, width, fill, Align::Right, fn_name),
+    }
+}
+
+fn render(
+    interpreter: &mut Interpreter,
+    value: &Value,
+    spec: &Spec,
+    fn_name: &str,
+) -> Result<String, String> {
+    let (text, numeric) = match (value, spec.precision) {
+        (Value::Float(f), Some(precision)) => (format!("{:.*}", precision, f), true),
+        (Value::Number(n), Some(precision)) => (format!("{:.*}", precision, *n as f64), true),
+        (Value::Number(_) | Value::Float(_), None) => (value.to_string(), true),
+        (Value::String(s), Some(precision)) => (s.chars().take(precision).collect(), false),
+        (Value::String(s), None) => (s.clone(), false),
+        _ => (value.to_string(), false),
+    };
+    let (fill, align) = match (spec.zero, spec.align) {
+        (true, _) if numeric => return pad_number(interpreter, &text, spec.width, '0', fn_name),
+        (true, None) => ('0', Align::Right),
+        (_, Some(align)) => (spec.fill, align),
+        (_, None) if numeric => (spec.fill, Align::Right),
+        (_, None) => (spec.fill, Align::Left),
+    };
+    pad(interpreter, &text, spec.width, fill, align, fn_name)
+}
+
+/// Fills the placeholders of `template` with `values` in order. `{{` and
+/// `}}` stand for literal braces.
+pub fn format_template(
+    interpreter: &mut Interpreter,
+    template: &str,
+    values: &[Value],
+    fn_name: &str,
+) -> Result<String, String> {
+    let mut out = String::with_capacity(template.len());
+    let mut values = values.iter();
+    let mut placeholders = 0;
+    let mut rest = template;
+    while let Some(at) = rest.find(['{', '}']) {
+        out.push_str(&rest[..at]);
+        let tail = &rest[at..];
+        if tail.starts_with("{{") || tail.starts_with("}}") {
+            out.push_str(&tail[..1]);
+            rest = &tail[2..];
+            continue;
+        }
+        if tail.starts_with('}') {
+            return Err(format!("{}: unmatched '}}' in \"{}\"", fn_name, template));
+        }
+        let Some(end) = tail.find('}') else {
+            return Err(format!(
+                "{}: unclosed placeholder in \"{}\"",
+                fn_name, template
+            ));
+        };
+        let placeholder = &tail[..=end];
+        let spec = match &placeholder[1..end] {
+            "" => parse_spec(""),
+            inner => inner.strip_prefix(':').and_then(parse_spec),
+        }
+        .ok_or_else(|| format!("{}: invalid format spec \"{}\"", fn_name, placeholder))?;
+        if spec.width > MAX_BUILD_SIZE || spec.precision.is_some_and(|p| p > MAX_BUILD_SIZE) {
+            return Err(format!(
+                "{}: format spec \"{}\" is wider than the limit of {}",
+                fn_name, placeholder, MAX_BUILD_SIZE
+            ));
+        }
+        placeholders += 1;
+        let value = values.next().ok_or_else(|| {
+            format!(
+                "{}: not enough values for the placeholders in \"{}\"",
+                fn_name, template
+            )
+        })?;
+        out.push_str(&render(interpreter, value, &spec, fn_name)?);
+        rest = &tail[end + 1..];
+    }
+    out.push_str(rest);
+    let extra = values.count();
+    if extra > 0 {
+        return Err(format!(
+            "{}: {} values given but \"{}\" has {} placeholders",
+            fn_name,
+            placeholders + extra,
+            template,
+            placeholders
+        ));
+    }
+    Ok(out)
+}
+
+/// Prints `template` with its placeholders filled by the remaining arguments.
+pub fn printf(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let template = expect_string(&args, 0, "printf")?;
+    let values = args.get(1..).unwrap_or_default();
+    let line = format_template(interpreter, &template, values, "printf")?;
+    interpreter.write_line(&line);
+    Ok(Value::Void)
+}
+
+fn width_arg(args: &[Value], index: usize, fn_name: &str) -> Result<usize, String> {
+    let width = expect_number(args, index, fn_name)?;
+    usize::try_from(width)
+        .map_err(|_| format!("{}: width must not be negative, got {}", fn_name, width))
+}
+
+/// The optional one-character fill argument, a space when left out.
+fn fill_arg(args: &[Value], index: usize, fn_name: &str) -> Result<char, String> {
+    if args.len() <= index {
+        return Ok(' ');
+    }
+    pad_char_arg(args, index, fn_name)
+}
+
+fn text_arg(args: &[Value], index: usize, fn_name: &str) -> Result<String, String> {
+    match args.get(index) {
+        Some(Value::String(s)) => Ok(s.clone()),
+        Some(number @ (Value::Number(_) | Value::Float(_))) => Ok(number.to_string()),
+        _ => Err(format!(
+            "{} expects a string or number as argument {}",
+            fn_name,
+            index + 1
+        )),
+    }
+}
+
+fn pad_with(
+    interpreter: &mut Interpreter,
+    args: &[Value],
+    align: Align,
+    fn_name: &str,
+) -> Result<Value, String> {
+    let text = text_arg(args, 0, fn_name)?;
+    let width = width_arg(args, 1, fn_name)?;
+    let fill = fill_arg(args, 2, fn_name)?;
+    pad(interpreter, &text, width, fill, align, fn_name).map(Value::String)
+}
+
+/// Right aligns a string or number in `width` characters of `pad_char`
+/// (a space by default).
+pub fn pad_left(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    pad_with(interpreter, &args, Align::Right, "pad_left")
+}
+
+/// Left aligns a string or number in `width` characters of `pad_char`
+/// (a space by default).
+pub fn pad_right(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    pad_with(interpreter, &args, Align::Left, "pad_right")
+}
+
+/// Formats a number right aligned in `width` characters of `pad_char`; with
+/// `"0"` a minus sign goes before the zeros: `format_number(0 - 7, 4, "0")`
+/// is `"-007"`.
+pub fn format_number(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let text = match args.first() {
+        Some(number @ (Value::Number(_) | Value::Float(_))) => number.to_string(),
+        _ => return Err("format_number expects a number as argument 1".to_string()),
+    };
+    let width = width_arg(&args, 1, "format_number")?;
+    let fill = fill_arg(&args, 2, "format_number")?;
+    pad_number(interpreter, &text, width, fill, "format_number").map(Value::String)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(template: &str, values: &[Value]) -> Result<String, String> {
+        format_template(&mut Interpreter::new(), template, values, "printf")
+    }
+
+    fn call(
+        f: fn(&mut Interpreter, Vec<Value>) -> Result<Value, String>,
+        args: Vec<Value>,
+    ) -> Result<Value, String> {
+        f(&mut Interpreter::new(), args)
+    }
+
+    fn s(text: &str) -> Value {
+        Value::String(text.to_string())
+    }
+
+    #[test]
+    fn test_zero_padding() {
+        assert_eq!(format("{:03}", &[Value::Number(42)]), Ok("042".to_string()));
+        assert_eq!(
+            format("{:05}", &[Value::Number(-42)]),
+            Ok("-0042".to_string())
+        );
+        assert_eq!(
+            call(
+                format_number,
+                vec![Value::Number(42), Value::Number(3), s("0")]
+            ),
+            Ok(s("042"))
+        );
+        assert_eq!(
+            call(
+                format_number,
+                vec![Value::Number(-7), Value::Number(4), s("0")]
+            ),
+            Ok(s("-007"))
+        );
+        assert_eq!(
+            call(
+                format_number,
+                vec![Value::Number(-7), Value::Number(4), s("*")]
+            ),
+            Ok(s("**-7"))
+        );
+    }
+
+    #[test]
+    fn test_alignment_of_strings_and_numbers() {
+        assert_eq!(format("[{:>8}]", &[s("abc")]), Ok("[     abc]".to_string()));
+        assert_eq!(
+            format("[{:<6}]", &[Value::Number(42)]),
+            Ok("[42    ]".to_string())
+        );
+        assert_eq!(format("[{:*^7}]", &[s("ab")]), Ok("[**ab***]".to_string()));
+        // Without an alignment numbers go right and strings left
+        assert_eq!(
+            format("[{:5}|{:5}]", &[Value::Number(7), s("x")]),
+            Ok("[    7|x    ]".to_string())
+        );
+        assert_eq!(
+            format("{:.2}", &[Value::Float(19.987)]),
+            Ok("19.99".to_string())
+        );
+        assert_eq!(
+            format("{:>7.1}", &[Value::Number(5)]),
+            Ok("    5.0".to_string())
+        );
+        assert_eq!(
+            format("{{{}}} {}", &[Value::Number(1), s("two")]),
+            Ok("{1} two".to_string())
+        );
+
+        assert_eq!(
+            call(pad_left, vec![s("ab"), Value::Number(5), s(".")]),
+            Ok(s("...ab"))
+        );
+        assert_eq!(
+            call(pad_right, vec![Value::Number(42), Value::Number(4)]),
+            Ok(s("42  "))
+        );
+    }
+
+    #[test]
+    fn test_width_smaller_than_content_does_not_truncate() {
+        assert_eq!(format("{:>2}", &[s("abcdef")]), Ok("abcdef".to_string()));
+        assert_eq!(
+            format("{:02}", &[Value::Number(12345)]),
+            Ok("12345".to_string())
+        );
+        assert_eq!(
+            call(pad_left, vec![s("abcdef"), Value::Number(3)]),
+            Ok(s("abcdef"))
+        );
+        assert_eq!(
+            call(
+                format_number,
+                vec![Value::Number(-1234), Value::Number(2), s("0")]
+            ),
+            Ok(s("-1234"))
+        );
+    }
+
+    #[test]
+    fn test_invalid_spec_is_quoted_in_the_error() {
+        assert_eq!(
+            format("total: {:x8}", &[Value::Number(1)]),
+            Err("printf: invalid format spec \"{:x8}\"".to_string())
+        );
+        assert_eq!(
+            format("{:>8.}", &[Value::Number(1)]),
+            Err("printf: invalid format spec \"{:>8.}\"".to_string())
+        );
+        assert_eq!(
+            format("{name}", &[Value::Number(1)]),
+            Err("printf: invalid format spec \"{name}\"".to_string())
+        );
+        assert_eq!(
+            format("{} {}", &[Value::Number(1)]),
+            Err("printf: not enough values for the placeholders in \"{} {}\"".to_string())
+        );
+        assert_eq!(
+            format("{}", &[Value::Number(1), Value::Number(2)]),
+            Err("printf: 2 values given but \"{}\" has 1 placeholders".to_string())
+        );
+        assert_eq!(
+            format("{:3", &[Value::Number(1)]),
+            Err("printf: unclosed placeholder in \"{:3\"".to_string())
+        );
+        assert_eq!(
+            call(pad_left, vec![s("a"), Value::Number(3), s("--")]),
+            Err("pad_left: pad_char must be exactly one character, got \"--\"".to_string())
+        );
+    }
+
+    #[test]
+    fn test_huge_widths_are_errors() {
+        assert_eq!(
+            format("{:99999999999}", &[Value::Number(1)]),
+            Err(
+                "printf: format spec \"{:99999999999}\" is wider than the limit of 16777216"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            format("{:.99999999999}", &[Value::Float(1.5)]),
+            Err(
+                "printf: format spec \"{:.99999999999}\" is wider than the limit of 16777216"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            format("{:999999999999999999999999}", &[s("x")]),
+            Err(
+                "printf: format spec \"{:999999999999999999999999}\" is wider than the limit of 16777216"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            call(pad_left, vec![s("a"), Value::Number(1_000_000_000_000)]),
+            Err("pad_left: width 1000000000000 is more than the limit of 16777216".to_string())
+        );
+        assert_eq!(
+            call(
+                format_number,
+                vec![Value::Number(-1), Value::Number(1_000_000_000_000), s("0")]
+            ),
+            Err(
+                "format_number: width 1000000000000 is more than the limit of 16777216".to_string()
+            )
+        );
+    }
+}
@@ -0,0 +1,434 @@
+//! `std.http`: an HTTP server whose routes are handled by a mouse function.
+//!
+//! The server itself runs on axum in its own thread. Interpreter values are
+//! not `Send`, so each request is handed to the interpreter's thread over a
+//! channel, turned into a request object there and answered through a
+//! oneshot once the handler returns. A server that stops on its own is
+//! reported through the interpreter: as an error from `serve`, and in the
+//! log for `serve_background`.
+
+use crate::interpreter::logging::LogLevel;
+use crate::interpreter::{Interpreter, Object, Value};
+use crate::parser::Stmt;
+use crate::std_lib::{expect_function, expect_number};
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use std::collections::HashMap;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// How often a serving interpreter checks whether it was cancelled.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A request as it crosses from the server thread to the interpreter.
+struct HttpRequest {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+/// A handler's answer on its way back to the server thread.
+struct HttpResponse {
+    status: StatusCode,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    body: String,
+}
+
+/// What the server thread hands the interpreter's thread.
+enum Event {
+    Request(HttpRequest, oneshot::Sender<HttpResponse>),
+    /// The server could not keep running
+    Failed(String),
+}
+
+impl HttpRequest {
+    fn into_value(self) -> Value {
+        let pairs = |name: &str, pairs: Vec<(String, String)>| {
+            let mut object = Object::new(name);
+            for (key, value) in pairs {
+                object.set_property(key, Value::String(value));
+            }
+            Value::object(object)
+        };
+
+        let mut request = Object::new("request");
+        request.set_property("method".to_string(), Value::String(self.method));
+        request.set_property("path".to_string(), Value::String(self.path));
+        request.set_property("query".to_string(), pairs("query", self.query));
+        request.set_property("headers".to_string(), pairs("headers", self.headers));
+        request.set_property("body".to_string(), Value::String(self.body));
+        Value::object(request)
+    }
+}
+
+impl HttpResponse {
+    fn error(message: String) -> Self {
+        HttpResponse {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            headers: Vec::new(),
+            body: message,
+        }
+    }
+
+    /// A string is a 200 text response; an object may set `status`, `body`
+    /// and `headers`, each optional.
+    fn from_value(value: &Value) -> Result<Self, String> {
+        let object = match value {
+            Value::String(body) => {
+                return Ok(HttpResponse {
+                    status: StatusCode::OK,
+                    headers: Vec::new(),
+                    body: body.clone(),
+                })
+            }
+            Value::Object(object) => object.borrow(),
+            other => {
+                return Err(format!(
+                    "http handler must return a string or an object, got {}",
+                    other.type_name()
+                ))
+            }
+        };
+
+        let status = match object.get_property("status") {
+            None => StatusCode::OK,
+            Some(Value::Number(code)) => u16::try_from(*code)
+                .ok()
+                .and_then(|code| StatusCode::from_u16(code).ok())
+                .ok_or_else(|| format!("http handler returned invalid status {}", code))?,
+            Some(other) => {
+                return Err(format!(
+                    "http response status must be a number, got {}",
+                    other.type_name()
+                ))
+            }
+        };
+        let body = match object.get_property("body") {
+            None | Some(Value::Void) => String::new(),
+            Some(Value::String(body)) => body.clone(),
+            Some(other) => other.to_string(),
+        };
+
+        let mut headers = Vec::new();
+        match object.get_property("headers") {
+            None => {}
+            Some(Value::Object(header_object)) => {
+                for (name, value) in header_object.borrow().properties() {
+                    let header = HeaderName::try_from(name.as_str())
+                        .map_err(|_| format!("invalid http header name '{}'", name))?;
+                    let value = match value {
+                        Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    };
+                    let value = HeaderValue::try_from(value)
+                        .map_err(|_| format!("invalid value for http header '{}'", name))?;
+                    headers.push((header, value));
+                }
+            }
+            Some(other) => {
+                return Err(format!(
+                    "http response headers must be an object, got {}",
+                    other.type_name()
+                ))
+            }
+        }
+
+        Ok(HttpResponse {
+            status,
+            headers,
+            body,
+        })
+    }
+}
+
+impl IntoResponse for HttpResponse {
+    fn into_response(self) -> Response {
+        let mut response = (self.status, self.body).into_response();
+        for (name, value) in self.headers {
+            response.headers_mut().insert(name, value);
+        }
+        response
+    }
+}
+
+async fn forward(
+    State(events): State<Sender<Event>>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    Query(query): Query<Vec<(String, String)>>,
+    body: String,
+) -> Response {
+    let mut header_pairs: Vec<(String, String)> = Vec::new();
+    for (name, value) in &headers {
+        let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+        // Repeated headers are folded into one comma separated value
+        match header_pairs
+            .iter_mut()
+            .find(|(seen, _)| seen == name.as_str())
+        {
+            Some((_, existing)) => {
+                existing.push_str(", ");
+                existing.push_str(&value);
+            }
+            None => header_pairs.push((name.to_string(), value)),
+        }
+    }
+    let request = HttpRequest {
+        method: method.to_string(),
+        path: uri.path().to_string(),
+        query,
+        headers: header_pairs,
+        body,
+    };
+
+    let (reply, answer) = oneshot::channel();
+    if events.send(Event::Request(request, reply)).is_err() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "server is shutting down").into_response();
+    }
+    match answer.await {
+        Ok(response) => response.into_response(),
+        Err(_) => (StatusCode::SERVICE_UNAVAILABLE, "server is shutting down").into_response(),
+    }
+}
+
+/// Server thread of a running `serve`. Dropping it shuts the server down.
+struct Listener {
+    addr: SocketAddr,
+    events: Receiver<Event>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+/// Binds `127.0.0.1:port` (0 picks a free port) and starts accepting
+/// requests on a new thread.
+fn listen(port: i64, fn_name: &str) -> Result<Listener, String> {
+    let port = u16::try_from(port).map_err(|_| format!("{}: invalid port {}", fn_name, port))?;
+    let socket = TcpListener::bind(("127.0.0.1", port))
+        .and_then(|socket| socket.set_nonblocking(true).map(|_| socket))
+        .map_err(|e| format!("{}: could not listen on port {}: {}", fn_name, port, e))?;
+    let addr = socket
+        .local_addr()
+        .map_err(|e| format!("{}: {}", fn_name, e))?;
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| format!("{}: could not start runtime: {}", fn_name, e))?;
+
+    let (event_sender, events) = mpsc::channel();
+    let (shutdown, stopped) = oneshot::channel::<()>();
+    thread::spawn(move || {
+        runtime.block_on(async move {
+            let failed = event_sender.clone();
+            let app = Router::<Sender<Event>>::new()
+                .fallback(forward)
+                .with_state(event_sender);
+            let listener = match tokio::net::TcpListener::from_std(socket) {
+                Ok(listener) => listener,
+                Err(e) => {
+                    let message = format!("http server could not listen on {}: {}", addr, e);
+                    let _ = failed.send(Event::Failed(message));
+                    return;
+                }
+            };
+            let server = axum::serve(listener, app).with_graceful_shutdown(async {
+                let _ = stopped.await;
+            });
+            if let Err(e) = server.await {
+                let message = format!("http server on {} stopped: {}", addr, e);
+                let _ = failed.send(Event::Failed(message));
+            }
+        });
+    });
+
+    Ok(Listener {
+        addr,
+        events,
+        shutdown: Some(shutdown),
+    })
+}
+
+/// Answers requests with `handler` until `stop` is set or the server
+/// fails.
+fn handle_requests(
+    interpreter: &mut Interpreter,
+    handler: &Value,
+    listener: &Listener,
+    stop: &AtomicBool,
+) -> Result<(), String> {
+    loop {
+        if stop.load(Ordering::Relaxed) {
+            return Err("Execution cancelled".to_string());
+        }
+        let (request, reply) = match listener.events.recv_timeout(POLL_INTERVAL) {
+            Ok(Event::Request(request, reply)) => (request, reply),
+            Ok(Event::Failed(message)) => return Err(message),
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        };
+        let response = interpreter
+            .call_function(handler, vec![request.into_value()])
+            .and_then(|value| HttpResponse::from_value(&value))
+            .unwrap_or_else(HttpResponse::error);
+        let _ = reply.send(response);
+    }
+}
+
+/// A `serve_background` server, registered as an interpreter resource so
+/// it stops on `stop()` or when the script's interpreter is dropped.
+struct BackgroundServer {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl BackgroundServer {
+    /// Stops answering requests and waits for the handler thread, whose
+    /// listener shuts the server down as it goes.
+    fn shut_down(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.thread.join();
+    }
+}
+
+fn server_object(addr: SocketAddr, handle: u64) -> Value {
+    let mut server = Object::new("server");
+    server.set_property("port".to_string(), Value::Number(addr.port() as i64));
+    server.set_property("handle".to_string(), Value::Number(handle as i64));
+    server.register_native_fn("stop", stop);
+    Value::object(server)
+}
+
+/// `server.stop()` shuts down a server started by `serve_background`; the
+/// port is free again once it returns.
+pub fn stop(interpreter: &mut Interpreter, _args: Vec<Value>) -> Result<Value, String> {
+    let server = interpreter
+        .current_receiver()
+        .ok_or("server.stop must be called on a server, e.g. server.stop()")?;
+    let (handle, port) = {
+        let server = server.borrow();
+        (
+            server.get_property("handle").cloned(),
+            server.get_property("port").cloned().unwrap_or(Value::Void),
+        )
+    };
+    let Some(Value::Number(handle)) = handle else {
+        return Err("server.stop: the server has no handle".to_string());
+    };
+    if !u64::try_from(handle).is_ok_and(|handle| interpreter.close_resource(handle)) {
+        return Err(format!(
+            "server.stop: the server on port {} is already stopped",
+            port
+        ));
+    }
+    Ok(Value::Void)
+}
+
+/// `serve(port, handler)` serves HTTP on `127.0.0.1:port` and calls
+/// `handler(request)` for every request. Blocks until the script is
+/// cancelled.
+pub fn serve(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let port = expect_number(&args, 0, "http.serve")?;
+    let handler = expect_function(&args, 1, "http.serve")?;
+    let listener = listen(port, "http.serve")?;
+    let cancelled = interpreter.cancel_handle();
+    handle_requests(interpreter, &handler, &listener, &cancelled)?;
+    Ok(Value::Void)
+}
+
+/// A global the background interpreter can take over. Arrays and objects
+/// are shared references tied to the script's thread and stay behind.
+enum Portable {
+    Number(i64),
+    Float(f64),
+    String(String),
+    Function(String, Vec<String>, Vec<Stmt>),
+}
+
+impl Portable {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Number(n) => Some(Portable::Number(*n)),
+            Value::Float(f) => Some(Portable::Float(*f)),
+            Value::String(s) => Some(Portable::String(s.clone())),
+            Value::Function(name, params, body) => Some(Portable::Function(
+                name.clone(),
+                params.clone(),
+                body.clone(),
+            )),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        match self {
+            Portable::Number(n) => Value::Number(n),
+            Portable::Float(f) => Value::Float(f),
+            Portable::String(s) => Value::String(s),
+            Portable::Function(name, params, body) => Value::Function(name, params, body),
+        }
+    }
+}
+
+/// `serve_background(port, handler)` starts the same server as `serve` and
+/// returns `{port, stop}` right away; port 0 picks a free port. The handler
+/// runs on its own thread with a copy of the script's functions and its
+/// number and string globals as they were at the call. The server runs
+/// until `stop()` is called or the script's interpreter is dropped; if it
+/// fails, the failure is logged as an error.
+pub fn serve_background(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let port = expect_number(&args, 0, "http.serve_background")?;
+    let handler = expect_function(&args, 1, "http.serve_background")?;
+    let handler = Portable::from_value(&handler)
+        .ok_or("http.serve_background needs a user defined handler function")?;
+
+    let globals: HashMap<String, Portable> = interpreter
+        .env
+        .get_object("global")
+        .map(|global| {
+            global
+                .properties()
+                .iter()
+                .filter_map(|(name, value)| Some((name.clone(), Portable::from_value(value)?)))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let listener = listen(port, "http.serve_background")?;
+    let addr = listener.addr;
+    let log_level = interpreter.log_level();
+    let stop = Arc::new(AtomicBool::new(false));
+    let stopped = stop.clone();
+    let thread = thread::spawn(move || {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_log_level(log_level);
+        for (name, value) in globals {
+            interpreter.env.set_variable(name, value.into_value());
+        }
+        let result = handle_requests(&mut interpreter, &handler.into_value(), &listener, &stopped);
+        if let Err(message) = result {
+            if !stopped.load(Ordering::Relaxed) {
+                interpreter.log(LogLevel::Error, &message);
+            }
+        }
+    });
+    let handle = interpreter.register_resource(BackgroundServer { stop, thread }, |server| {
+        server.shut_down()
+    });
+    Ok(server_object(addr, handle))
+}
@@ -0,0 +1,218 @@
+use crate::interpreter::{Interpreter, Value};
+use crate::std_lib::{expect_array, expect_string};
+use std::path::{Path, PathBuf};
+
+/// Reads the file at `path` and returns its contents as an array of byte values.
+pub fn read_bytes(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let path = expect_string(&args, 0, "io.read_bytes")?;
+    let bytes = std::fs::read(&path)
+        .map_err(|e| format!("io.read_bytes could not read '{}': {}", path, e))?;
+    Ok(Value::array(
+        bytes.into_iter().map(|b| Value::Number(b as i64)).collect(),
+    ))
+}
+
+/// Writes an array of numbers in `[0, 255]` to `path` as raw bytes.
+pub fn write_bytes(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let path = expect_string(&args, 0, "io.write_bytes")?;
+    let values = expect_array(&args, 1, "io.write_bytes")?;
+
+    let mut bytes = Vec::with_capacity(values.len());
+    for (index, value) in values.iter().enumerate() {
+        match value {
+            Value::Number(n) => {
+                let byte = u8::try_from(*n).map_err(|_| {
+                    format!(
+                        "io.write_bytes: value {} at index {} is not a byte (0-255)",
+                        n, index
+                    )
+                })?;
+                bytes.push(byte);
+            }
+            other => {
+                return Err(format!(
+                    "io.write_bytes: value {} at index {} is not a number",
+                    other, index
+                ))
+            }
+        }
+    }
+
+    std::fs::write(&path, bytes)
+        .map_err(|e| format!("io.write_bytes could not write '{}': {}", path, e))?;
+    Ok(Value::Void)
+}
+
+/// Returns the size of the file at `path` in bytes.
+pub fn file_size(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let path = expect_string(&args, 0, "io.file_size")?;
+    let metadata = std::fs::metadata(&path)
+        .map_err(|e| format!("io.file_size could not read '{}': {}", path, e))?;
+    i64::try_from(metadata.len())
+        .map(Value::Number)
+        .map_err(|_| {
+            format!(
+                "io.file_size: size of '{}' does not fit into a number",
+                path
+            )
+        })
+}
+
+fn path_string(path: &Path) -> Value {
+    Value::String(path.to_string_lossy().to_string())
+}
+
+/// Joins all string arguments into one path with the platform separator.
+/// An absolute part replaces everything before it, as with `Path::join`.
+pub fn path_join(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let mut path = PathBuf::new();
+    for index in 0..args.len() {
+        path = path.join(expect_string(&args, index, "io.path.join")?);
+    }
+    Ok(path_string(&path))
+}
+
+/// Returns the parent directory of `path`, or an empty string if it has none.
+pub fn path_dirname(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let path = expect_string(&args, 0, "io.path.dirname")?;
+    Ok(path_string(
+        Path::new(&path).parent().unwrap_or(Path::new("")),
+    ))
+}
+
+/// Returns the final component of `path`, or an empty string if it has none.
+pub fn path_basename(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let path = expect_string(&args, 0, "io.path.basename")?;
+    let name = Path::new(&path).file_name().unwrap_or_default();
+    Ok(Value::String(name.to_string_lossy().to_string()))
+}
+
+/// Returns the extension of `path` without the dot, or an empty string.
+pub fn path_extension(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let path = expect_string(&args, 0, "io.path.extension")?;
+    let extension = Path::new(&path).extension().unwrap_or_default();
+    Ok(Value::String(extension.to_string_lossy().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("mouse_io_{}_{}", std::process::id(), name))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[test]
+    fn test_write_and_read_bytes() {
+        let mut interpreter = Interpreter::new();
+        let path = temp_path("roundtrip.bin");
+        let bytes = Value::array(vec![
+            Value::Number(0),
+            Value::Number(127),
+            Value::Number(255),
+        ]);
+
+        let written = write_bytes(
+            &mut interpreter,
+            vec![Value::String(path.clone()), bytes.clone()],
+        );
+        assert_eq!(written, Ok(Value::Void));
+        assert_eq!(
+            read_bytes(&mut interpreter, vec![Value::String(path.clone())]),
+            Ok(bytes)
+        );
+        assert_eq!(
+            file_size(&mut interpreter, vec![Value::String(path.clone())]),
+            Ok(Value::Number(3))
+        );
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_write_bytes_rejects_out_of_range() {
+        let mut interpreter = Interpreter::new();
+        let path = temp_path("out_of_range.bin");
+        let result = write_bytes(
+            &mut interpreter,
+            vec![
+                Value::String(path.clone()),
+                Value::array(vec![Value::Number(1), Value::Number(256)]),
+            ],
+        );
+        assert!(result.unwrap_err().contains("index 1"));
+        assert!(!std::path::Path::new(&path).exists());
+    }
+
+    #[test]
+    fn test_write_bytes_rejects_non_numbers() {
+        let mut interpreter = Interpreter::new();
+        let result = write_bytes(
+            &mut interpreter,
+            vec![
+                Value::String(temp_path("non_number.bin")),
+                Value::array(vec![Value::String("a".to_string())]),
+            ],
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let mut interpreter = Interpreter::new();
+        let path = Value::String(temp_path("does_not_exist.bin"));
+        assert!(read_bytes(&mut interpreter, vec![path.clone()]).is_err());
+        assert!(file_size(&mut interpreter, vec![path]).is_err());
+    }
+
+    fn strings(values: &[&str]) -> Vec<Value> {
+        values
+            .iter()
+            .map(|s| Value::String(s.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_path_join() {
+        let mut interpreter = Interpreter::new();
+        let expected: PathBuf = ["data", "2024", "report.csv"].iter().collect();
+        assert_eq!(
+            path_join(&mut interpreter, strings(&["data", "2024", "report.csv"])),
+            Ok(Value::String(expected.to_string_lossy().to_string()))
+        );
+        assert!(path_join(&mut interpreter, vec![Value::Number(1)]).is_err());
+    }
+
+    #[test]
+    fn test_path_parts() {
+        let mut interpreter = Interpreter::new();
+        let path = strings(&["data/2024/report.csv"]);
+        assert_eq!(
+            path_dirname(&mut interpreter, path.clone()),
+            Ok(Value::String("data/2024".to_string()))
+        );
+        assert_eq!(
+            path_basename(&mut interpreter, path.clone()),
+            Ok(Value::String("report.csv".to_string()))
+        );
+        assert_eq!(
+            path_extension(&mut interpreter, path),
+            Ok(Value::String("csv".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_path_parts_missing() {
+        let mut interpreter = Interpreter::new();
+        let empty = Ok(Value::String(String::new()));
+        assert_eq!(path_dirname(&mut interpreter, strings(&["file"])), empty);
+        assert_eq!(path_basename(&mut interpreter, strings(&["/"])), empty);
+        assert_eq!(
+            path_extension(&mut interpreter, strings(&["Makefile"])),
+            empty
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use crate::interpreter::logging::LogLevel;
+use crate::interpreter::{Interpreter, Value};
+use crate::std_lib::expect_string;
+use crate::std_lib::print::format_message;
+
+fn log_at(
+    interpreter: &mut Interpreter,
+    args: &[Value],
+    level: LogLevel,
+    fn_name: &str,
+) -> Result<Value, String> {
+    let message = format_message(args, fn_name)?;
+    interpreter.log(level, &message);
+    Ok(Value::Void)
+}
+
+pub fn debug(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    log_at(interpreter, &args, LogLevel::Debug, "log.debug")
+}
+
+pub fn info(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    log_at(interpreter, &args, LogLevel::Info, "log.info")
+}
+
+pub fn warn(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    log_at(interpreter, &args, LogLevel::Warn, "log.warn")
+}
+
+pub fn error(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    log_at(interpreter, &args, LogLevel::Error, "log.error")
+}
+
+/// Drops entries below the named level: "debug", "info", "warn" or "error".
+pub fn set_level(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let name = expect_string(&args, 0, "log.set_level")?;
+    let level = name.parse().map_err(|e| format!("log.set_level: {}", e))?;
+    interpreter.set_log_level(level);
+    Ok(Value::Void)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_messages_are_formatted_like_print() {
+        let entries = Rc::new(RefCell::new(Vec::new()));
+        let sink = entries.clone();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_log_sink(Box::new(move |level: LogLevel, message: &str| {
+            sink.borrow_mut().push((level, message.to_string()))
+        }));
+
+        let items = Value::array(vec![Value::Number(1), Value::String("two".to_string())]);
+        warn(&mut interpreter, vec![items]).unwrap();
+        error(&mut interpreter, vec![Value::Number(7)]).unwrap();
+        assert_eq!(
+            *entries.borrow(),
+            vec![
+                (LogLevel::Warn, "1, two".to_string()),
+                (LogLevel::Error, "7".to_string())
+            ]
+        );
+
+        let err = info(&mut interpreter, vec![Value::Void]).unwrap_err();
+        assert_eq!(err, "log.info argument must be a string or number");
+    }
+
+    #[test]
+    fn test_set_level() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.log_level(), LogLevel::Info);
+
+        set_level(&mut interpreter, vec![Value::String("WARN".to_string())]).unwrap();
+        assert_eq!(interpreter.log_level(), LogLevel::Warn);
+
+        let err = set_level(&mut interpreter, vec![Value::String("loud".to_string())]);
+        assert!(err
+            .unwrap_err()
+            .starts_with("log.set_level: unknown log level"));
+        assert_eq!(interpreter.log_level(), LogLevel::Warn);
+    }
+}
@@ -0,0 +1,8 @@
+use crate::interpreter::{Interpreter, Value};
+
+/// Estimated bytes used by the script's values, see
+/// `Interpreter::memory_used`.
+pub fn memory_used(interpreter: &mut Interpreter, _args: Vec<Value>) -> Result<Value, String> {
+    let used = interpreter.memory_used();
+    Ok(Value::Number(i64::try_from(used).unwrap_or(i64::MAX)))
+}
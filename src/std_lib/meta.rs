@@ -0,0 +1,15 @@
+use crate::interpreter::{Interpreter, Value};
+
+/// `meta.line()`: line of the statement making the call.
+pub fn line(interpreter: &mut Interpreter, _args: Vec<Value>) -> Result<Value, String> {
+    Ok(Value::Number(interpreter.current_line() as i64))
+}
+
+/// `meta.function()`: name of the user function making the call, or void
+/// at the top level.
+pub fn function(interpreter: &mut Interpreter, _args: Vec<Value>) -> Result<Value, String> {
+    Ok(match interpreter.current_function() {
+        Some(name) => Value::String(name.to_string()),
+        None => Value::Void,
+    })
+}
@@ -1,5 +1,128 @@
+pub mod arr;
+pub mod counter;
+pub mod db;
+pub mod encode;
+pub mod format;
+pub mod http_server;
+pub mod io;
+pub mod log;
+pub mod memory;
+pub mod meta;
+pub mod obj;
+pub mod process;
+pub mod range;
+pub mod regex;
 pub mod sleep;
 pub mod socket_server;
 pub mod str_utils;
+pub mod time;
 
 pub mod print;
+
+use crate::interpreter::memory::VALUE_SIZE;
+use crate::interpreter::{ArrayRef, Interpreter, ObjectRef, Value};
+
+/// Most elements (or characters) a std library function builds from a size
+/// argument. Larger sizes are an error rather than an allocation that could
+/// abort the process.
+pub const MAX_BUILD_SIZE: usize = 1 << 24;
+
+/// Checks that an array of `count` elements may be built and reserves it
+/// with the memory limit, before the caller allocates it.
+pub fn reserve_elements(
+    interpreter: &mut Interpreter,
+    count: usize,
+    fn_name: &str,
+) -> Result<(), String> {
+    if count > MAX_BUILD_SIZE {
+        return Err(format!(
+            "{}: {} elements is more than the limit of {}",
+            fn_name, count, MAX_BUILD_SIZE
+        ));
+    }
+    interpreter.reserve_memory(count * VALUE_SIZE)
+}
+
+/// Returns the string argument at `index`, or an error naming the calling function.
+pub fn expect_string(args: &[Value], index: usize, fn_name: &str) -> Result<String, String> {
+    match args.get(index) {
+        Some(Value::String(s)) => Ok(s.clone()),
+        Some(other) => Err(format!(
+            "{} expects a string as argument {}, got {}",
+            fn_name,
+            index + 1,
+            other.type_name()
+        )),
+        None => Err(format!("{} is missing argument {}", fn_name, index + 1)),
+    }
+}
+
+/// Returns the number argument at `index`, or an error naming the calling function.
+pub fn expect_number(args: &[Value], index: usize, fn_name: &str) -> Result<i64, String> {
+    match args.get(index) {
+        Some(Value::Number(n)) => Ok(*n),
+        Some(other) => Err(format!(
+            "{} expects a number as argument {}, got {}",
+            fn_name,
+            index + 1,
+            other.type_name()
+        )),
+        None => Err(format!("{} is missing argument {}", fn_name, index + 1)),
+    }
+}
+
+/// Returns the array argument at `index`, or an error naming the calling function.
+pub fn expect_array(args: &[Value], index: usize, fn_name: &str) -> Result<Vec<Value>, String> {
+    match args.get(index) {
+        Some(Value::Array(values)) => Ok(values.borrow().clone()),
+        Some(other) => Err(format!(
+            "{} expects an array as argument {}, got {}",
+            fn_name,
+            index + 1,
+            other.type_name()
+        )),
+        None => Err(format!("{} is missing argument {}", fn_name, index + 1)),
+    }
+}
+
+/// Returns the callable argument at `index`, or an error naming the calling function.
+pub fn expect_function(args: &[Value], index: usize, fn_name: &str) -> Result<Value, String> {
+    match args.get(index) {
+        Some(func @ (Value::Function(..) | Value::NativeFunction(..))) => Ok(func.clone()),
+        Some(other) => Err(format!(
+            "{} expects a function as argument {}, got {}",
+            fn_name,
+            index + 1,
+            other.type_name()
+        )),
+        None => Err(format!("{} is missing argument {}", fn_name, index + 1)),
+    }
+}
+
+/// Returns the shared array argument at `index` for natives that mutate it in place.
+pub fn expect_array_ref(args: &[Value], index: usize, fn_name: &str) -> Result<ArrayRef, String> {
+    match args.get(index) {
+        Some(Value::Array(values)) => Ok(values.clone()),
+        Some(other) => Err(format!(
+            "{} expects an array as argument {}, got {}",
+            fn_name,
+            index + 1,
+            other.type_name()
+        )),
+        None => Err(format!("{} is missing argument {}", fn_name, index + 1)),
+    }
+}
+
+/// Returns the object argument at `index`, or an error naming the calling function.
+pub fn expect_object(args: &[Value], index: usize, fn_name: &str) -> Result<ObjectRef, String> {
+    match args.get(index) {
+        Some(Value::Object(object)) => Ok(object.clone()),
+        Some(other) => Err(format!(
+            "{} expects an object as argument {}, got {}",
+            fn_name,
+            index + 1,
+            other.type_name()
+        )),
+        None => Err(format!("{} is missing argument {}", fn_name, index + 1)),
+    }
+}
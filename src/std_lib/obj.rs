@@ -0,0 +1,563 @@
+use crate::interpreter::{Interpreter, Object, Value};
+use crate::std_lib::{expect_array, expect_object, expect_string};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Returns the properties of `object` as `[key, value]` pairs, sorted by key.
+pub fn entries(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let object = expect_object(&args, 0, "obj.entries")?.borrow().clone();
+
+    let mut keys: Vec<&String> = object.properties().keys().collect();
+    keys.sort();
+    Ok(Value::array(
+        keys.into_iter()
+            .map(|key| {
+                Value::array(vec![
+                    Value::String(key.clone()),
+                    object.get_property(key).unwrap().clone(),
+                ])
+            })
+            .collect(),
+    ))
+}
+
+/// Builds an object from an array of `[key, value]` pairs. Later pairs win
+/// on duplicate keys.
+pub fn from_entries(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let pairs = expect_array(&args, 0, "obj.from_entries")?;
+
+    let mut object = Object::new("object");
+    for (index, pair) in pairs.iter().enumerate() {
+        let pair = match pair {
+            Value::Array(pair) if pair.borrow().len() == 2 => pair.borrow().clone(),
+            other => {
+                return Err(format!(
+                    "obj.from_entries: entry {} must be a [key, value] array, got {}",
+                    index, other
+                ))
+            }
+        };
+        match &pair[0] {
+            Value::String(key) => object.set_property(key.clone(), pair[1].clone()),
+            other => {
+                return Err(format!(
+                    "obj.from_entries: key of entry {} must be a string, got {}",
+                    index, other
+                ))
+            }
+        }
+    }
+
+    Ok(Value::object(object))
+}
+
+/// Reads an array of property names, rejecting anything that is not a string.
+fn expect_keys(args: &[Value], index: usize, fn_name: &str) -> Result<Vec<String>, String> {
+    expect_array(args, index, fn_name)?
+        .into_iter()
+        .map(|key| match key {
+            Value::String(key) => Ok(key),
+            other => Err(format!("{}: keys must be strings, got {}", fn_name, other)),
+        })
+        .collect()
+}
+
+/// Returns a new object with only the listed properties. Missing keys are skipped.
+pub fn pick(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let object = expect_object(&args, 0, "obj.pick")?;
+    let keys = expect_keys(&args, 1, "obj.pick")?;
+
+    let object = object.borrow();
+    let mut picked = Object::new("object");
+    for key in keys {
+        if let Some(value) = object.get_property(&key) {
+            picked.set_property(key, value.clone());
+        }
+    }
+    Ok(Value::object(picked))
+}
+
+/// Returns a new object without the listed properties.
+pub fn omit(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let object = expect_object(&args, 0, "obj.omit")?;
+    let keys = expect_keys(&args, 1, "obj.omit")?;
+
+    let mut remaining = Object::new("object");
+    for (key, value) in object.borrow().properties() {
+        if !keys.contains(key) {
+            remaining.set_property(key.clone(), value.clone());
+        }
+    }
+    Ok(Value::object(remaining))
+}
+
+/// Splits a dotted property path, rejecting empty segments like `"a..b"`.
+fn path_segments(path: &str, fn_name: &str) -> Result<Vec<String>, String> {
+    let segments: Vec<String> = path.split('.').map(str::to_string).collect();
+    if segments.iter().any(String::is_empty) {
+        return Err(format!("{}: invalid path \"{}\"", fn_name, path));
+    }
+    Ok(segments)
+}
+
+/// Follows a dotted path like `"settings.database.host"` through nested
+/// objects. Returns void if any level is missing or not an object.
+pub fn deep_get(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let object = expect_object(&args, 0, "obj.deep_get")?;
+    let path = expect_string(&args, 1, "obj.deep_get")?;
+
+    let mut current = Value::Object(object);
+    for segment in path_segments(&path, "obj.deep_get")? {
+        let next = match &current {
+            Value::Object(object) => object.borrow().get_property(&segment).cloned(),
+            _ => None,
+        };
+        match next {
+            Some(value) => current = value,
+            None => return Ok(Value::Void),
+        }
+    }
+    Ok(current)
+}
+
+/// Sets the property at a dotted path, creating missing intermediate
+/// objects. Changes the object in place and returns it.
+pub fn deep_set(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let root = expect_object(&args, 0, "obj.deep_set")?;
+    let path = expect_string(&args, 1, "obj.deep_set")?;
+    let value = args
+        .get(2)
+        .cloned()
+        .ok_or("obj.deep_set is missing argument 3")?;
+
+    let mut segments = path_segments(&path, "obj.deep_set")?;
+    let last = segments.pop().unwrap_or_default();
+    let mut current = root.clone();
+    for (depth, segment) in segments.iter().enumerate() {
+        let existing = current.borrow().get_property(segment).cloned();
+        let next = match existing {
+            Some(Value::Object(next)) => next,
+            None | Some(Value::Void) => {
+                let created = Rc::new(RefCell::new(Object::new("object")));
+                current
+                    .borrow_mut()
+                    .set_property(segment.clone(), Value::Object(created.clone()));
+                created
+            }
+            Some(other) => {
+                return Err(format!(
+                    "obj.deep_set: \"{}\" is a {}, not an object",
+                    segments[..=depth].join("."),
+                    other.type_name()
+                ))
+            }
+        };
+        current = next;
+    }
+    current.borrow_mut().set_property(last, value);
+    Ok(Value::Object(root))
+}
+
+/// Swaps keys and values: each value, in its string form, becomes a key
+/// mapping back to the original key. Values must be numbers or strings.
+/// Keys are taken in sorted order, so when several share a value the one
+/// that sorts last wins.
+pub fn invert(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let object = expect_object(&args, 0, "obj.invert")?;
+    let object = object.borrow();
+
+    let mut keys: Vec<&String> = object.properties().keys().collect();
+    keys.sort();
+    let mut inverted = Object::new("object");
+    for key in keys {
+        let value = match object.get_property(key) {
+            Some(value @ (Value::Number(_) | Value::Float(_) | Value::String(_))) => value,
+            Some(other) => {
+                return Err(format!(
+                    "obj.invert: value of \"{}\" is a {}, only numbers and strings can become keys",
+                    key,
+                    other.type_name()
+                ))
+            }
+            None => continue,
+        };
+        inverted.set_property(value.to_string(), Value::String(key.clone()));
+    }
+    Ok(Value::object(inverted))
+}
+
+/// 1 if `object` has a property named `key`, even one holding void, else 0.
+pub fn has(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let object = expect_object(&args, 0, "obj.has")?;
+    let key = expect_string(&args, 1, "obj.has")?;
+    let found = object.borrow().properties().contains_key(&key);
+    Ok(Value::Number(found as i64))
+}
+
+/// Number of properties of `object`.
+pub fn size(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let object = expect_object(&args, 0, "obj.size")?;
+    let size = object.borrow().properties().len();
+    Ok(Value::Number(size as i64))
+}
+
+/// 1 if `object` has no properties, else 0.
+pub fn is_empty(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let object = expect_object(&args, 0, "obj.is_empty")?;
+    let empty = object.borrow().properties().is_empty();
+    Ok(Value::Number(empty as i64))
+}
+
+/// Objects `defaults_deep` descends into before giving up, so objects
+/// that contain themselves end in an error.
+const DEFAULTS_MAX_DEPTH: usize = 1000;
+
+/// `target`'s properties plus those of `defaults` that `target` lacks or
+/// holds void for. With `deep`, a property that is an object on both
+/// sides gets the same treatment.
+fn with_defaults(
+    target: &Object,
+    defaults: &Object,
+    deep: bool,
+    depth: usize,
+) -> Result<Object, String> {
+    if depth > DEFAULTS_MAX_DEPTH {
+        return Err(format!(
+            "obj.defaults_deep: objects are nested more than {} levels deep",
+            DEFAULTS_MAX_DEPTH
+        ));
+    }
+    let mut merged = target.clone();
+    for (key, fallback) in defaults.properties() {
+        let value = match (target.get_property(key), fallback) {
+            (None | Some(Value::Void), _) => fallback.clone(),
+            (Some(Value::Object(own)), Value::Object(fallback)) if deep => {
+                let nested = with_defaults(&own.borrow(), &fallback.borrow(), deep, depth + 1)?;
+                Value::object(nested)
+            }
+            (Some(_), _) => continue,
+        };
+        merged.set_property(key.clone(), value);
+    }
+    Ok(merged)
+}
+
+/// A new object with `target`'s properties, plus those of `defaults` that
+/// `target` lacks or holds void for. Unlike a merge, existing values win.
+pub fn defaults(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let target = expect_object(&args, 0, "obj.defaults")?;
+    let defaults = expect_object(&args, 1, "obj.defaults")?;
+    let merged = with_defaults(&target.borrow(), &defaults.borrow(), false, 0)?;
+    Ok(Value::object(merged))
+}
+
+/// Like `defaults`, but nested objects present on both sides are filled
+/// in the same way instead of `target`'s winning whole.
+pub fn defaults_deep(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let target = expect_object(&args, 0, "obj.defaults_deep")?;
+    let defaults = expect_object(&args, 1, "obj.defaults_deep")?;
+    let merged = with_defaults(&target.borrow(), &defaults.borrow(), true, 0)?;
+    Ok(Value::object(merged))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn s(value: &str) -> Value {
+        Value::String(value.to_string())
+    }
+
+    fn pair(key: &str, value: Value) -> Value {
+        Value::array(vec![s(key), value])
+    }
+
+    #[test]
+    fn test_from_entries() {
+        let mut interpreter = Interpreter::new();
+        let result = from_entries(
+            &mut interpreter,
+            vec![Value::array(vec![
+                pair("a", Value::Number(1)),
+                pair("b", s("two")),
+            ])],
+        )
+        .unwrap();
+
+        let Value::Object(object) = result else {
+            panic!("expected an object, got {}", result);
+        };
+        assert_eq!(object.borrow().get_property("a"), Some(&Value::Number(1)));
+        assert_eq!(object.borrow().get_property("b"), Some(&s("two")));
+    }
+
+    #[test]
+    fn test_entries_roundtrip() {
+        let mut interpreter = Interpreter::new();
+        let pairs = Value::array(vec![
+            pair("x", Value::Number(1)),
+            pair("y", Value::Number(2)),
+        ]);
+        let object = from_entries(&mut interpreter, vec![pairs.clone()]).unwrap();
+
+        assert_eq!(entries(&mut interpreter, vec![object]), Ok(pairs));
+    }
+
+    #[test]
+    fn test_from_entries_rejects_bad_pairs() {
+        let mut interpreter = Interpreter::new();
+        let not_a_pair = Value::array(vec![Value::array(vec![s("a")])]);
+        assert!(from_entries(&mut interpreter, vec![not_a_pair]).is_err());
+
+        let number_key = Value::array(vec![Value::array(vec![Value::Number(1), s("a")])]);
+        let err = from_entries(&mut interpreter, vec![number_key]).unwrap_err();
+        assert!(err.contains("must be a string"));
+    }
+
+    fn abc() -> Value {
+        let mut object = Object::new("object");
+        object.set_property("a".to_string(), Value::Number(1));
+        object.set_property("b".to_string(), Value::Number(2));
+        object.set_property("c".to_string(), Value::Number(3));
+        Value::object(object)
+    }
+
+    fn keys(names: &[&str]) -> Value {
+        Value::array(names.iter().map(|name| s(name)).collect())
+    }
+
+    fn property_names(value: &Value) -> Vec<String> {
+        let Value::Object(object) = value else {
+            panic!("expected an object, got {}", value);
+        };
+        let mut names: Vec<String> = object.borrow().properties().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_pick() {
+        let mut interpreter = Interpreter::new();
+        let original = abc();
+        let result = pick(
+            &mut interpreter,
+            vec![original.clone(), keys(&["a", "c", "missing"])],
+        )
+        .unwrap();
+
+        assert_eq!(property_names(&result), vec!["a", "c"]);
+        assert_eq!(property_names(&original), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_omit() {
+        let mut interpreter = Interpreter::new();
+        let original = abc();
+        let result = omit(&mut interpreter, vec![original.clone(), keys(&["a", "b"])]).unwrap();
+
+        assert_eq!(property_names(&result), vec!["c"]);
+        assert_eq!(property_names(&original), vec!["a", "b", "c"]);
+        assert!(!result.same_ref(&original));
+    }
+
+    #[test]
+    fn test_pick_rejects_non_string_keys() {
+        let mut interpreter = Interpreter::new();
+        let result = pick(
+            &mut interpreter,
+            vec![abc(), Value::array(vec![Value::Number(1)])],
+        );
+        assert!(result.is_err());
+    }
+
+    fn nested(path: &[&str], leaf: Value) -> Value {
+        path.iter().rev().fold(leaf, |inner, key| {
+            let mut object = Object::new("object");
+            object.set_property(key.to_string(), inner);
+            Value::object(object)
+        })
+    }
+
+    #[test]
+    fn test_deep_get() {
+        let mut interpreter = Interpreter::new();
+        let object = nested(&["a", "b", "c"], Value::Number(42));
+        let result = deep_get(&mut interpreter, vec![object.clone(), s("a.b.c")]);
+        assert_eq!(result, Ok(Value::Number(42)));
+        let result = deep_get(&mut interpreter, vec![object, s("a.x.c")]);
+        assert_eq!(result, Ok(Value::Void));
+
+        let flat = nested(&["a"], Value::Number(1));
+        let result = deep_get(&mut interpreter, vec![flat.clone(), s("a.b")]);
+        assert_eq!(result, Ok(Value::Void));
+        assert!(deep_get(&mut interpreter, vec![flat, s("a..b")]).is_err());
+    }
+
+    #[test]
+    fn test_deep_set_creates_intermediate_objects() {
+        let mut interpreter = Interpreter::new();
+        let root = nested(&["settings"], Value::object(Object::new("object")));
+        let args = vec![root.clone(), s("settings.database.host"), s("localhost")];
+        let result = deep_set(&mut interpreter, args).unwrap();
+        assert!(result.same_ref(&root));
+
+        let host = deep_get(&mut interpreter, vec![root, s("settings.database.host")]);
+        assert_eq!(host, Ok(s("localhost")));
+    }
+
+    #[test]
+    fn test_deep_set_through_non_object_fails() {
+        let mut interpreter = Interpreter::new();
+        let root = nested(&["a", "b"], Value::Number(1));
+        let err = deep_set(&mut interpreter, vec![root, s("a.b.c"), Value::Number(2)]).unwrap_err();
+        assert_eq!(err, "obj.deep_set: \"a.b\" is a number, not an object");
+    }
+
+    fn object_of(pairs: Vec<Value>) -> Value {
+        from_entries(&mut Interpreter::new(), vec![Value::array(pairs)]).unwrap()
+    }
+
+    #[test]
+    fn test_invert() {
+        let mut interpreter = Interpreter::new();
+        let object = object_of(vec![pair("a", s("1")), pair("b", s("2"))]);
+        assert_eq!(
+            invert(&mut interpreter, vec![object]),
+            Ok(object_of(vec![pair("1", s("a")), pair("2", s("b"))]))
+        );
+
+        let object = object_of(vec![pair("b", s("1")), pair("a", s("1"))]);
+        assert_eq!(
+            invert(&mut interpreter, vec![object]),
+            Ok(object_of(vec![pair("1", s("b"))]))
+        );
+
+        let object = object_of(vec![pair("id", Value::Number(7))]);
+        assert_eq!(
+            invert(&mut interpreter, vec![object]),
+            Ok(object_of(vec![pair("7", s("id"))]))
+        );
+    }
+
+    #[test]
+    fn test_invert_rejects_non_key_values() {
+        let mut interpreter = Interpreter::new();
+        let object = object_of(vec![pair("list", Value::array(vec![]))]);
+        assert_eq!(
+            invert(&mut interpreter, vec![object]),
+            Err(
+                "obj.invert: value of \"list\" is a array, only numbers and strings can become keys"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_has() {
+        let mut interpreter = Interpreter::new();
+        let mut object = Object::new("object");
+        object.set_property("nothing".to_string(), Value::Void);
+        let object = Value::object(object);
+
+        let has_key = |interpreter: &mut Interpreter, key: &str| {
+            has(interpreter, vec![object.clone(), s(key)])
+        };
+        // A property holding void still exists
+        assert_eq!(has_key(&mut interpreter, "nothing"), Ok(Value::Number(1)));
+        assert_eq!(has_key(&mut interpreter, "missing"), Ok(Value::Number(0)));
+        assert!(has(&mut interpreter, vec![object.clone(), Value::Number(1)]).is_err());
+    }
+
+    #[test]
+    fn test_size_and_is_empty() {
+        let mut interpreter = Interpreter::new();
+        let empty = Value::object(Object::new("object"));
+
+        assert_eq!(size(&mut interpreter, vec![abc()]), Ok(Value::Number(3)));
+        assert_eq!(
+            size(&mut interpreter, vec![empty.clone()]),
+            Ok(Value::Number(0))
+        );
+        assert_eq!(
+            is_empty(&mut interpreter, vec![abc()]),
+            Ok(Value::Number(0))
+        );
+        assert_eq!(
+            is_empty(&mut interpreter, vec![empty]),
+            Ok(Value::Number(1))
+        );
+        assert!(size(&mut interpreter, vec![Value::array(vec![])]).is_err());
+    }
+
+    fn object(pairs: &[(&str, Value)]) -> Value {
+        let mut object = Object::new("object");
+        for (key, value) in pairs {
+            object.set_property(key.to_string(), value.clone());
+        }
+        Value::object(object)
+    }
+
+    #[test]
+    fn test_defaults_keeps_existing_values() {
+        let mut interpreter = Interpreter::new();
+        let target = object(&[("a", Value::Number(1)), ("c", Value::Void)]);
+        let fallback = object(&[
+            ("a", Value::Number(99)),
+            ("b", Value::Number(2)),
+            ("c", Value::Number(3)),
+        ]);
+        let result = defaults(&mut interpreter, vec![target.clone(), fallback]);
+        assert_eq!(
+            result,
+            Ok(object(&[
+                ("a", Value::Number(1)),
+                ("b", Value::Number(2)),
+                ("c", Value::Number(3)),
+            ]))
+        );
+        // `target` itself is unchanged
+        assert_eq!(
+            target,
+            object(&[("a", Value::Number(1)), ("c", Value::Void)])
+        );
+    }
+
+    #[test]
+    fn test_defaults_deep_fills_nested_objects() {
+        let mut interpreter = Interpreter::new();
+        let target = object(&[("db", object(&[("host", s("example.org"))]))]);
+        let fallback = object(&[(
+            "db",
+            object(&[("host", s("localhost")), ("port", Value::Number(5432))]),
+        )]);
+
+        let shallow = defaults(&mut interpreter, vec![target.clone(), fallback.clone()]);
+        assert_eq!(shallow, Ok(target.clone()));
+        let deep = defaults_deep(&mut interpreter, vec![target, fallback]);
+        assert_eq!(
+            deep,
+            Ok(object(&[(
+                "db",
+                object(&[("host", s("example.org")), ("port", Value::Number(5432))]),
+            )]))
+        );
+
+        let Value::Object(cyclic) = object(&[]) else {
+            unreachable!()
+        };
+        cyclic
+            .borrow_mut()
+            .set_property("me".to_string(), Value::Object(cyclic.clone()));
+        let result = defaults_deep(
+            &mut interpreter,
+            vec![Value::Object(cyclic.clone()), Value::Object(cyclic.clone())],
+        );
+        assert_eq!(
+            result,
+            Err("obj.defaults_deep: objects are nested more than 1000 levels deep".to_string())
+        );
+        cyclic
+            .borrow_mut()
+            .set_property("me".to_string(), Value::Void);
+    }
+}
@@ -1,30 +1,38 @@
 use crate::interpreter::{Interpreter, Value};
 
-pub fn print(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
-    let message = match &args[0] {
-        Value::String(s) => s.clone(),
-        Value::Number(n) => n.to_string(),
-        Value::Array(a) => {
+/// Formats the first argument the way `print` shows it. `fn_name` is used in
+/// errors.
+pub fn format_message(args: &[Value], fn_name: &str) -> Result<String, String> {
+    let invalid = || format!("{} argument must be a string or number", fn_name);
+    let message = match args.first() {
+        Some(Value::String(s)) => s.clone(),
+        Some(number @ (Value::Number(_) | Value::Float(_))) => number.to_string(),
+        Some(Value::Array(a)) => {
             let mut msg = String::new();
             let mut first = true;
-            for v in a.iter() {
+            for v in a.borrow().iter() {
                 if !first {
                     msg.push_str(", ");
                 }
                 first = false;
                 match v {
                     Value::String(s) => msg.push_str(s.as_str()),
-                    Value::Number(n) => msg.push_str(&n.to_string()),
+                    Value::Number(_) | Value::Float(_) => msg.push_str(&v.to_string()),
                     Value::Array(_inner) => msg.push_str("[...]"),
-                    _ => return Err("print argument must be a string or number".to_string()),
+                    _ => return Err(invalid()),
                 }
             }
             msg
         }
-        _ => return Err("print argument must be a string or number".to_string()),
+        _ => return Err(invalid()),
     };
+    Ok(message)
+}
+
+pub fn print(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let message = format_message(&args, "print")?;
 
-    println!("{}", message);
+    interpreter.write_line(&message);
 
     Ok(Value::Void)
 }
@@ -0,0 +1,205 @@
+//! Running external programs. Not registered by default: hosts opt in with
+//! `Interpreter::enable_process_spawning`.
+
+use crate::interpreter::{Interpreter, Object, Value};
+use crate::std_lib::{expect_array, expect_number, expect_string};
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often a child with a timeout is checked for having exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Reads the optional timeout in milliseconds at `index`.
+fn timeout_arg(args: &[Value], index: usize, fn_name: &str) -> Result<Option<Duration>, String> {
+    if args.len() <= index {
+        return Ok(None);
+    }
+    let ms = expect_number(args, index, fn_name)?;
+    let ms = u64::try_from(ms).map_err(|_| format!("{}: timeout must not be negative", fn_name))?;
+    Ok(Some(Duration::from_millis(ms)))
+}
+
+/// Runs `command` to completion and returns `{status, stdout, stderr}`.
+/// `program` names it in errors; past `timeout` the child is killed.
+fn run(
+    mut command: Command,
+    program: &str,
+    timeout: Option<Duration>,
+    fn_name: &str,
+) -> Result<Value, String> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("{}: could not run '{}': {}", fn_name, program, e))?;
+
+    // Drain both pipes while waiting, a child blocked on a full pipe never exits
+    let read_pipe = |pipe: Option<Box<dyn Read + Send>>| {
+        thread::spawn(move || {
+            let mut bytes = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut bytes);
+            }
+            String::from_utf8_lossy(&bytes).into_owned()
+        })
+    };
+    let stdout = read_pipe(
+        child
+            .stdout
+            .take()
+            .map(|p| Box::new(p) as Box<dyn Read + Send>),
+    );
+    let stderr = read_pipe(
+        child
+            .stderr
+            .take()
+            .map(|p| Box::new(p) as Box<dyn Read + Send>),
+    );
+
+    let started = Instant::now();
+    let status = loop {
+        let Some(timeout) = timeout else {
+            break child.wait();
+        };
+        match child.try_wait() {
+            Ok(Some(status)) => break Ok(status),
+            Ok(None) if started.elapsed() >= timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!(
+                    "{}: '{}' timed out after {}ms",
+                    fn_name,
+                    program,
+                    timeout.as_millis()
+                ));
+            }
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+            Err(e) => break Err(e),
+        }
+    }
+    .map_err(|e| format!("{}: waiting for '{}' failed: {}", fn_name, program, e))?;
+
+    let mut result = Object::new("object");
+    // Killed by a signal: there is no exit code
+    let code = status.code().map_or(-1, i64::from);
+    result.set_property("status".to_string(), Value::Number(code));
+    for (name, reader) in [("stdout", stdout), ("stderr", stderr)] {
+        let output = reader.join().unwrap_or_default();
+        result.set_property(name.to_string(), Value::String(output));
+    }
+    Ok(Value::object(result))
+}
+
+/// `exec(program, args, timeout_ms?)` runs `program` directly with the
+/// strings in `args` as its arguments; nothing is interpreted by a shell.
+pub fn exec(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let program = expect_string(&args, 0, "exec")?;
+    let arguments = if args.len() > 1 {
+        expect_array(&args, 1, "exec")?
+    } else {
+        Vec::new()
+    };
+    let timeout = timeout_arg(&args, 2, "exec")?;
+
+    let mut command = Command::new(&program);
+    for (index, argument) in arguments.iter().enumerate() {
+        match argument {
+            Value::String(s) => command.arg(s),
+            other => {
+                return Err(format!(
+                    "exec expects an array of strings, element {} is a {}",
+                    index,
+                    other.type_name()
+                ))
+            }
+        };
+    }
+    run(command, &program, timeout, "exec")
+}
+
+/// `shell(cmdline, timeout_ms?)` runs a command line through `sh -c` (`cmd
+/// /C` on Windows). Pipes and globs work, but so does anything else: never
+/// build `cmdline` from untrusted input, use `exec` with an argument array.
+pub fn shell(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let cmdline = expect_string(&args, 0, "shell")?;
+    let timeout = timeout_arg(&args, 1, "shell")?;
+
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let mut command = Command::new(shell);
+    command.args([flag, &cmdline]);
+    run(command, shell, timeout, "shell")
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn s(value: &str) -> Value {
+        Value::String(value.to_string())
+    }
+
+    fn property(result: &Value, name: &str) -> Value {
+        let Value::Object(object) = result else {
+            panic!("expected an object, got {}", result);
+        };
+        object.borrow().get_property(name).cloned().unwrap()
+    }
+
+    #[test]
+    fn test_exec_captures_stdout() {
+        let mut interpreter = Interpreter::new();
+        let args = vec![s("echo"), Value::array(vec![s("hello"), s("a;b")])];
+        let result = exec(&mut interpreter, args).unwrap();
+        assert_eq!(property(&result, "status"), Value::Number(0));
+        // The `;` reaches echo as is, there is no shell in between
+        assert_eq!(property(&result, "stdout"), s("hello a;b\n"));
+        assert_eq!(property(&result, "stderr"), s(""));
+    }
+
+    #[test]
+    fn test_shell_reports_exit_status_and_stderr() {
+        let mut interpreter = Interpreter::new();
+        let result = shell(&mut interpreter, vec![s("echo oops >&2; exit 3")]).unwrap();
+        assert_eq!(property(&result, "status"), Value::Number(3));
+        assert_eq!(property(&result, "stderr"), s("oops\n"));
+    }
+
+    #[test]
+    fn test_missing_program_is_named() {
+        let mut interpreter = Interpreter::new();
+        let err = exec(&mut interpreter, vec![s("no-such-program-mouse")]).unwrap_err();
+        assert!(err.starts_with("exec: could not run 'no-such-program-mouse'"));
+    }
+
+    #[test]
+    fn test_timeout_kills_child() {
+        let mut interpreter = Interpreter::new();
+        let started = Instant::now();
+        let args = vec![s("sleep"), Value::array(vec![s("5")]), Value::Number(100)];
+        let err = exec(&mut interpreter, args).unwrap_err();
+        assert_eq!(err, "exec: 'sleep' timed out after 100ms");
+        assert!(started.elapsed() < Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_natives_absent_unless_enabled() {
+        let has_exec = |interpreter: &Interpreter| {
+            let std = interpreter.env.get_object("std").unwrap();
+            std.get_property("exec").is_some() && std.get_property("shell").is_some()
+        };
+        let mut interpreter = Interpreter::new();
+        assert!(!has_exec(&interpreter));
+        assert!(crate::run_source_with("std.exec(\"echo\");", &mut interpreter).is_err());
+
+        interpreter.enable_process_spawning();
+        assert!(has_exec(&interpreter));
+        assert!(crate::run_source_with("std.exec(\"echo\");", &mut interpreter).is_ok());
+    }
+}
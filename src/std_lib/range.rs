@@ -0,0 +1,158 @@
+//! `range(start, end, step?)`: the numbers from `start` up to (excluding)
+//! `end` as an object handing them out one at a time, so a range of a
+//! million numbers costs no more than a range of ten.
+//!
+//! Its `has_next()` and `next()` methods are what `for i in range(..)`
+//! drives, and what any object needs to be usable in a `for` loop.
+
+use crate::interpreter::{Interpreter, Object, ObjectRef, Value};
+use crate::std_lib::expect_number;
+
+/// The range a method was called on.
+fn receiver(interpreter: &mut Interpreter, fn_name: &str) -> Result<ObjectRef, String> {
+    interpreter.current_receiver().ok_or_else(|| {
+        format!(
+            "range.{} must be called on a range, e.g. r.{}()",
+            fn_name, fn_name
+        )
+    })
+}
+
+fn number(range: &Object, property: &str, fn_name: &str) -> Result<i64, String> {
+    match range.get_property(property) {
+        Some(Value::Number(n)) => Ok(*n),
+        _ => Err(format!(
+            "range.{}: `{}` must be a number",
+            fn_name, property
+        )),
+    }
+}
+
+/// Whether `current` still lies before `end`, coming from the direction
+/// `step` moves in.
+fn in_range(current: i64, end: i64, step: i64) -> bool {
+    if step > 0 {
+        current < end
+    } else {
+        current > end
+    }
+}
+
+/// `range(start, end, step?)` counts from `start` towards `end` by `step`
+/// (default 1, negative counts down) with `has_next()` and `next()`
+/// methods. A range that starts at or past its end is empty.
+pub fn new(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let start = expect_number(&args, 0, "range")?;
+    let end = expect_number(&args, 1, "range")?;
+    let step = match args.get(2) {
+        Some(_) => expect_number(&args, 2, "range")?,
+        None => 1,
+    };
+    if step == 0 {
+        return Err("range step must not be 0".to_string());
+    }
+
+    let mut range = Object::new("range");
+    range.set_property("start".to_string(), Value::Number(start));
+    range.set_property("end".to_string(), Value::Number(end));
+    range.set_property("step".to_string(), Value::Number(step));
+    range.set_property("current".to_string(), Value::Number(start));
+    range.register_native_fn("has_next", has_next);
+    range.register_native_fn("next", next);
+    Ok(Value::object(range))
+}
+
+/// 1 while there are numbers left, then 0.
+pub fn has_next(interpreter: &mut Interpreter, _args: Vec<Value>) -> Result<Value, String> {
+    let range = receiver(interpreter, "has_next")?;
+    let range = range.borrow();
+    let current = number(&range, "current", "has_next")?;
+    let end = number(&range, "end", "has_next")?;
+    let step = number(&range, "step", "has_next")?;
+    Ok(Value::Number(in_range(current, end, step) as i64))
+}
+
+/// The next number; an error once the range is used up.
+pub fn next(interpreter: &mut Interpreter, _args: Vec<Value>) -> Result<Value, String> {
+    let range = receiver(interpreter, "next")?;
+    let mut range = range.borrow_mut();
+    let current = number(&range, "current", "next")?;
+    let end = number(&range, "end", "next")?;
+    let step = number(&range, "step", "next")?;
+    if !in_range(current, end, step) {
+        return Err("range.next: the range is used up".to_string());
+    }
+    // Stepping past i64's limits ends the range rather than wrapping
+    let following = current.checked_add(step).unwrap_or(end);
+    range.set_property("current".to_string(), Value::Number(following));
+    Ok(Value::Number(current))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect(interpreter: &mut Interpreter, args: Vec<Value>) -> Vec<i64> {
+        let range = new(interpreter, args).unwrap();
+        let Value::Object(object) = &range else {
+            panic!("range should be an object");
+        };
+        let method = |name: &str| object.borrow().get_property(name).cloned().unwrap();
+        let mut numbers = Vec::new();
+        while interpreter.call_method(object, &method("has_next"), vec![]) == Ok(Value::Number(1)) {
+            match interpreter.call_method(object, &method("next"), vec![]) {
+                Ok(Value::Number(n)) => numbers.push(n),
+                other => panic!("next returned {:?}", other),
+            }
+        }
+        numbers
+    }
+
+    #[test]
+    fn test_range_steps() {
+        let mut interpreter = Interpreter::new();
+        let numbers = |n: &[i64]| n.iter().map(|n| Value::Number(*n)).collect::<Vec<_>>();
+        assert_eq!(
+            collect(&mut interpreter, numbers(&[0, 4])),
+            vec![0, 1, 2, 3]
+        );
+        assert_eq!(
+            collect(&mut interpreter, numbers(&[0, 7, 2])),
+            vec![0, 2, 4, 6]
+        );
+        assert_eq!(
+            collect(&mut interpreter, numbers(&[3, 0, -1])),
+            vec![3, 2, 1]
+        );
+        assert_eq!(
+            collect(&mut interpreter, numbers(&[5, 5])),
+            Vec::<i64>::new()
+        );
+        assert_eq!(
+            collect(&mut interpreter, numbers(&[5, 0])),
+            Vec::<i64>::new()
+        );
+        assert_eq!(
+            collect(&mut interpreter, numbers(&[0, 5, -1])),
+            Vec::<i64>::new()
+        );
+        assert_eq!(
+            collect(&mut interpreter, numbers(&[i64::MAX - 1, i64::MAX, 5])),
+            vec![i64::MAX - 1]
+        );
+    }
+
+    #[test]
+    fn test_range_errors() {
+        let mut interpreter = Interpreter::new();
+        let result = new(
+            &mut interpreter,
+            vec![Value::Number(0), Value::Number(3), Value::Number(0)],
+        );
+        assert_eq!(result, Err("range step must not be 0".to_string()));
+        assert_eq!(
+            next(&mut interpreter, vec![]),
+            Err("range.next must be called on a range, e.g. r.next()".to_string())
+        );
+    }
+}
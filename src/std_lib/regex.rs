@@ -0,0 +1,144 @@
+use crate::interpreter::{Interpreter, Value};
+use crate::std_lib::expect_string;
+use ::regex::Regex;
+
+/// Reads the pattern and subject arguments and compiles the pattern through
+/// the interpreter's cache.
+fn pattern_and_subject(
+    interpreter: &mut Interpreter,
+    args: &[Value],
+    fn_name: &str,
+) -> Result<(Regex, String), String> {
+    let pattern = expect_string(args, 0, fn_name)?;
+    let subject = expect_string(args, 1, fn_name)?;
+    let regex = interpreter
+        .regex(&pattern)
+        .map_err(|e| format!("{}: invalid pattern: {}", fn_name, e))?;
+    Ok((regex, subject))
+}
+
+/// 1 if `pattern` matches anywhere in `s`, otherwise 0.
+pub fn is_match(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let (regex, subject) = pattern_and_subject(interpreter, &args, "regex.is_match")?;
+    Ok(Value::Number(regex.is_match(&subject) as i64))
+}
+
+/// The first match of `pattern` in `s`, or void.
+pub fn find(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let (regex, subject) = pattern_and_subject(interpreter, &args, "regex.find")?;
+    Ok(regex.find(&subject).map_or(Value::Void, |found| {
+        Value::String(found.as_str().to_string())
+    }))
+}
+
+/// Every non-overlapping match of `pattern` in `s`, in order.
+pub fn find_all(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let (regex, subject) = pattern_and_subject(interpreter, &args, "regex.find_all")?;
+    Ok(Value::array(
+        regex
+            .find_iter(&subject)
+            .map(|found| Value::String(found.as_str().to_string()))
+            .collect(),
+    ))
+}
+
+/// Replaces every match of `pattern` in `s`. `replacement` may refer to
+/// groups as `$1` or `${name}`.
+pub fn replace(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let (regex, subject) = pattern_and_subject(interpreter, &args, "regex.replace")?;
+    let replacement = expect_string(&args, 2, "regex.replace")?;
+    Ok(Value::String(
+        regex
+            .replace_all(&subject, replacement.as_str())
+            .into_owned(),
+    ))
+}
+
+/// The groups of the first match: the whole match first, then each group,
+/// with void for groups that did not take part. Void if nothing matches.
+pub fn captures(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let (regex, subject) = pattern_and_subject(interpreter, &args, "regex.captures")?;
+    let Some(groups) = regex.captures(&subject) else {
+        return Ok(Value::Void);
+    };
+    Ok(Value::array(
+        groups
+            .iter()
+            .map(|group| group.map_or(Value::Void, |m| Value::String(m.as_str().to_string())))
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strs(values: &[&str]) -> Vec<Value> {
+        values
+            .iter()
+            .map(|s| Value::String(s.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_is_match() {
+        let mut interpreter = Interpreter::new();
+        let result = is_match(
+            &mut interpreter,
+            strs(&[r"^\w+@\w+\.com$", "ada@mouse.com"]),
+        );
+        assert_eq!(result, Ok(Value::Number(1)));
+        let result = is_match(&mut interpreter, strs(&[r"^\w+@\w+\.com$", "ada at mouse"]));
+        assert_eq!(result, Ok(Value::Number(0)));
+    }
+
+    #[test]
+    fn test_find() {
+        let mut interpreter = Interpreter::new();
+        let result = find(&mut interpreter, strs(&[r"\d+", "took 125ms, then 30ms"]));
+        assert_eq!(result, Ok(Value::String("125".to_string())));
+        let result = find(&mut interpreter, strs(&[r"\d+", "no digits"]));
+        assert_eq!(result, Ok(Value::Void));
+    }
+
+    #[test]
+    fn test_find_all() {
+        let mut interpreter = Interpreter::new();
+        let result = find_all(&mut interpreter, strs(&[r"\d+", "took 125ms, then 30ms"]));
+        assert_eq!(result, Ok(Value::array(strs(&["125", "30"]))));
+        let result = find_all(&mut interpreter, strs(&[r"\d+", "none"]));
+        assert_eq!(result, Ok(Value::array(vec![])));
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut interpreter = Interpreter::new();
+        let args = strs(&[r"(\w+)@(\w+)", "ada@mouse, bob@cat", "$2:$1"]);
+        let result = replace(&mut interpreter, args);
+        assert_eq!(result, Ok(Value::String("mouse:ada, cat:bob".to_string())));
+
+        let result = replace(&mut interpreter, strs(&["x", "abc", "y"]));
+        assert_eq!(result, Ok(Value::String("abc".to_string())));
+    }
+
+    #[test]
+    fn test_captures() {
+        let mut interpreter = Interpreter::new();
+        let pattern = r"\[(\w+)\] (\d+)(ms)?";
+        let result = captures(&mut interpreter, strs(&[pattern, "log: [WARN] 42 retries"]));
+        let mut expected = strs(&["[WARN] 42", "WARN", "42"]);
+        expected.push(Value::Void);
+        assert_eq!(result, Ok(Value::array(expected)));
+
+        let result = captures(&mut interpreter, strs(&[pattern, "nothing here"]));
+        assert_eq!(result, Ok(Value::Void));
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        let mut interpreter = Interpreter::new();
+        let err = find(&mut interpreter, strs(&["(unclosed", "text"])).unwrap_err();
+        assert!(err.starts_with("regex.find: invalid pattern: "), "{}", err);
+        assert!(err.contains("unclosed group"), "{}", err);
+    }
+}
@@ -1,4 +1,7 @@
 use crate::interpreter::{Interpreter, Value};
+use crate::std_lib::{expect_array, expect_number, expect_string, MAX_BUILD_SIZE};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
 
 pub fn split_string(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
     let string_val = args.get(0).ok_or("Missing string argument")?;
@@ -14,12 +17,588 @@ pub fn split_string(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<
             .map(|part| Value::String(part.to_string()))
             .collect();
 
-        Ok(Value::Array(parts_value))
+        Ok(Value::array(parts_value))
     } else {
         Err("Delimiter must be a string".to_string())
     }
 }
 
+/// Replaces `&`, `<`, `>`, `"` and `'` with their HTML entities.
+pub fn escape_html(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let string = expect_string(&args, 0, "escape_html")?;
+    let mut escaped = String::with_capacity(string.len());
+    for c in string.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    Ok(Value::String(escaped))
+}
+
+/// Reverses `escape_html`. `&amp;` is replaced last so `&amp;lt;` becomes `&lt;`.
+pub fn unescape_html(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let string = expect_string(&args, 0, "unescape_html")?;
+    let unescaped = string
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    Ok(Value::String(unescaped))
+}
+
+/// Percent-encodes every byte except the unreserved characters of RFC 3986.
+pub fn escape_url(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let string = expect_string(&args, 0, "escape_url")?;
+    let mut encoded = String::with_capacity(string.len());
+    for byte in string.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    Ok(Value::String(encoded))
+}
+
+/// Encodes the UTF-8 bytes of a string as standard (padded) base64.
+pub fn base64_encode(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let string = expect_string(&args, 0, "base64_encode")?;
+    Ok(Value::String(STANDARD.encode(string.as_bytes())))
+}
+
+/// Decodes standard base64 into a string; the decoded bytes must be UTF-8.
+pub fn base64_decode(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let string = expect_string(&args, 0, "base64_decode")?;
+    let bytes = STANDARD
+        .decode(string.as_bytes())
+        .map_err(|e| format!("base64_decode: invalid base64: {}", e))?;
+    String::from_utf8(bytes)
+        .map(Value::String)
+        .map_err(|_| "base64_decode: decoded data is not valid UTF-8".to_string())
+}
+
+/// Number of single-character edits that turn `a` into `b`.
+pub fn levenshtein(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let a = &expect_string(&args, 0, "levenshtein")?;
+    let b = &expect_string(&args, 1, "levenshtein")?;
+    Ok(Value::Number(strsim::levenshtein(a, b) as i64))
+}
+
+/// Levenshtein similarity of the two string arguments, from 0.0 (nothing
+/// in common) to 1.0 (equal), `1 - distance / longer length`.
+fn levenshtein_ratio(args: &[Value], fn_name: &str) -> Result<f64, String> {
+    let a = expect_string(args, 0, fn_name)?;
+    let b = expect_string(args, 1, fn_name)?;
+    Ok(strsim::normalized_levenshtein(&a, &b))
+}
+
+/// Levenshtein similarity scaled to 0 (nothing in common) ..= 100 (equal).
+pub fn similarity(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let ratio = levenshtein_ratio(&args, "similarity")?;
+    Ok(Value::Number((ratio * 100.0).round() as i64))
+}
+
+/// `std.str.similarity`: Levenshtein similarity as a float from 0.0
+/// to 1.0, where `std.similarity` gives a whole percentage.
+pub fn similarity_ratio(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    Ok(Value::Float(levenshtein_ratio(&args, "str.similarity")?))
+}
+
+/// 1 if `a` and `b` are at most `threshold` edits apart, else 0; the test
+/// the DB's `FuzzyMatch` filter applies to a column.
+pub fn fuzzy_match(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let a = &expect_string(&args, 0, "str.fuzzy_match")?;
+    let b = &expect_string(&args, 1, "str.fuzzy_match")?;
+    let threshold = expect_number(&args, 2, "str.fuzzy_match")?;
+    if threshold < 0 {
+        return Err(format!(
+            "str.fuzzy_match: threshold must not be negative, got {}",
+            threshold
+        ));
+    }
+    let distance = strsim::levenshtein(a, b);
+    Ok(Value::Number((distance as u64 <= threshold as u64) as i64))
+}
+
+/// Jaro-Winkler similarity from 0.0 to 1.0, which favours strings sharing
+/// a prefix; suited to short strings like names.
+pub fn jaro_winkler(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let a = &expect_string(&args, 0, "str.jaro_winkler")?;
+    let b = &expect_string(&args, 1, "str.jaro_winkler")?;
+    Ok(Value::Float(strsim::jaro_winkler(a, b)))
+}
+
+/// The element of `candidates` closest to `target` by Levenshtein distance,
+/// the earliest one on ties, or void if there are none.
+pub fn closest(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let target = &expect_string(&args, 0, "closest")?;
+    let candidates = expect_array(&args, 1, "closest")?;
+
+    let mut best: Option<(usize, &str)> = None;
+    for (index, candidate) in candidates.iter().enumerate() {
+        let Value::String(candidate) = candidate else {
+            return Err(format!(
+                "closest expects an array of strings, element {} is a {}",
+                index,
+                candidate.type_name()
+            ));
+        };
+        let distance = strsim::levenshtein(target, candidate);
+        if best.is_none_or(|(best_distance, _)| distance < best_distance) {
+            best = Some((distance, candidate));
+        }
+    }
+    Ok(best.map_or(Value::Void, |(_, candidate)| {
+        Value::String(candidate.to_string())
+    }))
+}
+
+/// Where `pad` puts the text in its field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+/// Pads `text` with `fill` to `width` characters; text already that wide is
+/// returned unchanged, never cut. The extra character of an odd centered
+/// padding goes on the right. Widths above `MAX_BUILD_SIZE` are an error and
+/// the result is reserved with the memory limit before it is built.
+pub(crate) fn pad(
+    interpreter: &mut Interpreter,
+    text: &str,
+    width: usize,
+    fill: char,
+    align: Align,
+    fn_name: &str,
+) -> Result<String, String> {
+    if width > MAX_BUILD_SIZE {
+        return Err(format!(
+            "{}: width {} is more than the limit of {}",
+            fn_name, width, MAX_BUILD_SIZE
+        ));
+    }
+    let len = text.chars().count();
+    if len >= width {
+        return Ok(text.to_string());
+    }
+    let (left, right) = match align {
+        Align::Left => (0, width - len),
+        Align::Right => (width - len, 0),
+        Align::Center => ((width - len) / 2, width - len - (width - len) / 2),
+    };
+    let size = text.len() + (left + right) * fill.len_utf8();
+    interpreter.reserve_memory(size)?;
+    let mut padded = String::with_capacity(size);
+    padded.extend(std::iter::repeat_n(fill, left));
+    padded.push_str(text);
+    padded.extend(std::iter::repeat_n(fill, right));
+    Ok(padded)
+}
+
+/// The one-character `pad_char` argument at `index`.
+pub(crate) fn pad_char_arg(args: &[Value], index: usize, fn_name: &str) -> Result<char, String> {
+    let pad_char = expect_string(args, index, fn_name)?;
+    let mut chars = pad_char.chars();
+    match (chars.next(), chars.next()) {
+        (Some(fill), None) => Ok(fill),
+        _ => Err(format!(
+            "{}: pad_char must be exactly one character, got \"{}\"",
+            fn_name, pad_char
+        )),
+    }
+}
+
+fn center_with(
+    interpreter: &mut Interpreter,
+    string: &str,
+    width: i64,
+    fill: char,
+    name: &str,
+) -> Result<Value, String> {
+    if width < 1 {
+        return Err(format!("{}: width must be at least 1, got {}", name, width));
+    }
+    let width = usize::try_from(width).unwrap_or(usize::MAX);
+    pad(interpreter, string, width, fill, Align::Center, name).map(Value::String)
+}
+
+/// Centers `string` in a field of `width` characters filled with `pad_char`.
+pub fn pad_center(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let string = expect_string(&args, 0, "str.pad_center")?;
+    let width = expect_number(&args, 1, "str.pad_center")?;
+    let fill = pad_char_arg(&args, 2, "str.pad_center")?;
+    center_with(interpreter, &string, width, fill, "str.pad_center")
+}
+
+/// `pad_center` with spaces.
+pub fn center(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let string = expect_string(&args, 0, "str.center")?;
+    let width = expect_number(&args, 1, "str.center")?;
+    center_with(interpreter, &string, width, ' ', "str.center")
+}
+
+fn check(args: &[Value], name: &str, predicate: impl Fn(&str) -> bool) -> Result<Value, String> {
+    let string = &expect_string(args, 0, name)?;
+    Ok(Value::Number(predicate(string) as i64))
+}
+
+/// 1 if the string has no characters, otherwise 0.
+pub fn is_empty(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    check(&args, "str.is_empty", str::is_empty)
+}
+
+/// 1 if the string is empty or only whitespace, otherwise 0.
+pub fn is_blank(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    check(&args, "str.is_blank", |s| s.trim().is_empty())
+}
+
+/// 1 if the string parses as a number (decimals and exponents included).
+pub fn is_numeric(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    check(&args, "str.is_numeric", |s| s.parse::<f64>().is_ok())
+}
+
+/// 1 if every character is alphabetic; an empty string counts as alphabetic.
+pub fn is_alpha(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    check(&args, "str.is_alpha", |s| {
+        s.chars().all(char::is_alphabetic)
+    })
+}
+
+/// 1 if every character is alphabetic or numeric.
+pub fn is_alphanumeric(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    check(&args, "str.is_alphanumeric", |s| {
+        s.chars().all(char::is_alphanumeric)
+    })
+}
+
+/// Number of characters `left`/`right` keep from a string of `len`
+/// characters: `n` clamped to the length, or for negative `n` all but `-n`.
+fn kept_chars(len: usize, n: i64) -> usize {
+    let magnitude = usize::try_from(n.unsigned_abs()).unwrap_or(usize::MAX);
+    if n >= 0 {
+        magnitude.min(len)
+    } else {
+        len.saturating_sub(magnitude)
+    }
+}
+
+/// The first `n` characters of the string. A negative `n` drops the last
+/// `-n` characters instead, like Python's `s[:-n]`.
+pub fn left(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let string = &expect_string(&args, 0, "str.left")?;
+    let n = expect_number(&args, 1, "str.left")?;
+    let keep = kept_chars(string.chars().count(), n);
+    Ok(Value::String(string.chars().take(keep).collect()))
+}
+
+/// The last `n` characters of the string. A negative `n` drops the first
+/// `-n` characters instead.
+pub fn right(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let string = &expect_string(&args, 0, "str.right")?;
+    let n = expect_number(&args, 1, "str.right")?;
+    let len = string.chars().count();
+    let keep = kept_chars(len, n);
+    Ok(Value::String(string.chars().skip(len - keep).collect()))
+}
+
+/// String, limit and ellipsis (default "…") of a `truncate` call.
+fn truncate_args(args: &[Value], fn_name: &str) -> Result<(Vec<char>, usize, Vec<char>), String> {
+    let string = &expect_string(args, 0, fn_name)?;
+    let max_len = expect_number(args, 1, fn_name)?;
+    let max_len = usize::try_from(max_len)
+        .map_err(|_| format!("{}: max_len must not be negative, got {}", fn_name, max_len))?;
+    let ellipsis = match args.get(2) {
+        Some(_) => &expect_string(args, 2, fn_name)?,
+        None => "…",
+    };
+    Ok((
+        string.chars().collect(),
+        max_len,
+        ellipsis.chars().collect(),
+    ))
+}
+
+/// Characters of the string to keep so the result, ellipsis included, fits
+/// in `max_len`. `None` if it fits already.
+fn truncated_keep(len: usize, max_len: usize, ellipsis: &mut Vec<char>) -> Option<usize> {
+    if len <= max_len {
+        return None;
+    }
+    // An ellipsis longer than the limit is cut down too
+    ellipsis.truncate(max_len);
+    Some(max_len - ellipsis.len())
+}
+
+/// The string cut to at most `max_len` characters, ending in `ellipsis`
+/// (default "…") when anything was cut.
+pub fn truncate(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let (chars, max_len, mut ellipsis) = truncate_args(&args, "str.truncate")?;
+    Ok(Value::String(
+        match truncated_keep(chars.len(), max_len, &mut ellipsis) {
+            Some(keep) => chars[..keep].iter().chain(&ellipsis).collect(),
+            None => chars.into_iter().collect(),
+        },
+    ))
+}
+
+/// Like `truncate`, but cuts from the start and begins with the ellipsis,
+/// for paths and other strings whose end matters most.
+pub fn truncate_start(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let (chars, max_len, mut ellipsis) = truncate_args(&args, "str.truncate_start")?;
+    Ok(Value::String(
+        match truncated_keep(chars.len(), max_len, &mut ellipsis) {
+            Some(keep) => ellipsis
+                .iter()
+                .chain(&chars[chars.len() - keep..])
+                .collect(),
+            None => chars.into_iter().collect(),
+        },
+    ))
+}
+
+/// The string with the characters from `start` up to (excluding) `end`
+/// replaced by `char` (default "X"). Negative positions count from the
+/// end, so `mask(card, 0, -4)` leaves only the last four characters.
+pub fn mask(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let string = &expect_string(&args, 0, "str.mask")?;
+    let start = expect_number(&args, 1, "str.mask")?;
+    let end = expect_number(&args, 2, "str.mask")?;
+    let mask_char = match args.get(3) {
+        Some(_) => {
+            let text = &expect_string(&args, 3, "str.mask")?;
+            let mut chars = text.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => {
+                    return Err(format!(
+                        "str.mask: the mask must be a single character, got {:?}",
+                        text
+                    ))
+                }
+            }
+        }
+        None => 'X',
+    };
+
+    // Positions resolve like `left`'s counts
+    let len = string.chars().count();
+    let masked = kept_chars(len, start)..kept_chars(len, end);
+    Ok(Value::String(
+        string
+            .chars()
+            .enumerate()
+            .map(|(i, c)| if masked.contains(&i) { mask_char } else { c })
+            .collect(),
+    ))
+}
+
+/// An email address with all of the local part but its first character
+/// replaced by `*`, e.g. `j*****@example.com`.
+pub fn mask_email(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let email = &expect_string(&args, 0, "str.mask_email")?;
+    let (local, domain) = email
+        .rsplit_once('@')
+        .ok_or_else(|| format!("str.mask_email: {:?} is not an email address", email))?;
+    let masked: String = local
+        .chars()
+        .enumerate()
+        .map(|(i, c)| if i == 0 { c } else { '*' })
+        .collect();
+    Ok(Value::String(format!("{}@{}", masked, domain)))
+}
+
+/// Lower-cased words of an identifier in any of the usual conventions.
+/// Words end at `_`, `-` and spaces, before an upper-case letter that
+/// follows a lower-case one or digit, and before the last capital of an
+/// acronym, so `parseHTTPResponse` is `parse`, `http`, `response`.
+fn case_words(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c == '_' || c == '-' || c.is_whitespace() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+        if c.is_uppercase() && !word.is_empty() {
+            let previous = chars[i - 1];
+            let next_is_lower = chars.get(i + 1).is_some_and(|next| next.is_lowercase());
+            if previous.is_lowercase()
+                || previous.is_ascii_digit()
+                || (previous.is_uppercase() && next_is_lower)
+            {
+                words.push(std::mem::take(&mut word));
+            }
+        }
+        word.extend(c.to_lowercase());
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// `helloWorld` from `hello_world`, `hello-world` or `HelloWorld`.
+pub fn camel_case(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let text = &expect_string(&args, 0, "str.camel_case")?;
+    let mut result = String::new();
+    for (i, word) in case_words(text).into_iter().enumerate() {
+        let mut chars = word.chars();
+        match chars.next() {
+            Some(first) if i > 0 => {
+                result.extend(first.to_uppercase());
+                result.push_str(chars.as_str());
+            }
+            _ => result.push_str(&word),
+        }
+    }
+    Ok(Value::String(result))
+}
+
+/// `hello_world` from `helloWorld`, `hello-world` or `HelloWorld`.
+pub fn snake_case(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let text = &expect_string(&args, 0, "str.snake_case")?;
+    Ok(Value::String(case_words(text).join("_")))
+}
+
+/// `hello-world` from `helloWorld`, `hello_world` or `HelloWorld`.
+pub fn kebab_case(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let text = &expect_string(&args, 0, "str.kebab_case")?;
+    Ok(Value::String(case_words(text).join("-")))
+}
+
+/// Word-wraps `text` into lines of at most `width` characters. Existing
+/// line breaks are kept, runs of other whitespace between words collapse to
+/// one space, and a word longer than `width` is split across lines.
+fn wrap_lines(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        let mut line_len = 0;
+        for word in paragraph.split_whitespace() {
+            let mut chars: Vec<char> = word.chars().collect();
+            if line_len > 0 && line_len + 1 + chars.len() <= width {
+                line.push(' ');
+                line.push_str(word);
+                line_len += 1 + chars.len();
+                continue;
+            }
+            if line_len > 0 {
+                lines.push(std::mem::take(&mut line));
+            }
+            while chars.len() > width {
+                lines.push(chars.drain(..width).collect());
+            }
+            line_len = chars.len();
+            line = chars.into_iter().collect();
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+fn wrap_args(args: &[Value], fn_name: &str) -> Result<Vec<String>, String> {
+    let text = &expect_string(args, 0, fn_name)?;
+    let width = expect_number(args, 1, fn_name)?;
+    if width < 1 {
+        return Err(format!(
+            "{}: width must be at least 1, got {}",
+            fn_name, width
+        ));
+    }
+    Ok(wrap_lines(text, width as usize))
+}
+
+/// Word-wraps the string to `width` characters per line and returns the
+/// lines as an array.
+pub fn wrap(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let lines = wrap_args(&args, "str.wrap")?;
+    Ok(Value::array(lines.into_iter().map(Value::String).collect()))
+}
+
+/// `wrap`, with the lines joined by newlines.
+pub fn wrap_join(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let lines = wrap_args(&args, "str.wrap_join")?;
+    Ok(Value::String(lines.join("\n")))
+}
+
+fn ngram_size(args: &[Value], fn_name: &str) -> Result<usize, String> {
+    let n = expect_number(args, 1, fn_name)?;
+    if n < 1 {
+        return Err(format!("{}: n must be at least 1, got {}", fn_name, n));
+    }
+    Ok(usize::try_from(n).unwrap_or(usize::MAX))
+}
+
+/// Every run of `n` consecutive characters in the string, one character
+/// apart. Empty if the string is shorter than `n`.
+pub fn ngrams(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let string = &expect_string(&args, 0, "str.ngrams")?;
+    let n = ngram_size(&args, "str.ngrams")?;
+    let chars: Vec<char> = string.chars().collect();
+    let grams = chars
+        .windows(n)
+        .map(|gram| Value::String(gram.iter().collect()))
+        .collect();
+    Ok(Value::array(grams))
+}
+
+/// Every run of `n` consecutive words, split on whitespace, as arrays of
+/// strings.
+pub fn word_ngrams(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let string = &expect_string(&args, 0, "str.word_ngrams")?;
+    let n = ngram_size(&args, "str.word_ngrams")?;
+    let words: Vec<&str> = string.split_whitespace().collect();
+    let grams = words
+        .windows(n)
+        .map(|gram| Value::array(gram.iter().map(|w| Value::String(w.to_string())).collect()))
+        .collect();
+    Ok(Value::array(grams))
+}
+
+fn count(args: &[Value], name: &str, counter: impl Fn(&str) -> usize) -> Result<Value, String> {
+    let string = &expect_string(args, 0, name)?;
+    Ok(Value::Number(counter(string) as i64))
+}
+
+/// Number of whitespace-separated words.
+pub fn count_words(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    count(&args, "str.count_words", |s| s.split_whitespace().count())
+}
+
+/// Number of `\n` characters plus one, or 0 for an empty string. A
+/// trailing newline starts an (empty) last line.
+pub fn count_lines(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    count(&args, "str.count_lines", |s| {
+        if s.is_empty() {
+            0
+        } else {
+            s.matches('\n').count() + 1
+        }
+    })
+}
+
+/// Number of Unicode characters.
+pub fn count_chars(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    count(&args, "str.count_chars", |s| s.chars().count())
+}
+
+/// Length of the string in UTF-8 bytes.
+pub fn count_bytes(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    count(&args, "str.count_bytes", str::len)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -36,7 +615,7 @@ mod tests {
         );
         assert_eq!(
             result,
-            Ok(Value::Array(vec![
+            Ok(Value::array(vec![
                 Value::String("hello".to_string()),
                 Value::String("world".to_string())
             ]))
@@ -55,7 +634,7 @@ mod tests {
         );
         assert_eq!(
             result,
-            Ok(Value::Array(vec![
+            Ok(Value::array(vec![
                 Value::String("".to_string()),
                 Value::String("h".to_string()),
                 Value::String("i".to_string()),
@@ -63,4 +642,586 @@ mod tests {
             ]))
         );
     }
+
+    #[test]
+    fn test_escape_html() {
+        let mut interpreter = Interpreter::new();
+        let result = escape_html(
+            &mut interpreter,
+            vec![Value::String("<a href=\"x\">Tom & Jerry's</a>".to_string())],
+        );
+        assert_eq!(
+            result,
+            Ok(Value::String(
+                "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_unescape_html_roundtrip() {
+        let mut interpreter = Interpreter::new();
+        let original = "<p class=\"a\">1 < 2 && 'b'</p> &lt;".to_string();
+        let escaped = escape_html(&mut interpreter, vec![Value::String(original.clone())]).unwrap();
+        let result = unescape_html(&mut interpreter, vec![escaped]);
+        assert_eq!(result, Ok(Value::String(original)));
+    }
+
+    #[test]
+    fn test_escape_url() {
+        let mut interpreter = Interpreter::new();
+        let result = escape_url(
+            &mut interpreter,
+            vec![Value::String("a b&c=d/ü~".to_string())],
+        );
+        assert_eq!(
+            result,
+            Ok(Value::String("a%20b%26c%3Dd%2F%C3%BC~".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_escape_html_rejects_non_string() {
+        let mut interpreter = Interpreter::new();
+        let result = escape_html(&mut interpreter, vec![Value::Number(1)]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_base64_encode() {
+        let mut interpreter = Interpreter::new();
+        let cases = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("user:pass", "dXNlcjpwYXNz"),
+        ];
+        for (input, expected) in cases {
+            let result = base64_encode(&mut interpreter, vec![Value::String(input.to_string())]);
+            assert_eq!(result, Ok(Value::String(expected.to_string())));
+        }
+    }
+
+    #[test]
+    fn test_base64_decode() {
+        let mut interpreter = Interpreter::new();
+        let result = base64_decode(&mut interpreter, vec![Value::String("Zm8=".to_string())]);
+        assert_eq!(result, Ok(Value::String("fo".to_string())));
+        let result = base64_decode(&mut interpreter, vec![Value::String("w6k=".to_string())]);
+        assert_eq!(result, Ok(Value::String("é".to_string())));
+    }
+
+    #[test]
+    fn test_base64_decode_errors() {
+        let mut interpreter = Interpreter::new();
+        let invalid = base64_decode(&mut interpreter, vec![Value::String("Zm8".to_string())]);
+        assert!(invalid.unwrap_err().contains("invalid base64"));
+        // 0xFF is not valid UTF-8
+        let not_utf8 = base64_decode(&mut interpreter, vec![Value::String("/w==".to_string())]);
+        assert!(not_utf8.unwrap_err().contains("UTF-8"));
+    }
+
+    fn pad_center_of(string: &str, width: i64, pad: &str) -> Result<Value, String> {
+        let mut interpreter = Interpreter::new();
+        pad_center(
+            &mut interpreter,
+            vec![
+                Value::String(string.to_string()),
+                Value::Number(width),
+                Value::String(pad.to_string()),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_pad_center() {
+        assert_eq!(
+            pad_center_of("ab", 6, "*"),
+            Ok(Value::String("**ab**".to_string()))
+        );
+        // odd padding puts the extra character on the right
+        assert_eq!(
+            pad_center_of("ab", 5, "-"),
+            Ok(Value::String("-ab--".to_string()))
+        );
+        assert_eq!(
+            pad_center_of("é", 3, "·"),
+            Ok(Value::String("·é·".to_string()))
+        );
+        assert_eq!(
+            pad_center_of("abc", 2, "*"),
+            Ok(Value::String("abc".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_pad_center_errors() {
+        assert!(pad_center_of("ab", 6, "").is_err());
+        assert!(pad_center_of("ab", 6, "**").is_err());
+        assert!(pad_center_of("ab", 0, "*").is_err());
+        assert_eq!(
+            pad_center_of("ab", 1_000_000_000_000, "*"),
+            Err(
+                "str.pad_center: width 1000000000000 is more than the limit of 16777216"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_center() {
+        let mut interpreter = Interpreter::new();
+        let result = center(
+            &mut interpreter,
+            vec![Value::String("hi".to_string()), Value::Number(7)],
+        );
+        assert_eq!(result, Ok(Value::String("  hi   ".to_string())));
+    }
+
+    fn strs(values: &[&str]) -> Vec<Value> {
+        values
+            .iter()
+            .map(|s| Value::String(s.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_levenshtein() {
+        let mut interpreter = Interpreter::new();
+        let result = levenshtein(&mut interpreter, strs(&["mouse", "mouse"]));
+        assert_eq!(result, Ok(Value::Number(0)));
+        let result = levenshtein(&mut interpreter, strs(&["kitten", "sitting"]));
+        assert_eq!(result, Ok(Value::Number(3)));
+    }
+
+    #[test]
+    fn test_similarity() {
+        let mut interpreter = Interpreter::new();
+        // 3 edits over 7 characters: 1 - 3/7 = 0.571...
+        let result = similarity(&mut interpreter, strs(&["kitten", "sitting"]));
+        assert_eq!(result, Ok(Value::Number(57)));
+        let result = similarity(&mut interpreter, strs(&["same", "same"]));
+        assert_eq!(result, Ok(Value::Number(100)));
+    }
+
+    #[test]
+    fn test_similarity_ratio() {
+        let mut interpreter = Interpreter::new();
+        let result = similarity_ratio(&mut interpreter, strs(&["kitten", "sitting"]));
+        assert_eq!(result, Ok(Value::Float(1.0 - 3.0 / 7.0)));
+        let result = similarity_ratio(&mut interpreter, strs(&["same", "same"]));
+        assert_eq!(result, Ok(Value::Float(1.0)));
+        let result = similarity_ratio(&mut interpreter, strs(&["abc", "xyz"]));
+        assert_eq!(result, Ok(Value::Float(0.0)));
+        let result = similarity_ratio(&mut interpreter, strs(&["", ""]));
+        assert_eq!(result, Ok(Value::Float(1.0)));
+    }
+
+    #[test]
+    fn test_fuzzy_match_agrees_with_db_filter() {
+        let mut interpreter = Interpreter::new();
+        // The DB fuzzy search test finds "Buch" for "buche" within 2 edits
+        let mut within = |threshold: i64| {
+            let mut args = strs(&["Buch", "buche"]);
+            args.push(Value::Number(threshold));
+            fuzzy_match(&mut interpreter, args)
+        };
+        assert_eq!(within(2), Ok(Value::Number(1)));
+        assert_eq!(within(1), Ok(Value::Number(0)));
+        assert_eq!(
+            within(-1),
+            Err("str.fuzzy_match: threshold must not be negative, got -1".to_string())
+        );
+
+        let distance = levenshtein(&mut interpreter, strs(&["Buch", "buche"]));
+        assert_eq!(distance, Ok(Value::Number(2)));
+        let ratio = similarity_ratio(&mut interpreter, strs(&["Buch", "buche"]));
+        assert_eq!(ratio, Ok(Value::Float(1.0 - 2.0 / 5.0)));
+    }
+
+    #[test]
+    fn test_jaro_winkler() {
+        let mut interpreter = Interpreter::new();
+        let Ok(Value::Float(score)) = jaro_winkler(&mut interpreter, strs(&["martha", "marhta"]))
+        else {
+            panic!("jaro_winkler should return a float");
+        };
+        assert!((score - 0.9611).abs() < 1e-4, "{}", score);
+        let result = jaro_winkler(&mut interpreter, strs(&["mouse", "mouse"]));
+        assert_eq!(result, Ok(Value::Float(1.0)));
+        let err = jaro_winkler(
+            &mut interpreter,
+            vec![Value::Number(1), Value::String("a".to_string())],
+        );
+        assert_eq!(
+            err,
+            Err("str.jaro_winkler expects a string as argument 1, got number".to_string())
+        );
+    }
+
+    #[test]
+    fn test_closest() {
+        let mut interpreter = Interpreter::new();
+        let candidates = Value::array(strs(&["print", "sleep", "split_str", "len"]));
+        let result = closest(
+            &mut interpreter,
+            vec![Value::String("prnt".to_string()), candidates],
+        );
+        assert_eq!(result, Ok(Value::String("print".to_string())));
+
+        let result = closest(
+            &mut interpreter,
+            vec![Value::String("x".to_string()), Value::array(vec![])],
+        );
+        assert_eq!(result, Ok(Value::Void));
+    }
+
+    #[test]
+    fn test_similarity_errors_name_the_type() {
+        let mut interpreter = Interpreter::new();
+        let err = levenshtein(
+            &mut interpreter,
+            vec![Value::String("a".to_string()), Value::Number(1)],
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            "levenshtein expects a string as argument 2, got number"
+        );
+
+        let candidates = Value::array(vec![Value::String("a".to_string()), Value::Void]);
+        let err = closest(
+            &mut interpreter,
+            vec![Value::String("a".to_string()), candidates],
+        )
+        .unwrap_err();
+        assert!(err.contains("element 1 is a void"));
+    }
+
+    #[test]
+    fn test_predicates() {
+        type Native = fn(&mut Interpreter, Vec<Value>) -> Result<Value, String>;
+        let cases: [(Native, &str, i64); 12] = [
+            (is_empty, "", 1),
+            (is_empty, " ", 0),
+            (is_blank, " \t\n", 1),
+            (is_blank, " a ", 0),
+            (is_numeric, "-1.5e3", 1),
+            (is_numeric, "12px", 0),
+            (is_numeric, "", 0),
+            (is_alpha, "Maus", 1),
+            (is_alpha, "Maus1", 0),
+            (is_alphanumeric, "Maus1", 1),
+            (is_alphanumeric, "Maus 1", 0),
+            (is_alpha, "ünïcode", 1),
+        ];
+        let mut interpreter = Interpreter::new();
+        for (predicate, input, expected) in cases {
+            let result = predicate(&mut interpreter, strs(&[input]));
+            assert_eq!(result, Ok(Value::Number(expected)), "input {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_predicates_reject_non_strings() {
+        let mut interpreter = Interpreter::new();
+        let err = is_blank(&mut interpreter, vec![Value::Void]).unwrap_err();
+        assert_eq!(err, "str.is_blank expects a string as argument 1, got void");
+    }
+
+    fn take(
+        f: fn(&mut Interpreter, Vec<Value>) -> Result<Value, String>,
+        s: &str,
+        n: i64,
+    ) -> String {
+        let mut interpreter = Interpreter::new();
+        match f(
+            &mut interpreter,
+            vec![Value::String(s.to_string()), Value::Number(n)],
+        ) {
+            Ok(Value::String(result)) => result,
+            other => panic!("expected a string, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_left() {
+        assert_eq!(take(left, "mouse", 3), "mou");
+        assert_eq!(take(left, "mouse", 10), "mouse");
+        assert_eq!(take(left, "mouse", 0), "");
+        assert_eq!(take(left, "mouse", -2), "mou");
+        assert_eq!(take(left, "mouse", -9), "");
+        assert_eq!(take(left, "grüße", 4), "grüß");
+        assert_eq!(take(left, "", 2), "");
+    }
+
+    #[test]
+    fn test_right() {
+        assert_eq!(take(right, "mouse", 3), "use");
+        assert_eq!(take(right, "mouse", 10), "mouse");
+        assert_eq!(take(right, "mouse", 0), "");
+        assert_eq!(take(right, "mouse", -2), "use");
+        assert_eq!(take(right, "mouse", i64::MIN), "");
+        assert_eq!(take(right, "日本語です", 2), "です");
+    }
+
+    fn truncated(
+        f: fn(&mut Interpreter, Vec<Value>) -> Result<Value, String>,
+        s: &str,
+        max_len: i64,
+        ellipsis: Option<&str>,
+    ) -> Result<Value, String> {
+        let mut args = vec![Value::String(s.to_string()), Value::Number(max_len)];
+        args.extend(ellipsis.map(|e| Value::String(e.to_string())));
+        f(&mut Interpreter::new(), args)
+    }
+
+    fn text(s: &str) -> Result<Value, String> {
+        Ok(Value::String(s.to_string()))
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(
+            truncated(truncate, "hello world", 8, None),
+            text("hello w…")
+        );
+        assert_eq!(
+            truncated(truncate, "hello world", 8, Some("...")),
+            text("hello...")
+        );
+        assert_eq!(truncated(truncate, "hello", 5, None), text("hello"));
+        assert_eq!(truncated(truncate, "hi", 20, Some("...")), text("hi"));
+        assert_eq!(truncated(truncate, "", 0, None), text(""));
+        // Counts characters, not bytes
+        assert_eq!(
+            truncated(truncate, "grüße aus köln", 6, None),
+            text("grüße…")
+        );
+        assert_eq!(
+            truncated(truncate, "日本語です", 3, Some("…")),
+            text("日本…")
+        );
+        // The ellipsis never pushes the result past the limit
+        assert_eq!(truncated(truncate, "hello", 2, Some("...")), text(".."));
+        assert_eq!(truncated(truncate, "hello", 0, None), text(""));
+    }
+
+    #[test]
+    fn test_truncate_start() {
+        assert_eq!(
+            truncated(truncate_start, "/home/user/notes.txt", 12, None),
+            text("…r/notes.txt")
+        );
+        assert_eq!(
+            truncated(truncate_start, "/home/user/notes.txt", 12, Some("...")),
+            text("...notes.txt")
+        );
+        assert_eq!(truncated(truncate_start, "short", 9, None), text("short"));
+        assert_eq!(
+            truncated(truncate_start, "日本語です", 3, None),
+            text("…です")
+        );
+    }
+
+    #[test]
+    fn test_truncate_rejects_negative_length() {
+        assert_eq!(
+            truncated(truncate, "hello", -1, None),
+            Err("str.truncate: max_len must not be negative, got -1".to_string())
+        );
+    }
+
+    fn convert(f: fn(&mut Interpreter, Vec<Value>) -> Result<Value, String>, s: &str) -> String {
+        match f(&mut Interpreter::new(), strs(&[s])) {
+            Ok(Value::String(result)) => result,
+            other => panic!("expected a string, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_case_conversions() {
+        assert_eq!(convert(camel_case, "hello_world"), "helloWorld");
+        assert_eq!(convert(camel_case, "helloWorld"), "helloWorld");
+        assert_eq!(convert(snake_case, "helloWorld"), "hello_world");
+        assert_eq!(convert(snake_case, "hello_world"), "hello_world");
+        assert_eq!(convert(kebab_case, "helloWorld"), "hello-world");
+        assert_eq!(convert(kebab_case, "hello_world"), "hello-world");
+
+        assert_eq!(convert(snake_case, "already_snake"), "already_snake");
+        assert_eq!(convert(camel_case, "kebab-case-input"), "kebabCaseInput");
+        assert_eq!(convert(camel_case, "HelloWorld"), "helloWorld");
+        assert_eq!(convert(snake_case, "__private--name__"), "private_name");
+        for f in [camel_case, snake_case, kebab_case] {
+            assert_eq!(convert(f, ""), "");
+        }
+    }
+
+    #[test]
+    fn test_case_conversions_split_acronyms_and_digits() {
+        assert_eq!(
+            convert(snake_case, "parseHTTPResponse"),
+            "parse_http_response"
+        );
+        assert_eq!(convert(kebab_case, "userID"), "user-id");
+        assert_eq!(convert(snake_case, "utf8String"), "utf8_string");
+        assert_eq!(convert(camel_case, "straße_größe"), "straßeGröße");
+    }
+
+    #[test]
+    fn test_wrap_breaks_at_word_boundaries() {
+        assert_eq!(
+            wrap_lines("the quick brown fox jumps over the lazy dog", 10),
+            ["the quick", "brown fox", "jumps over", "the lazy", "dog"]
+        );
+        // Existing breaks are kept, surplus spaces collapse
+        assert_eq!(wrap_lines("a  b\n\nc", 5), ["a b", "", "c"]);
+        assert_eq!(wrap_lines("", 5), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_wrap_splits_words_longer_than_width() {
+        assert_eq!(
+            wrap_lines("see supercalifragilistic now", 8),
+            ["see", "supercal", "ifragili", "stic now"]
+        );
+        assert_eq!(wrap_lines("grüßegrüße", 5), ["grüße", "grüße"]);
+    }
+
+    #[test]
+    fn test_wrap_natives() {
+        let mut interpreter = Interpreter::new();
+        let args = vec![Value::String("one two three".to_string()), Value::Number(7)];
+        assert_eq!(
+            wrap(&mut interpreter, args.clone()),
+            Ok(Value::array(strs(&["one two", "three"])))
+        );
+        assert_eq!(
+            wrap_join(&mut interpreter, args),
+            Ok(Value::String("one two\nthree".to_string()))
+        );
+
+        let err = wrap(
+            &mut interpreter,
+            vec![Value::String("a".to_string()), Value::Number(0)],
+        );
+        assert_eq!(
+            err,
+            Err("str.wrap: width must be at least 1, got 0".to_string())
+        );
+    }
+
+    #[test]
+    fn test_ngrams() {
+        let mut interpreter = Interpreter::new();
+        let grams = |interpreter: &mut Interpreter, s: &str, n: i64| {
+            ngrams(
+                interpreter,
+                vec![Value::String(s.to_string()), Value::Number(n)],
+            )
+        };
+        assert_eq!(
+            grams(&mut interpreter, "hello", 2),
+            Ok(Value::array(strs(&["he", "el", "ll", "lo"])))
+        );
+        assert_eq!(
+            grams(&mut interpreter, "grüß", 3),
+            Ok(Value::array(strs(&["grü", "rüß"])))
+        );
+        assert_eq!(
+            grams(&mut interpreter, "hi", 2),
+            Ok(Value::array(strs(&["hi"])))
+        );
+        assert_eq!(grams(&mut interpreter, "hi", 3), Ok(Value::array(vec![])));
+        assert_eq!(
+            grams(&mut interpreter, "hi", 0),
+            Err("str.ngrams: n must be at least 1, got 0".to_string())
+        );
+    }
+
+    #[test]
+    fn test_word_ngrams() {
+        let mut interpreter = Interpreter::new();
+        let result = word_ngrams(
+            &mut interpreter,
+            vec![
+                Value::String("the  quick brown\nfox".to_string()),
+                Value::Number(2),
+            ],
+        );
+        assert_eq!(
+            result,
+            Ok(Value::array(vec![
+                Value::array(strs(&["the", "quick"])),
+                Value::array(strs(&["quick", "brown"])),
+                Value::array(strs(&["brown", "fox"])),
+            ]))
+        );
+        let result = word_ngrams(
+            &mut interpreter,
+            vec![Value::String("one two".to_string()), Value::Number(3)],
+        );
+        assert_eq!(result, Ok(Value::array(vec![])));
+    }
+
+    #[test]
+    fn test_text_statistics() {
+        let mut interpreter = Interpreter::new();
+        let text = || vec![Value::String("grüße aus\n  Köln \n\nbis bald".to_string())];
+        assert_eq!(count_words(&mut interpreter, text()), Ok(Value::Number(5)));
+        assert_eq!(count_lines(&mut interpreter, text()), Ok(Value::Number(4)));
+        assert_eq!(count_chars(&mut interpreter, text()), Ok(Value::Number(27)));
+        assert_eq!(count_bytes(&mut interpreter, text()), Ok(Value::Number(30)));
+
+        let empty = || vec![Value::String(String::new())];
+        assert_eq!(count_words(&mut interpreter, empty()), Ok(Value::Number(0)));
+        assert_eq!(count_lines(&mut interpreter, empty()), Ok(Value::Number(0)));
+        assert_eq!(count_chars(&mut interpreter, empty()), Ok(Value::Number(0)));
+        assert_eq!(count_bytes(&mut interpreter, empty()), Ok(Value::Number(0)));
+    }
+
+    fn masked(args: Vec<Value>) -> Result<Value, String> {
+        mask(&mut Interpreter::new(), args)
+    }
+
+    #[test]
+    fn test_mask() {
+        let s = |s: &str| Value::String(s.to_string());
+        let n = Value::Number;
+        assert_eq!(
+            masked(vec![s("1234567890"), n(2), n(8)]),
+            Ok(s("12XXXXXX90"))
+        );
+        assert_eq!(
+            masked(vec![s("1234567890"), n(0), n(-4)]),
+            Ok(s("XXXXXX7890"))
+        );
+        assert_eq!(
+            masked(vec![s("geheimnis"), n(-3), n(100), s("•")]),
+            Ok(s("geheim•••"))
+        );
+        assert_eq!(masked(vec![s("äöüß"), n(1), n(3)]), Ok(s("äXXß")));
+        assert_eq!(masked(vec![s("abc"), n(2), n(1)]), Ok(s("abc")));
+        assert_eq!(
+            masked(vec![s("abc"), n(0), n(1), s("**")]),
+            Err("str.mask: the mask must be a single character, got \"**\"".to_string())
+        );
+    }
+
+    #[test]
+    fn test_mask_email() {
+        let mut interpreter = Interpreter::new();
+        let mut masked =
+            |email: &str| mask_email(&mut interpreter, vec![Value::String(email.to_string())]);
+        assert_eq!(
+            masked("jsmith@example.com"),
+            Ok(Value::String("j*****@example.com".to_string()))
+        );
+        assert_eq!(
+            masked("j@example.com"),
+            Ok(Value::String("j@example.com".to_string()))
+        );
+        assert!(masked("not an address").is_err());
+    }
 }
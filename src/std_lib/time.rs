@@ -0,0 +1,233 @@
+use crate::interpreter::{Interpreter, Value};
+use crate::iso8601;
+use crate::std_lib::{expect_number, expect_string};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat};
+use std::fmt::Write;
+
+const MS_PER_MINUTE: i64 = 60 * 1000;
+const MS_PER_HOUR: i64 = 60 * MS_PER_MINUTE;
+const MS_PER_DAY: i64 = 24 * MS_PER_HOUR;
+
+/// Parses `string` and returns the Unix timestamp in milliseconds (UTC).
+/// `string` is read as ISO 8601 unless a chrono `format` is given; date-only
+/// formats resolve to midnight.
+pub fn parse(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let string = expect_string(&args, 0, "time.parse")?;
+    if args.len() < 2 {
+        let datetime = iso8601::parse(&string).map_err(|e| format!("time.parse {}", e))?;
+        return Ok(Value::Number(datetime.timestamp_millis()));
+    }
+    let format = expect_string(&args, 1, "time.parse")?;
+
+    let datetime = match NaiveDateTime::parse_from_str(&string, &format) {
+        Ok(datetime) => datetime,
+        Err(datetime_err) => NaiveDate::parse_from_str(&string, &format)
+            .map(|date| date.and_hms_opt(0, 0, 0).unwrap())
+            .map_err(|_| {
+                format!(
+                    "time.parse could not parse '{}' with format '{}': {}",
+                    string, format, datetime_err
+                )
+            })?,
+    };
+
+    Ok(Value::Number(datetime.and_utc().timestamp_millis()))
+}
+
+/// Formats a millisecond timestamp in UTC, as ISO 8601 (the form `parse`
+/// reads back) or with a chrono `format`.
+pub fn format(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let ts = expect_number(&args, 0, "time.format")?;
+    let datetime = DateTime::from_timestamp_millis(ts)
+        .ok_or_else(|| format!("time.format: timestamp {} is out of range", ts))?;
+    if args.len() < 2 {
+        let iso = datetime.to_rfc3339_opts(SecondsFormat::AutoSi, true);
+        return Ok(Value::String(iso));
+    }
+
+    let format = expect_string(&args, 1, "time.format")?;
+    let mut formatted = String::new();
+    write!(formatted, "{}", datetime.format(&format))
+        .map_err(|_| format!("time.format: invalid format '{}'", format))?;
+    Ok(Value::String(formatted))
+}
+
+/// Returns `ts1 - ts2` in milliseconds.
+pub fn diff(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    let ts1 = expect_number(&args, 0, "time.diff")?;
+    let ts2 = expect_number(&args, 1, "time.diff")?;
+    ts1.checked_sub(ts2)
+        .map(Value::Number)
+        .ok_or_else(|| "time.diff overflowed".to_string())
+}
+
+fn add_duration(args: &[Value], fn_name: &str, unit_ms: i64) -> Result<Value, String> {
+    let ts = expect_number(args, 0, fn_name)?;
+    let amount = expect_number(args, 1, fn_name)?;
+    amount
+        .checked_mul(unit_ms)
+        .and_then(|offset| ts.checked_add(offset))
+        .map(Value::Number)
+        .ok_or_else(|| format!("{} overflowed", fn_name))
+}
+
+fn duration(args: &[Value], fn_name: &str, unit_ms: i64) -> Result<Value, String> {
+    let amount = expect_number(args, 0, fn_name)?;
+    amount
+        .checked_mul(unit_ms)
+        .map(Value::Number)
+        .ok_or_else(|| format!("{} overflowed", fn_name))
+}
+
+/// `n` days in milliseconds, for adding to and comparing with timestamps.
+pub fn days(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    duration(&args, "time.days", MS_PER_DAY)
+}
+
+/// `n` hours in milliseconds.
+pub fn hours(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    duration(&args, "time.hours", MS_PER_HOUR)
+}
+
+pub fn add_days(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    add_duration(&args, "time.add_days", MS_PER_DAY)
+}
+
+pub fn add_hours(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    add_duration(&args, "time.add_hours", MS_PER_HOUR)
+}
+
+pub fn add_minutes(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+    add_duration(&args, "time.add_minutes", MS_PER_MINUTE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn s(value: &str) -> Value {
+        Value::String(value.to_string())
+    }
+
+    #[test]
+    fn test_parse_datetime() {
+        let mut interpreter = Interpreter::new();
+        let result = parse(
+            &mut interpreter,
+            vec![s("1970-01-02 01:00:00"), s("%Y-%m-%d %H:%M:%S")],
+        );
+        assert_eq!(result, Ok(Value::Number(90_000_000)));
+    }
+
+    #[test]
+    fn test_parse_date_only() {
+        let mut interpreter = Interpreter::new();
+        let result = parse(&mut interpreter, vec![s("1970-01-03"), s("%Y-%m-%d")]);
+        assert_eq!(result, Ok(Value::Number(172_800_000)));
+    }
+
+    #[test]
+    fn test_parse_invalid_input() {
+        let mut interpreter = Interpreter::new();
+        let result = parse(&mut interpreter, vec![s("not a date"), s("%Y-%m-%d")]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_current_timestamp() {
+        let mut interpreter = Interpreter::new();
+        let result = parse(&mut interpreter, vec![s("2024-01-01"), s("%Y-%m-%d")]);
+        assert_eq!(result, Ok(Value::Number(1_704_067_200_000)));
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut interpreter = Interpreter::new();
+        let result = diff(
+            &mut interpreter,
+            vec![Value::Number(5_000), Value::Number(12_000)],
+        );
+        assert_eq!(result, Ok(Value::Number(-7_000)));
+    }
+
+    #[test]
+    fn test_add_units() {
+        let mut interpreter = Interpreter::new();
+        let day = add_days(&mut interpreter, vec![Value::Number(0), Value::Number(2)]);
+        assert_eq!(day, Ok(Value::Number(172_800_000)));
+        let hour = add_hours(&mut interpreter, vec![Value::Number(1), Value::Number(-1)]);
+        assert_eq!(hour, Ok(Value::Number(1 - 3_600_000)));
+        let minute = add_minutes(&mut interpreter, vec![Value::Number(0), Value::Number(3)]);
+        assert_eq!(minute, Ok(Value::Number(180_000)));
+    }
+
+    #[test]
+    fn test_add_overflow() {
+        let mut interpreter = Interpreter::new();
+        let result = add_days(
+            &mut interpreter,
+            vec![Value::Number(i64::MAX), Value::Number(1)],
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_iso8601() {
+        let mut interpreter = Interpreter::new();
+        let expected = Ok(Value::Number(1_672_531_200_000));
+        for text in [
+            "2023-01-01",
+            "2023-01-01T00:00:00",
+            "2023-01-01 00:00:00",
+            "2023-01-01T00:00:00Z",
+            "2023-01-01T01:00:00+01:00",
+        ] {
+            assert_eq!(parse(&mut interpreter, vec![s(text)]), expected, "{}", text);
+        }
+
+        let err = parse(&mut interpreter, vec![s("01/02/2023")]).unwrap_err();
+        assert!(err.contains("'01/02/2023'"));
+        assert!(err.contains("expected an ISO 8601 date"));
+    }
+
+    #[test]
+    fn test_format_parse_roundtrip() {
+        let mut interpreter = Interpreter::new();
+        for ts in [0, 1_672_531_200_000, 1_672_531_200_250, -86_400_000] {
+            let text = format(&mut interpreter, vec![Value::Number(ts)]).unwrap();
+            assert_eq!(parse(&mut interpreter, vec![text]), Ok(Value::Number(ts)));
+        }
+        assert_eq!(
+            format(&mut interpreter, vec![Value::Number(1_672_531_200_000)]),
+            Ok(s("2023-01-01T00:00:00Z"))
+        );
+        assert_eq!(
+            format(&mut interpreter, vec![Value::Number(0), s("%d.%m.%Y")]),
+            Ok(s("01.01.1970"))
+        );
+        assert!(format(&mut interpreter, vec![Value::Number(0), s("%Q")]).is_err());
+    }
+
+    #[test]
+    fn test_durations() {
+        let mut interpreter = Interpreter::new();
+        let start = parse(&mut interpreter, vec![s("2023-01-01")]).unwrap();
+        let end = parse(&mut interpreter, vec![s("2023-01-31T06:00:00Z")]).unwrap();
+        let thirty_days = days(&mut interpreter, vec![Value::Number(30)]).unwrap();
+        let six_hours = hours(&mut interpreter, vec![Value::Number(6)]).unwrap();
+
+        let (Value::Number(start), Value::Number(end)) = (start, end) else {
+            unreachable!()
+        };
+        assert_eq!(thirty_days, Value::Number(30 * 86_400 * 1000));
+        assert_eq!(six_hours, Value::Number(6 * 3_600 * 1000));
+        assert_eq!(
+            diff(
+                &mut interpreter,
+                vec![Value::Number(end), Value::Number(start)]
+            ),
+            Ok(Value::Number(30 * 86_400_000 + 6 * 3_600_000))
+        );
+        assert!(days(&mut interpreter, vec![Value::Number(i64::MAX)]).is_err());
+    }
+}
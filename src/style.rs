@@ -0,0 +1,76 @@
+//! Terminal styling for diagnostics. Everything that colors output goes
+//! through `Style` so it can be switched off in one place.
+
+use std::io::IsTerminal;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn label(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+const RED: &str = "1;31";
+const YELLOW: &str = "1;33";
+const DIM: &str = "2";
+const BOLD: &str = "1";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Style {
+    color: bool,
+}
+
+impl Style {
+    pub fn plain() -> Self {
+        Style { color: false }
+    }
+
+    pub fn colored() -> Self {
+        Style { color: true }
+    }
+
+    /// Colors output unless `no_color` is set, the `NO_COLOR` environment
+    /// variable is set to a non-empty value, or stderr is not a terminal.
+    pub fn for_stderr(no_color: bool) -> Self {
+        let disabled = no_color
+            || std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty())
+            || !std::io::stderr().is_terminal();
+        Style { color: !disabled }
+    }
+
+    pub fn is_colored(&self) -> bool {
+        self.color
+    }
+
+    /// Text in the color of `severity`, used for labels and carets.
+    pub fn severity(&self, severity: Severity, text: &str) -> String {
+        match severity {
+            Severity::Error => self.paint(RED, text),
+            Severity::Warning => self.paint(YELLOW, text),
+        }
+    }
+
+    pub fn dim(&self, text: &str) -> String {
+        self.paint(DIM, text)
+    }
+
+    pub fn bold(&self, text: &str) -> String {
+        self.paint(BOLD, text)
+    }
+
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.color {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_string()
+        }
+    }
+}
@@ -1,11 +1,12 @@
 #[cfg(test)]
 mod tests {
-    use crate::interpreter::{Interpreter, Value};
+    use crate::interpreter::logging::LogLevel;
+    use crate::interpreter::{Interpreter, Object, TraceEvent, Value};
     use crate::lexer::tokenize;
     use crate::parser::parse;
 
     fn run_code(code: &str) -> Result<Interpreter, String> {
-        let tokens = tokenize(code.to_string());
+        let tokens = tokenize(code.to_string()).map_err(|e| format!("Lex error: {}", e))?;
         let program = parse(&tokens).map_err(|e| format!("Parse error: {:?}", e))?;
         let mut interpreter = Interpreter::new();
         interpreter.interpret(&program)?;
@@ -115,4 +116,2482 @@ mod tests {
         let result = run_and_get_var(code, "result").unwrap();
         assert_eq!(result, Value::Number(26)); // (2 * 3) + (4 * 5) = 6 + 20 = 26
     }
+
+    // ===== Standard Library Tests =====
+
+    #[test]
+    fn test_std_str_escape_html() {
+        let code = r#"let html = std.str.escape_html("<b>");"#;
+        let result = run_and_get_var(code, "html").unwrap();
+        assert_eq!(result, Value::String("&lt;b&gt;".to_string()));
+    }
+
+    #[test]
+    fn test_std_str_similarity() {
+        let code = r#"let ratio = std.str.similarity("kitten", "sitting");
+                      let percent = std.similarity("kitten", "sitting");"#;
+        let interpreter = run_code(code).unwrap();
+        assert_eq!(
+            interpreter.env.get_variable("ratio"),
+            Some(&Value::Float(1.0 - 3.0 / 7.0))
+        );
+        assert_eq!(
+            interpreter.env.get_variable("percent"),
+            Some(&Value::Number(57))
+        );
+    }
+
+    // ===== Tracing Tests =====
+
+    #[test]
+    fn test_tracer_records_call_inside_loop() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let code =
+            "fn inc(n) {\n    return n + 1;\n}\nlet i = 0;\nwhile i < 2 {\n    i = inc(i);\n}\n";
+        let tokens = tokenize(code.to_string()).unwrap();
+        let program = parse(&tokens).unwrap();
+
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let sink = events.clone();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_tracer(Box::new(move |event| {
+            let line = match event {
+                TraceEvent::StatementEntered { span, kind } => format!("{} {}", span.line, kind),
+                TraceEvent::FunctionEntered { name, args } => format!("call {}({})", name, args[0]),
+                TraceEvent::FunctionExited { name, result } => {
+                    format!("exit {} = {}", name, result.unwrap())
+                }
+                TraceEvent::VariableAssigned { name, value } => format!("{} = {}", name, value),
+            };
+            sink.borrow_mut().push(line);
+        }));
+        interpreter.interpret(&program).unwrap();
+
+        assert_eq!(
+            *events.borrow(),
+            vec![
+                "1 fn",
+                "4 let",
+                "i = 0",
+                "5 while",
+                "6 assign",
+                "call inc(0)",
+                "2 return",
+                "exit inc = 1",
+                "i = 1",
+                "6 assign",
+                "call inc(1)",
+                "2 return",
+                "exit inc = 2",
+                "i = 2",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_std_io_path_nested_object() {
+        let code = r#"let ext = std.io.path.extension("notes/today.md");"#;
+        let result = run_and_get_var(code, "ext").unwrap();
+        assert_eq!(result, Value::String("md".to_string()));
+    }
+
+    // ===== Debugger Tests =====
+
+    #[test]
+    fn test_debugger_breakpoint_in_loop() {
+        use crate::interpreter::debugger::DebugAction;
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let code = "let i = 0;\nwhile i < 2 {\n    i = i + 1;\n}\nlet done = 1;\n";
+        let tokens = tokenize(code.to_string()).unwrap();
+        let program = parse(&tokens).unwrap();
+
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let sink = seen.clone();
+        let mut interpreter = Interpreter::new();
+        interpreter.enable_debugger(Box::new(move |pause| {
+            let scope = pause.snapshot_scope();
+            sink.borrow_mut()
+                .push((pause.span.line, scope.get("i").cloned().unwrap()));
+            DebugAction::Continue
+        }));
+        interpreter.set_breakpoint(3);
+        interpreter.interpret(&program).unwrap();
+
+        assert_eq!(
+            *seen.borrow(),
+            vec![(3, Value::Number(0)), (3, Value::Number(1))]
+        );
+        assert_eq!(
+            interpreter.env.get_variable("done"),
+            Some(&Value::Number(1))
+        );
+    }
+
+    #[test]
+    fn test_debugger_step_and_abort() {
+        use crate::interpreter::debugger::DebugAction;
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let code = "let a = 1;\nlet b = 2;\nlet c = 3;\n";
+        let tokens = tokenize(code.to_string()).unwrap();
+        let program = parse(&tokens).unwrap();
+
+        let lines = Rc::new(RefCell::new(Vec::new()));
+        let sink = lines.clone();
+        let mut interpreter = Interpreter::new();
+        interpreter.enable_debugger(Box::new(move |pause| {
+            sink.borrow_mut().push(pause.span.line);
+            if pause.span.line == 1 {
+                DebugAction::Step
+            } else {
+                DebugAction::Abort
+            }
+        }));
+        interpreter.set_breakpoint(1);
+
+        assert!(interpreter.interpret(&program).is_err());
+        assert_eq!(*lines.borrow(), vec![1, 2]);
+        assert_eq!(interpreter.env.get_variable("b"), None);
+    }
+
+    // ===== Profiler Tests =====
+
+    #[test]
+    fn test_profiler_counts_and_orders_calls() {
+        let code = r#"
+            fn hot(x) {
+                return x + 1;
+            }
+            fn once(n) {
+                let i = 0;
+                while i < n {
+                    i = hot(i);
+                }
+                return i;
+            }
+            let result = once(1000);
+        "#;
+        let tokens = tokenize(code.to_string()).unwrap();
+        let program = parse(&tokens).unwrap();
+
+        let mut interpreter = Interpreter::new();
+        interpreter.set_profiling(true);
+        interpreter.interpret(&program).unwrap();
+
+        let report = interpreter.profile_report().unwrap();
+        let names: Vec<&str> = report.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["once", "hot"]);
+
+        let once = report.get("once").unwrap();
+        let hot = report.get("hot").unwrap();
+        assert_eq!(once.calls, 1);
+        assert_eq!(hot.calls, 1000);
+        // time spent in hot is not counted as once's own time
+        assert_eq!(once.self_time + hot.total, once.total);
+        assert_eq!(hot.self_time, hot.total);
+    }
+
+    // ===== Destructuring Tests =====
+
+    fn run_with_vars(code: &str, vars: Vec<(&str, Value)>) -> Result<Interpreter, String> {
+        let tokens = tokenize(code.to_string()).unwrap();
+        let program = parse(&tokens).map_err(|e| format!("Parse error: {:?}", e))?;
+        let mut interpreter = Interpreter::new();
+        for (name, value) in vars {
+            interpreter.env.set_variable(name.to_string(), value);
+        }
+        interpreter.interpret(&program)?;
+        Ok(interpreter)
+    }
+
+    fn user() -> Value {
+        let mut user = Object::new("user");
+        user.set_property("name".to_string(), Value::String("Ada".to_string()));
+        user.set_property("age".to_string(), Value::Number(36));
+        Value::object(user)
+    }
+
+    #[test]
+    fn test_destructure_object() {
+        let interpreter =
+            run_with_vars("let { name, age } = user;", vec![("user", user())]).unwrap();
+        assert_eq!(
+            interpreter.env.get_variable("name"),
+            Some(&Value::String("Ada".to_string()))
+        );
+        assert_eq!(
+            interpreter.env.get_variable("age"),
+            Some(&Value::Number(36))
+        );
+    }
+
+    #[test]
+    fn test_destructure_array() {
+        let arr = Value::array(vec![Value::Number(1), Value::Number(2), Value::Number(3)]);
+        let interpreter = run_with_vars("let [first, second] = arr;", vec![("arr", arr)]).unwrap();
+        assert_eq!(
+            interpreter.env.get_variable("first"),
+            Some(&Value::Number(1))
+        );
+        assert_eq!(
+            interpreter.env.get_variable("second"),
+            Some(&Value::Number(2))
+        );
+    }
+
+    #[test]
+    fn test_destructure_nested_array() {
+        let arr = Value::array(vec![
+            Value::Number(1),
+            Value::array(vec![Value::Number(2), Value::Number(3)]),
+        ]);
+        let interpreter = run_with_vars("let [a, [b, c]] = arr;", vec![("arr", arr)]).unwrap();
+        assert_eq!(interpreter.env.get_variable("c"), Some(&Value::Number(3)));
+    }
+
+    #[test]
+    fn test_destructure_missing_field_fails() {
+        let result = run_with_vars("let { name, email } = user;", vec![("user", user())]);
+        assert!(result.err().unwrap().contains("email"));
+    }
+
+    #[test]
+    fn test_destructure_short_array_fails() {
+        let arr = Value::array(vec![Value::Number(1)]);
+        assert!(run_with_vars("let [a, b] = arr;", vec![("arr", arr)]).is_err());
+    }
+
+    #[test]
+    fn test_destructure_function_result() {
+        let code = r#"
+            fn load_user() {
+                return user;
+            }
+            let { age } = load_user();
+        "#;
+        let interpreter = run_with_vars(code, vec![("user", user())]).unwrap();
+        assert_eq!(
+            interpreter.env.get_variable("age"),
+            Some(&Value::Number(36))
+        );
+    }
+
+    // ===== Reference Semantics Tests =====
+
+    #[test]
+    fn test_array_mutated_inside_function_is_visible_outside() {
+        let code = r#"
+            fn add_four(list) {
+                let len = std.arr.push(list, 4);
+            }
+            let numbers = std.arr.range(1, 4);
+            add_four(numbers);
+        "#;
+        let result = run_and_get_var(code, "numbers").unwrap();
+        assert_eq!(result, Value::array((1..=4).map(Value::Number).collect()));
+    }
+
+    #[test]
+    fn test_array_aliasing_between_variables() {
+        let code = r#"
+            let a = std.arr.range(0, 2);
+            let b = a;
+            let len = std.arr.push(b, 2);
+        "#;
+        let interpreter = run_code(code).unwrap();
+        let a = interpreter.env.get_variable("a").unwrap();
+        let b = interpreter.env.get_variable("b").unwrap();
+        assert!(a.same_ref(b));
+        assert_eq!(a, &Value::array((0..3).map(Value::Number).collect()));
+    }
+
+    #[test]
+    fn test_array_equality_aliased_vs_distinct() {
+        let code = r#"
+            let a = std.arr.range(0, 3);
+            let alias = a;
+            let copy = std.arr.range(0, 3);
+        "#;
+        let interpreter = run_code(code).unwrap();
+        let a = interpreter.env.get_variable("a").unwrap();
+        let alias = interpreter.env.get_variable("alias").unwrap();
+        let copy = interpreter.env.get_variable("copy").unwrap();
+
+        assert_eq!(a, alias);
+        assert!(a.same_ref(alias));
+        // structurally equal, but a different array
+        assert_eq!(a, copy);
+        assert!(!a.same_ref(copy));
+    }
+
+    #[test]
+    fn test_display_of_cyclic_array_terminates() {
+        let code = r#"
+            let a = std.arr.range(0, 1);
+            let len = std.arr.push(a, a);
+        "#;
+        let interpreter = run_code(code).unwrap();
+        let shown = interpreter.env.get_variable("a").unwrap().to_string();
+        assert!(shown.starts_with("[0, [0, [0, "));
+        assert!(shown.contains("[...]"));
+        assert!(interpreter.env.get_variable("a").unwrap().to_bool());
+    }
+
+    #[test]
+    fn test_comparing_and_debug_printing_cyclic_values_terminates() {
+        let code = r#"
+            let a = std.arr.range(0, 1);
+            let len = std.arr.push(a, a);
+            let b = std.arr.range(0, 1);
+            let len = std.arr.push(b, b);
+            let o = std.obj.from_entries(std.arr.new(0));
+            let o = std.obj.deep_set(o, "self", o);
+        "#;
+        let interpreter = run_code(code).unwrap();
+        let a = interpreter.env.get_variable("a").unwrap();
+        let b = interpreter.env.get_variable("b").unwrap();
+        let o = interpreter.env.get_variable("o").unwrap();
+
+        assert_eq!(a, a);
+        assert_eq!(o, o);
+        // Below the depth limit only the same instance counts as equal
+        assert_ne!(a, b);
+        assert_ne!(a, o);
+
+        let shown = format!("{:?}", a);
+        assert!(shown.starts_with("Array([Number(0), Array([Number(0), "));
+        assert!(shown.contains("Array([...])"));
+        assert!(format!("{:?}", o).contains("Object({...})"));
+    }
+
+    // ===== Number Range Tests =====
+
+    #[test]
+    fn test_millisecond_timestamp_roundtrip() {
+        let code = r#"
+            let t = 1700000000000;
+            let later = t + 1000;
+            let shown = "" + later;
+        "#;
+        let interpreter = run_code(code).unwrap();
+        assert_eq!(
+            interpreter.env.get_variable("t"),
+            Some(&Value::Number(1_700_000_000_000))
+        );
+        assert_eq!(
+            interpreter.env.get_variable("shown"),
+            Some(&Value::String("1700000001000".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_arithmetic_beyond_i32() {
+        let result = run_and_get_var("let x = 2147483647 + 1;", "x").unwrap();
+        assert_eq!(result, Value::Number(2_147_483_648));
+        let result = run_and_get_var("let x = 0 - 2147483648 - 1;", "x").unwrap();
+        assert_eq!(result, Value::Number(-2_147_483_649));
+    }
+
+    #[test]
+    fn test_arithmetic_overflow_fails() {
+        let result = run_code("let x = 9223372036854775807 + 1;");
+        assert!(result.err().unwrap().contains("overflow"));
+    }
+
+    #[test]
+    fn test_lexer_rejects_huge_literal() {
+        let err =
+            tokenize("let x = 1;\nlet y = 1234567890123456789012345;".to_string()).unwrap_err();
+        assert_eq!((err.line, err.column), (2, 9));
+    }
+
+    // ===== Object Call Expression Tests =====
+
+    #[test]
+    fn test_object_call_in_while_condition() {
+        let code = r#"
+            let items = std.arr.range(0, 3);
+            let i = 0;
+            while i < std.len(items) {
+                i = i + 1;
+            }
+        "#;
+        assert_eq!(run_and_get_var(code, "i").unwrap(), Value::Number(3));
+    }
+
+    #[test]
+    fn test_object_call_in_if_condition() {
+        let code = r#"
+            let hit = 0;
+            if std.len(std.split_str("a,b", ",")) == 2 {
+                hit = 1;
+            }
+        "#;
+        assert_eq!(run_and_get_var(code, "hit").unwrap(), Value::Number(1));
+    }
+
+    #[test]
+    fn test_object_call_as_binary_operand() {
+        let code = r#"
+            let x = "abc";
+            let n = std.len(x) + 1;
+            let m = 10 - std.len(x) * 2;
+        "#;
+        assert_eq!(run_and_get_var(code, "n").unwrap(), Value::Number(4));
+        assert_eq!(run_and_get_var(code, "m").unwrap(), Value::Number(4));
+    }
+
+    #[test]
+    fn test_object_call_as_call_argument() {
+        let code = r#"
+            fn inc(x) {
+                return x + 1;
+            }
+            let n = inc(std.len("abcd"));
+        "#;
+        assert_eq!(run_and_get_var(code, "n").unwrap(), Value::Number(5));
+    }
+
+    #[test]
+    fn test_object_call_as_statement() {
+        let code = r#"
+            let items = std.arr.range(0, 1);
+            std.arr.push(items, 5);
+        "#;
+        let result = run_and_get_var(code, "items").unwrap();
+        assert_eq!(
+            result,
+            Value::array(vec![Value::Number(0), Value::Number(5)])
+        );
+    }
+
+    #[test]
+    fn test_property_of_object_variable() {
+        let result = run_with_vars("let n = user.name;", vec![("user", user())]).unwrap();
+        assert_eq!(
+            result.env.get_variable("n"),
+            Some(&Value::String("Ada".to_string()))
+        );
+    }
+
+    fn wrap_in_d(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, String> {
+        let mut object = Object::new("result");
+        object.set_property("d".to_string(), args[0].clone());
+        Ok(Value::object(object))
+    }
+
+    #[test]
+    fn test_nested_member_chain() {
+        let mut b = Object::new("b");
+        b.register_native_fn("c", wrap_in_d);
+        let mut a = Object::new("a");
+        a.set_property("b".to_string(), Value::object(b));
+
+        let code = "let x = 7;\nlet result = a.b.c(x).d + 1;";
+        let interpreter = run_with_vars(code, vec![("a", Value::object(a))]).unwrap();
+        assert_eq!(
+            interpreter.env.get_variable("result"),
+            Some(&Value::Number(8))
+        );
+    }
+
+    #[test]
+    fn test_member_of_non_object_fails() {
+        let code = "fn f() { return 1; }\nlet x = f().y;";
+        assert!(run_code(code).is_err());
+    }
+
+    // ===== Shebang and Comment Tests =====
+
+    #[test]
+    fn test_script_with_shebang_runs() {
+        let code = "#!/usr/bin/env mouse\nlet x = 1;\nlet y = x + 1;\n";
+        assert_eq!(run_and_get_var(code, "y").unwrap(), Value::Number(2));
+
+        let tokens = tokenize(code.to_string()).unwrap();
+        assert_eq!(tokens[0].line, 2);
+    }
+
+    #[test]
+    fn test_autofix_leaves_shebang_untouched() {
+        let code = "#!/usr/bin/env mouse\nlet x = 1\n";
+        let fixed = crate::lexer::autofix(code);
+        assert_eq!(fixed.lines().next(), Some("#!/usr/bin/env mouse"));
+        assert_eq!(fixed, "#!/usr/bin/env mouse\nlet x = 1;\n");
+    }
+
+    #[test]
+    fn test_hash_comments_keep_next_line() {
+        let code = "#\nlet x = 1;\n# comment\nlet y = 2;\n";
+        let interpreter = run_code(code).unwrap();
+        assert_eq!(interpreter.env.get_variable("x"), Some(&Value::Number(1)));
+        assert_eq!(interpreter.env.get_variable("y"), Some(&Value::Number(2)));
+    }
+
+    #[test]
+    fn test_shebang_only_on_first_line() {
+        // further down `#!` is just a comment
+        let code = "let x = 1;\n#!not a shebang\nlet y = 2;\n";
+        assert_eq!(run_and_get_var(code, "y").unwrap(), Value::Number(2));
+    }
+
+    // ===== Line Ending Tests =====
+
+    const LF_SCRIPT: &str =
+        "let x = 1;\nfn add(a, b) {\n    return a + b;\n}\nlet s = \"a b\";\nlet y = add(x, 2);\n";
+
+    #[test]
+    fn test_crlf_tokenizes_like_lf() {
+        let crlf = LF_SCRIPT.replace('\n', "\r\n");
+        let lf_tokens = tokenize(LF_SCRIPT.to_string()).unwrap();
+        let crlf_tokens = tokenize(crlf.clone()).unwrap();
+        assert_eq!(crlf_tokens, lf_tokens);
+        assert_eq!(run_and_get_var(&crlf, "y").unwrap(), Value::Number(3));
+    }
+
+    #[test]
+    fn test_autofix_keeps_line_endings() {
+        let crlf = LF_SCRIPT.replace('\n', "\r\n");
+        assert_eq!(crate::lexer::autofix(&crlf), crlf);
+        assert_eq!(crate::lexer::autofix(LF_SCRIPT), LF_SCRIPT);
+
+        let fixed = crate::lexer::autofix("let x = 1\r\nlet y = 2;\r\n");
+        assert_eq!(fixed, "let x = 1;\r\nlet y = 2;\r\n");
+    }
+
+    #[test]
+    fn test_autofix_keeps_missing_trailing_newline() {
+        assert_eq!(crate::lexer::autofix("let x = 1;"), "let x = 1;");
+        assert_eq!(crate::lexer::autofix("let x = 1"), "let x = 1;");
+        assert_eq!(crate::lexer::autofix(""), "");
+    }
+
+    #[test]
+    fn test_verify_autofix_allows_only_added_semicolons() {
+        use crate::lexer::{autofix, verify_autofix};
+
+        let code = "let x = 1\nfn f() {\n    return x\n}\n";
+        assert_eq!(verify_autofix(code, &autofix(code)), Ok(()));
+
+        let code = "let s = \"a\nb\";\n";
+        let err = verify_autofix(code, &autofix(code)).unwrap_err();
+        assert!(err.contains("line 1, column 9"), "{}", err);
+
+        let err = verify_autofix("let ab = 1;", "let a b = 1;").unwrap_err();
+        assert!(err.contains("line 1, column 5"), "{}", err);
+        assert!(verify_autofix("let x = 1;", "let x = ;").is_err());
+    }
+
+    // ===== Incremental Parsing Tests =====
+
+    const MULTILINE_SCRIPT: &str = "let total = 0;\nfn add(a, b) {\n    return a + b;\n}\n# comment\nlet note = \"two\nlines\";\nwhile total < 3 {\n    total = add(total, 1);\n}\nprint(note);\n";
+
+    fn tokenize_lines(lines: &[&str]) -> Vec<Vec<crate::lexer::Token>> {
+        let mut state = crate::lexer::LexState::default();
+        let mut all = Vec::new();
+        for line in lines {
+            let (tokens, next) = crate::lexer::tokenize_line(line, state).unwrap();
+            all.push(tokens);
+            state = next;
+        }
+        all
+    }
+
+    #[test]
+    fn test_line_by_line_lexing_matches_full_lex() {
+        let lines: Vec<&str> = MULTILINE_SCRIPT.lines().collect();
+        let by_line: Vec<_> = tokenize_lines(&lines).concat();
+        assert_eq!(by_line, tokenize(MULTILINE_SCRIPT.to_string()).unwrap());
+
+        // The string closes on line 7, but keeps its starting position
+        let string_token = by_line
+            .iter()
+            .find(|t| t.token == crate::lexer::TokenType::String("two\nlines".to_string()))
+            .unwrap();
+        assert_eq!((string_token.line, string_token.column), (6, 12));
+        let while_token = by_line
+            .iter()
+            .find(|t| t.token == crate::lexer::TokenType::KWWhile)
+            .unwrap();
+        assert_eq!(while_token.line, 8);
+    }
+
+    #[test]
+    fn test_relexing_a_changed_line_matches_full_relex() {
+        let mut lines: Vec<&str> = MULTILINE_SCRIPT.lines().collect();
+        let mut states = vec![crate::lexer::LexState::default()];
+        let mut by_line = Vec::new();
+        for line in &lines {
+            let (tokens, next) =
+                crate::lexer::tokenize_line(line, states.last().unwrap().clone()).unwrap();
+            by_line.push(tokens);
+            states.push(next);
+        }
+
+        // Edit line 9 and re-lex only it, from the state saved before it
+        lines[8] = "    total = add(total, 10) + 1;";
+        let (tokens, next) = crate::lexer::tokenize_line(lines[8], states[8].clone()).unwrap();
+        assert_eq!(next, states[9]);
+        by_line[8] = tokens;
+
+        let edited = lines.join("\n") + "\n";
+        assert_eq!(by_line.concat(), tokenize(edited).unwrap());
+        assert_eq!(
+            crate::lexer::tokenize_line("let x = 1;", crate::lexer::LexState::at_line(40))
+                .unwrap()
+                .0[0]
+                .line,
+            40
+        );
+    }
+
+    #[test]
+    fn test_statement_at_a_time_parsing_matches_parse() {
+        let tokens = tokenize(MULTILINE_SCRIPT.to_string()).unwrap();
+        let mut idx = 0;
+        let mut statements = Vec::new();
+        while idx < tokens.len() {
+            statements.push(crate::parser::parse_statement(&tokens, &mut idx).unwrap());
+        }
+        assert_eq!(statements.len(), 5);
+        assert_eq!(statements, parse(&tokens).unwrap().statements);
+
+        let tokens = tokenize("let x = 1;; }".to_string()).unwrap();
+        let mut idx = 0;
+        crate::parser::parse_statement(&tokens, &mut idx).unwrap();
+        assert_eq!(idx, 6);
+        assert!(crate::parser::parse_statement(&tokens, &mut idx).is_err());
+    }
+
+    // ===== Deadline Tests =====
+
+    #[test]
+    fn test_run_source_with_deadline_stops_infinite_loop() {
+        use crate::{run_source_with_deadline, RunError};
+        use std::time::{Duration, Instant};
+
+        let started = Instant::now();
+        let result = run_source_with_deadline(
+            "let i = 0; while 1 { i = i + 1; }",
+            Duration::from_millis(200),
+        );
+        assert_eq!(result, Err(RunError::TimedOut(Duration::from_millis(200))));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_run_source_with_deadline_finishes_in_time() {
+        use crate::{run_source_with_deadline, RunError};
+        use std::time::Duration;
+
+        let ok = run_source_with_deadline("let x = 1;", Duration::from_secs(10));
+        assert_eq!(ok, Ok(()));
+        let err = run_source_with_deadline("x = 1;", Duration::from_secs(10));
+        assert!(matches!(err, Err(RunError::Runtime(_))));
+    }
+
+    #[test]
+    fn test_cancel_handle_stops_execution() {
+        let tokens = tokenize("let i = 0; while 1 { }".to_string()).unwrap();
+        let program = parse(&tokens).unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .cancel_handle()
+            .store(true, std::sync::atomic::Ordering::SeqCst);
+        assert!(interpreter.interpret(&program).is_err());
+    }
+
+    // ===== Watch Tests =====
+
+    /// A `watch` running on another thread, see `spawn_watch`.
+    struct Watching {
+        /// What the runs printed.
+        lines: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+        /// The errors runs ended with.
+        errors: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+        stop: std::sync::Arc<std::sync::atomic::AtomicBool>,
+        handle: std::thread::JoinHandle<Result<(), String>>,
+    }
+
+    fn spawn_watch(script: &std::path::Path) -> Watching {
+        use crate::watch::{watch, WatchOptions};
+        use std::sync::{Arc, Mutex};
+        use std::time::Duration;
+
+        let lines: Arc<Mutex<Vec<String>>> = Arc::default();
+        let errors: Arc<Mutex<Vec<String>>> = Arc::default();
+        let options = WatchOptions {
+            debounce: Duration::from_millis(50),
+            ..WatchOptions::default()
+        };
+        let stop = options.stop.clone();
+        let handle = std::thread::spawn({
+            let (lines, errors, script) = (lines.clone(), errors.clone(), script.to_path_buf());
+            move || {
+                let make_interpreter = || {
+                    let lines = lines.clone();
+                    let mut interpreter = Interpreter::new();
+                    interpreter.set_import_base(script.parent().unwrap().to_path_buf());
+                    interpreter.set_output(Box::new(move |line| {
+                        lines.lock().unwrap().push(line.into())
+                    }));
+                    interpreter
+                };
+                let on_result = |_run, result: Result<(), crate::RunError>| {
+                    if let Err(e) = result {
+                        errors.lock().unwrap().push(e.to_string());
+                    }
+                };
+                watch(&script, &options, make_interpreter, on_result)
+            }
+        });
+        Watching {
+            lines,
+            errors,
+            stop,
+            handle,
+        }
+    }
+
+    fn wait_for(done: &dyn Fn() -> bool) {
+        use std::time::{Duration, Instant};
+
+        let started = Instant::now();
+        while !done() {
+            assert!(started.elapsed() < Duration::from_secs(10), "timed out");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn test_watch_reruns_after_change_and_survives_syntax_errors() {
+        use std::sync::atomic::Ordering;
+        use std::time::Duration;
+
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("main.mouse");
+        std::fs::write(&script, "std.print(\"first\");").unwrap();
+
+        let Watching {
+            lines,
+            errors,
+            stop,
+            handle,
+        } = spawn_watch(&script);
+        wait_for(&|| lines.lock().unwrap().len() == 1);
+        // Give the watcher time to start listening after the first run
+        std::thread::sleep(Duration::from_millis(200));
+
+        std::fs::write(&script, "std.print(\"broken\"").unwrap();
+        wait_for(&|| !errors.lock().unwrap().is_empty());
+
+        std::fs::write(&script, "std.print(\"second\");").unwrap();
+        wait_for(&|| lines.lock().unwrap().len() == 2);
+
+        stop.store(true, Ordering::Relaxed);
+        assert_eq!(handle.join().unwrap(), Ok(()));
+        assert_eq!(*lines.lock().unwrap(), vec!["first", "second"]);
+    }
+
+    #[test]
+    fn test_watch_reruns_when_a_transitive_import_changes() {
+        use std::sync::atomic::Ordering;
+        use std::time::Duration;
+
+        // main.mouse imports the library `helper` from mouse_modules, which
+        // imports util.mouse next to it
+        let dir = tempfile::tempdir().unwrap();
+        let library = dir.path().join("mouse_modules").join("helper");
+        std::fs::create_dir_all(&library).unwrap();
+        std::fs::write(library.join("lib.mouse"), "import \"util\";").unwrap();
+        std::fs::write(library.join("util.mouse"), "std.print(\"first\");").unwrap();
+        let script = dir.path().join("main.mouse");
+        std::fs::write(&script, "import \"helper\";").unwrap();
+
+        let Watching {
+            lines,
+            errors,
+            stop,
+            handle,
+        } = spawn_watch(&script);
+        wait_for(&|| lines.lock().unwrap().len() == 1);
+        std::thread::sleep(Duration::from_millis(200));
+
+        std::fs::write(library.join("util.mouse"), "std.print(\"second\");").unwrap();
+        wait_for(&|| lines.lock().unwrap().len() == 2);
+
+        stop.store(true, Ordering::Relaxed);
+        assert_eq!(handle.join().unwrap(), Ok(()));
+        assert_eq!(*lines.lock().unwrap(), vec!["first", "second"]);
+        assert!(errors.lock().unwrap().is_empty());
+    }
+
+    // ===== Diagnostics Tests =====
+
+    fn strip_ansi(text: &str) -> String {
+        let mut out = String::new();
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                // skip up to and including the terminating 'm'
+                for c in chars.by_ref() {
+                    if c == 'm' {
+                        break;
+                    }
+                }
+            } else {
+                out.push(c);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_render_error_with_and_without_color() {
+        use crate::style::Style;
+        use std::path::Path;
+
+        let source = "let x = 1;\nlet = 2;\n";
+        let tokens = tokenize(source.to_string()).unwrap();
+        let error = parse(&tokens).unwrap_err();
+        let path = Path::new("script.mouse");
+
+        let plain = error.render(Some(source), Some(path), Style::plain());
+        let colored = error.render(Some(source), Some(path), Style::colored());
+
+        assert!(!plain.contains('\x1b'));
+        assert!(colored.contains("\x1b[1;31merror[E0100]\x1b[0m"));
+        assert_eq!(strip_ansi(&colored), plain);
+        assert!(plain.starts_with("error[E0100]: Syntax error: unexpected "));
+        assert!(plain.contains(&format!("  --> script.mouse:2:{}", error.column)));
+        assert!(plain.contains("2 | let = 2;"));
+        assert!(plain.contains('^'));
+    }
+
+    #[test]
+    fn test_render_error_without_source_or_position() {
+        use crate::errors::Error;
+        use crate::style::Style;
+
+        let error = Error::unexpected_eof("test");
+        let rendered = error.render(None, None, Style::colored());
+        assert_eq!(
+            strip_ansi(&rendered),
+            "error[E0101]: Syntax error: UnexpectedEof\n  \
+             = help: run `mouse --explain E0101` for more about this error\n"
+        );
+    }
+
+    // ===== Call Depth Tests =====
+
+    const COUNTDOWN: &str = r#"
+        fn countdown(n) {
+            if n > 0 {
+                return countdown(n - 1);
+            }
+            return 0;
+        }
+    "#;
+
+    fn run_countdown(n: i64, max_depth: usize) -> Result<Interpreter, String> {
+        let code = format!("{}\nlet result = countdown({});", COUNTDOWN, n);
+        let tokens = tokenize(code).map_err(|e| e.to_string())?;
+        let program = parse(&tokens).map_err(|e| e.to_string())?;
+        let mut interpreter = Interpreter::new();
+        interpreter.set_max_call_depth(max_depth);
+        interpreter.interpret(&program)?;
+        Ok(interpreter)
+    }
+
+    #[test]
+    fn test_call_depth_limit() {
+        // countdown(n) makes n + 1 nested calls
+        assert!(run_countdown(49, 50).is_ok());
+        let err = run_countdown(50, 50).err().unwrap();
+        assert_eq!(err, "Stack overflow: maximum call depth exceeded");
+    }
+
+    #[test]
+    fn test_call_depth_is_released_after_return() {
+        let code = format!(
+            "{}\nlet a = countdown(30);\nlet b = countdown(30);",
+            COUNTDOWN
+        );
+        let tokens = tokenize(code).unwrap();
+        let program = parse(&tokens).unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_max_call_depth(40);
+        assert_eq!(interpreter.interpret(&program), Ok(()));
+    }
+
+    #[test]
+    fn test_default_call_depth_fails_cleanly() {
+        use crate::interpreter::DEFAULT_MAX_CALL_DEPTH;
+
+        // Run on a thread with a main-thread sized stack, as the CLI does
+        let result = std::thread::Builder::new()
+            .stack_size(8 * 1024 * 1024)
+            .spawn(|| {
+                let depth = DEFAULT_MAX_CALL_DEPTH;
+                let ok = run_countdown(depth as i64 - 1, depth).is_ok();
+                let err = run_countdown(depth as i64 * 2, depth).err();
+                (ok, err)
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert!(result.0);
+        assert_eq!(
+            result.1.as_deref(),
+            Some("Stack overflow: maximum call depth exceeded")
+        );
+    }
+
+    // ===== Prelude Tests =====
+
+    fn run_with_prelude(prelude: &str, code: &str) -> Result<Interpreter, String> {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .load_prelude(prelude, "helpers.mouse")
+            .map_err(|e| e.to_string())?;
+        crate::run_source_with(code, &mut interpreter).map_err(|e| e.to_string())?;
+        Ok(interpreter)
+    }
+
+    #[test]
+    fn test_prelude_definitions_are_global() {
+        let prelude = "fn log(msg) {\n    return \"[log] \" + msg;\n}\nlet level = 1;";
+        let interpreter = run_with_prelude(prelude, "let line = log(\"hi\");").unwrap();
+        assert_eq!(
+            interpreter.env.get_variable("line"),
+            Some(&Value::String("[log] hi".to_string()))
+        );
+        assert_eq!(
+            interpreter.env.get_variable("level"),
+            Some(&Value::Number(1))
+        );
+    }
+
+    #[test]
+    fn test_main_program_shadows_prelude() {
+        let prelude = "fn greet() { return \"prelude\"; }";
+        let code = "fn greet() { return \"main\"; }\nlet who = greet();";
+        let interpreter = run_with_prelude(prelude, code).unwrap();
+        assert_eq!(
+            interpreter.env.get_variable("who"),
+            Some(&Value::String("main".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_prelude_errors_name_the_prelude() {
+        let mut interpreter = Interpreter::new();
+        let err = interpreter
+            .load_prelude("fn log(msg) {\n    let = 1;\n}", "helpers.mouse")
+            .unwrap_err();
+        assert_eq!(err.name, "helpers.mouse");
+        assert!(matches!(&err.error, crate::RunError::Syntax(e) if e.line == 2));
+
+        let Err(err) = run_with_prelude("let x = missing;", "") else {
+            panic!("prelude with an undefined identifier loaded");
+        };
+        assert!(err.starts_with("in prelude helpers.mouse: Runtime error"));
+    }
+
+    // ===== Logging Tests =====
+
+    /// Runs `code` with printed lines and log entries captured separately.
+    fn run_logged(code: &str) -> (Vec<String>, Vec<(LogLevel, String)>) {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let printed = Rc::new(RefCell::new(Vec::new()));
+        let logged = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        let sink = printed.clone();
+        interpreter.set_output(Box::new(move |line| {
+            sink.borrow_mut().push(line.to_string())
+        }));
+        let sink = logged.clone();
+        interpreter.set_log_sink(Box::new(move |level: LogLevel, message: &str| {
+            sink.borrow_mut().push((level, message.to_string()))
+        }));
+        crate::run_source_with(code, &mut interpreter).unwrap();
+        drop(interpreter);
+        (
+            Rc::try_unwrap(printed).unwrap().into_inner(),
+            Rc::try_unwrap(logged).unwrap().into_inner(),
+        )
+    }
+
+    #[test]
+    fn test_log_level_filters_debug() {
+        let (_, logged) = run_logged("std.log.debug(\"hidden\");\nstd.log.info(\"shown\");");
+        assert_eq!(logged, vec![(LogLevel::Info, "shown".to_string())]);
+
+        let code =
+            "std.log.set_level(\"warn\");\nstd.log.info(\"hidden\");\nstd.log.error(\"shown\");";
+        let (_, logged) = run_logged(code);
+        assert_eq!(logged, vec![(LogLevel::Error, "shown".to_string())]);
+    }
+
+    #[test]
+    fn test_log_level_set_inside_function_persists() {
+        let code =
+            "fn quiet() { std.log.set_level(\"error\"); }\nquiet();\nstd.log.warn(\"hidden\");";
+        let (_, logged) = run_logged(code);
+        assert!(logged.is_empty());
+    }
+
+    #[test]
+    fn test_print_and_log_are_separate() {
+        let code = "print(\"out\");\nstd.log.warn(\"careful\");\nprint(2);";
+        let (printed, logged) = run_logged(code);
+        assert_eq!(printed, vec!["out".to_string(), "2".to_string()]);
+        assert_eq!(logged, vec![(LogLevel::Warn, "careful".to_string())]);
+    }
+
+    // ===== Float Tests =====
+
+    #[test]
+    fn test_float_arithmetic_and_display() {
+        let code = "let avg = std.arr.moving_average(std.arr.range(1, 5), 2);\nlet first = std.arr.first(avg);\nlet sum = first + 1;\nlet half = first / 3;\nlet bigger = first > 1;\nlet shown = \"\" + sum;";
+        let interpreter = run_code(code).unwrap();
+        let var = |name: &str| interpreter.env.get_variable(name).cloned().unwrap();
+        assert_eq!(var("first"), Value::Float(1.5));
+        assert_eq!(var("sum"), Value::Float(2.5));
+        assert_eq!(var("half"), Value::Float(0.5));
+        assert_eq!(var("bigger"), Value::Number(1));
+        assert_eq!(var("shown"), Value::String("2.5".to_string()));
+        assert_eq!(Value::Float(2.0).to_string(), "2.0");
+        assert_eq!(Value::Float(2.0).type_name(), "float");
+    }
+
+    #[test]
+    fn test_float_division_by_zero_fails() {
+        let code = "let f = std.arr.first(std.arr.moving_average(std.arr.range(1, 2), 1));\nlet x = f / 0;";
+        assert!(run_code(code).is_err());
+    }
+
+    // ===== Resource Tests =====
+
+    #[test]
+    fn test_resources_close_in_reverse_order_on_drop() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let closed = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        for name in ["first", "second", "third"] {
+            let closed = closed.clone();
+            interpreter.register_resource(name, move |name| closed.borrow_mut().push(name));
+        }
+        let second = 2;
+        assert_eq!(
+            interpreter.with_resource(second, |name: &mut &str| *name),
+            Some("second")
+        );
+        assert!(interpreter.close_resource(second));
+        assert!(!interpreter.close_resource(second));
+        assert_eq!(
+            interpreter.with_resource(second, |name: &mut &str| *name),
+            None
+        );
+
+        drop(interpreter);
+        assert_eq!(*closed.borrow(), vec!["second", "third", "first"]);
+    }
+
+    #[test]
+    fn test_resources_opened_in_functions_outlive_the_call() {
+        let code = r#"
+            fn open_table(dir) {
+                return std.db.open(dir, "items");
+            }
+            let table = open_table(dir);
+            std.db.insert(table, row);
+            let size = std.db.size(table);
+        "#;
+        let dir = tempfile::tempdir().unwrap();
+        let mut row = Object::new("object");
+        row.set_property("id".to_string(), Value::Number(1));
+        let vars = vec![
+            (
+                "dir",
+                Value::String(dir.path().to_string_lossy().into_owned()),
+            ),
+            ("row", Value::object(row)),
+        ];
+        let result = run_with_vars(code, vars)
+            .map(|interpreter| interpreter.env.get_variable("size").cloned());
+        assert_eq!(result, Ok(Some(Value::Number(1))));
+    }
+
+    // ===== Display Tests =====
+
+    #[test]
+    fn test_function_display_includes_params() {
+        let interpreter =
+            run_code("fn add(a, b) { return a + b; }\nfn now() { return 1; }").unwrap();
+        let add = interpreter.env.get_variable("add").unwrap();
+        assert_eq!(add.to_string(), "<function add(a, b)>");
+        let now = interpreter.env.get_variable("now").unwrap();
+        assert_eq!(now.to_string(), "<function now()>");
+    }
+
+    #[test]
+    fn test_native_function_display() {
+        let print = Value::NativeFunction("print".to_string(), crate::std_lib::print::print);
+        assert_eq!(print.to_string(), "<native function print>");
+    }
+
+    // ===== Snapshot Tests =====
+
+    #[test]
+    fn test_restore_rolls_back_globals() {
+        let mut interpreter = run_code(
+            "let count = 1;\nlet items = std.arr.new(0);\nstd.arr.push(items, 1);\nfn greet() { return \"hi\"; }",
+        )
+        .unwrap();
+        let snapshot = interpreter.snapshot();
+
+        let changes =
+            "count = 2;\nstd.arr.push(items, 2);\nlet extra = 3;\nfn greet() { return \"bye\"; }";
+        crate::run_source_with(changes, &mut interpreter).unwrap();
+        assert_eq!(
+            interpreter.env.get_variable("count"),
+            Some(&Value::Number(2))
+        );
+
+        interpreter.restore(snapshot);
+        crate::run_source_with("let greeting = greet();", &mut interpreter).unwrap();
+        let var = |name: &str| interpreter.env.get_variable(name).cloned();
+        assert_eq!(var("count"), Some(Value::Number(1)));
+        // The array pushed to after the snapshot was copied, not shared
+        assert_eq!(var("items"), Some(Value::array(vec![Value::Number(1)])));
+        assert_eq!(var("extra"), None);
+        assert_eq!(var("greeting"), Some(Value::String("hi".to_string())));
+    }
+
+    #[test]
+    fn test_snapshot_keeps_aliasing() {
+        let interpreter =
+            run_code("let a = std.arr.new(0);\nlet b = a;\nstd.arr.push(a, a);").unwrap();
+        let mut restored = Interpreter::new();
+        restored.restore(interpreter.snapshot());
+
+        let a = restored.env.get_variable("a").unwrap().clone();
+        let b = restored.env.get_variable("b").unwrap().clone();
+        assert!(a.same_ref(&b));
+        assert!(!a.same_ref(interpreter.env.get_variable("a").unwrap()));
+        let Value::Array(elements) = &a else {
+            panic!("expected an array, got {}", a);
+        };
+        assert!(elements.borrow()[0].same_ref(&a));
+    }
+
+    #[test]
+    fn test_serialized_snapshot_round_trip() {
+        let mut interpreter = run_code("let count = 1;\nfn double(n) { return n * 2; }").unwrap();
+        let bytes = serde_json::to_vec(&interpreter.snapshot()).unwrap();
+        crate::run_source_with("count = 5;", &mut interpreter).unwrap();
+
+        let mut deserializer = serde_json::Deserializer::from_slice(&bytes);
+        let snapshot = interpreter.deserialize_snapshot(&mut deserializer).unwrap();
+        interpreter.restore(snapshot);
+        crate::run_source_with(
+            "let result = double(count) + std.len(\"abc\");",
+            &mut interpreter,
+        )
+        .unwrap();
+        assert_eq!(
+            interpreter.env.get_variable("result"),
+            Some(&Value::Number(5))
+        );
+    }
+
+    // ===== Serialization Tests =====
+
+    fn round_trip(value: &Value) -> Value {
+        let json = serde_json::to_string(value).unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn test_value_round_trip() {
+        let mut user = Object::new("object");
+        user.set_property("name".to_string(), Value::String("mouse".to_string()));
+        user.set_property("age".to_string(), Value::Number(3));
+        let values = [
+            Value::Number(-7),
+            Value::Float(2.5),
+            Value::Float(2.0),
+            Value::String("cheese".to_string()),
+            Value::Void,
+            Value::array(vec![Value::Number(1), Value::array(vec![Value::Void])]),
+            Value::object(user),
+            Value::NativeFunction("print".to_string(), crate::std_lib::print::print),
+            Value::array(vec![Value::NativeFunction(
+                "len".to_string(),
+                crate::std_lib::arr::len,
+            )]),
+        ];
+        for value in values {
+            assert_eq!(round_trip(&value), value);
+        }
+    }
+
+    #[test]
+    fn test_value_serializes_naturally() {
+        let mut point = Object::new("point");
+        point.set_property("y".to_string(), Value::Number(2));
+        point.set_property("x".to_string(), Value::Float(1.5));
+        let value = Value::array(vec![Value::object(point), Value::Void]);
+        assert_eq!(
+            serde_json::to_string(&value).unwrap(),
+            r#"[{"x":1.5,"y":2},null]"#
+        );
+    }
+
+    #[test]
+    fn test_unknown_native_fails_to_deserialize() {
+        let result = serde_json::from_str::<Value>(r#"{"native": "launch_rockets"}"#);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("unknown native function 'launch_rockets'"));
+
+        // Natives a host registered resolve against that interpreter only
+        let mut interpreter = Interpreter::new();
+        interpreter.enable_process_spawning();
+        let json = r#"{"native": "exec"}"#;
+        let value = interpreter
+            .deserialize_value(&mut serde_json::Deserializer::from_str(json))
+            .unwrap();
+        assert!(matches!(value, Value::NativeFunction(name, _) if name == "exec"));
+        assert!(serde_json::from_str::<Value>(json).is_err());
+    }
+
+    #[test]
+    fn test_self_containing_value_fails_to_serialize() {
+        let array = Value::array(vec![]);
+        if let Value::Array(elements) = &array {
+            elements.borrow_mut().push(array.clone());
+        }
+        assert!(serde_json::to_string(&array).is_err());
+        if let Value::Array(elements) = &array {
+            elements.borrow_mut().clear();
+        }
+    }
+
+    #[test]
+    fn test_function_moves_between_interpreters() {
+        let source = run_code("fn greet(name) { return \"hi \" + name; }").unwrap();
+        let greet = source.env.get_variable("greet").unwrap();
+        let json = serde_json::to_string(greet).unwrap();
+        assert!(json.starts_with(r#"{"fn":"greet","params":["name"],"body":"#));
+
+        let mut target = Interpreter::new();
+        let greet = target
+            .deserialize_value(&mut serde_json::Deserializer::from_str(&json))
+            .unwrap();
+        let result = target.call_function(&greet, vec![Value::String("mouse".to_string())]);
+        assert_eq!(result, Ok(Value::String("hi mouse".to_string())));
+    }
+
+    // ===== Identifier Resolution Tests =====
+
+    #[test]
+    fn test_function_used_before_its_definition_is_undefined() {
+        let Err(err) = run_code("let x = later();\nfn later() { return 1; }") else {
+            panic!("calling a function before its definition should fail");
+        };
+        assert_eq!(err, "Undefined function: later");
+    }
+
+    #[test]
+    fn test_function_body_sees_functions_defined_after_it() {
+        // Bodies resolve names when they run, not when they are defined
+        let code =
+            "fn first() { return second() + 1; }\nfn second() { return 41; }\nlet x = first();";
+        assert_eq!(run_and_get_var(code, "x").unwrap(), Value::Number(42));
+    }
+
+    #[test]
+    fn test_variables_and_functions_shadow_each_other() {
+        // Both live in the global scope: whichever was bound last wins
+        let code = "let f = 1;\nfn f() { return 2; }\nlet x = f();";
+        assert_eq!(run_and_get_var(code, "x").unwrap(), Value::Number(2));
+
+        let code = "fn f() { return 2; }\nlet f = 1;\nlet x = f;";
+        assert_eq!(run_and_get_var(code, "x").unwrap(), Value::Number(1));
+    }
+
+    #[test]
+    fn test_identifier_read_in_scopes_of_different_shapes() {
+        // `v` lands in a later slot on the second call, and the slot it had
+        // on the first call holds `a` by then
+        let code =
+            "fn get(v) { return v; }\nlet a = get(1);\nlet b = 2;\nlet c = get(3) + a;\nc = c + b;";
+        assert_eq!(run_and_get_var(code, "c").unwrap(), Value::Number(6));
+    }
+
+    #[test]
+    fn test_reassignment_inside_function_stays_local() {
+        let code =
+            "let n = 1;\nfn bump() { n = n + 1; return n; }\nlet inner = bump();\nlet outer = n;";
+        assert_eq!(run_and_get_var(code, "inner").unwrap(), Value::Number(2));
+        assert_eq!(run_and_get_var(code, "outer").unwrap(), Value::Number(1));
+    }
+
+    // ===== Last Result Tests =====
+
+    #[test]
+    fn test_eval_source_chains_last_result() {
+        use crate::eval_source;
+
+        let mut interpreter = Interpreter::new();
+        interpreter.set_capture_last_result(true);
+        assert_eq!(
+            eval_source("2 + 3;", &mut interpreter),
+            Ok(Value::Number(5))
+        );
+        assert_eq!(
+            eval_source("_ * 2;", &mut interpreter),
+            Ok(Value::Number(10))
+        );
+        assert_eq!(
+            eval_source("let y = _ + 1;", &mut interpreter),
+            Ok(Value::Void)
+        );
+        assert_eq!(interpreter.env.get_variable("y"), Some(&Value::Number(11)));
+        assert_eq!(
+            eval_source("\"a\";\ny;", &mut interpreter),
+            Ok(Value::Number(11))
+        );
+        assert_eq!(interpreter.env.get_variable("_"), Some(&Value::Number(11)));
+    }
+
+    #[test]
+    fn test_last_result_not_bound_by_default() {
+        use crate::{eval_source, RunError};
+
+        let mut interpreter = Interpreter::new();
+        assert_eq!(
+            eval_source("1 + 1;", &mut interpreter),
+            Ok(Value::Number(2))
+        );
+        assert_eq!(interpreter.env.get_variable("_"), None);
+        assert!(matches!(
+            eval_source("let x = _;", &mut interpreter),
+            Err(RunError::Runtime(_))
+        ));
+    }
+
+    #[test]
+    fn test_eval_source_runs_nothing_when_a_statement_does_not_parse() {
+        use crate::{eval_source, RunError};
+
+        let mut interpreter = Interpreter::new();
+        assert!(matches!(
+            eval_source("let a = 1;\nlet = 2;", &mut interpreter),
+            Err(RunError::Syntax(_))
+        ));
+        assert_eq!(interpreter.env.get_variable("a"), None);
+        assert_eq!(
+            eval_source("let a = 1;; a + 1;", &mut interpreter),
+            Ok(Value::Number(2))
+        );
+    }
+
+    #[test]
+    fn test_user_bound_underscore_wins_over_last_result() {
+        use crate::eval_source;
+
+        let mut interpreter = Interpreter::new();
+        interpreter.set_capture_last_result(true);
+        eval_source("let _ = 7;", &mut interpreter).unwrap();
+        assert_eq!(
+            eval_source("1 + 2;", &mut interpreter),
+            Ok(Value::Number(3))
+        );
+        assert_eq!(eval_source("_;", &mut interpreter), Ok(Value::Number(7)));
+    }
+
+    #[test]
+    fn test_bare_expression_must_end_statement() {
+        assert!(run_code("let x = 1;\nx 5;").is_err());
+        assert!(run_code("let x = 1;\nx;\n2 + 3;").is_ok());
+    }
+
+    // ===== Embedding Tests =====
+
+    #[test]
+    fn test_call_function_from_rust() {
+        use crate::interpreter::call::CallError;
+
+        let code = "fn greet(name, punct) { return \"hi \" + name + punct; }\n\
+                    fn squares(n) {\n\
+                        let out = std.arr.new(0);\n\
+                        let i = 1;\n\
+                        while i <= n { std.arr.push(out, i * i); i = i + 1; }\n\
+                        return out;\n\
+                    }";
+        let mut interpreter = run_code(code).unwrap();
+
+        let greeting = interpreter
+            .call(
+                "greet",
+                vec![
+                    Value::String("mouse".to_string()),
+                    Value::String("!".to_string()),
+                ],
+            )
+            .unwrap();
+        assert_eq!(String::try_from(greeting), Ok("hi mouse!".to_string()));
+
+        let squares = interpreter.call("squares", vec![Value::Number(3)]).unwrap();
+        let squares: Result<Vec<i64>, String> = Vec::<Value>::try_from(squares)
+            .unwrap()
+            .into_iter()
+            .map(i64::try_from)
+            .collect();
+        assert_eq!(squares, Ok(vec![1, 4, 9]));
+
+        let err = interpreter.call("squares", vec![]).unwrap_err();
+        assert_eq!(
+            err,
+            CallError::Arity {
+                name: "squares".to_string(),
+                expected: 1,
+                got: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_call_object_methods_and_missing_functions() {
+        use crate::interpreter::call::CallError;
+
+        let code = "fn double(n) { return n * 2; }\n\
+                    let handlers = std.obj.from_entries(std.arr.new(0));\n\
+                    std.obj.deep_set(handlers, \"math.double\", double);\n\
+                    let count = 1;";
+        let mut interpreter = run_code(code).unwrap();
+        let doubled = interpreter
+            .call("handlers.math.double", vec![Value::Number(21)])
+            .unwrap();
+        assert_eq!(i64::try_from(doubled), Ok(42));
+        let len = interpreter
+            .call("std.len", vec![Value::String("mouse".to_string())])
+            .unwrap();
+        assert_eq!(i64::try_from(len), Ok(5));
+
+        assert_eq!(
+            interpreter.call("on_event", vec![]),
+            Err(CallError::NotFound("on_event".to_string()))
+        );
+        assert_eq!(
+            interpreter.call("handlers.on_event", vec![]),
+            Err(CallError::NotFound("handlers.on_event".to_string()))
+        );
+        let err = interpreter.call("count", vec![]).unwrap_err();
+        assert_eq!(err.to_string(), "'count' is a number, not a function");
+        assert_eq!(
+            bool::try_from(Value::String("yes".to_string())),
+            Err("expected a number, got string".to_string())
+        );
+    }
+
+    // ===== Method Receiver Tests =====
+
+    #[test]
+    fn test_counter_increment_is_visible_to_later_reads() {
+        let code = "let counter = std.counter();\n\
+                    counter.increment();\n\
+                    counter.increment();\n\
+                    let value = counter.value;";
+        assert_eq!(run_and_get_var(code, "value").unwrap(), Value::Number(2));
+    }
+
+    #[test]
+    fn test_native_method_on_user_object_uses_shared_handle() {
+        // `mine` borrows a counter's method; it acts on `mine`, not the counter
+        let code = "let c = std.counter();\n\
+                    let mine = std.obj.from_entries(std.arr.new(0));\n\
+                    std.obj.deep_set(mine, \"value\", 10);\n\
+                    std.obj.deep_set(mine, \"bump\", c.increment);\n\
+                    let alias = mine;\n\
+                    mine.bump();\n\
+                    alias.bump(5);\n\
+                    let value = mine.value;\n\
+                    let untouched = c.value;";
+        assert_eq!(run_and_get_var(code, "value").unwrap(), Value::Number(16));
+        assert_eq!(
+            run_and_get_var(code, "untouched").unwrap(),
+            Value::Number(0)
+        );
+    }
+
+    #[test]
+    fn test_native_method_on_builtin_object_is_stored_back() {
+        let mut interpreter = Interpreter::new();
+        let mut hits = Object::new("hits");
+        hits.set_property("value".to_string(), Value::Number(0));
+        hits.register_native_fn("increment", crate::std_lib::counter::increment);
+        interpreter.env.objects.insert("hits".to_string(), hits);
+
+        let code = "hits.increment();\nhits.increment();\nlet value = hits.value;";
+        crate::run_source_with(code, &mut interpreter).unwrap();
+        assert_eq!(
+            interpreter.env.get_variable("value"),
+            Some(&Value::Number(2))
+        );
+        assert_eq!(
+            interpreter
+                .env
+                .get_object("hits")
+                .unwrap()
+                .get_property("value"),
+            Some(&Value::Number(2))
+        );
+    }
+
+    #[test]
+    fn test_callback_does_not_see_the_outer_receiver() {
+        // `all` is called on `std.arr`, but `increment` is only a callback
+        let mut interpreter = Interpreter::new();
+        let code = "let c = std.counter();\n\
+                    let xs = std.arr.range(0, 2);\n\
+                    std.arr.all(xs, c.increment);";
+        let err = crate::run_source_with(code, &mut interpreter).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("increment must be called on a counter"),
+            "{}",
+            err
+        );
+        let std_object = interpreter.env.get_object("std").unwrap();
+        let Some(Value::Object(arr)) = std_object.get_property("arr") else {
+            panic!("std.arr is missing");
+        };
+        assert_eq!(arr.borrow().get_property("value"), None);
+    }
+
+    // ===== Memory Limit Tests =====
+
+    const GROWING_ARRAY: &str = "let items = std.arr.new(0);\n\
+                                 let i = 0;\n\
+                                 while i < 2000 {\n\
+                                     std.arr.push(items, \"item\");\n\
+                                     i = i + 1;\n\
+                                 }";
+
+    #[test]
+    fn test_memory_limit_stops_growing_array() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_memory_limit(16 * 1024);
+        let Err(err) = crate::run_source_with(GROWING_ARRAY, &mut interpreter) else {
+            panic!("the loop should run out of memory");
+        };
+        assert!(err.to_string().contains("Memory limit exceeded"), "{}", err);
+        let Some(Value::Number(i)) = interpreter.env.get_variable("i") else {
+            panic!("i should be a number");
+        };
+        assert!(*i < 2000, "stopped at {}", i);
+    }
+
+    #[test]
+    fn test_growing_array_runs_without_limit() {
+        let mut interpreter = Interpreter::new();
+        crate::run_source_with(GROWING_ARRAY, &mut interpreter).unwrap();
+        assert_eq!(
+            interpreter.env.get_variable("i"),
+            Some(&Value::Number(2000))
+        );
+        assert!(interpreter.memory_used() > 2000 * std::mem::size_of::<Value>());
+    }
+
+    #[test]
+    fn test_memory_used_grows_with_data() {
+        let code = "let items = std.arr.new(0);\n\
+                    let last = std.memory_used();\n\
+                    let shrank = 0;\n\
+                    let i = 0;\n\
+                    while i < 100 {\n\
+                        std.arr.push(items, \"some text\");\n\
+                        let used = std.memory_used();\n\
+                        if used <= last { shrank = 1; }\n\
+                        last = used;\n\
+                        i = i + 1;\n\
+                    }";
+        assert_eq!(run_and_get_var(code, "shrank").unwrap(), Value::Number(0));
+    }
+
+    #[test]
+    fn test_memory_used_shrinks_when_scope_ends() {
+        let code = "fn build() {\n\
+                        let local = std.arr.fill(1000, 0);\n\
+                        return std.memory_used();\n\
+                    }\n\
+                    let before = std.memory_used();\n\
+                    let inside = build();\n\
+                    let after = std.memory_used();";
+        let interpreter = run_code(code).unwrap();
+        let get = |name: &str| match interpreter.env.get_variable(name) {
+            Some(Value::Number(n)) => *n,
+            other => panic!("{} is {:?}", name, other),
+        };
+        assert!(get("inside") > get("before") + 1000);
+        assert!(get("after") < get("inside"));
+    }
+
+    #[test]
+    fn test_reservation_fails_before_allocating_and_is_not_kept() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_memory_limit(64 * 1024);
+        let err = interpreter.reserve_memory(usize::MAX / 2).unwrap_err();
+        assert!(err.contains("Memory limit exceeded"), "{}", err);
+        // The failed reservation does not count against later ones
+        interpreter.reserve_memory(1024).unwrap();
+    }
+
+    #[test]
+    fn test_memory_limit_stops_string_doubling() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_memory_limit(64 * 1024);
+        let code = "let s = \"ab\";\nwhile 1 {\n    s = s + s;\n}";
+        let err = crate::run_source_with(code, &mut interpreter).unwrap_err();
+        assert!(err.to_string().contains("Memory limit exceeded"), "{}", err);
+        let Some(Value::String(s)) = interpreter.env.get_variable("s") else {
+            panic!("s should be a string");
+        };
+        assert!(s.len() <= 64 * 1024, "grew to {}", s.len());
+    }
+
+    // ===== Coverage Tests =====
+
+    const COVERAGE_SCRIPT: &str = "let i = 0;\n\
+                                   while i < 4 {\n\
+                                       i = i + 1;\n\
+                                   }\n\
+                                   if i > 10 {\n\
+                                       print(\"big\");\n\
+                                   }\n\
+                                   fn unused() {\n\
+                                       return 1;\n\
+                                   }\n";
+
+    fn coverage_of(code: &str) -> crate::interpreter::coverage::CoverageReport {
+        let program = parse(&tokenize(code.to_string()).unwrap()).unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_coverage(true);
+        interpreter.interpret(&program).unwrap();
+        interpreter.coverage_report().unwrap()
+    }
+
+    #[test]
+    fn test_coverage_counts_lines() {
+        let report = coverage_of(COVERAGE_SCRIPT);
+        assert_eq!(report.hits("<eval>", 1), Some(1));
+        assert_eq!(report.hits("<eval>", 2), Some(1));
+        // loop body ran once per iteration
+        assert_eq!(report.hits("<eval>", 3), Some(4));
+        assert_eq!(report.hits("<eval>", 5), Some(1));
+        // untaken branch and uncalled function body
+        assert_eq!(report.hits("<eval>", 6), Some(0));
+        assert_eq!(report.hits("<eval>", 9), Some(0));
+        // closing braces hold no statement
+        assert_eq!(report.hits("<eval>", 4), None);
+        assert_eq!(report.lines_hit(), 5);
+    }
+
+    #[test]
+    fn test_coverage_counts_function_bodies() {
+        let report = coverage_of(
+            "fn twice(x) {\n    return x * 2;\n}\nlet a = twice(1);\nlet b = twice(2);\n",
+        );
+        assert_eq!(report.hits("<eval>", 2), Some(2));
+        assert_eq!(report.hits("<eval>", 4), Some(1));
+    }
+
+    #[test]
+    fn test_coverage_disabled_by_default() {
+        let interpreter = run_code(COVERAGE_SCRIPT).unwrap();
+        assert_eq!(interpreter.coverage_report(), None);
+    }
+
+    /// Checks the structure of a single-file lcov tracefile and returns its
+    /// `DA` line counts.
+    fn parse_lcov(lcov: &str) -> Vec<(u32, u64)> {
+        let mut lines: Vec<&str> = lcov.lines().collect();
+        assert_eq!(lines.first(), Some(&"TN:"));
+        assert!(lines[1].starts_with("SF:"), "{}", lcov);
+        assert_eq!(lines.pop(), Some("end_of_record"));
+        let hit = lines.pop().and_then(|l| l.strip_prefix("LH:")).unwrap();
+        let found = lines.pop().and_then(|l| l.strip_prefix("LF:")).unwrap();
+
+        let records: Vec<(u32, u64)> = lines[2..]
+            .iter()
+            .map(|line| {
+                let (number, hits) = line.strip_prefix("DA:").unwrap().split_once(',').unwrap();
+                (number.parse().unwrap(), hits.parse().unwrap())
+            })
+            .collect();
+        assert_eq!(found.parse::<usize>().unwrap(), records.len());
+        let lines_hit = records.iter().filter(|(_, hits)| *hits > 0).count();
+        assert_eq!(hit.parse::<usize>().unwrap(), lines_hit);
+        records
+    }
+
+    #[test]
+    fn test_coverage_lcov_output() {
+        let lcov = coverage_of(COVERAGE_SCRIPT).to_lcov();
+        assert!(lcov.contains("SF:<eval>\n"));
+        assert_eq!(
+            parse_lcov(&lcov),
+            vec![(1, 1), (2, 1), (3, 4), (5, 1), (6, 0), (8, 1), (9, 0)]
+        );
+    }
+
+    #[test]
+    fn test_coverage_counts_imported_modules_in_their_own_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let module = dir.path().join("helper.mouse");
+        std::fs::write(
+            &module,
+            "let loaded = 1;\nfn helper() {\n    return 2;\n}\n",
+        )
+        .unwrap();
+
+        let code = "import \"helper\";\nlet a = helper();\nlet b = helper();\n";
+        let program = parse(&tokenize(code.to_string()).unwrap()).unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_import_base(dir.path().to_path_buf());
+        interpreter.set_script_file("main.mouse");
+        interpreter.set_coverage(true);
+        interpreter.interpret(&program).unwrap();
+        let report = interpreter.coverage_report().unwrap();
+
+        let module = module.display().to_string();
+        assert_eq!(report.hits("main.mouse", 1), Some(1));
+        assert_eq!(report.hits("main.mouse", 3), Some(1));
+        assert_eq!(report.hits(&module, 1), Some(1));
+        // the function body is counted where it was defined
+        assert_eq!(report.hits(&module, 3), Some(2));
+        assert_eq!(report.files.len(), 2);
+
+        let lcov = report.to_lcov();
+        assert!(lcov.contains("SF:main.mouse\nDA:1,1\nDA:2,1\nDA:3,1\nLF:3\nLH:3\nend_of_record\n"));
+        assert!(lcov.contains(&format!(
+            "SF:{}\nDA:1,1\nDA:2,1\nDA:3,2\nLF:3\nLH:3\nend_of_record\n",
+            module
+        )));
+    }
+
+    // ===== Meta Tests =====
+
+    #[test]
+    fn test_meta_version_and_file() {
+        let interpreter = run_code("let version = meta.version;\nlet file = meta.file;").unwrap();
+        assert_eq!(
+            interpreter.env.get_variable("version"),
+            Some(&Value::String(env!("CARGO_PKG_VERSION").to_string()))
+        );
+        assert_eq!(
+            interpreter.env.get_variable("file"),
+            Some(&Value::String("<eval>".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_meta_file_set_by_embedder() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_script_file("jobs/nightly.mouse");
+        crate::run_source_with("let file = meta.file;", &mut interpreter).unwrap();
+        assert_eq!(
+            interpreter.env.get_variable("file"),
+            Some(&Value::String("jobs/nightly.mouse".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_meta_line() {
+        let code = "let a = 1;\n\nlet line = meta.line();\nfn where() {\n    return meta.line();\n}\nlet inner = where();";
+        let interpreter = run_code(code).unwrap();
+        assert_eq!(
+            interpreter.env.get_variable("line"),
+            Some(&Value::Number(3))
+        );
+        assert_eq!(
+            interpreter.env.get_variable("inner"),
+            Some(&Value::Number(5))
+        );
+    }
+
+    #[test]
+    fn test_meta_function() {
+        let code = "fn outer() {\n    let before = inner();\n    return before + \" \" + meta.function();\n}\n\
+                    fn inner() {\n    return meta.function();\n}\n\
+                    let inside = outer();\nlet outside = meta.function();";
+        let interpreter = run_code(code).unwrap();
+        assert_eq!(
+            interpreter.env.get_variable("inside"),
+            Some(&Value::String("inner outer".to_string()))
+        );
+        assert_eq!(interpreter.env.get_variable("outside"), Some(&Value::Void));
+    }
+
+    #[test]
+    fn test_variable_named_meta_shadows_the_builtin() {
+        let code = "let meta = std.obj.from_entries(std.arr.new(0));\n\
+                    std.obj.deep_set(meta, \"version\", \"mine\");\n\
+                    let version = meta.version;";
+        let interpreter = run_code(code).unwrap();
+        assert_eq!(
+            interpreter.env.get_variable("version"),
+            Some(&Value::String("mine".to_string()))
+        );
+    }
+
+    // ===== Nesting Limit Tests =====
+
+    fn parenthesized(depth: usize) -> String {
+        format!("let x = {}1{};", "(".repeat(depth), ")".repeat(depth))
+    }
+
+    /// Runs `f` on a thread with a main-thread sized stack, as the CLI does;
+    /// debug builds use several KB of stack per nesting level.
+    fn on_main_sized_stack<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+        std::thread::Builder::new()
+            .stack_size(8 * 1024 * 1024)
+            .spawn(f)
+            .unwrap()
+            .join()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_deep_parentheses_are_a_syntax_error() {
+        let started = std::time::Instant::now();
+        let tokens = tokenize(parenthesized(10_000)).unwrap();
+        let error = on_main_sized_stack(move || parse(&tokens).unwrap_err());
+        assert!(started.elapsed() < std::time::Duration::from_secs(2));
+        assert_eq!(
+            error.error_type,
+            crate::errors::ErrorTypes::SyntaxError(crate::errors::SyntaxError::NestingTooDeep(
+                crate::parser::DEFAULT_MAX_NESTING
+            ))
+        );
+        assert_eq!(error.line, 1);
+        let rendered = error.render(None, None, crate::style::Style::plain());
+        assert!(
+            rendered.contains("expression nesting too deep"),
+            "{}",
+            rendered
+        );
+    }
+
+    #[test]
+    fn test_moderate_nesting_still_parses() {
+        let result = on_main_sized_stack(|| {
+            run_and_get_var(&parenthesized(200), "x") == Ok(Value::Number(1))
+        });
+        assert!(result);
+        let result = run_and_get_var("let x = (1 + 2) * (10 - (3 - 1));", "x");
+        assert_eq!(result, Ok(Value::Number(24)));
+    }
+
+    #[test]
+    fn test_nesting_limit_is_configurable() {
+        let tokens = tokenize(parenthesized(20)).unwrap();
+        assert!(crate::parser::parse_with_max_nesting(&tokens, 10).is_err());
+        assert!(crate::parser::parse_with_max_nesting(&tokens, 30).is_ok());
+    }
+
+    #[test]
+    fn test_long_operator_chain_runs() {
+        // Nests one level per operator, well past the expression depth limit
+        let code = format!("let x = 1{};", " + 1".repeat(5_000));
+        assert_eq!(run_and_get_var(&code, "x"), Ok(Value::Number(5_001)));
+        let code = format!("let x = 1 + 2 * 3{} - 1;", " - 1 + 1".repeat(5_000));
+        assert_eq!(run_and_get_var(&code, "x"), Ok(Value::Number(6)));
+    }
+
+    #[test]
+    fn test_deep_ast_is_a_runtime_error() {
+        use crate::parser::{BinaryOp, Expr, Program, Span, Stmt, StmtKind};
+        let mut expr = Expr::Number(1);
+        for _ in 0..10_000 {
+            expr = Expr::Binary {
+                left: Box::new(Expr::Number(1)),
+                op: BinaryOp::Add,
+                right: Box::new(expr),
+            };
+        }
+        let program = Program {
+            statements: vec![Stmt::new(
+                StmtKind::Let {
+                    name: "x".to_string(),
+                    value: expr,
+                },
+                Span::new(1, 1),
+            )],
+        };
+        let error = on_main_sized_stack(move || Interpreter::new().interpret(&program));
+        assert_eq!(error, Err("Expression nesting too deep".to_string()));
+    }
+
+    // ===== Try/Catch Tests =====
+
+    #[test]
+    fn test_catch_division_by_zero() {
+        let code = "let message = \"\";\nlet line = 0;\ntry {\n  let x = 10 / 0;\n} catch (e) {\n  message = e.message;\n  line = e.line;\n}";
+        let interpreter = run_code(code).unwrap();
+        assert_eq!(
+            interpreter.env.get_variable("message"),
+            Some(&Value::String("Division by zero".to_string()))
+        );
+        assert_eq!(
+            interpreter.env.get_variable("line"),
+            Some(&Value::Number(4))
+        );
+    }
+
+    #[test]
+    fn test_catch_thrown_string() {
+        let result = run_and_get_var(
+            "let caught = 0; try { throw \"bad input\"; } catch (e) { caught = e; }",
+            "caught",
+        );
+        assert_eq!(result, Ok(Value::String("bad input".to_string())));
+    }
+
+    #[test]
+    fn test_catch_error_from_called_function() {
+        let code = "fn parse(x) {\n  throw \"not a number: \" + x;\n}\nlet caught = 0;\ntry { parse(\"abc\"); } catch (e) { caught = e; }";
+        let result = run_and_get_var(code, "caught");
+        assert_eq!(result, Ok(Value::String("not a number: abc".to_string())));
+    }
+
+    #[test]
+    fn test_rethrow_from_catch() {
+        let result = run_and_get_var(
+            "let got = 0; try { try { throw \"inner\"; } catch (e) { throw e; } } catch (outer) { got = outer; }",
+            "got",
+        );
+        assert_eq!(result, Ok(Value::String("inner".to_string())));
+
+        let result = run_code("try { 1 / 0; } catch (e) { throw e; }");
+        assert_eq!(result.err(), Some("Division by zero".to_string()));
+    }
+
+    #[test]
+    fn test_try_without_error_skips_catch() {
+        let result = run_and_get_var("let x = 1; try { x = 2; } catch (e) { x = 3; }", "x");
+        assert_eq!(result, Ok(Value::Number(2)));
+    }
+
+    #[test]
+    fn test_uncaught_throw_is_a_runtime_error() {
+        let result = run_code("let x = 1; throw \"bad input\"; x = 2;");
+        assert_eq!(result.err(), Some("bad input".to_string()));
+    }
+
+    // ===== Range Tests =====
+
+    const SUM_RANGE: &str = "let total = 0;\n\
+                             while r.has_next() {\n\
+                                 total = total + r.next();\n\
+                             }";
+
+    fn sum_range(range: &str) -> Result<Value, String> {
+        run_and_get_var(&format!("let r = {};\n{}", range, SUM_RANGE), "total")
+    }
+
+    #[test]
+    fn test_range_sums() {
+        assert_eq!(sum_range("range(1, 101)"), Ok(Value::Number(5050)));
+        assert_eq!(sum_range("range(0, 10, 2)"), Ok(Value::Number(20)));
+        assert_eq!(sum_range("range(5, 0, 0 - 1)"), Ok(Value::Number(15)));
+        assert_eq!(sum_range("std.range(3, 3)"), Ok(Value::Number(0)));
+        assert_eq!(sum_range("range(3, 0)"), Ok(Value::Number(0)));
+    }
+
+    #[test]
+    fn test_huge_range_is_not_materialized() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_memory_limit(16 * 1024);
+        let code = "let r = range(0, 1000000000);\n\
+                    let i = 0;\n\
+                    let last = 0;\n\
+                    while i < 1000 {\n\
+                        last = r.next();\n\
+                        i = i + 1;\n\
+                    }";
+        crate::run_source_with(code, &mut interpreter).unwrap();
+        assert_eq!(
+            interpreter.env.get_variable("last"),
+            Some(&Value::Number(999))
+        );
+        assert!(interpreter.memory_used() < 16 * 1024);
+    }
+
+    // ===== For-In Tests =====
+
+    /// A mouse-defined iterator counting from 0 up to (excluding) `limit`,
+    /// whose methods keep their state on the object itself.
+    const COUNT_UP: &str = "let it = std.obj.from_entries(std.arr.new(0));\n\
+                            std.obj.deep_set(it, \"n\", 0);\n\
+                            fn it_has_next() { return it.n < limit; }\n\
+                            fn it_next() {\n\
+                                let n = it.n;\n\
+                                std.obj.deep_set(it, \"n\", n + 1);\n\
+                                return n;\n\
+                            }\n\
+                            std.obj.deep_set(it, \"has_next\", it_has_next);\n\
+                            std.obj.deep_set(it, \"next\", it_next);\n";
+
+    #[test]
+    fn test_for_in_array_and_range() {
+        let code = "let joined = \"\";\n\
+                    for part in std.split_str(\"a,b,c\", \",\") {\n\
+                        joined = joined + part;\n\
+                    }\n\
+                    let total = 0;\n\
+                    for i in range(1, 101) {\n\
+                        total = total + i;\n\
+                    }";
+        assert_eq!(
+            run_and_get_var(code, "joined"),
+            Ok(Value::String("abc".to_string()))
+        );
+        assert_eq!(run_and_get_var(code, "total"), Ok(Value::Number(5050)));
+    }
+
+    #[test]
+    fn test_for_in_user_iterator_runs_to_completion() {
+        let code = format!(
+            "let limit = 4;\n{}\
+             let seen = \"\";\n\
+             for n in it {{\n\
+                 seen = seen + n;\n\
+             }}",
+            COUNT_UP
+        );
+        assert_eq!(
+            run_and_get_var(&code, "seen"),
+            Ok(Value::String("0123".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_break_out_of_infinite_iterator() {
+        let code = "let it = std.obj.from_entries(std.arr.new(0));\n\
+                    std.obj.deep_set(it, \"n\", 0);\n\
+                    fn forever() { return 1; }\n\
+                    fn it_next() {\n\
+                        let n = it.n;\n\
+                        std.obj.deep_set(it, \"n\", n + 1);\n\
+                        return n;\n\
+                    }\n\
+                    std.obj.deep_set(it, \"has_next\", forever);\n\
+                    std.obj.deep_set(it, \"next\", it_next);\n\
+                    let last = 0;\n\
+                    for n in it {\n\
+                        last = n;\n\
+                        if n == 10 { break; }\n\
+                    }\n\
+                    let i = 0;\n\
+                    while 1 {\n\
+                        i = i + 1;\n\
+                        if i == 3 { break; }\n\
+                    }";
+        assert_eq!(run_and_get_var(code, "last"), Ok(Value::Number(10)));
+        assert_eq!(run_and_get_var(code, "i"), Ok(Value::Number(3)));
+    }
+
+    #[test]
+    fn test_return_from_inside_for_in() {
+        let code = "fn first_over(limit) {\n\
+                        for i in range(0, 100) {\n\
+                            if i > limit { return i; }\n\
+                        }\n\
+                        return 0 - 1;\n\
+                    }\n\
+                    let found = first_over(41);";
+        assert_eq!(run_and_get_var(code, "found"), Ok(Value::Number(42)));
+    }
+
+    #[test]
+    fn test_for_in_errors() {
+        let code = "let it = std.obj.from_entries(std.arr.new(0));\n\
+                    fn yes() { return 1; }\n\
+                    std.obj.deep_set(it, \"has_next\", yes);\n\
+                    for x in it { }";
+        assert_eq!(
+            run_code(code).err(),
+            Some("Cannot iterate over an object without a next() method".to_string())
+        );
+        assert_eq!(
+            run_code("for x in 5 { }").err(),
+            Some("Cannot iterate over a number".to_string())
+        );
+        assert_eq!(
+            run_code("fn f() { break; }\nf();").err(),
+            Some("break outside of a loop".to_string())
+        );
+    }
+
+    // ===== Named Argument Tests =====
+
+    const SUBSTRING: &str = "fn substring(text, start, end) {\n\
+                                 return std.str.left(std.str.right(text, std.len(text) - start), end - start);\n\
+                             }\n";
+
+    fn call_substring(call: &str) -> Result<Value, String> {
+        run_and_get_var(&format!("{}let result = {};", SUBSTRING, call), "result")
+    }
+
+    #[test]
+    fn test_named_arguments() {
+        let hello = Ok(Value::String("hello".to_string()));
+        assert_eq!(
+            call_substring("substring(end: 5, text: \"hello world\", start: 0)"),
+            hello
+        );
+        assert_eq!(
+            call_substring("substring(\"hello world\", end: 5, start: 0)"),
+            hello
+        );
+        assert_eq!(call_substring("substring(\"hello world\", 0, 5)"), hello);
+
+        // Natives take named arguments in call order
+        let code = "let parts = std.split_str(text: \"a,b\", separator: \",\");\n\
+                    let count = std.len(parts);";
+        assert_eq!(run_and_get_var(code, "count"), Ok(Value::Number(2)));
+    }
+
+    #[test]
+    fn test_named_arguments_on_methods() {
+        let code = "fn greet(greeting, name) { return greeting + \", \" + name; }\n\
+                    let o = std.obj.from_entries(std.arr.new(0));\n\
+                    std.obj.deep_set(o, \"greet\", greet);\n\
+                    let message = o.greet(name: \"mouse\", greeting: \"hi\");";
+        assert_eq!(
+            run_and_get_var(code, "message"),
+            Ok(Value::String("hi, mouse".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_named_argument_errors() {
+        let error = |call: &str| call_substring(call).unwrap_err();
+        assert_eq!(
+            error("substring(text: \"abc\", end: 1, end: 2)"),
+            "Function expects argument 'end' once, got it twice"
+        );
+        assert_eq!(
+            error("substring(\"abc\", 0, 1, start: 2)"),
+            "Function expects 3 arguments, got 4"
+        );
+        assert_eq!(
+            error("substring(\"abc\", 0, start: 2)"),
+            "Function expects argument 'start' once, got it by position and by name"
+        );
+        assert_eq!(
+            error("substring(\"abc\", 0, edn: 2)"),
+            "Function expects no argument named 'edn', did you mean 'end'?"
+        );
+        assert_eq!(
+            error("substring(\"abc\", 0, length: 2)"),
+            "Function expects no argument named 'length'"
+        );
+        assert_eq!(
+            error("substring(\"abc\", end: 2)"),
+            "Function expects an argument for 'start'"
+        );
+        assert_eq!(
+            code_of(&format!(
+                "{}let result = substring(\"abc\", 0, edn: 2);",
+                SUBSTRING
+            )),
+            Some("E0002")
+        );
+        assert!(error("substring(text: \"abc\", 0, 1)").starts_with("Parse error"));
+    }
+
+    // ===== Import Tests =====
+
+    /// Runs `code` with `dir` as the import base.
+    fn run_in(dir: &std::path::Path, code: &str) -> Result<Interpreter, String> {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_import_base(dir.to_path_buf());
+        crate::run_source_with(code, &mut interpreter).map_err(|e| e.to_string())?;
+        Ok(interpreter)
+    }
+
+    fn variable(interpreter: &Interpreter, name: &str) -> Option<Value> {
+        interpreter.env.get_variable(name).cloned()
+    }
+
+    /// A library named `greet` whose `greeting()` says where it came from.
+    fn greet_library(dir: &std::path::Path) -> std::path::PathBuf {
+        let source = dir.join("greet-src");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::write(
+            source.join("mouse.json"),
+            r#"{"name": "greet", "version": "1.2.0"}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            source.join("lib.mouse"),
+            "fn greeting() { return \"from mouse_modules\"; }",
+        )
+        .unwrap();
+        source
+    }
+
+    #[test]
+    fn test_import_installed_library_by_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = greet_library(dir.path());
+        crate::modules::install(source.to_str().unwrap(), dir.path()).unwrap();
+
+        let code = "import \"greet\";\nlet message = greeting();";
+        let interpreter = run_in(dir.path(), code).unwrap();
+        assert_eq!(
+            variable(&interpreter, "message"),
+            Some(Value::String("from mouse_modules".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_relative_file_shadows_installed_library() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = greet_library(dir.path());
+        crate::modules::install(source.to_str().unwrap(), dir.path()).unwrap();
+        std::fs::write(
+            dir.path().join("greet.mouse"),
+            "fn greeting() { return \"from greet.mouse\"; }",
+        )
+        .unwrap();
+
+        let code = "import \"greet\";\nlet message = greeting();";
+        let interpreter = run_in(dir.path(), code).unwrap();
+        assert_eq!(
+            variable(&interpreter, "message"),
+            Some(Value::String("from greet.mouse".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_module_runs_once_and_imports_from_its_own_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let lib = dir.path().join("lib");
+        std::fs::create_dir_all(&lib).unwrap();
+        std::fs::write(
+            lib.join("outer.mouse"),
+            "import \"inner\";\nloads = loads + 1;",
+        )
+        .unwrap();
+        std::fs::write(lib.join("inner.mouse"), "let inner_file = meta.file;").unwrap();
+
+        let code = "let loads = 0;\n\
+                    import \"lib/outer\";\n\
+                    import \"lib/outer.mouse\";\n\
+                    let file = meta.file;";
+        let interpreter = run_in(dir.path(), code).unwrap();
+        assert_eq!(variable(&interpreter, "loads"), Some(Value::Number(1)));
+        assert_eq!(
+            variable(&interpreter, "inner_file"),
+            Some(Value::String(lib.join("inner.mouse").display().to_string()))
+        );
+        assert_eq!(
+            variable(&interpreter, "file"),
+            Some(Value::String("<eval>".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_installed_library_imports_another_from_a_project_subdirectory() {
+        let dir = tempfile::tempdir().unwrap();
+        let greet = greet_library(dir.path());
+        crate::modules::install(greet.to_str().unwrap(), dir.path()).unwrap();
+        let welcome = dir.path().join("welcome-src");
+        std::fs::create_dir_all(&welcome).unwrap();
+        std::fs::write(
+            welcome.join("mouse.json"),
+            r#"{"name": "welcome", "version": "0.1.0"}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            welcome.join("lib.mouse"),
+            "import \"greet\";\nfn welcome() { return greeting() + \"!\"; }",
+        )
+        .unwrap();
+        crate::modules::install(welcome.to_str().unwrap(), dir.path()).unwrap();
+
+        let scripts = dir.path().join("scripts");
+        std::fs::create_dir_all(&scripts).unwrap();
+        let code = "import \"welcome\";\nlet message = welcome();";
+        let interpreter = run_in(&scripts, code).unwrap();
+        assert_eq!(
+            variable(&interpreter, "message"),
+            Some(Value::String("from mouse_modules!".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_failed_import_runs_again_when_imported_again() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("flaky.mouse"),
+            "attempts = attempts + 1;\nif attempts < 2 { throw \"not yet\"; }",
+        )
+        .unwrap();
+
+        let code = "let attempts = 0;\n\
+                    try { import \"flaky\"; } catch (e) { let first = e; }\n\
+                    import \"flaky\";\n\
+                    import \"flaky\";";
+        let interpreter = run_in(dir.path(), code).unwrap();
+        assert_eq!(variable(&interpreter, "attempts"), Some(Value::Number(2)));
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = run_in(dir.path(), "import \"missing\";").err().unwrap();
+        assert!(
+            err.starts_with(&format!(
+                "Runtime error: Cannot find module 'missing', searched:\n  {}\n  {}\n",
+                dir.path().join("missing.mouse").display(),
+                dir.path().join("mouse_modules/missing/lib.mouse").display()
+            )),
+            "{}",
+            err
+        );
+
+        std::fs::write(dir.path().join("broken.mouse"), "let = 1;").unwrap();
+        let err = run_in(dir.path(), "import \"broken\";").err().unwrap();
+        assert!(
+            err.starts_with(&format!(
+                "Runtime error: in module {}:",
+                dir.path().join("broken.mouse").display()
+            )),
+            "{}",
+            err
+        );
+    }
+
+    // ===== Error Code Tests =====
+
+    /// The code of the runtime error `code` fails with.
+    fn code_of(code: &str) -> Option<&'static str> {
+        match crate::run_source_with(code, &mut Interpreter::new()) {
+            Err(crate::RunError::Runtime(error)) => error.code.map(|entry| entry.code),
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_runtime_error_constructors_have_codes() {
+        use crate::errors;
+        let code = |error: errors::RuntimeError| error.code.map(|entry| entry.code);
+        assert_eq!(code(errors::undefined_variable("x")), Some("E0001"));
+        assert_eq!(code(errors::assign_to_undefined("x")), Some("E0001"));
+        assert_eq!(code(errors::arity_mismatch(2, 1)), Some("E0002"));
+        assert_eq!(code(errors::missing_argument("a")), Some("E0002"));
+        assert_eq!(code(errors::undefined_function("f")), Some("E0003"));
+        assert_eq!(code(errors::division_by_zero()), Some("E0004"));
+        assert_eq!(code(errors::call_depth_exceeded()), Some("E0005"));
+        assert_eq!(code(errors::type_mismatch()), Some("E0006"));
+        let unsupported =
+            errors::unsupported_operation(&crate::parser::BinaryOp::Subtract, "strings");
+        assert_eq!(code(unsupported), Some("E0006"));
+    }
+
+    #[test]
+    fn test_interpreter_errors_carry_codes() {
+        let cases = [
+            ("x = 1;", "E0001"),
+            ("fn f(a) { return a; }\nlet y = f(1, 2);", "E0002"),
+            ("let y = nope(1);", "E0003"),
+            ("let y = 1 / 0;", "E0004"),
+            ("let y = \"a\" - \"b\";", "E0006"),
+        ];
+        for (code, expected) in cases {
+            assert_eq!(code_of(code), Some(expected), "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_errors_that_only_look_coded_have_no_code() {
+        assert_eq!(code_of("throw \"Division by zero\";"), None);
+        assert_eq!(
+            code_of("let f = std.io.read_bytes(\"/no/such/file\");"),
+            None
+        );
+        // Caught and thrown again, it is the script's own error
+        let rethrown = "try { let y = 1 / 0; } catch (e) { throw e.message; }";
+        assert_eq!(code_of(rethrown), None);
+    }
+
+    #[test]
+    fn test_parse_errors_carry_codes() {
+        let code = |source: &str| {
+            let tokens = tokenize(source.to_string()).unwrap();
+            parse(&tokens).unwrap_err().code().map(|entry| entry.code)
+        };
+        assert_eq!(code("let = 2;"), Some("E0100"));
+        assert_eq!(code(") x;"), Some("E0104"));
+        let error = crate::errors::Error::unexpected_eof("test");
+        assert_eq!(error.code().map(|entry| entry.code), Some("E0101"));
+        let error = tokenize("let x = 99999999999999999999;".to_string()).unwrap_err();
+        assert_eq!(error.code().map(|entry| entry.code), Some("E0103"));
+    }
+
+    #[test]
+    fn test_rendered_runtime_error_names_its_code() {
+        use crate::errors::{self, RuntimeError};
+        use crate::style::Style;
+        let rendered = errors::render_runtime_error(&errors::division_by_zero(), Style::plain());
+        assert_eq!(
+            rendered,
+            "Runtime error[E0004]: Division by zero\n  \
+             = help: run `mouse --explain E0004` for more about this error\n"
+        );
+        let custom = RuntimeError {
+            message: "Division by zero".to_string(),
+            code: None,
+        };
+        let rendered = errors::render_runtime_error(&custom, Style::plain());
+        assert_eq!(rendered, "Runtime error: Division by zero\n");
+    }
+
+    #[test]
+    fn test_explain_finds_registered_codes() {
+        use crate::errors::{explain, ERROR_CODES};
+        let entry = explain("E0002").unwrap();
+        assert_eq!(entry.title, "wrong number of arguments");
+        assert!(entry
+            .to_string()
+            .starts_with("E0002: wrong number of arguments\n\n"));
+        assert_eq!(explain("e0002"), Some(entry));
+        assert_eq!(explain("E9999"), None);
+        for entry in ERROR_CODES {
+            assert!(
+                entry.explanation.contains("Failing example:"),
+                "{}",
+                entry.code
+            );
+            assert!(entry.explanation.contains("Corrected:"), "{}", entry.code);
+        }
+    }
 }
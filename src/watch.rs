@@ -0,0 +1,123 @@
+//! Re-runs a script every time it changes on disk.
+//!
+//! Besides the script itself, every module it imported, directly or through
+//! other modules, in any run so far is watched. A module that has not been
+//! imported successfully yet is not.
+
+use crate::interpreter::Interpreter;
+use crate::{run_file_with, RunError};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How often the stop flag is checked while waiting for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+pub struct WatchOptions {
+    /// Quiet period after the last change before re-running, so editors that
+    /// save in several writes only trigger one run.
+    pub debounce: Duration,
+    /// Makes `watch` return once set. It may be set from another thread.
+    pub stop: Arc<AtomicBool>,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        WatchOptions {
+            debounce: Duration::from_millis(200),
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+/// Runs the script at `path` once, then again after every change until
+/// `options.stop` is set.
+///
+/// Each run gets a fresh interpreter from `make_interpreter`, and its result
+/// is passed to `on_result` together with the run number, starting at 1.
+/// Syntax and runtime errors are reported there and do not end the watch.
+pub fn watch(
+    path: &Path,
+    options: &WatchOptions,
+    mut make_interpreter: impl FnMut() -> Interpreter,
+    mut on_result: impl FnMut(usize, Result<(), RunError>),
+) -> Result<(), String> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| format!("Cannot watch {}: not a file", path.display()))?
+        .to_os_string();
+    // Watch the directory so editors that save by replacing the file are seen.
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)
+        .map_err(|e| format!("Cannot watch {}: {}", path.display(), e))?;
+    watcher
+        .watch(dir, RecursiveMode::NonRecursive)
+        .map_err(|e| format!("Cannot watch {}: {}", path.display(), e))?;
+
+    let mut watched_dirs = HashSet::from([dir.canonicalize().unwrap_or_else(|_| dir.into())]);
+    let mut modules = HashSet::new();
+    let is_relevant = |event: &notify::Event, modules: &HashSet<PathBuf>| {
+        matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+            && event.paths.iter().any(|changed| {
+                changed.file_name() == Some(file_name.as_os_str()) || modules.contains(changed)
+            })
+    };
+
+    let mut run = 1;
+    let mut interpreter = make_interpreter();
+    on_result(run, run_file_with(path, &mut interpreter));
+    watch_modules(&mut watcher, &interpreter, &mut watched_dirs, &mut modules)?;
+
+    while !options.stop.load(Ordering::Relaxed) {
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(Ok(event)) if is_relevant(&event, &modules) => {}
+            Ok(_) | Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(format!("Stopped watching {}", path.display()));
+            }
+        }
+
+        // Wait until the file has been quiet for the debounce period
+        while receiver.recv_timeout(options.debounce).is_ok() {}
+        if options.stop.load(Ordering::Relaxed) {
+            break;
+        }
+
+        run += 1;
+        let mut interpreter = make_interpreter();
+        on_result(run, run_file_with(path, &mut interpreter));
+        watch_modules(&mut watcher, &interpreter, &mut watched_dirs, &mut modules)?;
+    }
+    Ok(())
+}
+
+/// Adds the modules `interpreter` imported to `modules` and watches the
+/// directory of each, unless `watched_dirs` already has it.
+fn watch_modules(
+    watcher: &mut RecommendedWatcher,
+    interpreter: &Interpreter,
+    watched_dirs: &mut HashSet<PathBuf>,
+    modules: &mut HashSet<PathBuf>,
+) -> Result<(), String> {
+    for module in interpreter.imported_modules() {
+        if let Some(dir) = module.parent() {
+            if !watched_dirs.contains(dir) {
+                watcher
+                    .watch(dir, RecursiveMode::NonRecursive)
+                    .map_err(|e| format!("Cannot watch {}: {}", module.display(), e))?;
+                watched_dirs.insert(dir.to_path_buf());
+            }
+        }
+        modules.insert(module);
+    }
+    Ok(())
+}
@@ -0,0 +1,436 @@
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+fn write_script(name: &str, code: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("mouse_cli_{}_{}", std::process::id(), name));
+    std::fs::write(&path, code).unwrap();
+    path
+}
+
+#[test]
+fn test_timeout_stops_infinite_loop_and_keeps_output() {
+    let script = write_script(
+        "timeout.mouse",
+        "print(\"before loop\");\nlet i = 0;\nwhile 1 {\n    i = i + 1;\n}\n",
+    );
+
+    let started = Instant::now();
+    let output = Command::new(env!("CARGO_BIN_EXE_mouse-lang"))
+        .arg(&script)
+        .args(["--timeout", "0.2"])
+        .output()
+        .unwrap();
+    std::fs::remove_file(&script).unwrap();
+
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert_eq!(output.status.code(), Some(124));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stdout.contains("before loop"));
+    assert!(stderr.contains("execution timed out after 0.2s"));
+}
+
+#[test]
+fn test_unusable_timeout_is_a_cli_error() {
+    let script = write_script("bad_timeout.mouse", "print(\"ran\");\n");
+    for timeout in ["-1", "NaN", "inf", "1e300", "soon"] {
+        let output = Command::new(env!("CARGO_BIN_EXE_mouse-lang"))
+            .arg(&script)
+            .arg(format!("--timeout={}", timeout))
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(2), "{}: {}", timeout, stderr);
+        assert!(stderr.contains("--timeout"), "{}", stderr);
+        assert!(!stderr.contains("panicked"), "{}", stderr);
+        assert!(!String::from_utf8_lossy(&output.stdout).contains("ran"));
+    }
+    std::fs::remove_file(&script).unwrap();
+}
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("mouse_cli_{}_{}", std::process::id(), name));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_directory_runs_each_file_in_isolation() {
+    let dir = temp_dir("suite");
+    std::fs::write(dir.join("a.mouse"), "let shared = 1;\nprint(\"a done\");\n").unwrap();
+    std::fs::write(dir.join("b.mouse"), "print(shared);\n").unwrap();
+    std::fs::write(dir.join("c.mouse"), "print(\"c done\");\n").unwrap();
+    std::fs::write(dir.join("notes.txt"), "not a script").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_mouse-lang"))
+        .arg("-f")
+        .arg(&dir)
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let position = |needle: &str| {
+        stdout
+            .find(needle)
+            .unwrap_or_else(|| panic!("{} missing from output:\n{}", needle, stdout))
+    };
+
+    // files run in name order and a failure does not stop the rest
+    assert!(position("a.mouse ===") < position("a done"));
+    assert!(position("a done") < position("b.mouse ==="));
+    assert!(position("b.mouse ===") < position("c.mouse ==="));
+    assert!(position("c.mouse ===") < position("c done"));
+    assert!(!stdout.contains("notes.txt"));
+    // `shared` from a.mouse is not visible in b.mouse
+    assert!(stderr.contains("Undefined identifier: shared"));
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_repeated_filename_flags_succeed() {
+    let first = write_script("first.mouse", "print(\"first\");\n");
+    let second = write_script("second.mouse", "print(\"second\");\n");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_mouse-lang"))
+        .arg("-f")
+        .arg(&first)
+        .arg("-f")
+        .arg(&second)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&first).unwrap();
+    std::fs::remove_file(&second).unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.find("first").unwrap() < stdout.find("second").unwrap());
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_run_project_entry_point() {
+    let dir = temp_dir("project");
+    std::fs::write(dir.join("main.mouse"), "print(\"from main\");\n").unwrap();
+    std::fs::write(dir.join("helper.mouse"), "print(\"from helper\");\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_mouse-lang"))
+        .arg("run")
+        .arg(&dir)
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("from main"));
+    assert!(!stdout.contains("from helper"));
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_run_without_entry_point_fails() {
+    let dir = temp_dir("empty_project");
+    let output = Command::new(env!("CARGO_BIN_EXE_mouse-lang"))
+        .arg("run")
+        .arg(&dir)
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(String::from_utf8_lossy(&output.stderr).contains("main.mouse"));
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_prelude_functions_are_available() {
+    let prelude = write_script(
+        "prelude_log.mouse",
+        "fn log(msg) {\n    print(\"[log] \" + msg);\n}\n",
+    );
+    let script = write_script("uses_log.mouse", "log(\"hello\");\n");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_mouse-lang"))
+        .arg(&script)
+        .arg("--prelude")
+        .arg(&prelude)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&prelude).unwrap();
+    std::fs::remove_file(&script).unwrap();
+
+    assert!(String::from_utf8_lossy(&output.stdout).contains("[log] hello"));
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_prelude_syntax_error_names_prelude() {
+    let prelude = write_script("broken_prelude.mouse", "fn log(msg) {\n    let = 1;\n}\n");
+    let script = write_script("after_broken.mouse", "print(\"main ran\");\n");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_mouse-lang"))
+        .arg(&script)
+        .env("MOUSE_PRELUDE", &prelude)
+        .arg("--no-color")
+        .output()
+        .unwrap();
+    std::fs::remove_file(&prelude).unwrap();
+    std::fs::remove_file(&script).unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!("--> {}:2:", prelude.display())),
+        "{}",
+        stderr
+    );
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("main ran"));
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_prelude_with_eval() {
+    let prelude = write_script(
+        "prelude_eval.mouse",
+        "fn double(n) {\n    return n * 2;\n}\n",
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_mouse-lang"))
+        .arg("--prelude")
+        .arg(&prelude)
+        .args(["--eval", "print(double(21));"])
+        .output()
+        .unwrap();
+    std::fs::remove_file(&prelude).unwrap();
+
+    assert!(String::from_utf8_lossy(&output.stdout).contains("42"));
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_autofix_refuses_to_change_string_literal() {
+    // Adding a semicolon after the first line would land inside the string
+    let code = "let s = \"first\nsecond\";\nprint(s);\n";
+    let script = write_script("multiline_string.mouse", code);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_mouse-lang"))
+        .arg(&script)
+        .arg("--no-color")
+        .output()
+        .unwrap();
+    let after = std::fs::read_to_string(&script).unwrap();
+    std::fs::remove_file(&script).unwrap();
+
+    assert_eq!(after, code);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("warning: not applying autofix")
+            && stderr.contains("changes more than semicolons at line 1, column 9"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_autofix_writes_fixed_script_back() {
+    let script = write_script("fixable.mouse", "let x = 1\nprint(x + 1)\n");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_mouse-lang"))
+        .arg(&script)
+        .output()
+        .unwrap();
+    let after = std::fs::read_to_string(&script).unwrap();
+    let backup_exists = script.with_extension("mouse.bak").exists();
+    std::fs::remove_file(&script).unwrap();
+
+    assert_eq!(after, "let x = 1;\nprint(x + 1);\n");
+    assert!(!backup_exists);
+    assert!(String::from_utf8_lossy(&output.stdout).contains('2'));
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_autofix_backup_keeps_original() {
+    let original = "let x = 1\nprint(x)\n";
+    let script = write_script("backed_up.mouse", original);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_mouse-lang"))
+        .arg(&script)
+        .arg("--autofix-backup")
+        .output()
+        .unwrap();
+    let backup = script.with_extension("mouse.bak");
+    let after = std::fs::read_to_string(&script).unwrap();
+    let saved = std::fs::read_to_string(&backup).unwrap();
+    std::fs::remove_file(&script).unwrap();
+    std::fs::remove_file(&backup).unwrap();
+
+    assert_eq!(after, "let x = 1;\nprint(x);\n");
+    assert_eq!(saved, original);
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_coverage_lcov_file() {
+    let script = write_script(
+        "covered.mouse",
+        "let x = 1;\nif x > 1 {\n    print(x);\n}\n",
+    );
+    let lcov = std::env::temp_dir().join(format!("mouse_cli_{}_covered.info", std::process::id()));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_mouse-lang"))
+        .arg(&script)
+        .arg("--coverage")
+        .arg("--coverage-lcov")
+        .arg(&lcov)
+        .output()
+        .unwrap();
+    let written = std::fs::read_to_string(&lcov).unwrap();
+    std::fs::remove_file(&script).unwrap();
+    std::fs::remove_file(&lcov).unwrap();
+
+    assert!(written.contains(&format!("SF:{}\n", script.display())));
+    assert!(written.contains("DA:3,0\n"));
+    assert!(written.ends_with("LF:3\nLH:2\nend_of_record\n"));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("2 of 3 lines covered"), "{}", stdout);
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_meta_file_is_the_script_path() {
+    let script = write_script("meta.mouse", "print(meta.file);\n");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_mouse-lang"))
+        .arg(&script)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&script).unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(&script.display().to_string()), "{}", stdout);
+}
+
+#[test]
+fn test_allow_exec_registers_std_exec() {
+    let script = write_script(
+        "exec.mouse",
+        "let result = std.exec(\"echo\", std.arr.fill(1, \"from child\"));\nprint(result.stdout);\n",
+    );
+
+    let denied = Command::new(env!("CARGO_BIN_EXE_mouse-lang"))
+        .arg(&script)
+        .output()
+        .unwrap();
+    let allowed = Command::new(env!("CARGO_BIN_EXE_mouse-lang"))
+        .arg(&script)
+        .arg("--allow-exec")
+        .output()
+        .unwrap();
+    std::fs::remove_file(&script).unwrap();
+
+    assert!(!String::from_utf8_lossy(&denied.stdout).contains("from child"));
+    assert_ne!(denied.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&allowed.stdout);
+    assert_eq!(
+        allowed.status.code(),
+        Some(0),
+        "{}",
+        String::from_utf8_lossy(&allowed.stderr)
+    );
+    assert!(stdout.contains("from child"), "{}", stdout);
+}
+
+#[test]
+fn test_explain_prints_registered_code() {
+    let output = Command::new(env!("CARGO_BIN_EXE_mouse-lang"))
+        .args(["--explain", "E0002"])
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(
+        stdout.starts_with("E0002: wrong number of arguments"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("Corrected:"), "{}", stdout);
+}
+
+#[test]
+fn test_explain_rejects_unknown_code() {
+    let output = Command::new(env!("CARGO_BIN_EXE_mouse-lang"))
+        .args(["--explain", "E9999"])
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("E9999 is not a known error code"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_runtime_error_shows_its_code() {
+    let script = write_script("error_code.mouse", "let x = 1 / 0;\n");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_mouse-lang"))
+        .arg(&script)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&script).unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Runtime error[E0004]: Division by zero"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("mouse --explain E0004"), "{}", stderr);
+}
+
+#[test]
+fn test_install_then_import_by_name() {
+    let dir = temp_dir("install");
+    let library = dir.join("shout-src");
+    std::fs::create_dir_all(&library).unwrap();
+    std::fs::write(
+        library.join("mouse.json"),
+        r#"{"name": "shout", "version": "0.3.0"}"#,
+    )
+    .unwrap();
+    std::fs::write(
+        library.join("lib.mouse"),
+        "fn shout(s) {\n    return s + \"!\";\n}\n",
+    )
+    .unwrap();
+    let project = dir.join("project");
+    std::fs::create_dir_all(&project).unwrap();
+    std::fs::write(
+        project.join("main.mouse"),
+        "import \"shout\";\nprint(shout(\"hi\"));\n",
+    )
+    .unwrap();
+
+    let install = Command::new(env!("CARGO_BIN_EXE_mouse-lang"))
+        .arg("install")
+        .arg(&library)
+        .current_dir(&project)
+        .output()
+        .unwrap();
+    let run = Command::new(env!("CARGO_BIN_EXE_mouse-lang"))
+        .arg(project.join("main.mouse"))
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(install.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&install.stdout),
+        "installed shout 0.3.0\n"
+    );
+    assert!(run.status.success());
+    assert!(String::from_utf8_lossy(&run.stdout).contains("hi!"));
+}
@@ -0,0 +1,89 @@
+use mouse_lang::interpreter::{Interpreter, Value};
+
+const SCRIPT: &str = r#"
+let greeting = "hello";
+fn pair(k, v) {
+    let p = std.arr.new(0);
+    std.arr.push(p, k);
+    std.arr.push(p, v);
+    return p;
+}
+fn handle(req) {
+    if req.path == "/hello" {
+        return greeting + " " + req.query.name;
+    }
+    if req.path == "/echo" {
+        let entries = std.arr.new(0);
+        std.arr.push(entries, pair("status", 201));
+        std.arr.push(entries, pair("body", req.method + " " + req.body));
+        return std.obj.from_entries(entries);
+    }
+    return missing_function();
+}
+let server = std.http.serve_background(0, handle);
+"#;
+
+/// Starts the script's server; it runs as long as the returned interpreter.
+fn start_server() -> (Interpreter, String) {
+    let mut interpreter = Interpreter::new();
+    mouse_lang::run_source_with(SCRIPT, &mut interpreter).unwrap();
+    let Some(Value::Object(server)) = interpreter.env.get_variable("server") else {
+        panic!("serve_background did not return a server object");
+    };
+    let port = server.borrow().get_property("port").cloned();
+    let Some(Value::Number(port)) = port else {
+        panic!("server object has no port");
+    };
+    let base = format!("http://127.0.0.1:{}", port);
+    (interpreter, base)
+}
+
+#[test]
+fn test_routes_are_handled_by_mouse_function() {
+    let (_interpreter, base) = start_server();
+    let client = reqwest::blocking::Client::new();
+
+    let response = client
+        .get(format!("{}/hello?name=mouse", base))
+        .send()
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.text().unwrap(), "hello mouse");
+
+    let response = client
+        .post(format!("{}/echo", base))
+        .body("cheese")
+        .send()
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 201);
+    assert_eq!(response.text().unwrap(), "POST cheese");
+}
+
+#[test]
+fn test_failing_handler_returns_500_with_error() {
+    let (_interpreter, base) = start_server();
+    let response = reqwest::blocking::get(format!("{}/broken", base)).unwrap();
+    assert_eq!(response.status().as_u16(), 500);
+    assert!(response.text().unwrap().contains("missing_function"));
+}
+
+#[test]
+fn test_stop_shuts_the_server_down() {
+    let (mut interpreter, base) = start_server();
+    let client = reqwest::blocking::Client::new();
+    let hello = format!("{}/hello?name=mouse", base);
+    assert_eq!(client.get(&hello).send().unwrap().status().as_u16(), 200);
+
+    mouse_lang::run_source_with("server.stop();", &mut interpreter).unwrap();
+    let refused = (0..50).any(|_| {
+        let stopped = reqwest::blocking::get(&hello).is_err();
+        if !stopped {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        stopped
+    });
+    assert!(refused, "the server still answers after stop()");
+
+    let err = mouse_lang::run_source_with("server.stop();", &mut interpreter).unwrap_err();
+    assert!(err.to_string().contains("already stopped"), "{}", err);
+}